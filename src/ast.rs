@@ -21,7 +21,8 @@
 ///    patterns are _not sparse_ in the input.
 use crate::arena::{self, Arena};
 use crate::builtins::Function;
-use crate::common::{Either, FileSpec, Stage};
+use crate::common::{Either, FileSpec, InputSpec, Stage};
+use crate::runtime::splitter::ReaderState;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Unop {
@@ -57,6 +58,8 @@ pub struct Prog<'a, 'b, I> {
 
     // FS
     pub field_sep: Option<&'b [u8]>,
+    // FIELDWIDTHS
+    pub field_widths: Option<&'b [u8]>,
     pub prelude_vardecs: Vec<(I, &'a Expr<'a, 'b, I>)>,
     // OFS
     pub output_sep: Option<&'b [u8]>,
@@ -66,23 +69,42 @@ pub struct Prog<'a, 'b, I> {
     pub begin: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
     pub prepare: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
     pub end: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+    pub beginfile: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+    pub endfile: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
     pub pats: arena::Vec<'a, (Pattern<'a, 'b, I>, Option<&'a Stmt<'a, 'b, I>>)>,
     pub stage: Stage<()>,
     pub argv: Vec<&'b str>,
+    // Operands of the form `var=value`, along with the number of input files preceding them.
+    pub operand_assigns: Vec<(usize, I, &'a Expr<'a, 'b, I>)>,
+    // The input files, not including any assignment operands.
+    pub input_files: Vec<&'b str>,
     pub parse_header: bool,
+    // Whether the main input is JSON Lines. FI is only populated for such input with -H.
+    pub jsonl_input: bool,
+    // Whether to record the header's column names as keys for JSON output.
+    pub header_names: bool,
 }
 
+/// (Illegal) identifiers for the value of the last read from the main input when file events are
+/// enabled, and whether the header for -H was the first record of its file.
+const READ: &str = "--read";
+const HEADER_FILE: &str = "--header-file";
+
 fn parse_header<'a, 'b, I: From<&'b str> + Clone>(
     arena: &'a Arena,
     begin: &mut arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+    read: &'a Expr<'a, 'b, I>,
+    header_names: bool,
 ) {
     use {self::Expr::*, Stmt::*};
     // Pick an illegal frawk identifier.
     const LOOP_VAR: &str = "--";
-    // Append the following to begin:
-    // if (getline > 0) {
-    //  for (LOOP_VAR=1; LOOP_VAR <= NF; ++LOOP_VAR)
+    // Append the following to begin, where `read` reads the header and tests that it succeeded:
+    // if (read) {
+    //  for (LOOP_VAR=1; LOOP_VAR <= NF; ++LOOP_VAR) {
     //      FI[$LOOP_VAR] = LOOP_VAR;
+    //      set_json_key(LOOP_VAR); # if header_names is set
+    //  }
     //  update_used_fields()
     // }
 
@@ -98,10 +120,18 @@ fn parse_header<'a, 'b, I: From<&'b str> + Clone>(
         is_post: false,
         x: loop_var,
     })));
-    let body = arena.alloc(Expr(arena.alloc(Call(
+    let set_fi = arena.alloc(Expr(arena.alloc(Call(
         Either::Right(Function::SetFI),
         arena.alloc_slice(&[loop_var, loop_var]),
     ))));
+    let mut body = arena.new_vec_from_slice(&[set_fi]);
+    if header_names {
+        body.push(arena.alloc(Expr(arena.alloc(Call(
+            Either::Right(Function::SetJSONKey),
+            arena.alloc_slice(&[loop_var]),
+        )))));
+    }
+    let body = arena.alloc(Block(body));
 
     let block = arena.new_vec_from_slice(&[
         arena.alloc(For(Some(init), Some(cond), Some(update), body)),
@@ -109,21 +139,14 @@ fn parse_header<'a, 'b, I: From<&'b str> + Clone>(
             arena.alloc(Call(Either::Right(Function::UpdateUsedFields), &[])),
         )),
     ]);
-    begin.push(arena.alloc(If(
-        arena.alloc(Binop(
-            self::Binop::GT,
-            arena.alloc(ReadStdin),
-            arena.alloc(ILit(0)),
-        )),
-        arena.alloc(Block(block)),
-        /*else*/ None,
-    )));
+    begin.push(arena.alloc(If(read, arena.alloc(Block(block)), /*else*/ None)));
 }
 
 impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
     pub(crate) fn from_stage(arena: &'a Arena, stage: Stage<()>) -> Self {
         Prog {
             field_sep: None,
+            field_widths: None,
             prelude_vardecs: Vec::new(),
             output_sep: None,
             output_record_sep: None,
@@ -131,12 +154,188 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             begin: arena.new_vec(),
             prepare: arena.new_vec(),
             end: arena.new_vec(),
+            beginfile: arena.new_vec(),
+            endfile: arena.new_vec(),
             pats: arena.new_vec(),
             argv: Vec::new(),
+            operand_assigns: Vec::new(),
+            input_files: Vec::new(),
             parse_header: false,
+            jsonl_input: false,
+            header_names: false,
             stage,
         }
     }
+    // Desugar assignment operands that appear after the first input file. We track the number of
+    // files that the main loop has started reading in FILE_INDEX, incrementing it as each file
+    // starts (see file_transitions). Each time it increments, we run the assignments immediately
+    // preceding the new file. The first statement returned is run when the main loop enters a new
+    // file:
+    //
+    // {
+    //   ++FILE_INDEX;
+    //   if (FILE_INDEX == 2) { <assignments before file 2> }
+    //   ...
+    // }
+    //
+    // Assignments after the last file, or before files that we never reached, run before END:
+    //
+    // if (FILE_INDEX <= 1) { <assignments before file 2> }
+    // ...
+    //
+    // Returns None if there are no such assignments.
+    fn file_assignments(
+        &self,
+        arena: &'a Arena,
+    ) -> Option<(&'a Stmt<'a, 'b, I>, &'a Stmt<'a, 'b, I>)> {
+        use {self::Binop::*, self::Expr::*, Stmt::*};
+        // Pick an illegal frawk identifier.
+        const FILE_INDEX: &str = "--file-index";
+        let n_files = self.input_files.len();
+        if !self
+            .operand_assigns
+            .iter()
+            .any(|(files_before, _, _)| *files_before > 0)
+        {
+            return None;
+        }
+        let file_ix = arena.alloc(Var(FILE_INDEX.into()));
+        let assigns_before = |file: usize| {
+            let mut block = arena.new_vec();
+            for (files_before, ident, exp) in self.operand_assigns.iter() {
+                if *files_before == file {
+                    block.push(arena.alloc(Expr(
+                        arena.alloc(Assign(arena.alloc(Var(ident.clone())), exp)),
+                    )));
+                }
+            }
+            block
+        };
+        let ix_is = |op, i: usize| arena.alloc(Binop(op, file_ix, arena.alloc(ILit(i as i64))));
+
+        let mut update = arena.new_vec();
+        update.push(arena.alloc(Expr(arena.alloc(Inc {
+            is_inc: true,
+            is_post: false,
+            x: file_ix,
+        }))));
+        for file in 1..n_files {
+            let block = assigns_before(file);
+            if !block.is_empty() {
+                update.push(arena.alloc(If(ix_is(EQ, file + 1), arena.alloc(Block(block)), None)));
+            }
+        }
+        let update = arena.alloc(Block(update));
+
+        let mut remaining = arena.new_vec();
+        for file in 1..=n_files {
+            let block = assigns_before(file);
+            if !block.is_empty() {
+                remaining.push(arena.alloc(If(ix_is(LTE, file), arena.alloc(Block(block)), None)));
+            }
+        }
+        Some((update, arena.alloc(Block(remaining))))
+    }
+
+    fn has_main_loop(&self) -> bool {
+        !self.end.is_empty()
+            || !self.prepare.is_empty()
+            || !self.beginfile.is_empty()
+            || !self.endfile.is_empty()
+            || !self.pats.is_empty()
+    }
+
+    // Whether the main loop needs to know where each input file starts and ends, in which case
+    // the program is run with file events enabled (see LineReader::set_file_events).
+    pub(crate) fn file_events(&self) -> bool {
+        let serial = matches!(self.stage, Stage::Main(_));
+        let later_assigns = self
+            .operand_assigns
+            .iter()
+            .any(|(files_before, _, _)| *files_before > 0);
+        self.has_main_loop()
+            && (!self.beginfile.is_empty() || !self.endfile.is_empty() || (serial && later_assigns))
+    }
+
+    // Desugar BEGINFILE and ENDFILE blocks, along with any statement (see file_assignments) that
+    // must run when the main loop enters a new file. With file events enabled, reads from the
+    // main input also report the start and end of each input file without reading a record. The
+    // main loop dispatches on the value of each read:
+    //
+    // while ((READ = getline) > 0) {
+    //   if (READ == 1) {
+    //     <body>
+    //   } else if (READ == FILE_START) {
+    //     <on_enter>
+    //     <BEGINFILE>
+    //   } else {
+    //     <ENDFILE>
+    //   }
+    // }
+    //
+    // FILENAME and FNR are only reset when the next file starts, so ENDFILE sees the values for
+    // the file that ended, along with its last record.
+    //
+    // With -H, the header is read before BEGIN runs; we skip past the start of the first file
+    // there and enter it at the top of the main loop instead:
+    //
+    // while ((READ = getline) > 1) {
+    //   HEADER_FILE = (READ == FILE_START);
+    // }
+    // if (READ > 0) { <parse header> }
+    // ...
+    // while ((READ = getline) > 0) {
+    //   if (HEADER_FILE) { HEADER_FILE = 0; <on_enter> <BEGINFILE> }
+    //   ...
+    // }
+    fn file_transitions(
+        &self,
+        arena: &'a Arena,
+        on_enter: Option<&'a Stmt<'a, 'b, I>>,
+        body: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+    ) -> &'a Stmt<'a, 'b, I> {
+        use {self::Binop::*, self::Expr::*, Stmt::*};
+        let read = arena.alloc(Var(READ.into()));
+        let read_is =
+            |state: ReaderState| arena.alloc(Binop(EQ, read, arena.alloc(ILit(state as i64))));
+        let enter = || {
+            let mut block = arena.vec_with_capacity(self.beginfile.len() + 1);
+            block.extend(on_enter);
+            block.extend(self.beginfile.iter().cloned());
+            block
+        };
+        let mut dispatch = arena.alloc(If(
+            read_is(ReaderState::OK),
+            arena.alloc(Block(body)),
+            Some(arena.alloc(If(
+                read_is(ReaderState::FILE_START),
+                arena.alloc(Block(enter())),
+                Some(arena.alloc(Block(arena.new_vec_from_slice(&self.endfile[..])))),
+            ))),
+        ));
+        if self.parse_header {
+            let header_file = arena.alloc(Var(HEADER_FILE.into()));
+            let mut block = enter();
+            block.insert(
+                0,
+                arena.alloc(Expr(arena.alloc(Assign(header_file, arena.alloc(ILit(0)))))),
+            );
+            let mut loop_body = arena.vec_with_capacity(2);
+            loop_body.push(arena.alloc(If(header_file, arena.alloc(Block(block)), None)));
+            loop_body.push(dispatch);
+            dispatch = arena.alloc(Block(loop_body));
+        }
+        arena.alloc(While(
+            /*is_toplevel=*/ true,
+            arena.alloc(Binop(
+                GT,
+                arena.alloc(Assign(read, arena.alloc(ReadStdin))),
+                arena.alloc(ILit(0)),
+            )),
+            dispatch,
+        ))
+    }
+
     pub(crate) fn desugar_stage(&self, arena: &'a Arena) -> Stage<&'a Stmt<'a, 'b, I>> {
         use {self::Binop::*, self::Expr::*, Stmt::*};
         let mut conds = 0;
@@ -152,10 +351,42 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
                 arena.alloc(StrLit(sep)),
             )))));
         }
+        // Desugar -i fixed
+        if let Some(widths) = self.field_widths {
+            begin.push(arena.alloc(Expr(arena.alloc(Assign(
+                arena.alloc(Var("FIELDWIDTHS".into())),
+                arena.alloc(StrLit(widths)),
+            )))));
+        }
 
         // for -H
+        let file_events = self.file_events();
         if self.parse_header {
-            parse_header(arena, &mut begin);
+            let read = if file_events {
+                // See file_transitions.
+                let read = arena.alloc(Var(READ.into()));
+                begin.push(arena.alloc(While(
+                    /*is_toplevel=*/ false,
+                    arena.alloc(Binop(
+                        GT,
+                        arena.alloc(Assign(read, arena.alloc(ReadStdin))),
+                        arena.alloc(ILit(1)),
+                    )),
+                    arena.alloc(Expr(arena.alloc(Assign(
+                        arena.alloc(Var(HEADER_FILE.into())),
+                        arena.alloc(Binop(
+                            EQ,
+                            read,
+                            arena.alloc(ILit(ReaderState::FILE_START as i64)),
+                        )),
+                    )))),
+                )));
+                read
+            } else {
+                arena.alloc(ReadStdin)
+            };
+            let cond = arena.alloc(Binop(GT, read, arena.alloc(ILit(0))));
+            parse_header(arena, &mut begin, cond, self.header_names);
         }
 
         // Support "output csv/tsv" mode
@@ -201,6 +432,18 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
 
         begin.extend(self.begin.iter().cloned());
 
+        // Assignment operands that come before any input file take effect after BEGIN. The rest
+        // run as the main loop reaches the file that follows them (see file_assignments); they
+        // are not supported in parallel mode.
+        let serial = matches!(self.stage, Stage::Main(_));
+        for (files_before, ident, exp) in self.operand_assigns.iter() {
+            if *files_before == 0 {
+                begin.push(arena.alloc(Expr(
+                    arena.alloc(Assign(arena.alloc(Var(ident.clone())), exp)),
+                )));
+            }
+        }
+
         // Desugar patterns into if statements, with the usual desugaring for an empty action.
         let mut inner = arena.vec_with_capacity(10);
         inner.push(arena.alloc(Expr(arena.alloc(Inc {
@@ -213,7 +456,6 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             is_post: false,
             x: arena.alloc(Var("FNR".into())),
         }))));
-        let init_len = inner.len();
         for (pat, body) in self.pats.iter() {
             let body = if let Some(body) = body {
                 body
@@ -282,13 +524,27 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             }
         }
 
-        if !self.end.is_empty() || !self.prepare.is_empty() || inner.len() > init_len {
-            // Wrap the whole thing in a while((getline) > 0) { } statement.
-            let main_portion = arena.alloc(While(
-                /*is_toplevel=*/ true,
-                arena.alloc(Binop(GT, arena.alloc(ReadStdin), arena.alloc(ILit(0)))),
-                arena.alloc(Block(inner)),
-            ));
+        let has_main_loop = self.has_main_loop();
+        let mut end_prelude = None;
+        let mut on_enter = None;
+        if has_main_loop && serial {
+            if let Some((update, remaining)) = self.file_assignments(arena) {
+                on_enter = Some(update);
+                end_prelude = Some(remaining);
+            }
+        }
+
+        if has_main_loop {
+            let main_portion = if file_events {
+                self.file_transitions(arena, on_enter, inner)
+            } else {
+                // Wrap the whole thing in a while((getline) > 0) { } statement.
+                arena.alloc(While(
+                    /*is_toplevel=*/ true,
+                    arena.alloc(Binop(GT, arena.alloc(ReadStdin), arena.alloc(ILit(0)))),
+                    arena.alloc(Block(inner)),
+                ))
+            };
             main_loop = Some(if self.prepare.is_empty() {
                 main_portion
             } else {
@@ -299,7 +555,10 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             });
         }
         if !self.end.is_empty() {
-            end = Some(arena.alloc(Stmt::Block(self.end.clone())));
+            let mut block = arena.vec_with_capacity(self.end.len() + 1);
+            block.extend(end_prelude);
+            block.extend(self.end.iter().cloned());
+            end = Some(arena.alloc(Stmt::Block(block)));
         }
         match self.stage {
             Stage::Main(_) => {
@@ -384,7 +643,7 @@ pub enum Expr<'a, 'b, I> {
     Getline {
         into: Option<&'a Expr<'a, 'b, I>>,
         from: Option<&'a Expr<'a, 'b, I>>,
-        is_file: bool,
+        spec: InputSpec,
     },
     ReadStdin,
     // Used for comma patterns
@@ -434,3 +693,23 @@ pub enum Stmt<'a, 'b, I> {
     NextFile,
     Return(Option<&'a Expr<'a, 'b, I>>),
 }
+
+impl<'a, 'b, I> Stmt<'a, 'b, I> {
+    /// Whether this statement contains a `next` or `nextfile` statement.
+    pub fn has_next(&self) -> bool {
+        use Stmt::*;
+        match self {
+            Next | NextFile => true,
+            Block(stmts) => stmts.iter().any(|s| s.has_next()),
+            If(_, t, f) => t.has_next() || f.is_some_and(|f| f.has_next()),
+            For(init, _, update, body) => {
+                init.is_some_and(|s| s.has_next())
+                    || update.is_some_and(|s| s.has_next())
+                    || body.has_next()
+            }
+            DoWhile(_, body) | While(_, _, body) | ForEach(_, _, body) => body.has_next(),
+            StartCond(_) | EndCond(_) | LastCond(_) | Expr(_) | Print(..) | Printf(..) | Break
+            | Continue | Return(_) => false,
+        }
+    }
+}
@@ -6,6 +6,7 @@ use crate::common::{NodeIx, Result};
 use crate::compile;
 use crate::runtime::{Int, IntMap, Str, StrMap};
 use crate::types::{self, SmallVec};
+use lazy_static::lazy_static;
 use smallvec::smallvec;
 
 use std::convert::TryFrom;
@@ -17,12 +18,19 @@ pub enum Function {
     FloatFunc(FloatFunc),
     IntFunc(Bitwise),
     Close,
+    Fflush,
+    Exec,
+    ExecCmd,
     ReadErr,
     ReadErrCmd,
+    ReadErrCoproc,
+    ReadErrExec,
     Nextline,
     ReadErrStdin,
     NextlineStdin,
     NextlineCmd,
+    NextlineCoproc,
+    NextlineExec,
     ReadLineStdinFused,
     NextFile,
     Setcol,
@@ -31,15 +39,26 @@ pub enum Function {
     Contains,
     Delete,
     Clear,
+    Asort,
+    Asorti,
     Match,
+    // match(s, re, arr): the three-argument form of match, which also fills arr with the matched
+    // text and capture groups.
+    MatchCaptures,
     SubstrIndex,
     Sub,
     GSub,
+    GenSub,
     EscapeCSV,
     EscapeTSV,
+    EscapeJSON,
     JoinCols,
     JoinCSV,
     JoinTSV,
+    // For JSON output: all fields as an array (or as an object keyed by the header, given a
+    // nonzero argument), and one `key:value` member of an object.
+    JoinJSON,
+    JSONMember,
     Substr,
     ToInt,
     HexToInt,
@@ -47,9 +66,14 @@ pub enum Function {
     Srand,
     ReseedRng,
     System,
+    Systime,
+    Strftime,
+    Mktime,
+    Strptime,
     // For header-parsing logic
     UpdateUsedFields,
     SetFI,
+    SetJSONKey,
     ToUpper,
     ToLower,
     IncMap,
@@ -198,11 +222,17 @@ impl FloatFunc {
 static_map!(
     FUNCTIONS<&'static str, Function>,
     ["close", Function::Close],
+    ["fflush", Function::Fflush],
+    ["exec", Function::Exec],
+    ["exec_cmd", Function::ExecCmd],
     ["split", Function::Split],
+    ["asort", Function::Asort],
+    ["asorti", Function::Asorti],
     ["length", Function::Length],
     ["match", Function::Match],
     ["sub", Function::Sub],
     ["gsub", Function::GSub],
+    ["gensub", Function::GenSub],
     ["substr", Function::Substr],
     ["int", Function::ToInt],
     ["hex", Function::HexToInt],
@@ -227,12 +257,17 @@ static_map!(
     ["join_tsv", Function::JoinTSV],
     ["escape_csv", Function::EscapeCSV],
     ["escape_tsv", Function::EscapeTSV],
+    ["escape_json", Function::EscapeJSON],
     ["rand", Function::Rand],
     ["srand", Function::Srand],
     ["index", Function::SubstrIndex],
     ["toupper", Function::ToUpper],
     ["tolower", Function::ToLower],
     ["system", Function::System],
+    ["systime", Function::Systime],
+    ["strftime", Function::Strftime],
+    ["mktime", Function::Mktime],
+    ["strptime", Function::Strptime],
     ["exit", Function::Exit]
 );
 
@@ -267,7 +302,7 @@ impl Function {
                 let arg1 = ctx.constant(
                     Map {
                         key: BaseTy::Int,
-                        val: BaseTy::Str,
+                        val: BaseTy::StrNum,
                     }
                     .abs(),
                 );
@@ -298,6 +333,43 @@ impl Function {
                 ctx.nw.add_dep(v, arr, Constraint::ValIn(()));
                 ctx.nw.add_dep(arr, v, Constraint::Val(()));
             }
+            Function::Exec | Function::ExecCmd | Function::NextlineExec | Function::ReadErrExec => {
+                let arg0 = ctx.constant(
+                    Map {
+                        key: BaseTy::Int,
+                        val: BaseTy::Str,
+                    }
+                    .abs(),
+                );
+                ctx.nw.add_dep(arg0, args[0], Constraint::Flows(()));
+            }
+            Function::Asort | Function::Asorti => {
+                // The destination is an int-keyed array holding either the values or the keys of
+                // the source. We unify the two through an intermediate node.
+                let (src, dst) = (args[0], args[1]);
+                let int_key = ctx.constant(Scalar(BaseTy::Int).abs());
+                ctx.nw.add_dep(int_key, dst, Constraint::KeyIn(()));
+                let elt = ctx.var();
+                if *self == Function::Asort {
+                    ctx.nw.add_dep(src, elt, Constraint::Val(()));
+                    ctx.nw.add_dep(elt, src, Constraint::ValIn(()));
+                } else {
+                    ctx.nw.add_dep(src, elt, Constraint::Key(()));
+                    ctx.nw.add_dep(elt, src, Constraint::KeyIn(()));
+                }
+                ctx.nw.add_dep(dst, elt, Constraint::Val(()));
+                ctx.nw.add_dep(elt, dst, Constraint::ValIn(()));
+            }
+            Function::MatchCaptures => {
+                let arg2 = ctx.constant(
+                    Map {
+                        key: BaseTy::Int,
+                        val: BaseTy::StrNum,
+                    }
+                    .abs(),
+                );
+                ctx.nw.add_dep(arg2, args[2], Constraint::Flows(()));
+            }
             Function::Sub | Function::GSub => {
                 let out_str = args[2];
                 let str_const = ctx.constant(Scalar(BaseTy::Str).abs());
@@ -400,7 +472,10 @@ impl Function {
                 }
             }
             Srand => (smallvec![Int], Int),
-            System | HexToInt => (smallvec![Str], Int),
+            System | HexToInt | Mktime | Fflush => (smallvec![Str], Int),
+            Systime => (smallvec![], Int),
+            Strftime => (smallvec![Str, Int, Int], Str),
+            Strptime => (smallvec![Str, Str], Int),
             ReseedRng => (smallvec![], Int),
             Rand => (smallvec![], Float),
             ToInt => {
@@ -415,19 +490,49 @@ impl Function {
                     }
                 }
             }
-            NextlineCmd | Nextline => (smallvec![Str], Str),
-            ReadErrCmd | ReadErr => (smallvec![Str], Int),
+            NextlineCmd | NextlineCoproc | Nextline => (smallvec![Str], Str),
+            ReadErrCmd | ReadErrCoproc | ReadErr => (smallvec![Str], Int),
             UpdateUsedFields | NextFile | ReadLineStdinFused => (smallvec![], Int),
             NextlineStdin => (smallvec![], Str),
             ReadErrStdin => (smallvec![], Int),
             // irrelevant return type
             Setcol => (smallvec![Int, Str], Int),
             Length => (smallvec![incoming[0]], Int),
-            Close => (smallvec![Str], Str),
+            Close => (smallvec![Str, Str], Str),
             Sub | GSub => (smallvec![Str, Str, Str], Int),
+            GenSub => (smallvec![Str; 4], Str),
             ToUpper | ToLower | EscapeCSV | EscapeTSV => (smallvec![Str], Str),
+            // Numbers are emitted as JSON numbers, so we keep their types intact.
+            EscapeJSON => match incoming[0] {
+                Int | Float => (smallvec![incoming[0]], Str),
+                _ => (smallvec![Str], Str),
+            },
             Substr => (smallvec![Str, Int, Int], Str),
             Match => (smallvec![Str, Str], Int),
+            Exec | ExecCmd | NextlineExec | ReadErrExec => match incoming[0] {
+                MapIntStr => (
+                    smallvec![MapIntStr],
+                    if matches!(self, Exec | ReadErrExec) {
+                        Int
+                    } else {
+                        Str
+                    },
+                ),
+                _ => return err!("argv must be an array of strings, got: {:?}", incoming),
+            },
+            // As with split, the array can have keys of either type.
+            MatchCaptures => match incoming[2] {
+                MapIntStr | MapStrStr => (smallvec![Str, Str, incoming[2]], Int),
+                _ => return err!("invalid input spec for match: {:?}", incoming),
+            },
+            Asort | Asorti => {
+                let (src, dst) = (incoming[0], incoming[1]);
+                let elt = if *self == Asort { src.val() } else { src.key() };
+                match (elt, dst.val()) {
+                    (Ok(x), Ok(y)) if x == y => (smallvec![src, dst, Str], Int),
+                    _ => return err!("invalid input spec for {}: {:?}", self, incoming),
+                }
+            }
             Exit => (smallvec![Int], Null),
             // Split's second input can be a map of either type
             Split => {
@@ -440,6 +545,9 @@ impl Function {
             JoinCols => (smallvec![Int, Int, Str], Str),
             JoinCSV | JoinTSV => (smallvec![Int, Int], Str),
             SetFI => (smallvec![Int, Int], Int),
+            SetJSONKey => (smallvec![Int], Int),
+            JoinJSON => (smallvec![Int], Str),
+            JSONMember => (smallvec![Int, Int, Str], Str),
         })
     }
 
@@ -449,13 +557,17 @@ impl Function {
             FloatFunc(ff) => ff.arity(),
             IntFunc(bw) => bw.arity(),
             UpdateUsedFields | Rand | ReseedRng | ReadErrStdin | NextlineStdin | NextFile
-            | ReadLineStdinFused => 0,
+            | ReadLineStdinFused | Systime => 0,
             Exit | ToUpper | ToLower | Clear | Srand | System | HexToInt | ToInt | EscapeCSV
-            | EscapeTSV | Close | Length | ReadErr | ReadErrCmd | Nextline | NextlineCmd
-            | Unop(_) => 1,
-            SetFI | SubstrIndex | Match | Setcol | Binop(_) => 2,
+            | EscapeTSV | EscapeJSON | Length | ReadErr | ReadErrCmd | ReadErrCoproc | Nextline
+            | NextlineCmd | NextlineCoproc | Mktime | Fflush | Exec | ExecCmd | ReadErrExec
+            | NextlineExec | SetJSONKey | JoinJSON | Unop(_) => 1,
+            SetFI | SubstrIndex | Match | Setcol | Strptime | Close | Binop(_) => 2,
             JoinCSV | JoinTSV | Delete | Contains => 2,
-            IncMap | JoinCols | Substr | Sub | GSub | Split => 3,
+            IncMap | JoinCols | JSONMember | Substr | Sub | GSub | Split | MatchCaptures
+            | Strftime => 3,
+            Asort | Asorti => 3,
+            GenSub => 4,
         })
     }
 
@@ -468,9 +580,8 @@ impl Function {
         fn step_arith(x: &types::State, y: &types::State) -> types::State {
             use BaseTy::*;
             match (x, y) {
-                (Some(Scalar(Some(Str | Float))), _) | (_, Some(Scalar(Some(Str | Float)))) => {
-                    Scalar(Float).abs()
-                }
+                (Some(Scalar(Some(Str | StrNum | Float))), _)
+                | (_, Some(Scalar(Some(Str | StrNum | Float)))) => Scalar(Float).abs(),
                 (_, _) => Scalar(Int).abs(),
             }
         }
@@ -478,7 +589,7 @@ impl Function {
             IntFunc(bw) => Ok(bw.ret_state()),
             FloatFunc(ff) => Ok(ff.ret_state()),
             Unop(Neg) | Unop(Pos) => match &args[0] {
-                Some(Scalar(Some(BaseTy::Str))) | Some(Scalar(Some(BaseTy::Float))) => {
+                Some(Scalar(Some(BaseTy::Str | BaseTy::StrNum | BaseTy::Float))) => {
                     Ok(Scalar(BaseTy::Float).abs())
                 }
                 x => Ok(*x),
@@ -490,14 +601,18 @@ impl Function {
             Setcol => Ok(Scalar(BaseTy::Null).abs()),
             Clear | SubstrIndex | Srand | ReseedRng | Unop(Not) | Binop(IsMatch) | Binop(LT)
             | Binop(GT) | Binop(LTE) | Binop(GTE) | Binop(EQ) | Length | Split | ReadErr
-            | ReadErrCmd | ReadErrStdin | Contains | Delete | Match | Sub | GSub | ToInt
-            | System | HexToInt => Ok(Scalar(BaseTy::Int).abs()),
-            ToUpper | ToLower | JoinCSV | JoinTSV | JoinCols | EscapeCSV | EscapeTSV | Substr
-            | Unop(Column) | Binop(Concat) | Nextline | NextlineCmd | NextlineStdin => {
-                Ok(Scalar(BaseTy::Str).abs())
-            }
+            | ReadErrCmd | ReadErrCoproc | ReadErrExec | ReadErrStdin | Contains | Delete
+            | Match | MatchCaptures | Sub | GSub | ToInt | System | HexToInt | Systime | Mktime
+            | Strptime | Fflush | Exec | Asort | Asorti => Ok(Scalar(BaseTy::Int).abs()),
+            ToUpper | ToLower | JoinCSV | JoinTSV | JoinCols | EscapeCSV | EscapeTSV
+            | EscapeJSON | JoinJSON | JSONMember | Substr | GenSub | Strftime | ExecCmd
+            | Binop(Concat) => Ok(Scalar(BaseTy::Str).abs()),
+            // Input may hold numbers.
+            Unop(Column) | Nextline | NextlineCmd | NextlineCoproc | NextlineExec
+            | NextlineStdin => Ok(Scalar(BaseTy::StrNum).abs()),
             IncMap => Ok(step_arith(&types::val_of(&args[0])?, &args[2])),
-            Exit | SetFI | UpdateUsedFields | NextFile | ReadLineStdinFused | Close => Ok(None),
+            Exit | SetFI | SetJSONKey | UpdateUsedFields | NextFile | ReadLineStdinFused
+            | Close => Ok(None),
         }
     }
 }
@@ -521,20 +636,34 @@ pub(crate) enum Variable {
     FNR = 11,
     PID = 12,
     FI = 13,
+    CONVFMT = 14,
+    OFMT = 15,
+    ENVIRON = 16,
+    RT = 17,
+    FIELDWIDTHS = 18,
+    FPAT = 19,
+    PROCINFO = 20,
 }
 
 impl From<Variable> for compile::Ty {
     fn from(v: Variable) -> compile::Ty {
         use Variable::*;
         match v {
-            FS | OFS | ORS | RS | FILENAME => compile::Ty::Str,
+            FS | OFS | ORS | RS | FILENAME | CONVFMT | OFMT | RT | FIELDWIDTHS | FPAT => {
+                compile::Ty::Str
+            }
             PID | ARGC | NF | NR | FNR | RSTART | RLENGTH => compile::Ty::Int,
             ARGV => compile::Ty::MapIntStr,
             FI => compile::Ty::MapStrInt,
+            ENVIRON | PROCINFO => compile::Ty::MapStrStr,
         }
     }
 }
 
+/// The default value of `CONVFMT` and `OFMT`. Conversions using this format take a faster path
+/// that does not go through `printf`.
+pub(crate) const DEFAULT_FLOAT_FMT: &str = "%.6g";
+
 pub(crate) struct Variables<'a> {
     pub argc: Int,
     pub argv: IntMap<Str<'a>>,
@@ -550,6 +679,26 @@ pub(crate) struct Variables<'a> {
     pub rlength: Int,
     pub pid: Int,
     pub fi: StrMap<'a, Int>,
+    pub convfmt: Str<'a>,
+    pub ofmt: Str<'a>,
+    pub environ: StrMap<'a, Str<'a>>,
+    pub rt: Str<'a>,
+    pub fieldwidths: Str<'a>,
+    pub fpat: Str<'a>,
+    pub procinfo: StrMap<'a, Str<'a>>,
+}
+
+lazy_static! {
+    // The process environment, read once and borrowed by each copy of ENVIRON (e.g. one per
+    // worker thread).
+    static ref ENVIRONMENT: Vec<(String, String)> = std::env::vars_os()
+        .map(|(k, v)| {
+            (
+                k.to_string_lossy().into_owned(),
+                v.to_string_lossy().into_owned(),
+            )
+        })
+        .collect();
 }
 
 impl<'a> Default for Variables<'a> {
@@ -569,6 +718,16 @@ impl<'a> Default for Variables<'a> {
             pid: 0,
             rlength: -1,
             fi: Default::default(),
+            convfmt: DEFAULT_FLOAT_FMT.into(),
+            ofmt: DEFAULT_FLOAT_FMT.into(),
+            environ: ENVIRONMENT
+                .iter()
+                .map(|(k, v)| (k.as_str().into(), v.as_str().into()))
+                .collect(),
+            rt: Default::default(),
+            fieldwidths: Default::default(),
+            fpat: Default::default(),
+            procinfo: Default::default(),
         }
     }
 }
@@ -583,7 +742,8 @@ impl<'a> Variables<'a> {
             RSTART => self.rstart,
             RLENGTH => self.rlength,
             PID => self.pid,
-            FI | ORS | OFS | FS | RS | FILENAME | ARGV => return err!("var {} not an int", var),
+            FI | ORS | OFS | FS | RS | FILENAME | ARGV | CONVFMT | OFMT | ENVIRON | RT
+            | FIELDWIDTHS | FPAT | PROCINFO => return err!("var {} not an int", var),
         })
     }
 
@@ -597,7 +757,8 @@ impl<'a> Variables<'a> {
             RSTART => self.rstart = i,
             RLENGTH => self.rlength = i,
             PID => self.pid = i,
-            FI | ORS | OFS | FS | RS | FILENAME | ARGV => return err!("var {} not an int", var),
+            FI | ORS | OFS | FS | RS | FILENAME | ARGV | CONVFMT | OFMT | ENVIRON | RT
+            | FIELDWIDTHS | FPAT | PROCINFO => return err!("var {} not an int", var),
         }
         Ok(())
     }
@@ -610,7 +771,12 @@ impl<'a> Variables<'a> {
             ORS => self.ors.clone(),
             RS => self.rs.clone(),
             FILENAME => self.filename.clone(),
-            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH => {
+            RT => self.rt.clone(),
+            FIELDWIDTHS => self.fieldwidths.clone(),
+            FPAT => self.fpat.clone(),
+            CONVFMT => self.convfmt.clone(),
+            OFMT => self.ofmt.clone(),
+            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH | ENVIRON | PROCINFO => {
                 return err!("var {} not a string", var)
             }
         })
@@ -624,7 +790,12 @@ impl<'a> Variables<'a> {
             ORS => self.ors = s,
             RS => self.rs = s,
             FILENAME => self.filename = s,
-            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH => {
+            RT => self.rt = s,
+            FIELDWIDTHS => self.fieldwidths = s,
+            FPAT => self.fpat = s,
+            CONVFMT => self.convfmt = s,
+            OFMT => self.ofmt = s,
+            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH | ENVIRON | PROCINFO => {
                 return err!("var {} not a string", var)
             }
         };
@@ -635,7 +806,8 @@ impl<'a> Variables<'a> {
         use Variable::*;
         match var {
             ARGV => Ok(self.argv.clone()),
-            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | RLENGTH => {
+            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | RLENGTH
+            | CONVFMT | OFMT | ENVIRON | RT | FIELDWIDTHS | FPAT | PROCINFO => {
                 err!("var {} is not an int-keyed map", var)
             }
         }
@@ -648,7 +820,8 @@ impl<'a> Variables<'a> {
                 self.argv = m;
                 Ok(())
             }
-            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | RLENGTH => {
+            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | RLENGTH
+            | CONVFMT | OFMT | ENVIRON | RT | FIELDWIDTHS | FPAT | PROCINFO => {
                 err!("var {} is not an int-keyed map", var)
             }
         }
@@ -658,7 +831,7 @@ impl<'a> Variables<'a> {
         match var {
             FI => Ok(self.fi.clone()),
             ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART
-            | RLENGTH => {
+            | RLENGTH | CONVFMT | OFMT | ENVIRON | RT | FIELDWIDTHS | FPAT | PROCINFO => {
                 err!("var {} is not a string-keyed map", var)
             }
         }
@@ -672,11 +845,41 @@ impl<'a> Variables<'a> {
                 Ok(())
             }
             ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART
-            | RLENGTH => {
+            | RLENGTH | CONVFMT | OFMT | ENVIRON | RT | FIELDWIDTHS | FPAT | PROCINFO => {
                 err!("var {} is not a string-keyed map", var)
             }
         }
     }
+
+    pub fn load_strstrmap(&self, var: Variable) -> Result<StrMap<'a, Str<'a>>> {
+        use Variable::*;
+        match var {
+            ENVIRON => Ok(self.environ.clone()),
+            PROCINFO => Ok(self.procinfo.clone()),
+            FI | ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART
+            | RLENGTH | CONVFMT | OFMT | RT | FIELDWIDTHS | FPAT => {
+                err!("var {} is not a string-keyed map of strings", var)
+            }
+        }
+    }
+
+    pub fn store_strstrmap(&mut self, var: Variable, m: StrMap<'a, Str<'a>>) -> Result<()> {
+        use Variable::*;
+        match var {
+            ENVIRON => {
+                self.environ = m;
+                Ok(())
+            }
+            PROCINFO => {
+                self.procinfo = m;
+                Ok(())
+            }
+            FI | ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART
+            | RLENGTH | CONVFMT | OFMT | RT | FIELDWIDTHS | FPAT => {
+                err!("var {} is not a string-keyed map of strings", var)
+            }
+        }
+    }
 }
 
 impl Variable {
@@ -716,7 +919,13 @@ impl Variable {
                 key: types::BaseTy::Str,
                 val: types::BaseTy::Int,
             },
-            ORS | OFS | FS | RS | FILENAME => types::TVar::Scalar(types::BaseTy::Str),
+            ENVIRON | PROCINFO => types::TVar::Map {
+                key: types::BaseTy::Str,
+                val: types::BaseTy::Str,
+            },
+            ORS | OFS | FS | RS | FILENAME | CONVFMT | OFMT | RT | FIELDWIDTHS | FPAT => {
+                types::TVar::Scalar(types::BaseTy::Str)
+            }
         }
     }
 }
@@ -750,6 +959,13 @@ impl<'a> TryFrom<usize> for Variable {
             11 => Ok(FNR),
             12 => Ok(PID),
             13 => Ok(FI),
+            14 => Ok(CONVFMT),
+            15 => Ok(OFMT),
+            16 => Ok(ENVIRON),
+            17 => Ok(RT),
+            18 => Ok(FIELDWIDTHS),
+            19 => Ok(FPAT),
+            20 => Ok(PROCINFO),
             _ => Err(()),
         }
     }
@@ -770,5 +986,12 @@ static_map!(
     ["RSTART", Variable::RSTART],
     ["RLENGTH", Variable::RLENGTH],
     ["PID", Variable::PID],
-    ["FI", Variable::FI]
+    ["FI", Variable::FI],
+    ["CONVFMT", Variable::CONVFMT],
+    ["OFMT", Variable::OFMT],
+    ["ENVIRON", Variable::ENVIRON],
+    ["RT", Variable::RT],
+    ["FIELDWIDTHS", Variable::FIELDWIDTHS],
+    ["FPAT", Variable::FPAT],
+    ["PROCINFO", Variable::PROCINFO]
 );
@@ -3,7 +3,7 @@ use std::marker::PhantomData;
 use std::sync::Arc;
 
 use crate::builtins::{Bitwise, FloatFunc, Variable};
-use crate::common::{FileSpec, NumTy};
+use crate::common::{FileSpec, InputSpec, NumTy};
 use crate::compile::{self, Ty};
 use crate::interp::{index, index_mut, Storage};
 use crate::runtime::{self, Float, Int, Str, UniqueStr};
@@ -72,6 +72,9 @@ pub(crate) enum Instr<'a> {
     // Conversions
     IntToStr(Reg<Str<'a>>, Reg<Int>),
     FloatToStr(Reg<Str<'a>>, Reg<Float>),
+    // Convert a float to a string using the format stored in the given variable (CONVFMT or
+    // OFMT).
+    FormatFloat(Reg<Str<'a>>, Reg<Float>, Variable),
     StrToInt(Reg<Int>, Reg<Str<'a>>),
     HexStrToInt(Reg<Int>, Reg<Str<'a>>),
     FloatToInt(Reg<Int>, Reg<Float>),
@@ -111,6 +114,17 @@ pub(crate) enum Instr<'a> {
     ),
     ReseedRng(/* previous seed */ Reg<Int>),
 
+    // Time
+    Systime(Reg<Int>),
+    Strftime(
+        Reg<Str<'a>>,
+        /*fmt*/ Reg<Str<'a>>,
+        /*timestamp*/ Reg<Int>,
+        /*utc*/ Reg<Int>,
+    ),
+    Mktime(Reg<Int>, Reg<Str<'a>>),
+    Strptime(Reg<Int>, Reg<Str<'a>>, /*fmt*/ Reg<Str<'a>>),
+
     // String processing
     Concat(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
     StartsWithConst(Reg<Int>, Reg<Str<'a>>, Arc<[u8]>),
@@ -118,6 +132,19 @@ pub(crate) enum Instr<'a> {
     IsMatchConst(Reg<Int>, Reg<Str<'a>>, Arc<Regex>),
     Match(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
     MatchConst(Reg<Int>, Reg<Str<'a>>, Arc<Regex>),
+    // Like Match, but also stores the matched text and capture groups in the array.
+    MatchCapturesInt(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        /*pat*/ Reg<Str<'a>>,
+        Reg<runtime::IntMap<Str<'a>>>,
+    ),
+    MatchCapturesStr(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        /*pat*/ Reg<Str<'a>>,
+        Reg<runtime::StrMap<'a, Str<'a>>>,
+    ),
     // index(s, t) returns index of substring t in s, 0 if it does not appear.
     SubstrIndex(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
     LenStr(Reg<Int>, Reg<Str<'a>>),
@@ -133,8 +160,17 @@ pub(crate) enum Instr<'a> {
         /*for*/ Reg<Str<'a>>,
         /*in*/ Reg<Str<'a>>,
     ),
+    GenSub(
+        Reg<Str<'a>>,
+        /*pat*/ Reg<Str<'a>>,
+        /*for*/ Reg<Str<'a>>,
+        /*how*/ Reg<Str<'a>>,
+        /*in*/ Reg<Str<'a>>,
+    ),
     EscapeCSV(Reg<Str<'a>>, Reg<Str<'a>>),
     EscapeTSV(Reg<Str<'a>>, Reg<Str<'a>>),
+    EscapeJSON(Reg<Str<'a>>, Reg<Str<'a>>),
+    FloatToJSON(Reg<Str<'a>>, Reg<Float>),
     Substr(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Int>, Reg<Int>),
 
     // Comparison
@@ -153,6 +189,19 @@ pub(crate) enum Instr<'a> {
     EQFloat(Reg<Int>, Reg<Float>, Reg<Float>),
     EQInt(Reg<Int>, Reg<Int>, Reg<Int>),
     EQStr(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    // Comparisons between strings that may hold numbers; see Str::strnum_cmp.
+    LTStrNum(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    GTStrNum(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    LTEStrNum(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    GTEStrNum(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    EQStrNum(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    // Comparisons between a string that may hold a number and a number; see
+    // runtime::strnum_cmp_float.
+    LTStrNumFloat(Reg<Int>, Reg<Str<'a>>, Reg<Float>),
+    GTStrNumFloat(Reg<Int>, Reg<Str<'a>>, Reg<Float>),
+    LTEStrNumFloat(Reg<Int>, Reg<Str<'a>>, Reg<Float>),
+    GTEStrNumFloat(Reg<Int>, Reg<Str<'a>>, Reg<Float>),
+    EQStrNumFloat(Reg<Int>, Reg<Str<'a>>, Reg<Float>),
 
     // Columns
     SetColumn(Reg<Int> /* dst column */, Reg<Str<'a>>),
@@ -173,12 +222,22 @@ pub(crate) enum Instr<'a> {
         Reg<Int>,     /* end col */
         Reg<Str<'a>>, /* sep */
     ),
+    JoinJSON(
+        Reg<Str<'a>>, /* dst */
+        Reg<Int>,     /* keyed by the header */
+    ),
+    JSONMember(
+        Reg<Str<'a>>, /* dst */
+        Reg<Int>,     /* col (or -1) */
+        Reg<Int>,     /* position */
+        Reg<Str<'a>>, /* escaped value */
+    ),
     ToUpperAscii(Reg<Str<'a>>, Reg<Str<'a>>),
     ToLowerAscii(Reg<Str<'a>>, Reg<Str<'a>>),
 
     // File reading.
-    ReadErr(Reg<Int>, Reg<Str<'a>>, /*is_file=*/ bool),
-    NextLine(Reg<Str<'a>>, Reg<Str<'a>>, /*is_file=*/ bool),
+    ReadErr(Reg<Int>, Reg<Str<'a>>, InputSpec),
+    NextLine(Reg<Str<'a>>, Reg<Str<'a>>, InputSpec),
     ReadErrStdin(Reg<Int>),
     NextLineStdin(Reg<Str<'a>>),
     // Fetches line directly into $0.
@@ -189,6 +248,8 @@ pub(crate) enum Instr<'a> {
     // Set the corresponding index in the FI variable. This is equivalent of loading FI, but we
     // keep this as a separate instruction to make static analysis easier.
     SetFI(Reg<Int>, Reg<Int>),
+    // Set the name of the given column of the header, for use as a JSON key.
+    SetJSONKey(Reg<Int>),
 
     // Split
     SplitInt(
@@ -217,8 +278,14 @@ pub(crate) enum Instr<'a> {
         output: Option<(Reg<Str<'a>>, FileSpec)>,
         args: Vec<Reg<Str<'a>>>,
     },
-    Close(Reg<Str<'a>>),
+    // Close a file, command or coprocess; the second argument is "", "to" or "from".
+    Close(Reg<Str<'a>>, Reg<Str<'a>>),
+    Fflush(Reg<Int>, Reg<Str<'a>>),
     RunCmd(Reg<Int>, Reg<Str<'a>>),
+    // Run a command built by ExecCmd, without a shell.
+    RunExec(Reg<Int>, Reg<Str<'a>>),
+    // Build a command from an argv array that is run without a shell.
+    ExecCmd(Reg<Str<'a>>, Reg<runtime::IntMap<Str<'a>>>),
     Exit(Reg<Int>),
 
     // Map operations
@@ -243,6 +310,24 @@ pub(crate) enum Instr<'a> {
         map_ty: Ty,
         map: NumTy,
     },
+    // Store the values (Asort) or keys (Asorti) of `src`, sorted according to `how`, at indices
+    // 1 through n of `dst`; n is written to `dst_len`.
+    Asort {
+        src_ty: Ty,
+        src: NumTy,
+        dst_ty: Ty,
+        dst: NumTy,
+        how: Reg<Str<'a>>,
+        dst_len: Reg<Int>,
+    },
+    Asorti {
+        src_ty: Ty,
+        src: NumTy,
+        dst_ty: Ty,
+        dst: NumTy,
+        how: Reg<Str<'a>>,
+        dst_len: Reg<Int>,
+    },
     Len {
         map_ty: Ty,
         dst: NumTy,
@@ -292,6 +377,8 @@ pub(crate) enum Instr<'a> {
     StoreVarIntMap(Variable, Reg<runtime::IntMap<Str<'a>>>),
     LoadVarStrMap(Reg<runtime::StrMap<'a, Int>>, Variable),
     StoreVarStrMap(Variable, Reg<runtime::StrMap<'a, Int>>),
+    LoadVarStrStrMap(Reg<runtime::StrMap<'a, Str<'a>>>, Variable),
+    StoreVarStrStrMap(Variable, Reg<runtime::StrMap<'a, Str<'a>>>),
 
     LoadSlot {
         ty: Ty,
@@ -415,7 +502,7 @@ impl<'a> Instr<'a> {
                 sr.accum(&mut f);
                 ir.accum(&mut f)
             }
-            FloatToStr(sr, fr) => {
+            FloatToStr(sr, fr) | FormatFloat(sr, fr, _) => {
                 sr.accum(&mut f);
                 fr.accum(&mut f);
             }
@@ -520,6 +607,22 @@ impl<'a> Instr<'a> {
                 seed.accum(&mut f)
             }
             ReseedRng(res) => res.accum(&mut f),
+            Systime(res) => res.accum(&mut f),
+            Strftime(res, fmt, ts, utc) => {
+                res.accum(&mut f);
+                fmt.accum(&mut f);
+                ts.accum(&mut f);
+                utc.accum(&mut f);
+            }
+            Mktime(res, s) => {
+                res.accum(&mut f);
+                s.accum(&mut f);
+            }
+            Strptime(res, s, fmt) => {
+                res.accum(&mut f);
+                s.accum(&mut f);
+                fmt.accum(&mut f);
+            }
             StartsWithConst(res, s, _) => {
                 res.accum(&mut f);
                 s.accum(&mut f);
@@ -543,6 +646,18 @@ impl<'a> Instr<'a> {
                 res.accum(&mut f);
                 src.accum(&mut f);
             }
+            MatchCapturesInt(res, s, pat, arr) => {
+                res.accum(&mut f);
+                s.accum(&mut f);
+                pat.accum(&mut f);
+                arr.accum(&mut f);
+            }
+            MatchCapturesStr(res, s, pat, arr) => {
+                res.accum(&mut f);
+                s.accum(&mut f);
+                pat.accum(&mut f);
+                arr.accum(&mut f);
+            }
             SubstrIndex(res, s, t) => {
                 res.accum(&mut f);
                 s.accum(&mut f);
@@ -558,10 +673,21 @@ impl<'a> Instr<'a> {
                 s.accum(&mut f);
                 in_s.accum(&mut f);
             }
-            EscapeCSV(res, s) | EscapeTSV(res, s) => {
+            GenSub(res, pat, s, how, in_s) => {
+                res.accum(&mut f);
+                pat.accum(&mut f);
+                s.accum(&mut f);
+                how.accum(&mut f);
+                in_s.accum(&mut f);
+            }
+            EscapeCSV(res, s) | EscapeTSV(res, s) | EscapeJSON(res, s) => {
                 res.accum(&mut f);
                 s.accum(&mut f);
             }
+            FloatToJSON(res, x) => {
+                res.accum(&mut f);
+                x.accum(&mut f);
+            }
             Substr(res, base, l, r) => {
                 res.accum(&mut f);
                 base.accum(&mut f);
@@ -643,6 +769,24 @@ impl<'a> Instr<'a> {
                 l.accum(&mut f);
                 r.accum(&mut f);
             }
+            LTStrNum(res, l, r)
+            | GTStrNum(res, l, r)
+            | LTEStrNum(res, l, r)
+            | GTEStrNum(res, l, r)
+            | EQStrNum(res, l, r) => {
+                res.accum(&mut f);
+                l.accum(&mut f);
+                r.accum(&mut f);
+            }
+            LTStrNumFloat(res, l, r)
+            | GTStrNumFloat(res, l, r)
+            | LTEStrNumFloat(res, l, r)
+            | GTEStrNumFloat(res, l, r)
+            | EQStrNumFloat(res, l, r) => {
+                res.accum(&mut f);
+                l.accum(&mut f);
+                r.accum(&mut f);
+            }
             SetColumn(dst, src) => {
                 dst.accum(&mut f);
                 src.accum(&mut f)
@@ -662,6 +806,16 @@ impl<'a> Instr<'a> {
                 end.accum(&mut f);
                 sep.accum(&mut f);
             }
+            JoinJSON(dst, keyed) => {
+                dst.accum(&mut f);
+                keyed.accum(&mut f);
+            }
+            JSONMember(dst, col, pos, val) => {
+                dst.accum(&mut f);
+                col.accum(&mut f);
+                pos.accum(&mut f);
+                val.accum(&mut f);
+            }
             ToUpperAscii(dst, src) | ToLowerAscii(dst, src) => {
                 dst.accum(&mut f);
                 src.accum(&mut f);
@@ -702,8 +856,19 @@ impl<'a> Instr<'a> {
                     reg.accum(&mut f)
                 }
             }
-            Close(file) => file.accum(&mut f),
-            RunCmd(dst, cmd) => {
+            Close(file, how) => {
+                file.accum(&mut f);
+                how.accum(&mut f);
+            }
+            Fflush(dst, file) => {
+                dst.accum(&mut f);
+                file.accum(&mut f);
+            }
+            ExecCmd(dst, argv) => {
+                dst.accum(&mut f);
+                argv.accum(&mut f);
+            }
+            RunCmd(dst, cmd) | RunExec(dst, cmd) => {
                 dst.accum(&mut f);
                 cmd.accum(&mut f);
             }
@@ -736,6 +901,27 @@ impl<'a> Instr<'a> {
                 f(*map, *map_ty);
             }
             Clear { map_ty, map } => f(*map, *map_ty),
+            Asort {
+                src_ty,
+                src,
+                dst_ty,
+                dst,
+                how,
+                dst_len,
+            }
+            | Asorti {
+                src_ty,
+                src,
+                dst_ty,
+                dst,
+                how,
+                dst_len,
+            } => {
+                f(*src, *src_ty);
+                f(*dst, *dst_ty);
+                how.accum(&mut f);
+                dst_len.accum(&mut f);
+            }
             Len { map_ty, map, dst } => {
                 f(*dst, Ty::Int);
                 f(*map, *map_ty);
@@ -786,6 +972,8 @@ impl<'a> Instr<'a> {
             StoreVarIntMap(_var, src) => src.accum(&mut f),
             LoadVarStrMap(dst, _var) => dst.accum(&mut f),
             StoreVarStrMap(_var, src) => src.accum(&mut f),
+            LoadVarStrStrMap(dst, _var) => dst.accum(&mut f),
+            StoreVarStrStrMap(_var, src) => src.accum(&mut f),
 
             LoadSlot { ty, dst, .. } => f(*dst, *ty),
             StoreSlot { ty, src, .. } => f(*src, *ty),
@@ -820,6 +1008,7 @@ impl<'a> Instr<'a> {
                 key.accum(&mut f);
                 val.accum(&mut f);
             }
+            SetJSONKey(col) => col.accum(&mut f),
             UpdateUsedFields() | NextFile() | NextLineStdinFused() | Call(_) | Jmp(_) | Ret => {}
         }
     }
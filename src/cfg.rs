@@ -1,7 +1,7 @@
 use crate::arena;
 use crate::ast::{self, Expr, Stmt, Unop};
 use crate::builtins::{self, IsSprintf};
-use crate::common::{Either, FileSpec, Graph, NodeIx, NumTy, Result, Stage};
+use crate::common::{Either, FileSpec, Graph, InputSpec, NodeIx, NumTy, Result, Stage};
 use crate::dom;
 
 use hashbrown::{HashMap, HashSet};
@@ -137,6 +137,8 @@ impl Ident {
 pub enum Escaper {
     CSV,
     TSV,
+    // Print each record as a JSON array, or as an object keyed by the header when parsing one.
+    JSON,
     Identity,
 }
 
@@ -207,6 +209,11 @@ impl<'a> PrimVal<'a> {
             *ident = update(*ident)
         }
     }
+    pub(crate) fn uses(&self, mut visit: impl FnMut(&Ident)) {
+        if let PrimVal::Var(ident) = self {
+            visit(ident)
+        }
+    }
 }
 
 impl<'a> PrimExpr<'a> {
@@ -236,6 +243,31 @@ impl<'a> PrimExpr<'a> {
             LoadBuiltin(_) => {}
         }
     }
+
+    /// Call `visit` on every identifier read by this expression. Like `replace`, this skips the
+    /// operands of Phi nodes.
+    pub(crate) fn uses(&self, mut visit: impl FnMut(&Ident)) {
+        use PrimExpr::*;
+        match self {
+            Val(v) | IterBegin(v) | HasNext(v) | Next(v) => v.uses(visit),
+            Phi(_) | LoadBuiltin(_) => {}
+            Sprintf(fmt, args) => {
+                fmt.uses(&mut visit);
+                for a in args.iter() {
+                    a.uses(&mut visit)
+                }
+            }
+            CallBuiltin(_, args) | CallUDF(_, args) => {
+                for a in args.iter() {
+                    a.uses(&mut visit)
+                }
+            }
+            Index(v1, v2) => {
+                v1.uses(&mut visit);
+                v2.uses(visit);
+            }
+        }
+    }
 }
 
 impl<'a> PrimStmt<'a> {
@@ -272,6 +304,38 @@ impl<'a> PrimStmt<'a> {
             IterDrop(v) | Return(v) => v.replace(update),
         }
     }
+
+    /// Call `visit` on every identifier read by this statement. The target of an `AsgnVar` is not
+    /// a use, but the map in an `AsgnIndex` is.
+    pub(crate) fn uses(&self, mut visit: impl FnMut(&Ident)) {
+        use PrimStmt::*;
+        match self {
+            AsgnIndex(ident, v, exp) => {
+                visit(ident);
+                v.uses(&mut visit);
+                exp.uses(visit);
+            }
+            AsgnVar(_, e) | SetBuiltin(_, e) => e.uses(visit),
+            PrintAll(specs, output) => {
+                for s in specs.iter() {
+                    s.uses(&mut visit);
+                }
+                if let Some((out, _)) = output {
+                    out.uses(visit);
+                }
+            }
+            Printf(fmt, specs, output) => {
+                fmt.uses(&mut visit);
+                for s in specs.iter() {
+                    s.uses(&mut visit);
+                }
+                if let Some((out, _)) = output {
+                    out.uses(visit);
+                }
+            }
+            IterDrop(v) | Return(v) => v.uses(visit),
+        }
+    }
 }
 
 fn valid_lhs<I>(e: &ast::Expr<I>) -> bool {
@@ -291,8 +355,13 @@ pub(crate) struct ProgramContext<'a, I> {
     // Lower certain regular expression instructions to direct invocations of a given pattern,
     // rather than dynamic lookups
     pub fold_regex_constants: bool,
+    // Interpret regular expressions as POSIX EREs, rather than using Rust regex syntax.
+    pub posix_regex: bool,
     // Thread through information regarding header columns used.
     pub parse_header: bool,
+    // Whether the main input should report the start and end of each file (see
+    // ast::Prog::file_events).
+    pub file_events: bool,
 }
 
 impl<'a, I> ProgramContext<'a, I> {
@@ -302,6 +371,46 @@ impl<'a, I> ProgramContext<'a, I> {
     pub fn main_offsets(&self) -> impl Iterator<Item = usize> + '_ {
         self.main_offset.iter().cloned()
     }
+
+    /// Whether the parallel main loop, or any function it calls, sends the output of `print` or
+    /// `printf` somewhere other than "/dev/stdout". Only standard output is written in input
+    /// order with `--ordered`.
+    pub fn main_loop_redirects_output(&self) -> bool {
+        let mut stack = match self.main_offset {
+            Stage::Par {
+                main_loop: Some(main_loop),
+                ..
+            } => vec![main_loop],
+            _ => return false,
+        };
+        let mut visited = HashSet::new();
+        while let Some(f) = stack.pop() {
+            if !visited.insert(f) {
+                continue;
+            }
+            for bb in self.funcs[f].cfg.raw_nodes() {
+                for stmt in bb.weight.q.iter() {
+                    let expr = match stmt {
+                        PrimStmt::Printf(_, _, Some((out, _)))
+                        | PrimStmt::PrintAll(_, Some((out, _))) => {
+                            if !matches!(out, PrimVal::StrLit(b"/dev/stdout")) {
+                                return true;
+                            }
+                            continue;
+                        }
+                        PrimStmt::AsgnIndex(_, _, e)
+                        | PrimStmt::AsgnVar(_, e)
+                        | PrimStmt::SetBuiltin(_, e) => e,
+                        _ => continue,
+                    };
+                    if let PrimExpr::CallUDF(callee, _) = expr {
+                        stack.push(*callee as usize);
+                    }
+                }
+            }
+        }
+        false
+    }
 }
 
 impl<'a> ProgramContext<'a, &'a str> {
@@ -328,6 +437,9 @@ pub enum SepAssign<'a> {
     Potential {
         field_sep: Option<&'a [u8]>,
         record_sep: Option<&'a [u8]>,
+        // FPAT, if it is assigned. The single-byte readers cannot split fields by pattern, so
+        // this forces the regex-based reader.
+        field_pat: Option<&'a [u8]>,
     },
     Unsure,
 }
@@ -368,14 +480,23 @@ where
         }
     }
     pub fn analyze_sep_assignments(&self) -> SepAssign<'a> {
+        // Only the regex-based reader keeps track of the text matching RS, or supports
+        // fixed-width fields.
+        if self.loads_builtin(builtins::Variable::RT)
+            || self.assigns_builtin(builtins::Variable::FIELDWIDTHS)
+        {
+            return SepAssign::Unsure;
+        }
         let mut field_sep = None;
         let mut record_sep = None;
+        let mut field_pat = None;
         let mut has_getline = false;
         for (i, f) in self.funcs.iter().enumerate() {
             if Some(i) == self.begin_offset() {
                 for (bi, sep) in [
                     (builtins::Variable::FS, &mut field_sep),
                     (builtins::Variable::RS, &mut record_sep),
+                    (builtins::Variable::FPAT, &mut field_pat),
                 ]
                 .iter_mut()
                 {
@@ -391,7 +512,7 @@ where
                         if bb != 0 {
                             return SepAssign::Unsure;
                         }
-                        // FS/RS assigned to a non-string-literal value.
+                        // FS/RS/FPAT assigned to a non-string-literal value.
                         if v.is_none() {
                             return SepAssign::Unsure;
                         }
@@ -409,24 +530,49 @@ where
                     has_getline = true;
                 }
             } else {
-                for bi in [builtins::Variable::FS, builtins::Variable::RS].iter() {
+                for bi in [
+                    builtins::Variable::FS,
+                    builtins::Variable::RS,
+                    builtins::Variable::FPAT,
+                ]
+                .iter()
+                {
                     if f.vars.get(&Some(*bi)).is_some() {
                         return SepAssign::Unsure;
                     }
                 }
             }
         }
-        // We called getline() _and_ assigned to FS/RS in the begin block; let's bail out just to
-        // be safe.
-        if has_getline && (field_sep.is_some() || record_sep.is_some()) {
+        // We called getline() _and_ assigned to FS/RS/FPAT in the begin block; let's bail out just
+        // to be safe.
+        if has_getline && (field_sep.is_some() || record_sep.is_some() || field_pat.is_some()) {
             return SepAssign::Unsure;
         }
         SepAssign::Potential {
             field_sep,
             record_sep,
+            field_pat,
         }
     }
 
+    /// Whether the program reads the builtin variable `var` anywhere.
+    pub fn loads_builtin(&self, var: builtins::Variable) -> bool {
+        self.funcs.iter().any(|f| {
+            f.cfg.raw_nodes().iter().any(|n| {
+                n.weight.q.iter().any(|stmt| {
+                    matches!(stmt, PrimStmt::AsgnVar(_, PrimExpr::LoadBuiltin(v)) if *v == var)
+                })
+            })
+        })
+    }
+
+    /// Whether the program assigns to the builtin variable `var` anywhere.
+    pub fn assigns_builtin(&self, var: builtins::Variable) -> bool {
+        self.funcs
+            .iter()
+            .any(|f| f.vars.get(&Some(var)).is_some_and(|v| !v.is_empty()))
+    }
+
     // for debugging: get a mapping from the raw identifiers to the synthetic ones.
     pub(crate) fn _invert_ident(&self) -> HashMap<Ident, I> {
         self.shared
@@ -449,7 +595,12 @@ where
             max: 1, // 0 reserved for assigning to "unused" var for side-effecting operations
             conds: Default::default(),
             esc,
+            header_names: p.header_names,
+            next_flag: None,
         };
+        if p.decs.iter().any(|fundec| fundec.body.has_next()) {
+            shared.next_flag = Some(shared.fresh());
+        }
         let mut func_table: HashMap<FunctionName<I>, NumTy> = Default::default();
         let mut funcs: Vec<Function<'a, I>> = Default::default();
         for fundec in p.decs.iter() {
@@ -496,6 +647,7 @@ where
         }
         // Now that we have all the functions in place, it's time to fill them up and convert them
         // to SSA.
+        let file_events = p.file_events();
         macro_rules! fill {
             ($stmt: expr, $name:expr) => {
                 if let Some(s) = $stmt {
@@ -506,6 +658,9 @@ where
                         f: &mut func,
                         func_table: &func_table,
                         parse_header: p.parse_header,
+                        jsonl_input: p.jsonl_input,
+                        file_events,
+                        arena,
                     }
                     .fill(s)?;
                     func_table.insert($name, offset as NumTy);
@@ -526,6 +681,9 @@ where
                 f: funcs.get_mut(f as usize).unwrap(),
                 func_table: &func_table,
                 parse_header: p.parse_header,
+                jsonl_input: p.jsonl_input,
+                file_events,
+                arena,
             }
             .fill(fundec.body)?;
         }
@@ -566,7 +724,9 @@ where
             main_offset,
             allow_arbitrary_commands: false,
             fold_regex_constants: false,
+            posix_regex: true,
             parse_header: p.parse_header,
+            file_events,
         })
     }
 }
@@ -576,6 +736,11 @@ struct View<'a, 'b, I> {
     f: &'a mut Function<'b, I>,
     func_table: &'a HashMap<FunctionName<I>, NumTy>,
     parse_header: bool,
+    jsonl_input: bool,
+    file_events: bool,
+    // For string constants introduced while filling in default arguments. These have to be
+    // allocated in the arena like other string literals, as runtime strings rely on its alignment.
+    arena: &'b arena::Arena,
 }
 
 #[derive(Debug)]
@@ -596,6 +761,11 @@ struct GlobalContext<I> {
     max: NumTy,
     conds: HashMap<usize, Ident>,
     esc: Escaper,
+    // Whether we are parsing a header line to key JSON output.
+    header_names: bool,
+    // A (hidden) global variable set when a user-defined function executes `next` or
+    // `nextfile`. It is None if no function does so.
+    next_flag: Option<Ident>,
 }
 
 impl<I> GlobalContext<I> {
@@ -699,7 +869,7 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
 where
     builtins::Variable: TryFrom<I>,
     builtins::Function: TryFrom<I>,
-    I: IsSprintf,
+    I: IsSprintf + From<&'b str> + 'b,
 {
     fn fill<'c>(&mut self, stmt: &'c Stmt<'c, 'b, I>) -> Result<()> {
         // Add a Cfg corresponding to `stmt`
@@ -812,10 +982,10 @@ where
                     arg_vs.push(arg_v);
                     current_open = next;
                 }
-                let out_v = if let Some((out, spec)) = out {
-                    let (next, out_v) = self.convert_val(out, current_open)?;
+                let out_v = if let Some(out) = out {
+                    let (next, out_v) = self.convert_output(out, current_open)?;
                     current_open = next;
-                    Some((out_v, *spec))
+                    Some(out_v)
                 } else {
                     None
                 };
@@ -831,14 +1001,18 @@ where
                     )?;
                     PrimVal::Var(ors)
                 };
-                let (next, out) = if let Some((o, spec)) = out {
-                    let (next, e) = self.convert_val(o, current_open)?;
-                    (next, Some((e, *spec)))
+                let (next, out) = if let Some(o) = out {
+                    let (next, o) = self.convert_output(o, current_open)?;
+                    (next, Some(o))
                 } else {
                     (current_open, None)
                 };
                 current_open = next;
 
+                if let Escaper::JSON = self.ctx.esc {
+                    return self.print_json(vs, ors, out, current_open);
+                }
+
                 // Why a macro? breaking this out into methods too easily runs afoul of aliasing
                 // rules, a previous version here had to split out several local variables into
                 // parameters of outer functions; it was a lot more code.
@@ -1092,6 +1266,11 @@ where
                     // To maximize compatibility with other scripts, we don't have FI in scope as a
                     // builtin if we are not parsing the header line.
                     if matches!(bi, builtins::Variable::FI) && !self.parse_header {
+                        // Without -H, FI would silently be empty for JSON Lines input, where it
+                        // is the only way to look up a column by its key.
+                        if self.jsonl_input {
+                            return err!("FI is only populated for JSON Lines input with -H");
+                        }
                         let ident = self.get_identifier(id);
                         PrimExpr::Val(PrimVal::Var(ident))
                     } else {
@@ -1214,11 +1393,7 @@ where
                     current_open,
                 );
             }
-            Getline {
-                from,
-                into,
-                is_file,
-            } => {
+            Getline { from, into, spec } => {
                 // break up getline calls to help the FS analysis out
                 let next = self.f.cfg.add_node(Default::default());
                 self.f.cfg.add_edge(current_open, next, Transition::null());
@@ -1239,14 +1414,45 @@ where
                 //  getline < file => getline $0 < file
                 //  getline var => getline var < stdin
                 //  getline => getline $0
+                //  exec_cmd(argv) | getline var => var = nextline_exec(argv)...
                 use builtins::Function::{
-                    Nextline, NextlineCmd, NextlineStdin, ReadErr, ReadErrCmd, ReadErrStdin,
+                    Nextline, NextlineCmd, NextlineCoproc, NextlineExec, NextlineStdin, ReadErr,
+                    ReadErrCmd, ReadErrCoproc, ReadErrExec, ReadErrStdin,
+                };
+                let exec_argv = from.and_then(Self::exec_cmd_argv);
+                let (next_line, read_err) = match (spec, exec_argv) {
+                    (InputSpec::Cmd, Some(_)) => (NextlineExec, ReadErrExec),
+                    (_, Some(_)) => return err!("exec_cmd(argv) can only be read with `|`"),
+                    (InputSpec::File, None) => (Nextline, ReadErr),
+                    (InputSpec::Cmd, None) => (NextlineCmd, ReadErrCmd),
+                    (InputSpec::Coproc, None) => (NextlineCoproc, ReadErrCoproc),
+                    (InputSpec::Exec, None) => return err!("unexpected input spec for getline"),
                 };
-                let next_line = if *is_file { Nextline } else { NextlineCmd };
-                let read_err = if *is_file { ReadErr } else { ReadErrCmd };
                 match (from, into) {
                     // an unadorned `getline` is uses the "fused" stdin construct, which in turn
                     // enables some optimizations.
+                    // With file events enabled, reads from the main input also report the start
+                    // and end of each file (see ast::Prog::file_transitions). Skip past them,
+                    // without running BEGINFILE or ENDFILE:
+                    //   while ((GETLINE = getline) > 1);
+                    //   GETLINE
+                    (None /* stdin */, None /* $0 */) if self.file_events => {
+                        // Pick an illegal frawk identifier.
+                        const GETLINE: &str = "--getline";
+                        let read = self.arena.alloc(ast::Expr::Var(GETLINE.into()));
+                        let skip = self.arena.alloc(Stmt::While(
+                            /*is_toplevel=*/ false,
+                            self.arena.alloc(ast::Expr::Binop(
+                                ast::Binop::GT,
+                                self.arena
+                                    .alloc(ast::Expr::Assign(read, &ast::Expr::ReadStdin)),
+                                &ast::Expr::ILit(1),
+                            )),
+                            self.arena.alloc(Stmt::Block(self.arena.new_vec())),
+                        ));
+                        let next = self.convert_stmt(skip, current_open)?;
+                        return self.convert_expr_inner(read, next, in_cond);
+                    }
                     (None /* stdin */, None /* $0 */) => {
                         return self.convert_expr_inner(
                             &ast::Expr::ReadStdin,
@@ -1259,12 +1465,13 @@ where
                             &ast::Expr::Getline {
                                 from: *from,
                                 into: Some(&Unop(ast::Unop::Column, &ast::Expr::ILit(0))),
-                                is_file: *is_file,
+                                spec: *spec,
                             },
                             current_open,
                         )
                     }
                     (Some(from), Some(into)) => {
+                        let from = exec_argv.unwrap_or(from);
                         let (next, _) = self.convert_expr(
                             &ast::Expr::Assign(
                                 into,
@@ -1498,29 +1705,89 @@ where
     }
 
     // Handles "next", "nextfile" statements.
+    //
+    // Inside a user-defined function, these set `next_flag` and return. Callers check the flag
+    // after each call; see `unwind_next`.
     fn do_next(&mut self, current_open: NodeIx, is_next_file: bool) -> Result<()> {
-        if let Some(header) = self.f.toplevel_header {
-            if is_next_file {
+        let dst = match (self.f.toplevel_header, self.ctx.next_flag, &self.f.name) {
+            (Some(header), _, _) => header,
+            (None, Some(flag), FunctionName::Named(_)) => {
                 self.add_stmt(
                     current_open,
-                    PrimStmt::AsgnVar(
-                        Ident::unused(),
-                        PrimExpr::CallBuiltin(builtins::Function::NextFile, smallvec![]),
-                    ),
+                    PrimStmt::AsgnVar(flag, PrimExpr::Val(PrimVal::ILit(1))),
                 )?;
+                self.f.exit
             }
-            self.f
-                .cfg
-                .add_edge(current_open, header, Transition::null());
-            self.seal(current_open);
-            Ok(())
-        } else {
-            err!(
-                "Cannot use `{}` from outside of the toplevel loop! \
-                 Note that frawk does not support `next` or `nextfile` from inside functions.",
-                if is_next_file { "nextfile" } else { "next" }
-            )
+            (None, _, _) => {
+                return err!(
+                    "Cannot use `{}` from outside of the toplevel loop!",
+                    if is_next_file { "nextfile" } else { "next" }
+                )
+            }
+        };
+        if is_next_file {
+            self.add_stmt(
+                current_open,
+                PrimStmt::AsgnVar(
+                    Ident::unused(),
+                    PrimExpr::CallBuiltin(builtins::Function::NextFile, smallvec![]),
+                ),
+            )?;
         }
+        self.f.cfg.add_edge(current_open, dst, Transition::null());
+        self.seal(current_open);
+        Ok(())
+    }
+
+    // Check `next_flag` after a call to a user-defined function. If it is set, the callee ran
+    // `next` or `nextfile`: functions return to their caller in turn, until we reach the
+    // toplevel loop, which clears the flag and moves on to the next record. Outside of the
+    // toplevel loop (e.g. in BEGIN or END) there is no record to move on to, so we report an
+    // error and exit, as other awks do.
+    fn unwind_next(&mut self, current_open: NodeIx) -> Result<NodeIx> {
+        let flag = match self.ctx.next_flag {
+            Some(flag) => flag,
+            None => return Ok(current_open),
+        };
+        let unwind = self.f.cfg.add_node(Default::default());
+        let next = self.f.cfg.add_node(Default::default());
+        self.f
+            .cfg
+            .add_edge(current_open, unwind, Transition::new(PrimVal::Var(flag)));
+        self.f.cfg.add_edge(current_open, next, Transition::null());
+        let header = self.f.toplevel_header;
+        let in_toplevel = self.f.loop_ctx.iter().any(|(h, _)| Some(*h) == header);
+        let (unwind, dst) = match (&self.f.name, header) {
+            (FunctionName::Named(_), _) => (unwind, self.f.exit),
+            (_, Some(header)) if in_toplevel => {
+                self.add_stmt(
+                    unwind,
+                    PrimStmt::AsgnVar(flag, PrimExpr::Val(PrimVal::ILit(0))),
+                )?;
+                (unwind, header)
+            }
+            _ => {
+                use ast::Expr::*;
+                // print MSG > "/dev/stderr"; exit(2);
+                const MSG: &[u8] =
+                    b"frawk: `next` and `nextfile` cannot be called outside of the main input loop";
+                let arena = self.arena;
+                let fail = Stmt::Block(arena.new_vec_from_slice(&[
+                    arena.alloc(Stmt::Print(
+                        arena.alloc_slice(&[arena.alloc(StrLit(arena.alloc_bytes(MSG)))]),
+                        Some((arena.alloc(StrLit(b"/dev/stderr")), FileSpec::Append)),
+                    )),
+                    arena.alloc(Stmt::Expr(arena.alloc(Call(
+                        Either::Right(builtins::Function::Exit),
+                        arena.alloc_slice(&[arena.alloc(ILit(2))]),
+                    )))),
+                ]));
+                (self.convert_stmt(&fail, unwind)?, next)
+            }
+        };
+        self.f.cfg.add_edge(unwind, dst, Transition::null());
+        self.seal(unwind);
+        Ok(next)
     }
 
     fn make_loop<'c>(
@@ -1584,6 +1851,46 @@ where
         })
     }
 
+    // The argument of `e`, if `e` is a call to exec_cmd.
+    fn exec_cmd_argv<'c>(e: &'c Expr<'c, 'b, I>) -> Option<&'c Expr<'c, 'b, I>> {
+        let is_exec_cmd = match e {
+            Expr::Call(Either::Left(fname), args) if args.len() == 1 => {
+                !fname.is_sprintf()
+                    && matches!(
+                        builtins::Function::try_from(fname.clone()),
+                        Ok(builtins::Function::ExecCmd)
+                    )
+            }
+            Expr::Call(Either::Right(bi), args) if args.len() == 1 => {
+                *bi == builtins::Function::ExecCmd
+            }
+            _ => false,
+        };
+        match e {
+            Expr::Call(_, args) if is_exec_cmd => Some(args[0]),
+            _ => None,
+        }
+    }
+
+    // Convert the destination of print or printf. Output to exec_cmd(argv) is run without a
+    // shell, so it is passed along as its own kind of output rather than as a command string.
+    fn convert_output<'c>(
+        &mut self,
+        (out, spec): &(&'c Expr<'c, 'b, I>, FileSpec),
+        current_open: NodeIx,
+    ) -> Result<(NodeIx, (PrimVal<'b>, FileSpec))> {
+        if let Some(argv) = Self::exec_cmd_argv(out) {
+            if !matches!(spec, FileSpec::Cmd) {
+                return err!("output can only be written to exec_cmd(argv) with `|`");
+            }
+            let (next, argv) = self.convert_val(argv, current_open)?;
+            let cmd = PrimExpr::CallBuiltin(builtins::Function::ExecCmd, smallvec![argv]);
+            return Ok((next, (self.to_val(cmd, next)?, FileSpec::Exec)));
+        }
+        let (next, out) = self.convert_val(out, current_open)?;
+        Ok((next, (out, *spec)))
+    }
+
     fn call<'c>(
         &mut self,
         current_open: NodeIx,
@@ -1614,8 +1921,17 @@ where
         };
         let mut prim_args = SmallVec::with_capacity(args.len());
         let mut open = current_open;
-        for a in args.iter() {
-            let (next, v) = self.convert_val(a, open)?;
+        for (i, a) in args.iter().enumerate() {
+            let (next, v) = match Self::exec_cmd_argv(a) {
+                // close(exec_cmd(argv)) closes the command started by `print | exec_cmd(argv)`
+                // or `exec_cmd(argv) | getline`.
+                Some(argv) if i == 0 && matches!(bi, Either::Right(builtins::Function::Close)) => {
+                    let (next, argv) = self.convert_val(argv, open)?;
+                    let cmd = PrimExpr::CallBuiltin(builtins::Function::ExecCmd, smallvec![argv]);
+                    (next, self.to_val(cmd, next)?)
+                }
+                _ => self.convert_val(a, open)?,
+            };
             open = next;
             prim_args.push(v);
         }
@@ -1631,11 +1947,19 @@ where
                         .entry(None)
                         .or_insert_with(Vec::new)
                         .push((current_open.index(), None));
-                    Ok((open, PrimExpr::CallUDF(*i, prim_args)))
+                    if self.ctx.next_flag.is_none() {
+                        return Ok((open, PrimExpr::CallUDF(*i, prim_args)));
+                    }
+                    let res = self.to_val(PrimExpr::CallUDF(*i, prim_args), open)?;
+                    let open = self.unwind_next(open)?;
+                    Ok((open, PrimExpr::Val(res)))
                 } else {
                     err!("Call to unknown function \"{}\"", fname)
                 };
             }
+            Either::Right(builtins::Function::ExecCmd) => {
+                err!("exec_cmd(argv) can only be used as a command of print, getline or close")
+            }
             // Now to "fill in the extras."
             Either::Right(mut bi) => {
                 // split(string, array) => split(string, array, FS)
@@ -1648,13 +1972,62 @@ where
                     prim_args.push(PrimVal::Var(fs));
                 }
 
+                // strftime() => strftime(DEFAULT_STRFTIME_FORMAT, systime(), 0)
+                // strftime(fmt) => strftime(fmt, systime(), 0)
+                // strftime(fmt, ts) => strftime(fmt, ts, 0)
+                if bi == builtins::Function::Strftime && args.len() < 3 {
+                    if args.is_empty() {
+                        let fmt = crate::runtime::time::DEFAULT_STRFTIME_FORMAT;
+                        prim_args.push(PrimVal::StrLit(self.arena.alloc_bytes(fmt.as_bytes())));
+                    }
+                    if args.len() < 2 {
+                        let now = PrimExpr::CallBuiltin(builtins::Function::Systime, smallvec![]);
+                        prim_args.push(self.to_val(now, open)?);
+                    }
+                    prim_args.push(PrimVal::ILit(0));
+                }
+
+                // fflush() => fflush(""), which flushes all open output
+                if bi == builtins::Function::Fflush && args.is_empty() {
+                    prim_args.push(PrimVal::StrLit(b""));
+                }
+
+                // close(file) => close(file, ""), which closes both directions of a coprocess
+                if bi == builtins::Function::Close && args.len() == 1 {
+                    prim_args.push(PrimVal::StrLit(b""));
+                }
+
+                // asort(src) => asort(src, src, "")
+                // asort(src, dst) => asort(src, dst, ""), which uses the default order
+                if matches!(bi, builtins::Function::Asort | builtins::Function::Asorti) {
+                    if args.len() == 1 {
+                        prim_args.push(prim_args[0].clone());
+                    }
+                    if args.len() < 3 {
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
+                }
+
+                // match(s, re, arr) => the variant of match that fills in arr
+                if bi == builtins::Function::Match && args.len() == 3 {
+                    bi = builtins::Function::MatchCaptures;
+                }
+
+                // gensub(re, repl, how) => gensub(re, repl, how, $0)
+                if bi == builtins::Function::GenSub && args.len() == 3 {
+                    let (next, v) =
+                        self.convert_val(&Expr::Unop(ast::Unop::Column, &Expr::ILit(0)), open)?;
+                    open = next;
+                    prim_args.push(v);
+                }
+
                 // join_fields(start, end) => join_{c,t}sv (if in csv/tsv output mode)
                 // join_fields(start, end) => join_fields(start, end, OFS) (otherwise)
                 if bi == builtins::Function::JoinCols && args.len() == 2 {
                     match self.ctx.esc {
                         Escaper::CSV => bi = builtins::Function::JoinCSV,
                         Escaper::TSV => bi = builtins::Function::JoinTSV,
-                        Escaper::Identity => {
+                        Escaper::JSON | Escaper::Identity => {
                             let fs = self.fresh_local();
                             self.add_stmt(
                                 current_open,
@@ -1747,12 +2120,105 @@ where
             Escaper::CSV => builtins::Function::EscapeCSV,
 
             Escaper::TSV => builtins::Function::EscapeTSV,
+            Escaper::JSON => builtins::Function::EscapeJSON,
             Escaper::Identity => return Ok(v),
         };
         let e = PrimExpr::CallBuiltin(builtin, smallvec![v]);
         self.to_val(e, current_open)
     }
 
+    // Print `vs` as a JSON array. If we are parsing a header, print an object instead, keyed as
+    // described in runtime::JSONKeys: the json_member builtin writes each key along with its
+    // value, and reports any key that repeats another. With no arguments, all of the fields in
+    // the record are printed.
+    fn print_json<'c>(
+        &mut self,
+        vs: &[&'c Expr<'c, 'b, I>],
+        ors: PrimVal<'b>,
+        out: Option<(PrimVal<'b>, FileSpec)>,
+        mut current_open: NodeIx,
+    ) -> Result<NodeIx> {
+        use ast::Expr::*;
+        let keyed = self.ctx.header_names;
+        if vs.is_empty() {
+            let fields = self.to_val(
+                PrimExpr::CallBuiltin(
+                    builtins::Function::JoinJSON,
+                    smallvec![PrimVal::ILit(keyed as i64)],
+                ),
+                current_open,
+            )?;
+            self.add_stmt(
+                current_open,
+                PrimStmt::PrintAll(smallvec![fields, ors], out),
+            )?;
+            return Ok(current_open);
+        }
+        let (open, close): (&'static [u8], &'static [u8]) =
+            if keyed { (b"{", b"}") } else { (b"[", b"]") };
+        if keyed {
+            // Printing the same column twice would repeat its key.
+            let mut cols = HashSet::<i64>::default();
+            for v in vs.iter() {
+                if let Unop(ast::Unop::Column, ILit(n)) = v {
+                    if !cols.insert(*n) {
+                        return err!("print would repeat the JSON key for column ${}", n);
+                    }
+                }
+            }
+        }
+        // The column each value was read from (or -1), and the escaped value.
+        let mut vals = Vec::with_capacity(vs.len());
+        for v in vs.iter() {
+            let col = match v {
+                Unop(ast::Unop::Column, ix) if keyed => {
+                    // Evaluate the column index once, for both the key and the value.
+                    let (next, ix) = self.convert_val(ix, current_open)?;
+                    current_open = next;
+                    let col = self.fresh_local();
+                    self.add_stmt(current_open, PrimStmt::AsgnVar(col, PrimExpr::Val(ix)))?;
+                    PrimVal::Var(col)
+                }
+                _ => PrimVal::ILit(-1),
+            };
+            let to_print = if let PrimVal::Var(_) = col {
+                let get_col = builtins::Function::Unop(ast::Unop::Column);
+                self.to_val(
+                    PrimExpr::CallBuiltin(get_col, smallvec![col.clone()]),
+                    current_open,
+                )?
+            } else {
+                let (next, to_print) = self.convert_val(v, current_open)?;
+                current_open = next;
+                to_print
+            };
+            vals.push((col, self.escape(to_print, current_open)?));
+        }
+        let mut print_args = SmallVec::with_capacity(vs.len() * 2 + 2);
+        print_args.push(PrimVal::StrLit(open));
+        // All of the values are evaluated before any of the members are built, so that a print
+        // in one of them cannot interleave its keys with ours.
+        for (i, (col, v)) in vals.into_iter().enumerate() {
+            if i > 0 {
+                print_args.push(PrimVal::StrLit(b","));
+            }
+            print_args.push(if keyed {
+                let pos = PrimVal::ILit(i as i64 + 1);
+                let member = builtins::Function::JSONMember;
+                self.to_val(
+                    PrimExpr::CallBuiltin(member, smallvec![col, pos, v]),
+                    current_open,
+                )?
+            } else {
+                v
+            });
+        }
+        print_args.push(PrimVal::StrLit(close));
+        print_args.push(ors);
+        self.add_stmt(current_open, PrimStmt::PrintAll(print_args, out))?;
+        Ok(current_open)
+    }
+
     fn fresh(&mut self) -> Ident {
         self.ctx.fresh()
     }
@@ -1987,7 +2453,7 @@ where
             };
             self.ctx.max as usize
         ];
-        for id in self.ctx.hm.values() {
+        for id in self.ctx.hm.values().chain(self.ctx.next_flag.iter()) {
             if id.global && self.ctx.local_globals.get(&id.low).is_none() {
                 state[id.low as usize].global = true;
             }
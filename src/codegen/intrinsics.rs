@@ -7,16 +7,17 @@ use super::{Backend, FunctionAttr, Sig};
 use crate::runtime::{
     self,
     printf::{printf, FormatArg},
+    sort::{self, SortOrder},
     splitter::{
         batch::{ByteReader, CSVReader, WhitespaceOffsets},
         chunk::{ChunkProducer, OffsetChunk},
         regex::RegexSplitter,
     },
-    ChainedReader, FileRead, Float, Int, IntMap, Line, LineReader, RegexCache, Str, StrMap,
+    ChainedReader, Cmd, FileRead, Float, Int, IntMap, Line, LineReader, RegexCache, Str, StrMap,
 };
 use crate::{
     builtins::Variable,
-    common::{CancelSignal, Cleanup, FileSpec, Notification, Result},
+    common::{CancelSignal, Cleanup, FileSpec, InputSpec, Notification, Result},
     compile::Ty,
     pushdown::FieldSet,
 };
@@ -99,6 +100,7 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         ref_map(map_ty);
         [ReadOnly] int_to_str(int_ty) -> str_ty;
         [ReadOnly] float_to_str(float_ty) -> str_ty;
+        [ReadOnly] format_float(rt_ty, int_ty, float_ty) -> str_ty;
         [ReadOnly] str_to_int(str_ref_ty) -> int_ty;
         [ReadOnly] hex_str_to_int(str_ref_ty) -> int_ty;
         [ReadOnly] str_to_float(str_ref_ty) -> float_ty;
@@ -109,16 +111,23 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         [ReadOnly] match_const_pat(str_ref_ty, rt_ty) -> int_ty;
         [ReadOnly] match_pat_loc(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] match_const_pat_loc(rt_ty, str_ref_ty, rt_ty) -> int_ty;
+        match_pat_captures_int(rt_ty, str_ref_ty, str_ref_ty, map_ty) -> int_ty;
+        match_pat_captures_str(rt_ty, str_ref_ty, str_ref_ty, map_ty) -> int_ty;
         [ReadOnly] substr_index(str_ref_ty, str_ref_ty) -> int_ty;
         subst_first(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> int_ty;
         subst_all(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        gensub(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> str_ty;
         escape_csv(str_ref_ty) -> str_ty;
         escape_tsv(str_ref_ty) -> str_ty;
+        escape_json(str_ref_ty) -> str_ty;
+        [ReadOnly] float_to_json(float_ty) -> str_ty;
         substr(str_ref_ty, int_ty, int_ty) -> str_ty;
         [ReadOnly] get_col(rt_ty, int_ty) -> str_ty;
         [ReadOnly] join_csv(rt_ty, int_ty, int_ty) -> str_ty;
         [ReadOnly] join_tsv(rt_ty, int_ty, int_ty) -> str_ty;
         [ReadOnly] join_cols(rt_ty, int_ty, int_ty, str_ref_ty) -> str_ty;
+        join_json(rt_ty, int_ty) -> str_ty;
+        json_member(rt_ty, int_ty, int_ty, str_ref_ty) -> str_ty;
         [ReadOnly] to_upper_ascii(str_ref_ty) -> str_ty;
         [ReadOnly] to_lower_ascii(str_ref_ty) -> str_ty;
         set_col(rt_ty, int_ty, str_ref_ty);
@@ -127,15 +136,22 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         rand_float(rt_ty) -> float_ty;
         seed_rng(rt_ty, int_ty) -> int_ty;
         reseed_rng(rt_ty) -> int_ty;
+        systime() -> int_ty;
+        strftime_impl(rt_ty, str_ref_ty, int_ty, int_ty) -> str_ty;
+        mktime_impl(rt_ty, str_ref_ty) -> int_ty;
+        strptime_impl(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
 
         exit(rt_ty, int_ty);
         run_system(str_ref_ty) -> int_ty;
+        run_exec(str_ref_ty) -> int_ty;
+        exec_cmd(rt_ty, map_ty) -> str_ty;
         print_all_stdout(rt_ty, pa_args_ty, int_ty);
         print_all_file(rt_ty, pa_args_ty, int_ty, str_ref_ty, int_ty);
         sprintf_impl(rt_ty, str_ref_ty, fmt_args_ty, fmt_tys_ty, int_ty) -> str_ty;
         printf_impl_file(rt_ty, str_ref_ty, fmt_args_ty, fmt_tys_ty, int_ty, str_ref_ty, int_ty);
         printf_impl_stdout(rt_ty, str_ref_ty, fmt_args_ty, fmt_tys_ty, int_ty);
-        close_file(rt_ty, str_ref_ty);
+        close_file(rt_ty, str_ref_ty, str_ref_ty);
+        flush_file(rt_ty, str_ref_ty) -> int_ty;
         read_err(rt_ty, str_ref_ty, int_ty) -> int_ty;
         read_err_stdin(rt_ty) -> int_ty;
         next_line(rt_ty, str_ref_ty, int_ty) -> str_ty;
@@ -144,6 +160,7 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         next_file(rt_ty);
         update_used_fields(rt_ty);
         set_fi_entry(rt_ty, int_ty, int_ty);
+        set_json_key(rt_ty, int_ty);
 
         // TODO: we are no longer relying on avoiding collisions with exisint library symbols
         // (everything in this module was one no_mangle); we should look into removing the _frawk
@@ -170,84 +187,108 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         store_var_intmap(rt_ty, int_ty, map_ty);
         [ReadOnly] load_var_strmap(rt_ty, int_ty) -> map_ty;
         store_var_strmap(rt_ty, int_ty, map_ty);
+        [ReadOnly] load_var_strstrmap(rt_ty, int_ty) -> map_ty;
+        store_var_strstrmap(rt_ty, int_ty, map_ty);
 
         [ReadOnly] str_lt(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] str_gt(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] str_lte(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] str_gte(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] str_eq(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] strnum_lt(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] strnum_gt(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] strnum_lte(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] strnum_gte(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] strnum_eq(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] strnum_float_lt(rt_ty, str_ref_ty, float_ty) -> int_ty;
+        [ReadOnly] strnum_float_gt(rt_ty, str_ref_ty, float_ty) -> int_ty;
+        [ReadOnly] strnum_float_lte(rt_ty, str_ref_ty, float_ty) -> int_ty;
+        [ReadOnly] strnum_float_gte(rt_ty, str_ref_ty, float_ty) -> int_ty;
+        [ReadOnly] strnum_float_eq(rt_ty, str_ref_ty, float_ty) -> int_ty;
 
         drop_iter_int(iter_int_ty, int_ty);
         drop_iter_str(iter_str_ty, int_ty);
 
         alloc_intint() -> map_ty;
-        iter_intint(map_ty) -> iter_int_ty;
+        iter_intint(rt_ty, map_ty) -> iter_int_ty;
         [ReadOnly] len_intint(map_ty) -> int_ty;
         [ReadOnly] lookup_intint(map_ty, int_ty) -> int_ty;
         [ReadOnly] contains_intint(map_ty, int_ty) -> int_ty;
         insert_intint(map_ty, int_ty, int_ty);
         delete_intint(map_ty, int_ty);
         clear_intint(map_ty);
+        asort_intint(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
+        asorti_intint(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
         drop_intint(map_ty);
         inc_int_intint(map_ty, int_ty, int_ty) -> int_ty;
         inc_float_intint(map_ty, int_ty, float_ty) -> int_ty;
 
         alloc_intfloat() -> map_ty;
-        iter_intfloat(map_ty) -> iter_int_ty;
+        iter_intfloat(rt_ty, map_ty) -> iter_int_ty;
         [ReadOnly] len_intfloat(map_ty) -> int_ty;
         [ReadOnly] lookup_intfloat(map_ty, int_ty) -> float_ty;
         [ReadOnly] contains_intfloat(map_ty, int_ty) -> int_ty;
         insert_intfloat(map_ty, int_ty, float_ty);
         delete_intfloat(map_ty, int_ty);
         clear_intfloat(map_ty);
+        asort_intfloat(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
+        asorti_intfloat(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
         drop_intfloat(map_ty);
         inc_int_intfloat(map_ty, int_ty, int_ty) -> float_ty;
         inc_float_intfloat(map_ty, int_ty, float_ty) -> float_ty;
 
         alloc_intstr() -> map_ty;
-        iter_intstr(map_ty) -> iter_int_ty;
+        iter_intstr(rt_ty, map_ty) -> iter_int_ty;
         [ReadOnly] len_intstr(map_ty) -> int_ty;
         [ReadOnly] lookup_intstr(map_ty, int_ty) -> str_ty;
         [ReadOnly] contains_intstr(map_ty, int_ty) -> int_ty;
         insert_intstr(map_ty, int_ty, str_ref_ty);
         delete_intstr(map_ty, int_ty);
         clear_intstr(map_ty);
+        asort_intstr(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
+        asorti_intstr(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
         drop_intstr(map_ty);
         inc_int_intstr(map_ty, int_ty, int_ty) -> str_ty;
         inc_float_intstr(map_ty, int_ty, float_ty) -> str_ty;
 
         alloc_strint() -> map_ty;
-        iter_strint(map_ty) -> iter_str_ty;
+        iter_strint(rt_ty, map_ty) -> iter_str_ty;
         [ReadOnly] len_strint(map_ty) -> int_ty;
         [ReadOnly] lookup_strint(map_ty, str_ref_ty) -> int_ty;
         [ReadOnly] contains_strint(map_ty, str_ref_ty) -> int_ty;
         insert_strint(map_ty, str_ref_ty, int_ty);
         delete_strint(map_ty, str_ref_ty);
         clear_strint(map_ty);
+        asort_strint(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
+        asorti_strint(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
         drop_strint(map_ty);
         inc_int_strint(map_ty, str_ref_ty, int_ty) -> int_ty;
         inc_float_strint(map_ty, str_ref_ty, float_ty) -> int_ty;
 
         alloc_strfloat() -> map_ty;
-        iter_strfloat(map_ty) -> iter_str_ty;
+        iter_strfloat(rt_ty, map_ty) -> iter_str_ty;
         [ReadOnly] len_strfloat(map_ty) -> int_ty;
         [ReadOnly] lookup_strfloat(map_ty, str_ref_ty) -> float_ty;
         [ReadOnly] contains_strfloat(map_ty, str_ref_ty) -> int_ty;
         insert_strfloat(map_ty, str_ref_ty, float_ty);
         delete_strfloat(map_ty, str_ref_ty);
         clear_strfloat(map_ty);
+        asort_strfloat(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
+        asorti_strfloat(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
         drop_strfloat(map_ty);
         inc_int_strfloat(map_ty, str_ref_ty, int_ty) -> float_ty;
         inc_float_strfloat(map_ty, str_ref_ty, float_ty) -> float_ty;
 
         alloc_strstr() -> map_ty;
-        iter_strstr(map_ty) -> iter_str_ty;
+        iter_strstr(rt_ty, map_ty) -> iter_str_ty;
         [ReadOnly] len_strstr(map_ty) -> int_ty;
         [ReadOnly] lookup_strstr(map_ty, str_ref_ty) -> str_ty;
         [ReadOnly] contains_strstr(map_ty, str_ref_ty) -> int_ty;
         insert_strstr(map_ty, str_ref_ty, str_ref_ty);
         delete_strstr(map_ty, str_ref_ty);
         clear_strstr(map_ty);
+        asort_strstr(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
+        asorti_strstr(rt_ty, map_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
         drop_strstr(map_ty);
         inc_int_strstr(map_ty, str_ref_ty, int_ty) -> str_ty;
         inc_float_strstr(map_ty, str_ref_ty, float_ty) -> str_ty;
@@ -279,6 +320,7 @@ macro_rules! fail {
     ($rt:expr, $($es:expr),+) => {{
         #[cfg(test)]
         {
+            let _ = $rt;
             eprintln_ignore!("failure in runtime {}. Halting execution", format!($($es),*));
             panic!("failure in runtime")
         }
@@ -363,7 +405,7 @@ pub(crate) enum InputData {
     V4(InputTuple<ChainedReader<RegexSplitter<Box<dyn io::Read + Send>>>>),
 }
 
-pub(crate) trait IntoRuntime {
+pub(crate) trait IntoRuntime: LineReader {
     fn into_runtime<'a>(
         self,
         ff: impl runtime::writers::FileFactory,
@@ -423,10 +465,28 @@ pub(crate) struct Runtime<'a> {
 
 impl<'a> Runtime<'a> {
     fn reset_file_vars(&mut self) {
-        self.core.vars.fnr = 0;
-        self.core.vars.filename = with_input!(&mut self.input_data, |(_, read_files)| {
-            read_files.stdin_filename().upcast()
+        let (fnr, filename) = with_input!(&mut self.input_data, |(_, read_files)| {
+            (
+                read_files.stdin_fnr_offset(),
+                read_files.stdin_filename().upcast(),
+            )
+        });
+        self.core.vars.fnr = fnr;
+        self.core.vars.filename = filename;
+    }
+    fn update_fi(&mut self) {
+        let fi = &self.core.vars.fi;
+        with_input!(&mut self.input_data, |(_, read_files)| {
+            read_files.update_fi(fi)
+        });
+    }
+    fn update_rt(&mut self) {
+        let rt = with_input!(&mut self.input_data, |(_, read_files)| {
+            read_files.stdin_terminator()
         });
+        if let Some(rt) = rt {
+            self.core.vars.rt = rt.upcast();
+        }
     }
 }
 
@@ -442,7 +502,19 @@ pub(crate) unsafe extern "C" fn exit(runtime: *mut c_void, code: Int) {
 
 pub(crate) unsafe extern "C" fn run_system(cmd: *mut U128) -> Int {
     let s: &Str = &*(cmd as *mut Str);
-    s.with_bytes(runtime::run_command)
+    s.with_bytes(|bs| runtime::run_command(Cmd::Shell(bs)))
+}
+
+pub(crate) unsafe extern "C" fn run_exec(cmd: *mut U128) -> Int {
+    let s: &Str = &*(cmd as *mut Str);
+    s.with_bytes(|bs| runtime::run_command(Cmd::Argv(bs)))
+}
+
+pub(crate) unsafe extern "C" fn exec_cmd(runtime: *mut c_void, argv: *mut c_void) -> U128 {
+    let argv = mem::transmute::<*mut c_void, IntMap<Str>>(argv);
+    let res = try_abort!(runtime, runtime::exec_command(&argv));
+    mem::forget(argv);
+    mem::transmute::<Str, U128>(res)
 }
 
 pub(crate) unsafe extern "C" fn rand_float(runtime: *mut c_void) -> f64 {
@@ -463,17 +535,21 @@ pub(crate) unsafe extern "C" fn reseed_rng(runtime: *mut c_void) -> Int {
 pub(crate) unsafe extern "C" fn read_err(
     runtime: *mut c_void,
     file: *mut c_void,
-    is_file: Int,
+    spec: Int,
 ) -> Int {
     let runtime = &mut *(runtime as *mut Runtime);
+    let spec = try_abort!(runtime, InputSpec::try_from(spec));
     let res = try_abort!(
         runtime,
         with_input!(&mut runtime.input_data, |(_, read_files)| {
             let file = &*(file as *mut Str);
-            if is_file == 0 {
-                read_files.read_err_cmd(file)
-            } else {
-                read_files.read_err(file)
+            match spec {
+                InputSpec::File => read_files.read_err(file),
+                InputSpec::Cmd => read_files.read_err_cmd(file, false),
+                InputSpec::Exec => read_files.read_err_cmd(file, true),
+                InputSpec::Coproc => {
+                    read_files.read_err_coproc(file, &mut runtime.core.write_files)
+                }
             }
         }),
         "unexpected error when reading error status of file:"
@@ -502,6 +578,8 @@ pub(crate) unsafe extern "C" fn next_line_stdin_fused(runtime: *mut c_void) {
     if changed {
         runtime.reset_file_vars();
     }
+    runtime.update_rt();
+    runtime.update_fi();
 }
 
 pub(crate) unsafe extern "C" fn next_file(runtime: *mut c_void) {
@@ -529,21 +607,27 @@ pub(crate) unsafe extern "C" fn next_line_stdin(runtime: *mut c_void) -> U128 {
     if changed {
         runtime.reset_file_vars();
     }
+    runtime.update_rt();
+    runtime.update_fi();
     mem::transmute::<Str, U128>(res)
 }
 
 pub(crate) unsafe extern "C" fn next_line(
     runtime: *mut c_void,
     file: *mut c_void,
-    is_file: Int,
+    spec: Int,
 ) -> U128 {
     let runtime = &mut *(runtime as *mut Runtime);
     let file = &*(file as *mut Str);
+    let spec = try_abort!(runtime, InputSpec::try_from(spec));
     let res = with_input!(&mut runtime.input_data, |(_, read_files)| {
-        runtime
-            .core
-            .regexes
-            .get_line(file, &runtime.core.vars.rs, read_files, is_file != 0)
+        runtime.core.regexes.get_line(
+            file,
+            &runtime.core.vars.rs,
+            read_files,
+            &mut runtime.core.write_files,
+            spec,
+        )
     });
     match res {
         Ok(res) => mem::transmute::<Str, U128>(res),
@@ -566,6 +650,12 @@ pub(crate) unsafe extern "C" fn set_fi_entry(runtime: *mut c_void, key: Int, val
     fi.insert(k, val);
 }
 
+pub(crate) unsafe extern "C" fn set_json_key(runtime: *mut c_void, col: Int) {
+    let rt = &mut *(runtime as *mut Runtime);
+    let name = mem::transmute::<U128, Str>(get_col(runtime, col));
+    try_abort!(rt, rt.core.json_keys.set_header_name(col, &name));
+}
+
 pub(crate) unsafe extern "C" fn split_str(
     runtime: *mut c_void,
     to_split: *mut c_void,
@@ -685,6 +775,39 @@ pub(crate) unsafe extern "C" fn join_cols(
     mem::transmute::<Str, U128>(res)
 }
 
+pub(crate) unsafe extern "C" fn join_json(runtime: *mut c_void, keyed: Int) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let res = try_abort!(
+        runtime,
+        with_input!(&mut runtime.input_data, |(line, _)| {
+            let nf = try_abort!(
+                runtime,
+                line.nf(&runtime.core.vars.fs, &mut runtime.core.regexes),
+                "nf:"
+            );
+            let keys = if keyed != 0 {
+                Some(&mut runtime.core.json_keys)
+            } else {
+                None
+            };
+            runtime::join_json(line, nf, keys)
+        })
+    );
+    mem::transmute::<Str, U128>(res)
+}
+
+pub(crate) unsafe extern "C" fn json_member(
+    runtime: *mut c_void,
+    col: Int,
+    pos: Int,
+    val: *mut U128,
+) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let val = &*(val as *mut Str);
+    let res = try_abort!(runtime, runtime.core.json_keys.member(col, pos, val));
+    mem::transmute::<Str, U128>(res)
+}
+
 pub(crate) unsafe extern "C" fn to_upper_ascii(s: *mut U128) -> U128 {
     let res = (&*(s as *mut Str as *const Str)).to_upper_ascii();
     mem::transmute::<Str, U128>(res)
@@ -791,6 +914,50 @@ pub(crate) unsafe extern "C" fn substr_index(s: *mut U128, t: *mut U128) -> Int
     runtime::string_search::index_substr(/*needle*/ t, /*haystack*/ s)
 }
 
+pub(crate) unsafe extern "C" fn match_pat_captures_int(
+    runtime: *mut c_void,
+    s: *mut c_void,
+    pat: *mut c_void,
+    arr: *mut c_void,
+) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let arr = mem::transmute::<*mut c_void, IntMap<Str>>(arr);
+    let s = &*(s as *mut Str);
+    let pat = &*(pat as *mut Str);
+    let res = try_abort!(
+        runtime,
+        runtime
+            .core
+            .regexes
+            .regex_match_captures_intmap(&mut runtime.core.vars, pat, s, &arr),
+        "match_pat_captures_int:"
+    );
+    mem::forget(arr);
+    res
+}
+
+pub(crate) unsafe extern "C" fn match_pat_captures_str(
+    runtime: *mut c_void,
+    s: *mut c_void,
+    pat: *mut c_void,
+    arr: *mut c_void,
+) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let arr = mem::transmute::<*mut c_void, StrMap<Str>>(arr);
+    let s = &*(s as *mut Str);
+    let pat = &*(pat as *mut Str);
+    let res = try_abort!(
+        runtime,
+        runtime
+            .core
+            .regexes
+            .regex_match_captures_strmap(&mut runtime.core.vars, pat, s, &arr),
+        "match_pat_captures_str:"
+    );
+    mem::forget(arr);
+    res
+}
+
 pub(crate) unsafe extern "C" fn subst_first(
     runtime: *mut c_void,
     pat: *mut U128,
@@ -841,6 +1008,30 @@ pub(crate) unsafe extern "C" fn escape_tsv(s: *mut U128) -> U128 {
     mem::transmute::<Str, U128>(runtime::escape_tsv(&*(s as *mut Str)))
 }
 
+pub(crate) unsafe extern "C" fn escape_json(s: *mut U128) -> U128 {
+    mem::transmute::<Str, U128>(runtime::escape_json(&*(s as *mut Str)))
+}
+
+pub(crate) unsafe extern "C" fn float_to_json(f: Float) -> U128 {
+    mem::transmute::<Str, U128>(runtime::float_to_json(f))
+}
+
+pub(crate) unsafe extern "C" fn gensub(
+    runtime: *mut c_void,
+    pat: *mut U128,
+    s: *mut U128,
+    how: *mut U128,
+    in_s: *mut U128,
+) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let pat = &*(pat as *mut Str);
+    let s = &*(s as *mut Str);
+    let how = &*(how as *mut Str);
+    let in_s = &*(in_s as *mut Str);
+    let res = try_abort!(runtime, runtime.core.regexes.gensub(pat, s, how, in_s));
+    mem::transmute::<Str, U128>(res)
+}
+
 pub(crate) unsafe extern "C" fn substr(base: *mut U128, l: Int, r: Int) -> U128 {
     use std::cmp::{max, min};
     let base = &*(base as *mut Str);
@@ -854,6 +1045,34 @@ pub(crate) unsafe extern "C" fn substr(base: *mut U128, l: Int, r: Int) -> U128
     }
 }
 
+pub(crate) unsafe extern "C" fn systime() -> Int {
+    runtime::time::systime()
+}
+
+pub(crate) unsafe extern "C" fn strftime_impl(
+    runtime: *mut c_void,
+    fmt: *mut U128,
+    ts: Int,
+    utc: Int,
+) -> U128 {
+    let fmt = &*(fmt as *mut Str);
+    let res = try_abort!(runtime, runtime::time::strftime_impl(fmt, ts, utc != 0));
+    mem::transmute::<Str, U128>(res)
+}
+
+pub(crate) unsafe extern "C" fn mktime_impl(runtime: *mut c_void, s: *mut U128) -> Int {
+    try_abort!(runtime, runtime::time::mktime_impl(&*(s as *mut Str)))
+}
+
+pub(crate) unsafe extern "C" fn strptime_impl(
+    runtime: *mut c_void,
+    s: *mut U128,
+    fmt: *mut U128,
+) -> Int {
+    let res = runtime::time::strptime_impl(&*(s as *mut Str), &*(fmt as *mut Str));
+    try_abort!(runtime, res)
+}
+
 pub(crate) unsafe extern "C" fn ref_str(s: *mut c_void) {
     mem::forget((&*(s as *mut Str)).clone())
 }
@@ -892,6 +1111,17 @@ pub(crate) unsafe extern "C" fn float_to_str(f: Float) -> U128 {
     mem::transmute::<Str, U128>(runtime::convert::<Float, Str>(f))
 }
 
+pub(crate) unsafe extern "C" fn format_float(rt: *mut c_void, var: usize, f: Float) -> U128 {
+    let runtime = &mut *(rt as *mut Runtime);
+    if let Ok(var) = Variable::try_from(var) {
+        let fmt = try_abort!(runtime, runtime.core.vars.load_str(var));
+        let res = try_abort!(runtime, runtime::format_float(&fmt, f));
+        mem::transmute::<Str, U128>(res)
+    } else {
+        fail!(runtime, "invalid variable code={}", var)
+    }
+}
+
 pub(crate) unsafe extern "C" fn str_to_int(s: *mut c_void) -> Int {
     let s = &*(s as *mut Str);
     runtime::convert::<&Str, Int>(s)
@@ -921,6 +1151,11 @@ pub(crate) unsafe extern "C" fn store_var_str(rt: *mut c_void, var: usize, s: *m
     let runtime = &mut *(rt as *mut Runtime);
     if let Ok(var) = Variable::try_from(var) {
         let s = (&*(s as *mut Str)).clone();
+        try_abort!(
+            runtime,
+            with_input!(&mut runtime.input_data, |(_, read_files)| read_files
+                .update_splitting(var, &s))
+        );
         try_abort!(runtime, runtime.core.vars.store_str(var, s))
     } else {
         fail!(runtime, "invalid variable code={}", var)
@@ -995,6 +1230,27 @@ pub(crate) unsafe extern "C" fn store_var_strmap(rt: *mut c_void, var: usize, ma
     }
 }
 
+pub(crate) unsafe extern "C" fn load_var_strstrmap(rt: *mut c_void, var: usize) -> *mut c_void {
+    let runtime = &mut *(rt as *mut Runtime);
+    if let Ok(var) = Variable::try_from(var) {
+        let res = try_abort!(runtime, runtime.core.vars.load_strstrmap(var));
+        mem::transmute::<StrMap<_>, *mut c_void>(res)
+    } else {
+        fail!(runtime, "invalid variable code={}", var)
+    }
+}
+
+pub(crate) unsafe extern "C" fn store_var_strstrmap(rt: *mut c_void, var: usize, map: *mut c_void) {
+    let runtime = &mut *(rt as *mut Runtime);
+    if let Ok(var) = Variable::try_from(var) {
+        let map = mem::transmute::<*mut c_void, StrMap<Str>>(map);
+        try_abort!(runtime, runtime.core.vars.store_strstrmap(var, map.clone()));
+        mem::forget(map);
+    } else {
+        fail!(runtime, "invalid variable code={}", var)
+    }
+}
+
 macro_rules! str_compare_inner {
     ($name:ident, $op:tt) => {
         pub(crate) unsafe extern "C" fn $name(s1: *mut c_void, s2: *mut c_void) -> Int {
@@ -1013,6 +1269,37 @@ str_compare! {
     str_lt(<); str_gt(>); str_lte(<=); str_gte(>=); str_eq(==);
 }
 
+macro_rules! strnum_compare {
+    ($($name:ident ($pred:ident);)*) => { $(
+        pub(crate) unsafe extern "C" fn $name(s1: *mut c_void, s2: *mut c_void) -> Int {
+            let s1 = &*(s1 as *mut Str);
+            let s2 = &*(s2 as *mut Str);
+            s1.strnum_cmp(s2).$pred() as Int
+        }
+    )* };
+}
+
+strnum_compare! {
+    strnum_lt(is_lt); strnum_gt(is_gt); strnum_lte(is_le); strnum_gte(is_ge); strnum_eq(is_eq);
+}
+
+macro_rules! strnum_float_compare {
+    ($($name:ident ($pred:ident);)*) => { $(
+        pub(crate) unsafe extern "C" fn $name(rt: *mut c_void, s: *mut c_void, f: Float) -> Int {
+            let runtime = &mut *(rt as *mut Runtime);
+            let s = &*(s as *mut Str);
+            let vars = &runtime.core.vars;
+            let ord = runtime::strnum_cmp_float(s, f, || vars.load_str(Variable::CONVFMT));
+            try_abort!(runtime, ord).$pred() as Int
+        }
+    )* };
+}
+
+strnum_float_compare! {
+    strnum_float_lt(is_lt); strnum_float_gt(is_gt); strnum_float_lte(is_le);
+    strnum_float_gte(is_ge); strnum_float_eq(is_eq);
+}
+
 pub(crate) unsafe extern "C" fn drop_iter_int(iter: *mut Int, len: usize) {
     mem::drop(Box::from_raw(slice::from_raw_parts_mut(iter, len)))
 }
@@ -1104,10 +1391,12 @@ pub(crate) unsafe extern "C" fn printf_impl_file(
     let rt = rt as *mut Runtime;
     try_abort!(
         rt,
-        (*rt)
-            .core
-            .write_files
-            .printf(output_wrapped, &*(spec as *mut Str), &format_args[..],)
+        (*rt).core.write_files.printf(
+            output_wrapped,
+            &*(spec as *mut Str),
+            &format_args[..],
+            &(*rt).core.vars.convfmt,
+        )
     )
 }
 
@@ -1123,7 +1412,8 @@ pub(crate) unsafe extern "C" fn sprintf_impl(
     let rt = &mut *(rt as *mut _);
     let format_args = wrap_args(rt, args, tys, num_args);
     let spec = &*(spec as *mut Str);
-    if let Err(e) = spec.with_bytes(|bs| printf(&mut buf, bs, &format_args[..])) {
+    let convfmt = &rt.core.vars.convfmt;
+    if let Err(e) = spec.with_bytes(|bs| printf(&mut buf, bs, &format_args[..], convfmt)) {
         fail!(rt, "unexpected failure during sprintf: {}", e);
     }
     mem::transmute::<Str, U128>(buf.into_str())
@@ -1137,21 +1427,33 @@ pub(crate) unsafe extern "C" fn printf_impl_stdout(
     num_args: Int,
 ) {
     let format_args = wrap_args(&mut *(rt as *mut _), args, tys, num_args);
-    let res = (*(rt as *mut Runtime)).core.write_files.printf(
+    let rt = rt as *mut Runtime;
+    let res = (*rt).core.write_files.printf(
         None,
         &*(spec as *mut Str),
         &format_args[..],
+        &(*rt).core.vars.convfmt,
     );
     if res.is_err() {
         exit!(rt);
     }
 }
 
-pub(crate) unsafe extern "C" fn close_file(rt: *mut c_void, file: *mut U128) {
+pub(crate) unsafe extern "C" fn close_file(rt: *mut c_void, file: *mut U128, how: *mut U128) {
     let rt = &mut *(rt as *mut Runtime);
     let file = &*(file as *mut Str);
-    with_input!(&mut rt.input_data, |(_, read_files)| read_files.close(file));
-    try_abort!(rt, rt.core.write_files.close(file));
+    let how = &*(how as *mut Str);
+    let write_files = &mut rt.core.write_files;
+    let res = with_input!(&mut rt.input_data, |(_, read_files)| {
+        runtime::close_file(write_files, read_files, file, how)
+    });
+    try_abort!(rt, res);
+}
+
+pub(crate) unsafe extern "C" fn flush_file(rt: *mut c_void, file: *mut U128) -> Int {
+    let rt = &mut *(rt as *mut Runtime);
+    let file = &*(file as *mut Str);
+    try_abort!(rt, rt.core.write_files.flush(file))
 }
 
 pub(crate) unsafe extern "C" fn _frawk_cos(f: Float) -> Float {
@@ -1284,10 +1586,12 @@ macro_rules! map_impl {
                 mem::transmute::<runtime::SharedMap<$k, $v>, *mut c_void>(res)
             }
 
-            pub(crate) unsafe extern "C" fn [< iter_ $ty >](map: *mut c_void) -> iter_ty!($k) {
+            pub(crate) unsafe extern "C" fn [< iter_ $ty >](runtime: *mut c_void, map: *mut c_void) -> iter_ty!($k) {
                 debug_assert!(!map.is_null());
+                let runtime = &mut *(runtime as *mut Runtime);
+                let order = try_abort!(runtime, SortOrder::sorted_in(&runtime.core.vars.procinfo));
                 let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
-                let iter: Vec<_> = map.to_vec();
+                let iter: Vec<_> = map.to_vec(order);
                 mem::forget(map);
                 let b = iter.into_boxed_slice();
                 Box::into_raw(b) as _
@@ -1344,6 +1648,56 @@ macro_rules! map_impl {
                 mem::forget(map);
             }
 
+            pub(crate) unsafe extern "C" fn [<asort_ $ty>](
+                runtime: *mut c_void,
+                src: *mut c_void,
+                dst: *mut c_void,
+                str_keys: Int,
+                how: *mut U128,
+            ) -> Int {
+                debug_assert!(!src.is_null() && !dst.is_null());
+                let how = &*(how as *mut Str);
+                let src = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(src);
+                let res = if str_keys != 0 {
+                    let dst = mem::transmute::<*mut c_void, runtime::SharedMap<Str, $v>>(dst);
+                    let res = sort::asort(&src, &dst, how);
+                    mem::forget(dst);
+                    res
+                } else {
+                    let dst = mem::transmute::<*mut c_void, runtime::SharedMap<Int, $v>>(dst);
+                    let res = sort::asort(&src, &dst, how);
+                    mem::forget(dst);
+                    res
+                };
+                mem::forget(src);
+                try_abort!(runtime, res)
+            }
+
+            pub(crate) unsafe extern "C" fn [<asorti_ $ty>](
+                runtime: *mut c_void,
+                src: *mut c_void,
+                dst: *mut c_void,
+                str_keys: Int,
+                how: *mut U128,
+            ) -> Int {
+                debug_assert!(!src.is_null() && !dst.is_null());
+                let how = &*(how as *mut Str);
+                let src = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(src);
+                let res = if str_keys != 0 {
+                    let dst = mem::transmute::<*mut c_void, runtime::SharedMap<Str, $k>>(dst);
+                    let res = sort::asorti(&src, &dst, how);
+                    mem::forget(dst);
+                    res
+                } else {
+                    let dst = mem::transmute::<*mut c_void, runtime::SharedMap<Int, $k>>(dst);
+                    let res = sort::asorti(&src, &dst, how);
+                    mem::forget(dst);
+                    res
+                };
+                mem::forget(src);
+                try_abort!(runtime, res)
+            }
+
             pub(crate) unsafe extern "C" fn [<drop_ $ty>](map: *mut c_void) {
                 debug_assert!(!map.is_null());
                 drop_map_generic::<$k, $v>(map)
@@ -490,7 +490,8 @@ impl<'a> CodeGenerator for View<'a> {
                 _ => return err!("iterating over non-map type: {:?}", map.1),
             };
 
-            let iter_ptr = self.call(begin_fn, &mut [arrv]);
+            let rt = self.runtime_val();
+            let iter_ptr = self.call(begin_fn, &mut [rt, arrv]);
             let cur_index = self.alloca(Ty::Int)?;
 
             let ty = self.tmap.get_ty(Ty::Int);
@@ -137,6 +137,7 @@ pub(crate) trait Jit: Sized {
 
 /// Run the main function (or functions, for parallel scripts) given a [`Jit`] and the various
 /// other parameters required to construct a runtime.
+#[allow(clippy::too_many_arguments)]
 pub(crate) unsafe fn run_main<R, FF, J>(
     mut jit: J,
     stdin: R,
@@ -144,6 +145,7 @@ pub(crate) unsafe fn run_main<R, FF, J>(
     used_fields: &FieldSet,
     named_columns: Option<Vec<&[u8]>>,
     num_workers: usize,
+    posix_regex: bool,
     cancel_signal: CancelSignal,
 ) -> Result<()>
 where
@@ -152,6 +154,7 @@ where
     J: Jit,
 {
     let mut rt = stdin.into_runtime(ff, used_fields, named_columns, cancel_signal.clone());
+    rt.core.regexes = runtime::RegexCache::new(posix_regex);
     let main = jit.main_functions()?;
     match main {
         Stage::Main(m) => {
@@ -170,7 +173,15 @@ where
                 let reads = read_files.try_resize(num_workers.saturating_sub(1));
                 if num_workers <= 1 || reads.is_empty() || main_loop.is_none() {
                     // execute serially.
-                    for main in begin.into_iter().chain(main_loop).chain(end) {
+                    if let Some(begin) = begin {
+                        begin.invoke(&mut rt);
+                        // Record output in ordered mode goes to stdout through a separate handle,
+                        // so BEGIN's output has to be written out before we read any input.
+                        if rt.core.write_files.flush_stdout().is_err() {
+                            return Ok(());
+                        }
+                    }
+                    for main in main_loop.into_iter().chain(end) {
                         main.invoke(&mut rt);
                     }
                     return Ok(());
@@ -219,6 +230,9 @@ where
                                         core: shuttle(),
                                         input_data: reader.into(),
                                         cleanup: Cleanup::<Runtime>::new(move |rt| {
+                                            // Hand off the output of the last chunk before
+                                            // reporting this worker's results.
+                                            let _ = runtime::writers::finish_chunk();
                                             sender.send(rt.core.extract_result(0)).unwrap();
                                         }),
                                         cancel_signal,
@@ -231,10 +245,13 @@ where
                         {
                             rt.core.vars.pid = 1;
                             let r = receiver.clone();
-                            rt.cleanup =
-                                Cleanup::<Runtime>::new(move |_| while r.recv().is_ok() {});
+                            rt.cleanup = Cleanup::<Runtime>::new(move |_| {
+                                let _ = runtime::writers::finish_chunk();
+                                while r.recv().is_ok() {}
+                            });
                             main_loop_fn.invoke(&mut rt);
                             rt.cleanup.cancel();
+                            let _ = runtime::writers::finish_chunk();
                         }
                         rt.core.vars.pid = 0;
 
@@ -488,6 +505,41 @@ pub(crate) trait CodeGenerator: Backend {
         Ok(())
     }
 
+    /// Sorts the values (or, if `indices` is set, the keys) of `src` into `dst`, storing the
+    /// number of elements in `dst_len`.
+    fn asort_map(
+        &mut self,
+        src: Ref,
+        dst: Ref,
+        how: Ref,
+        dst_len: Ref,
+        indices: bool,
+    ) -> Result<()> {
+        use compile::Ty::*;
+        let func = match (src.1, indices) {
+            (MapIntInt, false) => intrinsic!(asort_intint),
+            (MapIntFloat, false) => intrinsic!(asort_intfloat),
+            (MapIntStr, false) => intrinsic!(asort_intstr),
+            (MapStrInt, false) => intrinsic!(asort_strint),
+            (MapStrFloat, false) => intrinsic!(asort_strfloat),
+            (MapStrStr, false) => intrinsic!(asort_strstr),
+            (MapIntInt, true) => intrinsic!(asorti_intint),
+            (MapIntFloat, true) => intrinsic!(asorti_intfloat),
+            (MapIntStr, true) => intrinsic!(asorti_intstr),
+            (MapStrInt, true) => intrinsic!(asorti_strint),
+            (MapStrFloat, true) => intrinsic!(asorti_strfloat),
+            (MapStrStr, true) => intrinsic!(asorti_strstr),
+            (ty, _) => return err!("non-map type: {:?}", ty),
+        };
+        let rt = self.runtime_val();
+        let srcv = self.get_val(src)?;
+        let dstv = self.get_val(dst)?;
+        let str_keys = self.const_int((dst.1.key()? == Str) as i64);
+        let howv = self.get_val(how)?;
+        let resv = self.call_intrinsic(func, &mut [rt, srcv, dstv, str_keys, howv])?;
+        self.bind_val(dst_len, resv)
+    }
+
     /// Determines if `map` contains `key` and stores the result (0 or 1) in `dst`.
     ///
     /// Assumes that map and key types match up.
@@ -585,6 +637,22 @@ pub(crate) trait CodeGenerator: Backend {
         self.bind_val(dst.reflect(), res)
     }
 
+    /// Wraps `call_intrinsic` for comparisons between a strnum and a float, which also take the
+    /// runtime.
+    fn strnum_float_cmp(
+        &mut self,
+        op: Op,
+        dst: &impl Accum,
+        l: &impl Accum,
+        r: &impl Accum,
+    ) -> Result<()> {
+        let rt = self.runtime_val();
+        let lv = self.get_val(l.reflect())?;
+        let rv = self.get_val(r.reflect())?;
+        let res = self.call_intrinsic(op, &mut [rt, lv, rv])?;
+        self.bind_val(dst.reflect(), res)
+    }
+
     /// Wraps `call_intrinsic` for [`Op`]s that have one argument and return a value.
     fn unop(&mut self, op: Op, dst: &impl Accum, x: &impl Accum) -> Result<()> {
         let xv = self.get_val(x.reflect())?;
@@ -609,6 +677,13 @@ pub(crate) trait CodeGenerator: Backend {
             }
             IntToStr(sr, ir) => self.unop(intrinsic!(int_to_str), sr, ir),
             FloatToStr(sr, fr) => self.unop(intrinsic!(float_to_str), sr, fr),
+            FormatFloat(sr, fr, var) => {
+                let rt = self.runtime_val();
+                let varv = self.const_int(*var as i64);
+                let fv = self.get_val(fr.reflect())?;
+                let res = self.call_intrinsic(intrinsic!(format_float), &mut [rt, varv, fv])?;
+                self.bind_val(sr.reflect(), res)
+            }
             StrToInt(ir, sr) => self.unop(intrinsic!(str_to_int), ir, sr),
             HexStrToInt(ir, sr) => self.unop(intrinsic!(hex_str_to_int), ir, sr),
             StrToFloat(fr, sr) => self.unop(intrinsic!(str_to_float), fr, sr),
@@ -673,6 +748,32 @@ pub(crate) trait CodeGenerator: Backend {
                 let res = self.call_intrinsic(intrinsic!(reseed_rng), &mut [rt])?;
                 self.bind_val(dst.reflect(), res)
             }
+            Systime(dst) => {
+                let res = self.call_intrinsic(intrinsic!(systime), &mut [])?;
+                self.bind_val(dst.reflect(), res)
+            }
+            Strftime(dst, fmt, ts, utc) => {
+                let rt = self.runtime_val();
+                let fmtv = self.get_val(fmt.reflect())?;
+                let tsv = self.get_val(ts.reflect())?;
+                let utcv = self.get_val(utc.reflect())?;
+                let res =
+                    self.call_intrinsic(intrinsic!(strftime_impl), &mut [rt, fmtv, tsv, utcv])?;
+                self.bind_val(dst.reflect(), res)
+            }
+            Mktime(dst, s) => {
+                let rt = self.runtime_val();
+                let sv = self.get_val(s.reflect())?;
+                let res = self.call_intrinsic(intrinsic!(mktime_impl), &mut [rt, sv])?;
+                self.bind_val(dst.reflect(), res)
+            }
+            Strptime(dst, s, fmt) => {
+                let rt = self.runtime_val();
+                let sv = self.get_val(s.reflect())?;
+                let fmtv = self.get_val(fmt.reflect())?;
+                let res = self.call_intrinsic(intrinsic!(strptime_impl), &mut [rt, sv, fmtv])?;
+                self.bind_val(dst.reflect(), res)
+            }
             Concat(dst, l, r) => self.binop(intrinsic!(concat), dst, l, r),
             StartsWithConst(dst, s, bs) => {
                 let s = self.get_val(s.reflect())?;
@@ -688,6 +789,28 @@ pub(crate) trait CodeGenerator: Backend {
                 let res = self.call_intrinsic(intrinsic!(match_pat_loc), &mut [rt, lv, rv])?;
                 self.bind_val(dst.reflect(), res)
             }
+            MatchCapturesInt(dst, s, pat, arr) => {
+                let rt = self.runtime_val();
+                let sv = self.get_val(s.reflect())?;
+                let patv = self.get_val(pat.reflect())?;
+                let arrv = self.get_val(arr.reflect())?;
+                let res = self.call_intrinsic(
+                    intrinsic!(match_pat_captures_int),
+                    &mut [rt, sv, patv, arrv],
+                )?;
+                self.bind_val(dst.reflect(), res)
+            }
+            MatchCapturesStr(dst, s, pat, arr) => {
+                let rt = self.runtime_val();
+                let sv = self.get_val(s.reflect())?;
+                let patv = self.get_val(pat.reflect())?;
+                let arrv = self.get_val(arr.reflect())?;
+                let res = self.call_intrinsic(
+                    intrinsic!(match_pat_captures_str),
+                    &mut [rt, sv, patv, arrv],
+                )?;
+                self.bind_val(dst.reflect(), res)
+            }
             IsMatch(dst, l, r) => {
                 let lv = self.get_val(l.reflect())?;
                 let rv = self.get_val(r.reflect())?;
@@ -729,8 +852,20 @@ pub(crate) trait CodeGenerator: Backend {
                     self.call_intrinsic(intrinsic!(subst_all), &mut [rt, patv, sv, in_sv])?;
                 self.bind_val(res.reflect(), resv)
             }
+            GenSub(res, pat, s, how, in_s) => {
+                let rt = self.runtime_val();
+                let patv = self.get_val(pat.reflect())?;
+                let sv = self.get_val(s.reflect())?;
+                let howv = self.get_val(how.reflect())?;
+                let in_sv = self.get_val(in_s.reflect())?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(gensub), &mut [rt, patv, sv, howv, in_sv])?;
+                self.bind_val(res.reflect(), resv)
+            }
             EscapeCSV(dst, s) => self.unop(intrinsic!(escape_csv), dst, s),
             EscapeTSV(dst, s) => self.unop(intrinsic!(escape_tsv), dst, s),
+            EscapeJSON(dst, s) => self.unop(intrinsic!(escape_json), dst, s),
+            FloatToJSON(dst, x) => self.unop(intrinsic!(float_to_json), dst, x),
             Substr(res, base, l, r) => {
                 let basev = self.get_val(base.reflect())?;
                 let lv = self.get_val(l.reflect())?;
@@ -753,6 +888,26 @@ pub(crate) trait CodeGenerator: Backend {
             LTEStr(res, l, r) => self.binop(intrinsic!(str_lte), res, l, r),
             GTEStr(res, l, r) => self.binop(intrinsic!(str_gte), res, l, r),
             EQStr(res, l, r) => self.binop(intrinsic!(str_eq), res, l, r),
+            LTStrNum(res, l, r) => self.binop(intrinsic!(strnum_lt), res, l, r),
+            GTStrNum(res, l, r) => self.binop(intrinsic!(strnum_gt), res, l, r),
+            LTEStrNum(res, l, r) => self.binop(intrinsic!(strnum_lte), res, l, r),
+            GTEStrNum(res, l, r) => self.binop(intrinsic!(strnum_gte), res, l, r),
+            EQStrNum(res, l, r) => self.binop(intrinsic!(strnum_eq), res, l, r),
+            LTStrNumFloat(res, l, r) => {
+                self.strnum_float_cmp(intrinsic!(strnum_float_lt), res, l, r)
+            }
+            GTStrNumFloat(res, l, r) => {
+                self.strnum_float_cmp(intrinsic!(strnum_float_gt), res, l, r)
+            }
+            LTEStrNumFloat(res, l, r) => {
+                self.strnum_float_cmp(intrinsic!(strnum_float_lte), res, l, r)
+            }
+            GTEStrNumFloat(res, l, r) => {
+                self.strnum_float_cmp(intrinsic!(strnum_float_gte), res, l, r)
+            }
+            EQStrNumFloat(res, l, r) => {
+                self.strnum_float_cmp(intrinsic!(strnum_float_eq), res, l, r)
+            }
             SetColumn(dst, src) => {
                 let rt = self.runtime_val();
                 let srcv = self.get_val(src.reflect())?;
@@ -789,6 +944,21 @@ pub(crate) trait CodeGenerator: Backend {
                     self.call_intrinsic(intrinsic!(join_cols), &mut [rt, startv, endv, sepv])?;
                 self.bind_val(dst.reflect(), resv)
             }
+            JoinJSON(dst, keyed) => {
+                let rt = self.runtime_val();
+                let keyedv = self.get_val(keyed.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(join_json), &mut [rt, keyedv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            JSONMember(dst, col, pos, val) => {
+                let rt = self.runtime_val();
+                let colv = self.get_val(col.reflect())?;
+                let posv = self.get_val(pos.reflect())?;
+                let valv = self.get_val(val.reflect())?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(json_member), &mut [rt, colv, posv, valv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             SplitInt(flds, to_split, arr, pat) => {
                 let rt = self.runtime_val();
                 let tsv = self.get_val(to_split.reflect())?;
@@ -810,32 +980,45 @@ pub(crate) trait CodeGenerator: Backend {
             Printf { output, fmt, args } => self.printf(output, fmt, &args[..]),
             Sprintf { dst, fmt, args } => self.sprintf(dst, fmt, &args[..]),
             PrintAll { output, args } => self.print_all(output, &args[..]),
-            Close(file) => {
+            Close(file, how) => {
                 let rt = self.runtime_val();
                 let filev = self.get_val(file.reflect())?;
-                self.call_void(external!(close_file), &mut [rt, filev])?;
+                let howv = self.get_val(how.reflect())?;
+                self.call_void(external!(close_file), &mut [rt, filev, howv])?;
                 Ok(())
             }
+            Fflush(dst, file) => {
+                let rt = self.runtime_val();
+                let filev = self.get_val(file.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(flush_file), &mut [rt, filev])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             RunCmd(dst, cmd) => self.unop(intrinsic!(run_system), dst, cmd),
+            RunExec(dst, cmd) => self.unop(intrinsic!(run_exec), dst, cmd),
+            ExecCmd(dst, argv) => {
+                let rt = self.runtime_val();
+                let argvv = self.get_val(argv.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(exec_cmd), &mut [rt, argvv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             Exit(code) => {
                 let rt = self.runtime_val();
                 let codev = self.get_val(code.reflect())?;
                 self.call_void(external!(exit), &mut [rt, codev])?;
                 Ok(())
             }
-            ReadErr(dst, file, is_file) => {
+            ReadErr(dst, file, spec) => {
                 let rt = self.runtime_val();
                 let filev = self.get_val(file.reflect())?;
-                let is_filev = self.const_int(*is_file as i64);
-                let resv = self.call_intrinsic(intrinsic!(read_err), &mut [rt, filev, is_filev])?;
+                let specv = self.const_int(*spec as i64);
+                let resv = self.call_intrinsic(intrinsic!(read_err), &mut [rt, filev, specv])?;
                 self.bind_val(dst.reflect(), resv)
             }
-            NextLine(dst, file, is_file) => {
+            NextLine(dst, file, spec) => {
                 let rt = self.runtime_val();
                 let filev = self.get_val(file.reflect())?;
-                let is_filev = self.const_int(*is_file as i64);
-                let resv =
-                    self.call_intrinsic(intrinsic!(next_line), &mut [rt, filev, is_filev])?;
+                let specv = self.const_int(*spec as i64);
+                let resv = self.call_intrinsic(intrinsic!(next_line), &mut [rt, filev, specv])?;
                 self.bind_val(dst.reflect(), resv)
             }
             ReadErrStdin(dst) => {
@@ -863,6 +1046,12 @@ pub(crate) trait CodeGenerator: Backend {
                 self.call_void(external!(update_used_fields), &mut [rt])?;
                 Ok(())
             }
+            SetJSONKey(col) => {
+                let rt = self.runtime_val();
+                let colv = self.get_val(col.reflect())?;
+                self.call_void(external!(set_json_key), &mut [rt, colv])?;
+                Ok(())
+            }
             SetFI(key, val) => {
                 // We could probably get away without an extra intrinsic here, but this way we can
                 // avoid repeated refs and drops of the FI variable outside of the existing
@@ -895,6 +1084,34 @@ pub(crate) trait CodeGenerator: Backend {
             ),
             Delete { map_ty, map, key } => self.delete_map((*map, *map_ty), (*key, map_ty.key()?)),
             Clear { map_ty, map } => self.clear_map((*map, *map_ty)),
+            Asort {
+                src_ty,
+                src,
+                dst_ty,
+                dst,
+                how,
+                dst_len,
+            } => self.asort_map(
+                (*src, *src_ty),
+                (*dst, *dst_ty),
+                how.reflect(),
+                dst_len.reflect(),
+                false,
+            ),
+            Asorti {
+                src_ty,
+                src,
+                dst_ty,
+                dst,
+                how,
+                dst_len,
+            } => self.asort_map(
+                (*src, *src_ty),
+                (*dst, *dst_ty),
+                how.reflect(),
+                dst_len.reflect(),
+                true,
+            ),
             Len { map_ty, map, dst } => self.len_map((*map, *map_ty), (*dst, compile::Ty::Int)),
             Store {
                 map_ty,
@@ -985,6 +1202,19 @@ pub(crate) trait CodeGenerator: Backend {
                 self.call_void(external!(store_var_strmap), &mut [rt, varv, srcv])?;
                 Ok(())
             }
+            LoadVarStrStrMap(dst, var) => {
+                let rt = self.runtime_val();
+                let varv = self.const_int(*var as i64);
+                let res = self.call_intrinsic(intrinsic!(load_var_strstrmap), &mut [rt, varv])?;
+                let dref = dst.reflect();
+                self.bind_val(dref, res)
+            }
+            StoreVarStrStrMap(var, src) => {
+                let rt = self.runtime_val();
+                let varv = self.const_int(*var as i64);
+                let srcv = self.get_val(src.reflect())?;
+                self.call_void(external!(store_var_strstrmap), &mut [rt, varv, srcv])
+            }
             LoadSlot { ty, dst, slot } => self.load_slot((*dst, *ty), *slot),
             StoreSlot { ty, src, slot } => self.store_slot((*src, *ty), *slot),
             Mov(ty, dst, src) => self.mov(*ty, *dst, *src),
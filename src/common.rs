@@ -18,9 +18,12 @@ pub enum ExecutionStrategy {
     /// Execute the script in a single thread. This is the default.
     Serial,
     /// Attempt to parallelize the script, breaking the input into chunks of records with different
-    /// worker threads processing different chunks.
-    // TODO: support multiple readers producing chunks from multiple files, if possible.
+    /// worker threads processing different chunks. Input files are chunked one after another, so
+    /// chunks from any file may go to any worker.
     ShardPerRecord,
+    /// Like ShardPerRecord, but write standard output in the order of the input records, as a
+    /// serial execution would.
+    ShardPerRecordOrdered,
     /// Attempt to parallelize the script, where multiple worker threads each process a file at a
     /// time.
     ShardPerFile,
@@ -30,14 +33,18 @@ impl ExecutionStrategy {
     pub fn num_workers(&self) -> usize {
         use ExecutionStrategy::*;
         match self {
-            ShardPerFile | ShardPerRecord => num_cpus::get(),
+            ShardPerFile | ShardPerRecord | ShardPerRecordOrdered => num_cpus::get(),
             Serial => 1,
         }
     }
+    /// Whether output must be written in input order.
+    pub fn ordered(&self) -> bool {
+        matches!(self, ExecutionStrategy::ShardPerRecordOrdered)
+    }
     pub fn stage(&self) -> Stage<()> {
         use ExecutionStrategy::*;
         match self {
-            ShardPerRecord | ShardPerFile => Stage::Par {
+            ShardPerRecord | ShardPerRecordOrdered | ShardPerFile => Stage::Par {
                 begin: None,
                 main_loop: None,
                 end: None,
@@ -388,6 +395,10 @@ pub enum FileSpec {
     Trunc = 0,
     Append = 1,
     Cmd = 2,
+    // `|&`: write to a coprocess.
+    Coproc = 3,
+    // `| exec_cmd(argv)`: write to a command run without a shell.
+    Exec = 4,
 }
 
 #[derive(Debug)]
@@ -408,6 +419,38 @@ impl std::convert::TryFrom<i64> for FileSpec {
             Ok(FileSpec::Append)
         } else if i == FileSpec::Cmd as i64 {
             Ok(FileSpec::Cmd)
+        } else if i == FileSpec::Coproc as i64 {
+            Ok(FileSpec::Coproc)
+        } else if i == FileSpec::Exec as i64 {
+            Ok(FileSpec::Exec)
+        } else {
+            Err(InvalidFileSpec)
+        }
+    }
+}
+
+/// The source of a `getline` that reads from somewhere other than the main input: `getline <
+/// file`, `cmd | getline`, `cmd |& getline` or `exec_cmd(argv) | getline`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(i64)]
+pub enum InputSpec {
+    Cmd = 0,
+    File = 1,
+    Coproc = 2,
+    Exec = 3,
+}
+
+impl std::convert::TryFrom<i64> for InputSpec {
+    type Error = InvalidFileSpec;
+    fn try_from(i: i64) -> std::result::Result<InputSpec, InvalidFileSpec> {
+        if i == InputSpec::Cmd as i64 {
+            Ok(InputSpec::Cmd)
+        } else if i == InputSpec::File as i64 {
+            Ok(InputSpec::File)
+        } else if i == InputSpec::Coproc as i64 {
+            Ok(InputSpec::Coproc)
+        } else if i == InputSpec::Exec as i64 {
+            Ok(InputSpec::Exec)
         } else {
             Err(InvalidFileSpec)
         }
@@ -5,7 +5,7 @@ use crate::codegen;
 #[cfg(feature = "llvm_backend")]
 use crate::codegen::llvm;
 use crate::common::{
-    CancelSignal, CompileError, Either, Graph, NodeIx, NumTy, Result, Stage, WorkList,
+    CancelSignal, CompileError, Either, Graph, InputSpec, NodeIx, NumTy, Result, Stage, WorkList,
 };
 use crate::cross_stage;
 use crate::input_taint::TaintedStringAnalysis;
@@ -158,10 +158,13 @@ fn visit_string_constant_analysis<'a>(
 
 pub(crate) fn bytecode<'a, LR: runtime::LineReader>(
     ctx: &mut cfg::ProgramContext<'a, &'a str>,
-    reader: LR,
+    mut reader: LR,
     ff: impl runtime::writers::FileFactory,
     num_workers: usize,
 ) -> Result<bytecode::Interp<'a, LR>> {
+    if ctx.file_events {
+        reader.set_file_events();
+    }
     Typer::init_from_ctx(ctx)?.to_interp(reader, ff, num_workers)
 }
 
@@ -205,11 +208,14 @@ pub(crate) fn compile_llvm<'a>(
 #[cfg(feature = "llvm_backend")]
 pub(crate) fn run_llvm<'a>(
     ctx: &mut cfg::ProgramContext<'a, &'a str>,
-    reader: impl codegen::intrinsics::IntoRuntime,
+    mut reader: impl codegen::intrinsics::IntoRuntime,
     ff: impl runtime::writers::FileFactory,
     cfg: llvm::Config,
     cancel_signal: CancelSignal,
 ) -> Result<()> {
+    if ctx.file_events {
+        reader.set_file_events();
+    }
     use llvm::Generator;
     let mut typer = Typer::init_from_ctx(ctx)?;
     let used_fields = typer.used_fields.clone();
@@ -223,6 +229,7 @@ pub(crate) fn run_llvm<'a>(
             &used_fields,
             named_cols,
             cfg.num_workers,
+            typer.posix_regex,
             cancel_signal,
         )
     }
@@ -230,11 +237,14 @@ pub(crate) fn run_llvm<'a>(
 
 pub(crate) fn run_cranelift<'a>(
     ctx: &mut cfg::ProgramContext<'a, &'a str>,
-    reader: impl codegen::intrinsics::IntoRuntime,
+    mut reader: impl codegen::intrinsics::IntoRuntime,
     ff: impl runtime::writers::FileFactory,
     cfg: codegen::Config,
     cancel_signal: CancelSignal,
 ) -> Result<()> {
+    if ctx.file_events {
+        reader.set_file_events();
+    }
     use codegen::clif::Generator;
     let mut typer = Typer::init_from_ctx(ctx)?;
     let used_fields = typer.used_fields.clone();
@@ -248,6 +258,7 @@ pub(crate) fn run_cranelift<'a>(
             &used_fields,
             named_cols,
             cfg.num_workers,
+            typer.posix_regex,
             cancel_signal,
         )
     }
@@ -274,6 +285,17 @@ pub(crate) enum HighLevel {
 struct Registers {
     stats: RegStatuses,
     globals: HashMap<Ident, (u32, Ty)>,
+    // Str registers holding strnums (see the types module), which compare numerically when they
+    // look like numbers.
+    strnums: HashSet<NumTy>,
+}
+
+// Whether the program ever assigns to CONVFMT or OFMT. If it does not, we can convert floats to
+// strings without consulting the current format.
+#[derive(Default, Debug, Copy, Clone)]
+struct FloatFormats {
+    convfmt: bool,
+    ofmt: bool,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -361,6 +383,9 @@ pub(crate) struct Typer<'a> {
     // For analysis passes that introspect into the set of constant string values that will
     // dynamically be assigned to a register
     string_constants: Option<StringConstantAnalysis<'a>>,
+    // Whether regular expressions are POSIX EREs (see runtime::ere).
+    posix_regex: bool,
+    float_fmts: FloatFormats,
     // Not used for bytecode generation.
     callgraph: Graph<HashSet<(NumTy, Ty)>, ()>,
 
@@ -448,6 +473,7 @@ struct View<'a, 'b> {
     local_globals: &'b HashSet<NumTy>,
     arity: &'b HashMap<NumTy, NumTy>,
     func_info: &'b Vec<FuncInfo>,
+    float_fmts: FloatFormats,
     // The current basic block being filled; It'll be swaped into `frame.cfg` as we translate a
     // given function cfg.
     stream: &'b mut Node<'a>,
@@ -543,6 +569,7 @@ impl<'a> Typer<'a> {
             ff,
             &self.used_fields,
             cols,
+            self.posix_regex,
         ))
     }
 
@@ -718,7 +745,14 @@ impl<'a> Typer<'a> {
         // Type-check the code, then initialize a Typer, assigning registers to local
         // and global variables.
 
-        let mut gen = Typer::default();
+        let mut gen = Typer {
+            float_fmts: FloatFormats {
+                convfmt: pc.assigns_builtin(builtins::Variable::CONVFMT),
+                ofmt: pc.assigns_builtin(builtins::Variable::OFMT),
+            },
+            posix_regex: pc.posix_regex,
+            ..Default::default()
+        };
         if !pc.allow_arbitrary_commands {
             gen.taint_analysis = Some(Default::default());
         }
@@ -730,7 +764,11 @@ impl<'a> Typer<'a> {
                 },
             ));
         }
-        let types::TypeInfo { var_tys, func_tys } = types::get_types(pc)?;
+        let types::TypeInfo {
+            var_tys,
+            func_tys,
+            strnums,
+        } = types::get_types(pc)?;
         let local_globals = pc.local_globals();
         macro_rules! init_entry {
             ($v:expr, $func_id:expr, $args:expr) => {
@@ -777,6 +815,9 @@ impl<'a> Typer<'a> {
                     RegStatus::Local
                 },
             );
+            if *ty == Ty::Str && strnums.contains(&(*id, *func_id, args.clone())) {
+                gen.regs.strnums.insert(reg);
+            }
             if let Some(old) = map.insert(*id, (reg, *ty)) {
                 return err!(
                     "internal error: duplicate entries for same local in types at id={:?}; {:?} vs {:?}",
@@ -801,6 +842,7 @@ impl<'a> Typer<'a> {
                 arity: &gen.arity,
                 local_globals: &gen.local_globals,
                 func_info: &gen.func_info,
+                float_fmts: gen.float_fmts,
                 stream: &mut stream,
             }
             .process_function(&pc.funcs[src_func])?;
@@ -860,6 +902,13 @@ impl<'a> Typer<'a> {
                     let text = std::str::from_utf8(strs[0]).map_err(|e| {
                         CompileError(format!("regex patterns must be valid UTF-8: {}", e))
                     })?;
+                    let translated;
+                    let text = if self.posix_regex {
+                        translated = runtime::ere::translate(text)?;
+                        translated.as_str()
+                    } else {
+                        text
+                    };
                     let re = Arc::new(Regex::new(text).map_err(|err| {
                         CompileError(format!("regex parse error during compilation: {}", err))
                     })?);
@@ -1056,6 +1105,13 @@ impl<'a, 'b> View<'a, 'b> {
                                 self.convert(dst, Ty::Int, reg, ty)?;
                                 reg = dst;
                             }
+                            Ty::Str if self.regs.strnums.contains(&reg) => {
+                                let is_null = self.regs.stats.reg_of_ty(Ty::Int);
+                                self.strnum_is_null(is_null, reg);
+                                let dst = self.regs.stats.reg_of_ty(Ty::Int);
+                                self.pushl(LL::Not(dst.into(), is_null.into()));
+                                reg = dst;
+                            }
                             Ty::Str => {
                                 let dst = self.regs.stats.reg_of_ty(Ty::Int);
                                 self.pushl(LL::LenStr(dst.into(), reg.into()));
@@ -1108,6 +1164,50 @@ impl<'a, 'b> View<'a, 'b> {
         (res_reg, res_ty, status)
     }
 
+    // Strnums are "false" when they are empty or equal to 0. Store 1 in `dst` if the strnum in
+    // `reg` is false, and 0 otherwise.
+    fn strnum_is_null(&mut self, dst: u32, reg: u32) {
+        let empty = self.regs.stats.reg_of_ty(Ty::Str);
+        self.pushl(LL::StoreConstStr(empty.into(), Default::default()));
+        self.pushl(LL::EQStrNum(dst.into(), reg.into(), empty.into()));
+    }
+
+    // Compare the strnum in `strnum` with the number in `num`, storing the result in `dst_reg`.
+    fn strnum_float_cmp(
+        &mut self,
+        dst_reg: u32,
+        dst_ty: Ty,
+        bf: &builtins::Function,
+        strnum: u32,
+        mut num: u32,
+        num_ty: Ty,
+    ) -> Result<()> {
+        use crate::ast::Binop::*;
+        if dst_reg == UNUSED {
+            return Ok(());
+        }
+        if num_ty != Ty::Float {
+            let conv = self.regs.stats.reg_of_ty(Ty::Float);
+            self.convert(conv, Ty::Float, num, num_ty)?;
+            num = conv;
+        }
+        let res = if dst_ty == Ty::Int {
+            dst_reg
+        } else {
+            self.regs.stats.reg_of_ty(Ty::Int)
+        };
+        let (res_r, l, r) = (res.into(), strnum.into(), num.into());
+        self.pushl(match bf {
+            builtins::Function::Binop(LT) => LL::LTStrNumFloat(res_r, l, r),
+            builtins::Function::Binop(GT) => LL::GTStrNumFloat(res_r, l, r),
+            builtins::Function::Binop(LTE) => LL::LTEStrNumFloat(res_r, l, r),
+            builtins::Function::Binop(GTE) => LL::GTEStrNumFloat(res_r, l, r),
+            builtins::Function::Binop(EQ) => LL::EQStrNumFloat(res_r, l, r),
+            _ => return err!("unexpected strnum comparison {:?}", bf),
+        });
+        self.convert(dst_reg, dst_ty, res, Ty::Int)
+    }
+
     fn pushl(&mut self, i: LL<'a>) {
         // NB: unlike pushr, this isn't the sole entrypoint for adding LLs to the stream. See also
         // the load_slots and store_slots functions.
@@ -1194,7 +1294,7 @@ impl<'a, 'b> View<'a, 'b> {
             (Str, Int) => LL::IntToStr(dst_reg.into(), src_reg.into()),
 
             (Int, Float) => LL::FloatToInt(dst_reg.into(), src_reg.into()),
-            (Str, Float) => LL::FloatToStr(dst_reg.into(), src_reg.into()),
+            (Str, Float) => self.float_to_str(dst_reg, src_reg, builtins::Variable::CONVFMT),
 
             (Int, Str) => LL::StrToInt(dst_reg.into(), src_reg.into()),
             (Float, Str) => LL::StrToFloat(dst_reg.into(), src_reg.into()),
@@ -1213,6 +1313,20 @@ impl<'a, 'b> View<'a, 'b> {
         Ok(())
     }
 
+    // Convert a float to a string using the format in `var`. We only consult the variable if the
+    // program assigns to it; otherwise the default format is in effect.
+    fn float_to_str(&self, dst_reg: u32, src_reg: u32, var: builtins::Variable) -> LL<'a> {
+        let custom = match var {
+            builtins::Variable::OFMT => self.float_fmts.ofmt,
+            _ => self.float_fmts.convfmt,
+        };
+        if custom {
+            LL::FormatFloat(dst_reg.into(), src_reg.into(), var)
+        } else {
+            LL::FloatToStr(dst_reg.into(), src_reg.into())
+        }
+    }
+
     // Store values into a register at a given type, converting if necessary.
     fn store(&mut self, dst_reg: u32, dst_ty: Ty, src: &PrimVal<'a>) -> Result<()> {
         match src {
@@ -1318,6 +1432,42 @@ impl<'a, 'b> View<'a, 'b> {
             args_tys.push(ty);
         }
 
+        // Comparisons between a strnum and a number depend on whether the strnum looks like a
+        // number at runtime.
+        if let Binop(LT | GT | LTE | GTE | EQ) = bf {
+            let is_strnum =
+                |i: usize| args_tys[i] == Ty::Str && self.regs.strnums.contains(&args_regs[i]);
+            let is_num = |i: usize| matches!(args_tys[i], Ty::Int | Ty::Float);
+            if is_strnum(0) && is_num(1) {
+                return self.strnum_float_cmp(
+                    dst_reg,
+                    dst_ty,
+                    bf,
+                    args_regs[0],
+                    args_regs[1],
+                    args_tys[1],
+                );
+            }
+            if is_num(0) && is_strnum(1) {
+                // Swap the operands, and flip the comparison to match.
+                let flipped = match bf {
+                    Binop(LT) => Binop(GT),
+                    Binop(GT) => Binop(LT),
+                    Binop(LTE) => Binop(GTE),
+                    Binop(GTE) => Binop(LTE),
+                    _ => *bf,
+                };
+                return self.strnum_float_cmp(
+                    dst_reg,
+                    dst_ty,
+                    &flipped,
+                    args_regs[1],
+                    args_regs[0],
+                    args_tys[0],
+                );
+            }
+        }
+
         // Now, perform any necessary conversions if input types do not match the argument types.
         let mut conv_regs: cfg::SmallVec<_> = smallvec![UNUSED; args.len()];
         let (conv_tys, res_ty) = bf.type_sig(&args_tys[..])?;
@@ -1338,6 +1488,11 @@ impl<'a, 'b> View<'a, 'b> {
             }
         }
 
+        // Comparisons between two strnums use strnum_cmp rather than comparing the strings
+        // directly.
+        let strnum_args = conv_tys.iter().all(|ty| *ty == Ty::Str)
+            && conv_regs.iter().all(|reg| self.regs.strnums.contains(reg));
+
         let mut res_reg = if dst_ty == res_ty {
             dst_reg
         } else {
@@ -1372,6 +1527,11 @@ impl<'a, 'b> View<'a, 'b> {
 
         match bf {
             Unop(Column) => self.pushl(LL::GetColumn(res_reg.into(), conv_regs[0].into())),
+            Unop(Not) if strnum_args => {
+                if res_reg != UNUSED {
+                    self.strnum_is_null(res_reg, conv_regs[0])
+                }
+            }
             Unop(Not) => self.pushl(if conv_tys[0] == Ty::Str {
                 LL::NotStr(res_reg.into(), conv_regs[0].into())
             } else {
@@ -1392,6 +1552,11 @@ impl<'a, 'b> View<'a, 'b> {
             Binop(Mod) => gen_op!(Mod, [Float, ModFloat], [Int, ModInt]),
             Binop(Concat) => gen_op!(Concat, [Str, Concat]),
             Binop(IsMatch) => gen_op!(IsMatch, [Str, IsMatch]),
+            Binop(LT) if strnum_args => gen_op!(LT, [Str, LTStrNum]),
+            Binop(GT) if strnum_args => gen_op!(GT, [Str, GTStrNum]),
+            Binop(LTE) if strnum_args => gen_op!(LTE, [Str, LTEStrNum]),
+            Binop(GTE) if strnum_args => gen_op!(GTE, [Str, GTEStrNum]),
+            Binop(EQ) if strnum_args => gen_op!(EQ, [Str, EQStrNum]),
             Binop(LT) => gen_op!(LT, [Float, LTFloat], [Int, LTInt], [Str, LTStr]),
             Binop(GT) => gen_op!(GT, [Float, GTFloat], [Int, GTInt], [Str, GTStr]),
             Binop(LTE) => gen_op!(LTE, [Float, LTEFloat], [Int, LTEInt], [Str, LTEStr]),
@@ -1416,6 +1581,28 @@ impl<'a, 'b> View<'a, 'b> {
                 }
             }
             Match => gen_op!(Match, [Str, Match]),
+            MatchCaptures => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(if conv_tys[2] == Ty::MapIntStr {
+                    LL::MatchCapturesInt(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    )
+                } else if conv_tys[2] == Ty::MapStrStr {
+                    LL::MatchCapturesStr(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    )
+                } else {
+                    return err!("invalid input types to match: {:?}", &conv_tys[..]);
+                })
+            }
             SubstrIndex => gen_op!(SubstrIndex, [Str, SubstrIndex]),
             Contains => {
                 if res_reg != UNUSED {
@@ -1439,6 +1626,7 @@ impl<'a, 'b> View<'a, 'b> {
             }
             UpdateUsedFields => self.pushl(LL::UpdateUsedFields()),
             SetFI => self.pushl(LL::SetFI(conv_regs[0].into(), conv_regs[1].into())),
+            SetJSONKey => self.pushl(LL::SetJSONKey(conv_regs[0].into())),
             System => {
                 if res_reg == UNUSED {
                     res_reg = self.regs.stats.reg_of_ty(res_ty);
@@ -1446,34 +1634,34 @@ impl<'a, 'b> View<'a, 'b> {
                 self.pushl(LL::RunCmd(res_reg.into(), conv_regs[0].into()))
             }
             Exit => self.pushl(LL::Exit(conv_regs[0].into())),
-            ReadErr => {
+            ReadErr | ReadErrCmd | ReadErrCoproc => {
+                let spec = match bf {
+                    ReadErr => InputSpec::File,
+                    ReadErrCmd => InputSpec::Cmd,
+                    _ => InputSpec::Coproc,
+                };
                 if res_reg != UNUSED {
-                    self.pushl(LL::ReadErr(
-                        res_reg.into(),
-                        conv_regs[0].into(),
-                        /*is_file=*/ true,
-                    ))
+                    self.pushl(LL::ReadErr(res_reg.into(), conv_regs[0].into(), spec))
                 }
             }
-            ReadErrCmd => {
-                if res_reg != UNUSED {
-                    self.pushl(LL::ReadErr(
-                        res_reg.into(),
-                        conv_regs[0].into(),
-                        /*is_file=*/ false,
-                    ))
+            Nextline | NextlineCmd | NextlineCoproc => {
+                let spec = match bf {
+                    Nextline => InputSpec::File,
+                    NextlineCmd => InputSpec::Cmd,
+                    _ => InputSpec::Coproc,
+                };
+                self.pushl(LL::NextLine(res_reg.into(), conv_regs[0].into(), spec))
+            }
+            ReadErrExec | NextlineExec => {
+                // Read from exec_cmd(argv), without a shell.
+                let cmd = self.regs.stats.reg_of_ty(Ty::Str);
+                self.pushl(LL::ExecCmd(cmd.into(), conv_regs[0].into()));
+                if *bf == NextlineExec {
+                    self.pushl(LL::NextLine(res_reg.into(), cmd.into(), InputSpec::Exec))
+                } else if res_reg != UNUSED {
+                    self.pushl(LL::ReadErr(res_reg.into(), cmd.into(), InputSpec::Exec))
                 }
             }
-            Nextline => self.pushl(LL::NextLine(
-                res_reg.into(),
-                conv_regs[0].into(),
-                /*is_file=*/ true,
-            )),
-            NextlineCmd => self.pushl(LL::NextLine(
-                res_reg.into(),
-                conv_regs[0].into(),
-                /*is_file=*/ false,
-            )),
             ReadErrStdin => {
                 if res_reg != UNUSED {
                     self.pushl(LL::ReadErrStdin(res_reg.into()))
@@ -1505,6 +1693,17 @@ impl<'a, 'b> View<'a, 'b> {
                     conv_regs[2].into(),
                 ))
             }
+            GenSub => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::GenSub(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                        conv_regs[3].into(),
+                    ))
+                }
+            }
             EscapeCSV => {
                 if res_reg != UNUSED {
                     self.pushl(LL::EscapeCSV(res_reg.into(), conv_regs[0].into()))
@@ -1515,6 +1714,17 @@ impl<'a, 'b> View<'a, 'b> {
                     self.pushl(LL::EscapeTSV(res_reg.into(), conv_regs[0].into()))
                 }
             }
+            EscapeJSON => {
+                if res_reg != UNUSED {
+                    match conv_tys[0] {
+                        Ty::Int => self.pushl(LL::IntToStr(res_reg.into(), conv_regs[0].into())),
+                        Ty::Float => {
+                            self.pushl(LL::FloatToJSON(res_reg.into(), conv_regs[0].into()))
+                        }
+                        _ => self.pushl(LL::EscapeJSON(res_reg.into(), conv_regs[0].into())),
+                    }
+                }
+            }
             ToUpper => {
                 if res_reg != UNUSED {
                     self.pushl(LL::ToUpperAscii(res_reg.into(), conv_regs[0].into()))
@@ -1559,6 +1769,35 @@ impl<'a, 'b> View<'a, 'b> {
                 }
                 self.pushl(LL::ReseedRng(res_reg.into()))
             }
+            Systime => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Systime(res_reg.into()))
+                }
+            }
+            Strftime => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Strftime(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    ))
+                }
+            }
+            Mktime => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Mktime(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            Strptime => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Strptime(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
             Split => {
                 if res_reg == UNUSED {
                     res_reg = self.regs.stats.reg_of_ty(res_ty);
@@ -1660,8 +1899,55 @@ impl<'a, 'b> View<'a, 'b> {
                     return err!("incorrect parameter types for Clear: {:?}", &conv_tys[..]);
                 }
             }
+            Asort | Asorti => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                let (src_ty, src, dst_ty, dst) =
+                    (conv_tys[0], conv_regs[0], conv_tys[1], conv_regs[1]);
+                let (how, dst_len) = (conv_regs[2].into(), res_reg.into());
+                self.pushl(if *bf == Asort {
+                    LL::Asort {
+                        src_ty,
+                        src,
+                        dst_ty,
+                        dst,
+                        how,
+                        dst_len,
+                    }
+                } else {
+                    LL::Asorti {
+                        src_ty,
+                        src,
+                        dst_ty,
+                        dst,
+                        how,
+                        dst_len,
+                    }
+                })
+            }
+            Exec => {
+                // exec(argv) => system(exec_cmd(argv)), but without a shell.
+                let cmd = self.regs.stats.reg_of_ty(Ty::Str);
+                self.pushl(LL::ExecCmd(cmd.into(), conv_regs[0].into()));
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::RunExec(res_reg.into(), cmd.into()))
+            }
+            ExecCmd => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::ExecCmd(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            Fflush => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::Fflush(res_reg.into(), conv_regs[0].into()))
+            }
             Close => {
-                self.pushl(LL::Close(conv_regs[0].into()));
+                self.pushl(LL::Close(conv_regs[0].into(), conv_regs[1].into()));
                 assert_eq!(res_ty, Ty::Str);
                 if res_reg != UNUSED {
                     self.pushl(LL::StoreConstStr(res_reg.into(), Default::default()));
@@ -1685,6 +1971,23 @@ impl<'a, 'b> View<'a, 'b> {
                     ))
                 }
             }
+            JoinJSON => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::JoinJSON(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            JSONMember => {
+                // Members are checked for repeated keys, so we emit them even if they are unused.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::JSONMember(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                    conv_regs[2].into(),
+                ))
+            }
             JoinCols => {
                 if res_reg != UNUSED {
                     self.pushl(LL::JoinColumns(
@@ -1878,6 +2181,7 @@ impl<'a, 'b> View<'a, 'b> {
                     Ty::Int => LL::LoadVarInt(target_reg.into(), *bv),
                     Ty::MapIntStr => LL::LoadVarIntMap(target_reg.into(), *bv),
                     Ty::MapStrInt => LL::LoadVarStrMap(target_reg.into(), *bv),
+                    Ty::MapStrStr => LL::LoadVarStrStrMap(target_reg.into(), *bv),
                     _ => unreachable!(),
                 });
                 self.convert(dst_reg, dst_ty, target_reg, target_ty)?
@@ -1928,6 +2232,7 @@ impl<'a, 'b> View<'a, 'b> {
                     Str => LL::StoreVarStr(*v, reg.into()),
                     MapIntStr => LL::StoreVarIntMap(*v, reg.into()),
                     MapStrInt => LL::StoreVarStrMap(*v, reg.into()),
+                    MapStrStr => LL::StoreVarStrStrMap(*v, reg.into()),
                     Int => LL::StoreVarInt(*v, reg.into()),
                     _ => return err!("unexpected type for variable {} : {:?}", v, ty),
                 });
@@ -1944,7 +2249,16 @@ impl<'a, 'b> View<'a, 'b> {
                 let mut arg_regs = Vec::with_capacity(args.len());
                 for a in args {
                     let (a_reg, a_ty) = self.get_reg(a)?;
-                    arg_regs.push(self.ensure_ty(a_reg, a_ty, Ty::Str)?.into());
+                    let a_reg = if a_ty == Ty::Float {
+                        // print uses OFMT rather than CONVFMT
+                        let s_reg = self.regs.stats.reg_of_ty(Ty::Str);
+                        let conv = self.float_to_str(s_reg, a_reg, builtins::Variable::OFMT);
+                        self.pushl(conv);
+                        s_reg
+                    } else {
+                        self.ensure_ty(a_reg, a_ty, Ty::Str)?
+                    };
+                    arg_regs.push(a_reg.into());
                 }
                 let out_reg = if let Some((out, append)) = out {
                     // Would use map, but I supposed we have no equivalent to sequenceA_ and/or
@@ -2005,7 +2319,8 @@ fn extract_anchored_literal(text: &str) -> Option<Arc<[u8]>> {
             && matches!(
                 asts[0],
                 Ast::Assertion(Assertion {
-                    kind: AssertionKind::StartLine,
+                    // Translated EREs anchor with \A
+                    kind: AssertionKind::StartLine | AssertionKind::StartText,
                     ..
                 })
             )
@@ -195,6 +195,10 @@ pub(crate) mod boilerplate {
             IntToStr(dst, src) => f(dst.into(), Some(src.into())),
             IntToFloat(dst, src) => f(dst.into(), Some(src.into())),
             FloatToStr(dst, src) => f(dst.into(), Some(src.into())),
+            FormatFloat(dst, src, var) => {
+                f(dst.into(), Some(src.into()));
+                f(dst.into(), Some(Key::Var(*var)));
+            }
             FloatToInt(dst, src) => f(dst.into(), Some(src.into())),
             StrToFloat(dst, src) => f(dst.into(), Some(src.into())),
             LenStr(dst, src) | StrToInt(dst, src) | HexStrToInt(dst, src) => f(dst.into(), Some(src.into())),
@@ -234,6 +238,17 @@ pub(crate) mod boilerplate {
                 f(Key::Rng, Some(new.into()));
             }
             ReseedRng(new) => f(Key::Rng, Some(new.into())),
+            Systime(dst) => f(dst.into(), None),
+            Strftime(dst, fmt, ts, utc) => {
+                f(dst.into(), Some(fmt.into()));
+                f(dst.into(), Some(ts.into()));
+                f(dst.into(), Some(utc.into()));
+            }
+            Mktime(dst, src) => f(dst.into(), Some(src.into())),
+            Strptime(dst, x, y) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(y.into()));
+            }
             Concat(dst, x, y) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
@@ -254,7 +269,29 @@ pub(crate) mod boilerplate {
                 f(dstin.into(), Some(x.into()));
                 f(dstin.into(), Some(y.into()));
             }
-            EscapeTSV(dst, src) | EscapeCSV(dst, src) => f(dst.into(), Some(src.into())),
+            GenSub(dst, x, y, z, w) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(y.into()));
+                f(dst.into(), Some(z.into()));
+                f(dst.into(), Some(w.into()));
+            }
+            MatchCapturesInt(dst1, src1, src2, dst2) => {
+                f(dst1.into(), Some(src1.into()));
+                f(dst1.into(), Some(src2.into()));
+                let (dst2_reg, dst2_ty) = dst2.reflect();
+                f(Key::MapVal(dst2_reg, dst2_ty), Some(src1.into()));
+                f(Key::MapVal(dst2_reg, dst2_ty), Some(src2.into()));
+            }
+            MatchCapturesStr(dst1, src1, src2, dst2) => {
+                f(dst1.into(), Some(src1.into()));
+                f(dst1.into(), Some(src2.into()));
+                f(dst2.into(), Some(src1.into()));
+                f(dst2.into(), Some(src2.into()));
+            }
+            EscapeTSV(dst, src) | EscapeCSV(dst, src) | EscapeJSON(dst, src) => {
+                f(dst.into(), Some(src.into()))
+            }
+            FloatToJSON(dst, src) => f(dst.into(), Some(src.into())),
             Substr(dst, x, y, z) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
@@ -280,15 +317,31 @@ pub(crate) mod boilerplate {
             | GTStr(dst, x, y)
             | LTEStr(dst, x, y)
             | GTEStr(dst, x, y)
-            | EQStr(dst, x, y) => {
+            | EQStr(dst, x, y)
+            | LTStrNum(dst, x, y)
+            | GTStrNum(dst, x, y)
+            | LTEStrNum(dst, x, y)
+            | GTEStrNum(dst, x, y)
+            | EQStrNum(dst, x, y) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
             }
+            LTStrNumFloat(dst, x, y)
+            | GTStrNumFloat(dst, x, y)
+            | LTEStrNumFloat(dst, x, y)
+            | GTEStrNumFloat(dst, x, y)
+            | EQStrNumFloat(dst, x, y) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(y.into()));
+                f(dst.into(), Some(Key::Var(Variable::CONVFMT)));
+            }
             GetColumn(dst, _) => f(dst.into(), None),
             JoinTSV(dst, start, end) | JoinCSV(dst, start, end) => {
                 f(dst.into(), Some(start.into()));
                 f(dst.into(), Some(end.into()));
             }
+            JoinJSON(dst, _) => f(dst.into(), None),
+            JSONMember(dst, _, _, val) => f(dst.into(), Some(val.into())),
             JoinColumns(dst, x, y, z) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
@@ -321,7 +374,11 @@ pub(crate) mod boilerplate {
                     f(dst.into(), Some(Key::Reg(*reg, *ty)));
                 }
             }
-            RunCmd(dst, _) => f(dst.into(), None),
+            RunCmd(dst, _) | RunExec(dst, _) | Fflush(dst, _) => f(dst.into(), None),
+            ExecCmd(dst, argv) => {
+                let (argv_reg, argv_ty) = argv.reflect();
+                f(dst.into(), Some(Key::MapVal(argv_reg, argv_ty)))
+            }
             Lookup {
                 map_ty,
                 dst,
@@ -351,6 +408,16 @@ pub(crate) mod boilerplate {
                 f(Key::MapVal(*map, *map_ty), Some(Key::Reg(reg, ty)));
                 f(Key::Reg(*dst, map_ty.val().unwrap()), Some(Key::MapVal(*map, *map_ty)));
             }
+            Asort { src_ty, src, dst_ty, dst, dst_len, .. } => {
+                f(Key::MapKey(*dst, *dst_ty), None);
+                f(Key::MapVal(*dst, *dst_ty), Some(Key::MapVal(*src, *src_ty)));
+                f(dst_len.into(), None);
+            }
+            Asorti { src_ty, src, dst_ty, dst, dst_len, .. } => {
+                f(Key::MapKey(*dst, *dst_ty), None);
+                f(Key::MapVal(*dst, *dst_ty), Some(Key::MapKey(*src, *src_ty)));
+                f(dst_len.into(), None);
+            }
             IterBegin { map_ty, dst, map } => {
                 f(Key::Reg(*dst, map_ty.key_iter().unwrap()), Some(Key::MapKey(*map, *map_ty)))
             }
@@ -375,6 +442,14 @@ pub(crate) mod boilerplate {
                 f(Key::VarKey(*v), Some(Key::MapKey(reg, ty)));
                 f(Key::VarVal(*v), Some(Key::MapVal(reg, ty)));
             },
+            StoreVarStrStrMap(v, reg) | LoadVarStrStrMap(reg, v) => {
+                let (reg, ty) = reg.reflect();
+
+                f(Key::MapKey(reg, ty), Some(Key::VarKey(*v)));
+                f(Key::MapVal(reg, ty), Some(Key::VarVal(*v)));
+                f(Key::VarKey(*v), Some(Key::MapKey(reg, ty)));
+                f(Key::VarVal(*v), Some(Key::MapVal(reg, ty)));
+            },
             StoreVarStr(v, src) => f(Key::Var(*v), Some(src.into())),
             StoreVarInt(v, src) => f(Key::Var(*v), Some(src.into())),
 
@@ -386,6 +461,7 @@ pub(crate) mod boilerplate {
             | Clear {..}
             | UpdateUsedFields()
             | SetFI(..)
+            | SetJSONKey(..)
             | PrintAll{..}
             | Contains{..} // 0 or 1
             | IterHasNext{..}
@@ -398,7 +474,7 @@ pub(crate) mod boilerplate {
             | Call(_)
             | Ret
             | Printf { .. }
-            | Close(_)
+            | Close(..)
             | NextLineStdinFused()
             | NextFile()
             | SetColumn(_, _)
@@ -56,7 +56,8 @@ impl<'a> Display for PrimStmt<'a> {
                     let redirect = match ap {
                         FileSpec::Trunc => ">",
                         FileSpec::Append => ">>",
-                        FileSpec::Cmd => "|",
+                        FileSpec::Cmd | FileSpec::Exec => "|",
+                        FileSpec::Coproc => "|&",
                     };
                     write!(f, " {} {}", out, redirect)?;
                 }
@@ -76,7 +77,8 @@ impl<'a> Display for PrimStmt<'a> {
                     let redirect = match ap {
                         FileSpec::Trunc => ">",
                         FileSpec::Append => ">>",
-                        FileSpec::Cmd => "|",
+                        FileSpec::Cmd | FileSpec::Exec => "|",
+                        FileSpec::Coproc => "|&",
                     };
                     write!(f, " {} {}", out, redirect)?;
                 }
@@ -154,8 +156,12 @@ impl Display for Function {
             IntFunc(bw) => write!(f, "{}", bw.func_name()),
             ReadErr => write!(f, "hasline"),
             ReadErrCmd => write!(f, "hasline(cmd)"),
+            ReadErrCoproc => write!(f, "hasline(coproc)"),
+            ReadErrExec => write!(f, "hasline(exec_cmd)"),
             Nextline => write!(f, "nextline"),
             NextlineCmd => write!(f, "nextline(cmd)"),
+            NextlineCoproc => write!(f, "nextline(coproc)"),
+            NextlineExec => write!(f, "nextline(exec_cmd)"),
             ReadErrStdin => write!(f, "hasline(stdin)"),
             NextlineStdin => write!(f, "nextline(stdin)"),
             ReadLineStdinFused => write!(f, "stdin-fused"),
@@ -167,15 +173,28 @@ impl Display for Function {
             Delete => write!(f, "delete"),
             Clear => write!(f, "clear"),
             Close => write!(f, "close"),
-            Match => write!(f, "match"),
+            Fflush => write!(f, "fflush"),
+            Exec => write!(f, "exec"),
+            ExecCmd => write!(f, "exec_cmd"),
+            Asort => write!(f, "asort"),
+            Asorti => write!(f, "asorti"),
+            Match | MatchCaptures => write!(f, "match"),
             SubstrIndex => write!(f, "index"),
             Sub => write!(f, "sub"),
             GSub => write!(f, "gsub"),
+            GenSub => write!(f, "gensub"),
+            Systime => write!(f, "systime"),
+            Strftime => write!(f, "strftime"),
+            Mktime => write!(f, "mktime"),
+            Strptime => write!(f, "strptime"),
             EscapeCSV => write!(f, "escape_csv"),
             EscapeTSV => write!(f, "escape_tsv"),
+            EscapeJSON => write!(f, "escape_json"),
             JoinCSV => write!(f, "join_csv"),
             JoinTSV => write!(f, "join_tsv"),
             JoinCols => write!(f, "join_fields"),
+            JoinJSON => write!(f, "join_json"),
+            JSONMember => write!(f, "json_member"),
             Substr => write!(f, "substr"),
             ToInt => write!(f, "int"),
             HexToInt => write!(f, "hex"),
@@ -185,6 +204,7 @@ impl Display for Function {
             System => write!(f, "system"),
             UpdateUsedFields => write!(f, "update_used_fields"),
             SetFI => write!(f, "set-FI"),
+            SetJSONKey => write!(f, "set-json-key"),
             ToLower => write!(f, "tolower"),
             ToUpper => write!(f, "toupper"),
             IncMap => write!(f, "inc_map"),
@@ -214,6 +234,13 @@ impl Display for Variable {
                 RLENGTH => "RLENGTH",
                 PID => "PID",
                 FI => "FI",
+                CONVFMT => "CONVFMT",
+                OFMT => "OFMT",
+                ENVIRON => "ENVIRON",
+                RT => "RT",
+                FIELDWIDTHS => "FIELDWIDTHS",
+                FPAT => "FPAT",
+                PROCINFO => "PROCINFO",
             }
         )
     }
@@ -279,6 +306,8 @@ impl<'a> Display for lexer::Tok<'a> {
             Begin => "BEGIN",
             Prepare => "PREPARE",
             End => "END",
+            BeginFile => "BEGINFILE",
+            EndFile => "ENDFILE",
             Break => "break",
             Continue => "continue",
             Next => "next",
@@ -308,6 +337,7 @@ impl<'a> Display for lexer::Tok<'a> {
 
             Getline => "getline",
             Pipe => "|",
+            PipeAmp => "|&",
             Assign => "=",
             Add => "+",
             AddAssign => "+=",
@@ -57,11 +57,12 @@ macro_rules! with_reader {
             cfg::SepAssign::Potential {
                 field_sep,
                 record_sep,
+                field_pat,
             } => {
                 // TODO: unify this code with the code in main.
                 let field_sep = field_sep.unwrap_or(b" ");
                 let record_sep = record_sep.unwrap_or(b"\n");
-                if field_sep.len() == 1 && record_sep.len() == 1 {
+                if field_pat.is_none() && field_sep.len() == 1 && record_sep.len() == 1 {
                     if field_sep == b" " && record_sep == b"\n" {
                         let $id = simulate_stdin_whitespace($inp);
                         $body
@@ -82,7 +83,7 @@ fn simulate_stdin_singlechar(
     field_sep: u8,
     record_sep: u8,
     inp: impl Into<String>,
-) -> impl IntoRuntime + runtime::LineReader {
+) -> impl IntoRuntime {
     ByteReader::new(
         split_stdin(inp.into()),
         field_sep,
@@ -94,7 +95,7 @@ fn simulate_stdin_singlechar(
     )
 }
 
-fn simulate_stdin_whitespace(inp: impl Into<String>) -> impl IntoRuntime + runtime::LineReader {
+fn simulate_stdin_whitespace(inp: impl Into<String>) -> impl IntoRuntime {
     ByteReader::new_whitespace(
         split_stdin(inp.into()),
         runtime::CHUNK_SIZE,
@@ -118,7 +119,7 @@ fn simulate_stdin_csv(
     ifmt: InputFormat,
     inp: impl Into<String>,
     strat: ExecutionStrategy,
-) -> impl IntoRuntime + runtime::LineReader {
+) -> impl IntoRuntime {
     CSVReader::new(
         split_stdin(inp.into()),
         ifmt,
@@ -129,7 +130,7 @@ fn simulate_stdin_csv(
     )
 }
 
-fn simulate_stdin_regex(inp: impl Into<String>) -> impl IntoRuntime + runtime::LineReader {
+fn simulate_stdin_regex(inp: impl Into<String>) -> impl IntoRuntime {
     simulate_stdin(inp, |reader, name| {
         RegexSplitter::new(
             reader,
@@ -327,7 +328,7 @@ pub(crate) fn parse_program<'a, 'inp>(
             match esc {
                 Escaper::CSV => program.output_sep = Some(b","),
                 Escaper::TSV => program.output_sep = Some(b"\t"),
-                Escaper::Identity => {}
+                Escaper::JSON | Escaper::Identity => {}
             };
             Ok(a.alloc(program))
         }
@@ -395,7 +396,9 @@ pub(crate) fn run_prog<'a>(
         writeln!(&mut instrs_buf, "\nCFG:").unwrap();
         ctx.dbg_print(&mut instrs_buf).unwrap();
         writeln!(&mut instrs_buf).unwrap();
-        let types::TypeInfo { var_tys, func_tys } = get_types(&ctx)?;
+        let types::TypeInfo {
+            var_tys, func_tys, ..
+        } = get_types(&ctx)?;
         // ident_map : Ident -> &str (but only has globals)
         // ts: Ident -> Type
         //
@@ -593,6 +596,16 @@ mod tests {
         };
     }
 
+    macro_rules! test_program_jsonl {
+        ($desc:ident, $e:expr, $out:expr, @input $inp:expr) => {
+            test_program!(
+                $desc, $e, $out, @input $inp,
+                @types [], @out_fmt Escaper::Identity,
+                @csv Some(InputFormat::JSONL { header: false })
+            );
+        };
+    }
+
     // TODO: extend testing macros to read non-stdout files and test more of those cases.
 
     #[test]
@@ -715,6 +728,33 @@ mod tests {
         ""
     );
 
+    test_program!(
+        null_join_points,
+        r#"BEGIN { if (0) { x=6; }; print "[" x "]"; if (1) { y=6; }; print y; if (c) { z=0; }; print !z, z+1; }"#,
+        "[]\n6\n1 1\n"
+    );
+
+    // Awk prints "[]" here; see the Incompatibilities section of info/types.md.
+    test_program!(
+        null_join_points_global,
+        r#"function f() { x = 6 } BEGIN { if (0) f(); print "[" x "]"; }"#,
+        "[0]\n"
+    );
+
+    test_program!(
+        strnum_comparisons,
+        r#"{ print ($1 > $2), ($1 == $3), ($1 < "9"), ($4 > $2), !$3, !$4; split($0, a); print (a[1] > a[2]); }"#,
+        "1 1 1 1 0 0\n1\n",
+        @input "10 9 10.0 abc\n"
+    );
+
+    test_program!(
+        strnum_number_comparisons,
+        r#"BEGIN { FS = "," } { if (NR == 1) y = $1; else y = 5; print (y < 10), ($2 == 0), ($2 == ""), !$2, ($5 == 0), (10 > $1), ($3 < 2.5); }"#,
+        "0 0 1 1 1 0 1\n1 1 0 1 1 1 0\n",
+        @input "x,,1\n9,0,2.5\n"
+    );
+
     test_program!(
         rand_basics,
         r#" BEGIN {
@@ -744,6 +784,13 @@ r#"hi,there
         @out_fmt Escaper::TSV
     );
 
+    test_program!(
+        basic_json_render,
+        r#"BEGIN { x = 1; y = 2.5; print x, y, "q\"t\n", log(0); print; }"#,
+        "[1,2.5,\"q\\\"t\\n\",null]\n[]\n",
+        @out_fmt Escaper::JSON
+    );
+
     test_program!(
         basic_multi_file,
         // test some OFS/ORS behavior for good measure
@@ -795,6 +842,49 @@ is the second file
 it has one more line"#
     );
 
+    test_program!(
+        next_in_function,
+        r#"
+        function skip_even() { if ((NR%2) == 0) { next; } return NR; }
+        function check() { return skip_even() * 10; }
+        { x = check(); print "["FILENAME,NR,FNR"]", x, $0; }
+        END { print "done", NR; }"#,
+          r#"[fake_stdin_0 1 1] 10 this is
+[fake_stdin_1 3 1] 30 And this
+[fake_stdin_1 5 3] 50 it has one more line
+done 5
+"#,
+          @input r#"this is
+the first file<<<FILE BREAK>>>And this
+is the second file
+it has one more line"#
+    );
+    test_program!(
+        next_in_function_not_taken,
+        r#"
+        function skip(x) { if (x) { next; } return "kept"; }
+        BEGIN { print skip(0); }
+        { skip($1 == "skip"); print $1; }
+        END { print skip(0), NR; }"#,
+          "kept\na\nb\nkept 3\n",
+          @input "a\nskip\nb\n"
+    );
+    test_program!(
+        next_file_in_function,
+        r#"
+        function skip_file() { nextfile; }
+        NR == 1 { skip_file(); print "unreachable"; }
+        { print "["FILENAME,NR,FNR"]", $0;}"#,
+          r#"[fake_stdin_1 2 1] And this
+[fake_stdin_1 3 2] is the second file
+[fake_stdin_1 4 3] it has one more line
+"#,
+          @input r#"this is
+the first file<<<FILE BREAK>>>And this
+is the second file
+it has one more line"#
+    );
+
     test_program_csv!(
         csv_no_escaping,
         r#"function max(x, y) { return x<y?y:x; }
@@ -818,12 +908,35 @@ it has one more line"#
           "1,2\t,3\"4\n",
           @input r#"help,"1,2\t,3""4",5"#
     );
+    test_program!(
+        csv_no_escape,
+        r#"{ print $2; }"#,
+          "1,2\\t,3\"4\n",
+          @input r#"help,"1,2\t,3""4",5"#,
+          @csv Some(InputFormat::parse("csv;escape=none", false).unwrap())
+    );
     test_program_tsv!(
         tsv_escaping,
         r#"{ print $1,$2,$3; }"#,
         "1 2 3\n1\t23 4 5\n\t6\n",
         @input "1\t2\t3\n1\\t23\t4\t5\\n\\t6\n"
     );
+    test_program!(
+        tsv_no_escaping,
+        r#"{ print $1,$2,$3; }"#,
+        "1 2 3\n1\\t23 4 5\\n\\t6\n",
+        @input "1\t2\t3\n1\\t23\t4\t5\\n\\t6\n",
+        @csv Some(InputFormat::parse("tsv;escape=none", false).unwrap())
+    );
+    test_program_jsonl!(
+        jsonl_basic,
+        r#"{ print NF, $1, $2; s += $3 } END { print s }"#,
+        "3 a x\\\"y\n3 b \n0  \n1 c \n3.5\n",
+        @input r#"{"name": "a", "tag": "x\\\"y", "n": 1}
+{"n": 2.5, "name": "b"}
+
+{"name": "c"}"#
+    );
 
     test_program!(
         tsv_join,
@@ -1208,6 +1321,18 @@ print w,z;
         "232      hello 00\n\n\n232      hello 00 2.56 320\n\n\n"
     );
 
+    test_program!(
+        printf_conversions,
+        r#"BEGIN {
+            printf "%i %u %X %E %G %a\n", 42.9, 7, 255, 1234.5, 0.00001234, 1;
+            printf "[%*d] [%-*.*f] %'d\n", 5, 42, 8, 2, 3.14159, 1234567;
+            printf "%2$s %1$s\n", "world", "hello";
+            printf "%c%c|%05s|%-3c|\n", 65, 321, "ab", "xyz";
+            print sprintf("%1$5.1E %1$s", 12345);
+        }"#,
+        "42 7 FF 1.234500E+03 1.234E-05 0x1p+0\n[   42] [3.14    ] 1,234,567\nhello world\nAA|   ab|x  |\n1.2E+04 12345\n"
+    );
+
     test_program!(
         comma_patterns,
         r#"
@@ -1294,6 +1419,27 @@ this as well"#
         @input "x 2.5\n"
     );
 
+    test_program!(
+        convfmt_ofmt,
+        r#"BEGIN { OFMT="%.2f"; CONVFMT="%.3f"; }
+        { x = $2 + 0.14159; y = x ""; m[$2 + 0.14159] = 1; print x, y, ("3.142" in m), $1 / 2; }"#,
+        "3.14 3.142 1 2\n",
+        @input "4 3\n"
+    );
+
+    test_program!(
+        ofmt_large_integers,
+        r#"BEGIN { OFMT="%.2f"; CONVFMT="%.3f"; x = 1e20; print 1e20, -x, (x ""), 2^62, 2^63, 1e20 + 0.5; }"#,
+        "1e20 -1e20 1e20 4611686018427387904 9.223372036854776e18 1e20\n"
+    );
+
+    test_program!(
+        convfmt_printf_s,
+        r#"BEGIN { CONVFMT="%.3f"; x = 3.14159; printf "%s %s|%6s|\n", x, 2.0, x;
+        s = sprintf("%s", x); print s; }"#,
+        "3.142 2| 3.142|\n3.142\n"
+    );
+
     test_program!(
         bitwise_operators,
         r#"BEGIN {
@@ -1369,6 +1515,181 @@ this as well"#
         "1 2 3\n"
     );
 
+    test_program!(
+        subsep_membership,
+        r#"{ total[$1, $2] += $3; }
+        END {
+            print (("a", "b") in total), (("b", "a") in total), total["a", "b"];
+            SUBSEP = ":";
+            total["x", "y"] = 1;
+            print (("x" ":" "y") in total), !(("x", "z") in total);
+            delete total["a", "c"];
+            print length(total);
+        }"#,
+        "1 0 6\n1 1\n3\n",
+        @input "a b 1\na c 2\na b 5\n"
+    );
+
+    test_program!(
+        paragraph_mode,
+        r#"BEGIN { RS = ""; } { print NR, NF, $1, $NF; }"#,
+        "1 4 name 3\n2 4 name 4\n",
+        @input "\n\nname a\nage 3\n\n\n\nname b\nage 4\n"
+    );
+
+    test_program!(
+        record_terminator,
+        r#"BEGIN { RS = ";+"; } { printf "%s|%s|", $0, RT; } END { print NR; }"#,
+        "a|;;|b|;|c||3\n",
+        @input "a;;b;c"
+    );
+
+    test_program!(
+        fixed_width_fields,
+        r#"BEGIN { FIELDWIDTHS = "3 1:2 *"; } { print NF, $1, $2, $3; }"#,
+        "3 abc ef ghij\n2 xyz 12 \n1 ab  \n",
+        @input "abcdefghij\nxyz012\nab\n"
+    );
+
+    test_program!(
+        fixed_width_then_fs,
+        r#"BEGIN { FIELDWIDTHS = "2 2"; } { print NF, $2; FS = " "; }"#,
+        "2 cd\n3 b\n",
+        @input "abcd ef\na b c\n"
+    );
+
+    test_program!(
+        field_pattern,
+        r#"BEGIN { FPAT = "(\"[^\"]*\")|([^,]*)"; } { print NF, $1, $2, $3, $4; }"#,
+        "4 abc \"x,y\"  z\n1 q   \n",
+        @input "abc,\"x,y\",,z\nq\n"
+    );
+
+    // Alternatives match leftmost-first rather than longest-first, so gawk's example only splits
+    // quoted fields correctly once the quoted alternative comes first.
+    test_program!(
+        field_pattern_alternation_order,
+        r#"BEGIN { FPAT = "([^,]*)|(\"[^\"]+\")"; } { print NF, $2; FPAT = "(\"[^\"]+\")|([^,]*)"; }"#,
+        "5 \"d\n4 \"d,e\"\n",
+        @input "abc,\"d,e\",,f\nabc,\"d,e\",,f\n"
+    );
+
+    test_program!(
+        field_pattern_then_fs,
+        r#"NR == 1 { FPAT = "[0-9]+"; } { print NF, $2; } NR == 2 { FS = ","; }"#,
+        "2 1\n3 22\n3 y\n",
+        @input "a 1\na1b22c333\nx,y,z\n"
+    );
+
+    test_program!(
+        match_captures,
+        r#"{ if (match($0, /([a-z]+)=([0-9]+)( *;)?/, m)) { print RSTART, RLENGTH, m[0], m[1], m[2] + 1, length(m); } else { print "none", length(m); } }"#,
+        "3 4 x=12 x 13 3\nnone 0\n",
+        @input "  x=12 y=3\nnothing here\n"
+    );
+
+    test_program!(
+        match_captures_existing_array,
+        r#"BEGIN {
+            n[1] = 5; n[2] = n[1] * 1.5;
+            s["a"] = "b";
+            print match("ab12", /([a-z]+)([0-9]+)/, n), n[1], n[2] + 1, (n[2] < 20), length(n);
+            print match("cd34", /([a-z]+)([0-9]+)/, s), s[1], s["2"] + 1, ("a" in s), length(s);
+        }"#,
+        "1 ab 13 1 3\n1 cd 35 0 3\n"
+    );
+
+    test_program!(
+        gensub_backrefs,
+        r#"{ print gensub(/([a-z])=([0-9])/, "\\2=\\1", "g"), gensub(/[0-9]/, "<&>", 2), gensub(/=/, ":", 1, $2), $0; }"#,
+        "1=a 2=b a=1 b=<2> b:2 a=1 b=2\n",
+        @input "a=1 b=2\n"
+    );
+
+    test_program!(
+        time_functions,
+        r#"BEGIN {
+            ts = strptime("2001-09-09T03:46:40+0200 GET /", "%Y-%m-%dT%H:%M:%S%z");
+            print ts, strftime("%Y-%m-%d %H:%M:%S", ts, 1), strptime("bad", "%Y");
+            local = "2001 09 09 01 46 40";
+            print (strftime("%Y %m %d %H %M %S", mktime(local)) == local), mktime("2001 09");
+            print (systime() > ts), (length(strftime()) > 0);
+        }"#,
+        "1000000000 2001-09-09 01:46:40 -1\n1 -1\n1 1\n"
+    );
+
+    test_program!(
+        special_output_files,
+        r#"BEGIN {
+            print "a"; print "b" > "/dev/stdout"; printf "c\n" > "/dev/fd/1";
+            print "d" > "/tmp/frawk_fflush_out";
+            print fflush(), fflush("/not/open"), fflush("/dev/stdout"), fflush("/tmp/frawk_fflush_out");
+        }"#,
+        "a\nb\nc\n0 -1 0 0\n"
+    );
+
+    test_program!(
+        asort_asorti,
+        r#"{ a[$1] = $2; }
+        END {
+            n = asort(a, b);
+            for (i = 1; i <= n; i++) printf "%s ", b[i];
+            n = asorti(a, c, "@ind_str_desc");
+            for (i = 1; i <= n; i++) printf "%s ", c[i];
+            n = asort(a);
+            print n, a[1], a[n];
+        }"#,
+        "-1 9 10 apple z y x w 4 -1 apple\n",
+        @input "x 10\ny 9\nz apple\nw -1\n"
+    );
+
+    test_program!(
+        sorted_in,
+        r#"BEGIN {
+            m[3] = "c"; m[20] = "a"; m[100] = "b";
+            PROCINFO["sorted_in"] = "@ind_num_asc";
+            for (k in m) { printf "%s ", k; }
+            PROCINFO["sorted_in"] = "@ind_str_asc";
+            for (k in m) { printf "%s ", k; }
+            PROCINFO["sorted_in"] = "@val_str_desc";
+            for (k in m) { printf "%s ", k; }
+            print "";
+        }"#,
+        "3 20 100 100 20 3 3 100 20 \n"
+    );
+
+    test_program!(
+        beginfile_endfile,
+        r#"BEGINFILE { print "begin", FILENAME, FNR; }
+        { sum += $1; }
+        ENDFILE { print "end", FILENAME, FNR, NR; }
+        END { print sum, FILENAME, FNR; }"#,
+        "begin fake_stdin_0 0\nend fake_stdin_0 3 3\n6 fake_stdin_0 3\n",
+        @input "1\n2\n3\n"
+    );
+
+    // Reads from the main input skip over the start and end of each file, so these two programs
+    // read the same records.
+    test_program!(
+        getline_main_input,
+        r#"BEGIN { r = (getline); print "B", r, $0; r = (getline x); print "Bv", r, x; }
+        { print "M", $0; r = (getline); print "m", r, $0; r = (getline y); print "mv", r, y; }
+        END { r = (getline); print "E", r; r = (getline z); print "Ev", r, z; }"#,
+        "B 1 1\nBv 1 2\nM 3\nm 1 4\nmv 1 5\nE 0\nEv 0 \n",
+        @input "1\n2\n3\n<<<FILE BREAK>>>4\n5\n"
+    );
+
+    test_program!(
+        getline_main_input_file_events,
+        r#"BEGINFILE { files++; }
+        BEGIN { r = (getline); print "B", r, $0; r = (getline x); print "Bv", r, x; }
+        { print "M", $0; r = (getline); print "m", r, $0; r = (getline y); print "mv", r, y; }
+        END { r = (getline); print "E", r; r = (getline z); print "Ev", r, z; }
+        ENDFILE { files--; }"#,
+        "B 1 1\nBv 1 2\nM 3\nm 1 4\nmv 1 5\nE 0\nEv 0 \n",
+        @input "1\n2\n3\n<<<FILE BREAK>>>4\n5\n"
+    );
+
     test_program!(
         function_locals,
         r#"function p(n,  i,res) {
@@ -43,10 +43,11 @@
 //! Users who wish to execute a script they believe is safe, but is rejected by the analysis
 //! (either because the analysis is too conservative, or because they trust user input) can opt out
 //! of taint analysis using the -A flag.
-use crate::bytecode::Instr;
-use crate::common::{FileSpec, NumTy};
+use crate::bytecode::{Instr, Reg};
+use crate::common::{FileSpec, InputSpec, NumTy};
 use crate::compile::HighLevel;
 use crate::dataflow::{self, JoinSemiLattice};
+use crate::runtime::Str;
 
 /// aka bool, with join = ||; making our own enum for explicitness.
 #[derive(Copy, Clone, Debug)]
@@ -101,17 +102,13 @@ impl TaintedStringAnalysis {
         // should read up on the potential attack surface first.
         use Instr::*;
         match inst {
-            ReadErr(dst, cmd, is_file) => {
+            ReadErr(dst, cmd, spec) => {
                 self.dfa.add_src(dst, Taint::Tainted);
-                if !*is_file {
-                    self.dfa.add_query(cmd);
-                }
+                self.add_input_query(cmd, *spec);
             }
-            NextLine(dst, cmd, is_file) => {
+            NextLine(dst, cmd, spec) => {
                 self.dfa.add_src(dst, Taint::Tainted);
-                if !*is_file {
-                    self.dfa.add_query(cmd);
-                }
+                self.add_input_query(cmd, *spec);
             }
             GetColumn(dst, _) => self.dfa.add_src(dst, Taint::Tainted),
             ReadErrStdin(dst) => self.dfa.add_src(dst, Taint::Tainted),
@@ -123,14 +120,25 @@ impl TaintedStringAnalysis {
                 output: Some((cmd, FileSpec::Cmd)),
                 ..
             }
+            | PrintAll {
+                output: Some((cmd, FileSpec::Coproc)),
+                ..
+            }
             | Printf {
                 output: Some((cmd, FileSpec::Cmd)),
                 ..
+            }
+            | Printf {
+                output: Some((cmd, FileSpec::Coproc)),
+                ..
             } => self.dfa.add_query(cmd),
             RunCmd(dst, cmd) => {
                 self.dfa.add_query(cmd);
                 self.dfa.add_src(dst, Taint::Tainted);
             }
+            // exec and exec_cmd run their program directly rather than through a shell, so their
+            // arguments are not checked.
+            RunExec(dst, _) => self.dfa.add_src(dst, Taint::Tainted),
             _ => dataflow::boilerplate::visit_ll(inst, |dst, src| {
                 if let Some(src) = src {
                     self.dfa.add_dep(dst, src, ())
@@ -139,6 +147,13 @@ impl TaintedStringAnalysis {
         }
     }
 
+    fn add_input_query<'a>(&mut self, cmd: &Reg<Str<'a>>, spec: InputSpec) {
+        match spec {
+            InputSpec::File | InputSpec::Exec => {}
+            InputSpec::Cmd | InputSpec::Coproc => self.dfa.add_query(cmd),
+        }
+    }
+
     pub(crate) fn ok(&mut self) -> bool {
         matches!(self.dfa.root(), Taint::Okay)
     }
@@ -190,6 +205,9 @@ mod tests {
             BEGIN {  system(x($2, "dog")); }"#,
             r#"BEGIN { for (i=1; i<10; i++) m[i]=$i; system(m[3]); }"#,
             r#"BEGIN { for (i=1; i<10; i++) m[$i]=i; for (i in m) system(i); }"#,
+            "BEGIN { print $1 |& $2; }",
+            "BEGIN { while ($1 |& getline) print; }",
+            r#"BEGIN { "cat" |& getline x; print "hi" |& x; }"#,
         ];
 
         for p in progs.iter() {
@@ -202,11 +220,21 @@ mod tests {
         let progs: &[&str] = &[
             r#"BEGIN { print "hello" | "command"; }"#,
             r#"BEGIN { while ("command" | getline) print; }"#,
+            r#"BEGIN { print $1 |& "command"; "command" |& getline x; }"#,
             r#"BEGIN { if ($1) x=5; else y="hi"; print "should work" | x; }"#,
             r#"function x(a, b) { print $2; return a b;}
             BEGIN { while(x("echo ", "hi") | getline) print; }"#,
             r#"function x(a, b) { return a b; }
             BEGIN {  system(x($2, "dog") ? "echo hello" : "echo goodbye"); }"#,
+            r#"{ argv[1] = "convert"; argv[2] = $1; argv[3] = $2; exec(argv); }"#,
+            r#"{ argv[1] = "cat"; argv[2] = $1; print "x" | exec_cmd(argv); close(exec_cmd(argv)); }"#,
+            r#"{ argv[1] = "cat"; argv[2] = $1; exec_cmd(argv) | getline; }"#,
+            r#"{ split($0, argv); exec(argv); }"#,
+            r#"{ split($0, argv); print "x" | exec_cmd(argv); }"#,
+            r#"{ split($0, argv); exec_cmd(argv) | getline; }"#,
+            r#"{ argv[1] = $1; argv[2] = "x"; exec(argv); }"#,
+            r#"{ argv[1] = "echo"; argv[int($2)] = $1; exec(argv); }"#,
+            r#"{ argv[1] = "echo"; argv[2] = $1; x[1] = argv[2]; exec(x); }"#,
         ];
         for p in progs.iter() {
             assert_analysis_accept(*p);
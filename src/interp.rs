@@ -1,9 +1,13 @@
 use crate::builtins::Variable;
 use crate::bytecode::{Get, Instr, Label, Reg};
-use crate::common::{NumTy, Result, Stage};
+use crate::common::{InputSpec, NumTy, Result, Stage};
 use crate::compile::{self, Ty};
 use crate::pushdown::FieldSet;
-use crate::runtime::{self, Float, Int, Line, LineReader, Str, UniqueStr};
+use crate::runtime::{
+    self,
+    sort::{self, SortOrder},
+    Cmd, Float, Int, Line, LineReader, Str, UniqueStr,
+};
 
 use crossbeam::scope;
 use crossbeam_channel::bounded;
@@ -31,6 +35,8 @@ pub(crate) struct Core<'a> {
     pub rng: StdRng,
     pub current_seed: u64,
     pub slots: Slots,
+    // The keys for JSON objects, taken from the header.
+    pub json_keys: runtime::JSONKeys,
 }
 
 impl<'a> Drop for Core<'a> {
@@ -160,9 +166,17 @@ impl<'a> Core<'a> {
         let rs: UniqueStr<'a> = self.vars.rs.clone().into();
         let ors: UniqueStr<'a> = self.vars.ors.clone().into();
         let filename: UniqueStr<'a> = self.vars.filename.clone().into();
+        let convfmt: UniqueStr<'a> = self.vars.convfmt.clone().into();
+        let ofmt: UniqueStr<'a> = self.vars.ofmt.clone().into();
+        let fieldwidths: UniqueStr<'a> = self.vars.fieldwidths.clone().into();
+        let fpat: UniqueStr<'a> = self.vars.fpat.clone().into();
         let argv = self.vars.argv.shuttle();
         let fi = self.vars.fi.shuttle();
+        let environ = self.vars.environ.shuttle();
+        let procinfo = self.vars.procinfo.shuttle();
         let slots = self.slots.clone();
+        let json_keys = self.json_keys.clone();
+        let posix_regex = self.regexes.posix();
         move || {
             let vars = Variables {
                 fs: fs.into_str(),
@@ -179,14 +193,22 @@ impl<'a> Core<'a> {
                 argc: 0,
                 argv: argv.into(),
                 fi: fi.into(),
+                environ: environ.into(),
+                convfmt: convfmt.into_str(),
+                ofmt: ofmt.into_str(),
+                rt: Default::default(),
+                fieldwidths: fieldwidths.into_str(),
+                fpat: fpat.into_str(),
+                procinfo: procinfo.into(),
             };
             Core {
                 vars,
-                regexes: Default::default(),
+                regexes: runtime::RegexCache::new(posix_regex),
                 write_files: fw,
                 rng: rand::rngs::StdRng::seed_from_u64(seed),
                 current_seed: seed,
                 slots,
+                json_keys,
             }
         }
     }
@@ -199,6 +221,7 @@ impl<'a> Core<'a> {
             rng: rand::rngs::StdRng::seed_from_u64(seed),
             current_seed: seed,
             slots: Default::default(),
+            json_keys: Default::default(),
         }
     }
 
@@ -444,6 +467,7 @@ fn default_of<T: Default>(n: usize) -> Storage<T> {
 }
 
 impl<'a, LR: LineReader> Interp<'a, LR> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         instrs: Vec<Vec<Instr<'a>>>,
         main_func: Stage<usize>,
@@ -453,8 +477,11 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         ff: impl runtime::writers::FileFactory,
         used_fields: &FieldSet,
         named_columns: Option<Vec<&[u8]>>,
+        posix_regex: bool,
     ) -> Self {
         use compile::Ty::*;
+        let mut core = Core::new(ff);
+        core.regexes = runtime::RegexCache::new(posix_regex);
         Interp {
             main_func,
             num_workers,
@@ -463,7 +490,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             floats: default_of(regs(Float)),
             ints: default_of(regs(Int)),
             strs: default_of(regs(Str)),
-            core: Core::new(ff),
+            core,
 
             line: Default::default(),
             read_files: runtime::FileRead::new(stdin, used_fields.clone(), named_columns),
@@ -494,8 +521,25 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         })
     }
 
+    fn strnum_cmp_float(&mut self, l: Reg<Str<'a>>, r: Reg<Float>) -> Result<std::cmp::Ordering> {
+        let vars = &self.core.vars;
+        runtime::strnum_cmp_float(index(&self.strs, &l), *index(&self.floats, &r), || {
+            vars.load_str(Variable::CONVFMT)
+        })
+    }
+
+    fn update_rt(&mut self) {
+        if let Some(rt) = self.read_files.stdin_terminator() {
+            self.core.vars.rt = rt.upcast();
+        }
+    }
+
+    fn update_fi(&mut self) {
+        self.read_files.update_fi(&self.core.vars.fi);
+    }
+
     fn reset_file_vars(&mut self) {
-        self.core.vars.fnr = 0;
+        self.core.vars.fnr = self.read_files.stdin_fnr_offset();
         self.core.vars.filename = self.read_files.stdin_filename().upcast();
     }
 
@@ -583,6 +627,9 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             iters_str: default_of(iters_str_size),
                         };
                         let res = interp.run_at(main_loop);
+                        // Write out the output of the last chunk, even on an error, so that other
+                        // workers in ordered mode do not wait on it.
+                        let res = runtime::writers::finish_chunk().and(res);
 
                         // Ignore errors, as it means another thread executed with an error and we are
                         // exiting anyway.
@@ -595,7 +642,8 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             }
             mem::drop(sender);
             self.core.vars.pid = 1;
-            let mut rc = self.run_at(main_loop)?;
+            let res = self.run_at(main_loop);
+            let mut rc = runtime::writers::finish_chunk().and(res)?;
             self.core.vars.pid = 0;
             while let Ok(res) = receiver.recv() {
                 let res = res?;
@@ -621,11 +669,20 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
 
     pub(crate) fn run_serial(&mut self) -> Result<i32> {
         let offs: smallvec::SmallVec<[usize; 3]> = self.main_func.iter().cloned().collect();
+        let begin = match self.main_func {
+            Stage::Par { begin, .. } => begin,
+            Stage::Main(_) => None,
+        };
         for off in offs.into_iter() {
             let rc = self.run_at(off)?;
             if rc != 0 {
                 return Ok(rc);
             }
+            // Record output in ordered mode goes to stdout through a separate handle, so BEGIN's
+            // output has to be written out before we read any input, as in run_parallel.
+            if Some(off) == begin && self.core.write_files.flush_stdout().is_err() {
+                return Ok(1);
+            }
         }
         Ok(0)
     }
@@ -675,6 +732,12 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let sr = *sr;
                         *self.get_mut(sr) = s;
                     }
+                    FormatFloat(sr, fr, var) => {
+                        let fmt = self.core.vars.load_str(*var)?;
+                        let s = runtime::format_float(&fmt, *self.get(*fr))?;
+                        let sr = *sr;
+                        *self.get_mut(sr) = s;
+                    }
                     StrToInt(ir, sr) => {
                         let i = runtime::convert::<_, Int>(self.get(*sr));
                         let ir = *ir;
@@ -814,6 +877,27 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     ReseedRng(res) => {
                         *index_mut(&mut self.ints, res) = self.core.reseed_random() as Int;
                     }
+                    Systime(res) => {
+                        *index_mut(&mut self.ints, res) = runtime::time::systime();
+                    }
+                    Strftime(res, fmt, ts, utc) => {
+                        let formatted = runtime::time::strftime_impl(
+                            index(&self.strs, fmt),
+                            *index(&self.ints, ts),
+                            *index(&self.ints, utc) != 0,
+                        )?;
+                        *index_mut(&mut self.strs, res) = formatted.upcast();
+                    }
+                    Mktime(res, s) => {
+                        *index_mut(&mut self.ints, res) =
+                            runtime::time::mktime_impl(index(&self.strs, s))?;
+                    }
+                    Strptime(res, s, fmt) => {
+                        *index_mut(&mut self.ints, res) = runtime::time::strptime_impl(
+                            index(&self.strs, s),
+                            index(&self.strs, fmt),
+                        )?;
+                    }
                     StartsWithConst(res, s, bs) => {
                         let s_bytes = unsafe { &*index(&self.strs, s).get_bytes() };
                         *index_mut(&mut self.ints, res) =
@@ -830,6 +914,24 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             .core
                             .match_regex(index(&self.strs, l), index(&self.strs, r))?;
                     }
+                    MatchCapturesInt(res, s, pat, arr) => {
+                        let s = index(&self.strs, s);
+                        let pat = index(&self.strs, pat);
+                        let arr = index(&self.maps_int_str, arr);
+                        *index_mut(&mut self.ints, res) = self
+                            .core
+                            .regexes
+                            .regex_match_captures_intmap(&mut self.core.vars, pat, s, arr)?;
+                    }
+                    MatchCapturesStr(res, s, pat, arr) => {
+                        let s = index(&self.strs, s);
+                        let pat = index(&self.strs, pat);
+                        let arr = index(&self.maps_str_str, arr);
+                        *index_mut(&mut self.ints, res) = self
+                            .core
+                            .regexes
+                            .regex_match_captures_strmap(&mut self.core.vars, pat, s, arr)?;
+                    }
                     IsMatch(res, l, r) => {
                         *index_mut(&mut self.ints, res) = self
                             .core
@@ -883,6 +985,16 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         *index_mut(&mut self.strs, in_s) = subbed;
                         *index_mut(&mut self.ints, res) = subs_made;
                     }
+                    GenSub(res, pat, s, how, in_s) => {
+                        let subbed = {
+                            let pat = index(&self.strs, pat);
+                            let s = index(&self.strs, s);
+                            let how = index(&self.strs, how);
+                            let in_s = index(&self.strs, in_s);
+                            self.core.regexes.gensub(pat, s, how, in_s)?
+                        };
+                        *index_mut(&mut self.strs, res) = subbed;
+                    }
                     EscapeCSV(res, s) => {
                         *index_mut(&mut self.strs, res) = {
                             let s = index(&self.strs, s);
@@ -895,6 +1007,16 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             runtime::escape_tsv(s)
                         };
                     }
+                    EscapeJSON(res, s) => {
+                        *index_mut(&mut self.strs, res) = {
+                            let s = index(&self.strs, s);
+                            runtime::escape_json(s)
+                        };
+                    }
+                    FloatToJSON(res, f) => {
+                        let f = *index(&self.floats, f);
+                        *index_mut(&mut self.strs, res) = runtime::float_to_json(f);
+                    }
                     Substr(res, base, l, r) => {
                         let base = index(&self.strs, base);
                         let len = base.len();
@@ -997,6 +1119,56 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let r = self.get(*r);
                         *self.get_mut(res) = (l == r) as Int;
                     }
+                    LTStrNum(res, l, r) => {
+                        let res = *res;
+                        let ord = self.get(*l).strnum_cmp(self.get(*r));
+                        *self.get_mut(res) = ord.is_lt() as Int;
+                    }
+                    GTStrNum(res, l, r) => {
+                        let res = *res;
+                        let ord = self.get(*l).strnum_cmp(self.get(*r));
+                        *self.get_mut(res) = ord.is_gt() as Int;
+                    }
+                    LTEStrNum(res, l, r) => {
+                        let res = *res;
+                        let ord = self.get(*l).strnum_cmp(self.get(*r));
+                        *self.get_mut(res) = ord.is_le() as Int;
+                    }
+                    GTEStrNum(res, l, r) => {
+                        let res = *res;
+                        let ord = self.get(*l).strnum_cmp(self.get(*r));
+                        *self.get_mut(res) = ord.is_ge() as Int;
+                    }
+                    EQStrNum(res, l, r) => {
+                        let res = *res;
+                        let ord = self.get(*l).strnum_cmp(self.get(*r));
+                        *self.get_mut(res) = ord.is_eq() as Int;
+                    }
+                    LTStrNumFloat(res, l, r) => {
+                        let res = *res;
+                        let ord = self.strnum_cmp_float(*l, *r)?;
+                        *self.get_mut(res) = ord.is_lt() as Int;
+                    }
+                    GTStrNumFloat(res, l, r) => {
+                        let res = *res;
+                        let ord = self.strnum_cmp_float(*l, *r)?;
+                        *self.get_mut(res) = ord.is_gt() as Int;
+                    }
+                    LTEStrNumFloat(res, l, r) => {
+                        let res = *res;
+                        let ord = self.strnum_cmp_float(*l, *r)?;
+                        *self.get_mut(res) = ord.is_le() as Int;
+                    }
+                    GTEStrNumFloat(res, l, r) => {
+                        let res = *res;
+                        let ord = self.strnum_cmp_float(*l, *r)?;
+                        *self.get_mut(res) = ord.is_ge() as Int;
+                    }
+                    EQStrNumFloat(res, l, r) => {
+                        let res = *res;
+                        let ord = self.strnum_cmp_float(*l, *r)?;
+                        *self.get_mut(res) = ord.is_eq() as Int;
+                    }
                     SetColumn(dst, src) => {
                         let col = *self.get(*dst);
                         let v = index(&self.strs, src);
@@ -1014,6 +1186,25 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         )?;
                         *self.get_mut(dst) = res;
                     }
+                    JoinJSON(dst, keyed) => {
+                        let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
+                        let keys = if *index(&self.ints, keyed) != 0 {
+                            Some(&mut self.core.json_keys)
+                        } else {
+                            None
+                        };
+                        *index_mut(&mut self.strs, dst) =
+                            runtime::join_json(&mut self.line, nf, keys)?;
+                    }
+                    JSONMember(dst, col, pos, val) => {
+                        let col = *index(&self.ints, col);
+                        let pos = *index(&self.ints, pos);
+                        let member =
+                            self.core
+                                .json_keys
+                                .member(col, pos, index(&self.strs, val))?;
+                        *index_mut(&mut self.strs, dst) = member;
+                    }
                     JoinCSV(dst, start, end) => {
                         let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
                         *index_mut(&mut self.strs, dst) = {
@@ -1079,8 +1270,10 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         use runtime::str_impl::DynamicBuf;
                         let fmt_str = index(&self.strs, fmt);
                         let mut buf = DynamicBuf::new(0);
-                        fmt_str
-                            .with_bytes(|bs| runtime::printf::printf(&mut buf, bs, &scratch[..]))?;
+                        let convfmt = &self.core.vars.convfmt;
+                        fmt_str.with_bytes(|bs| {
+                            runtime::printf::printf(&mut buf, bs, &scratch[..], convfmt)
+                        })?;
                         scratch.clear();
                         let res = unsafe { buf.into_str() };
                         let dst = *dst;
@@ -1116,27 +1309,48 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                                 Some((out_path, *fspec)),
                                 fmt_str,
                                 &scratch[..],
+                                &self.core.vars.convfmt,
                             )
                         } else {
                             // print to stdout.
-                            self.core.write_files.printf(None, fmt_str, &scratch[..])
+                            self.core.write_files.printf(
+                                None,
+                                fmt_str,
+                                &scratch[..],
+                                &self.core.vars.convfmt,
+                            )
                         };
                         if res.is_err() {
                             return Ok(0);
                         }
                         scratch.clear();
                     }
-                    Close(file) => {
+                    Close(file, how) => {
                         let file = index(&self.strs, file);
-                        // NB this may create an unused entry in write_files. It would not be
-                        // terribly difficult to optimize the close path to include an existence
-                        // check first.
-                        self.core.write_files.close(file)?;
-                        self.read_files.close(file);
+                        let how = index(&self.strs, how);
+                        runtime::close_file(
+                            &mut self.core.write_files,
+                            &mut self.read_files,
+                            file,
+                            how,
+                        )?;
+                    }
+                    Fflush(dst, file) => {
+                        let file = index(&self.strs, file);
+                        let res = self.core.write_files.flush(file)?;
+                        *index_mut(&mut self.ints, dst) = res;
+                    }
+                    ExecCmd(dst, argv) => {
+                        let res = runtime::exec_command(index(&self.maps_int_str, argv))?;
+                        *index_mut(&mut self.strs, dst) = res;
                     }
                     RunCmd(dst, cmd) => {
-                        *index_mut(&mut self.ints, dst) =
-                            index(&self.strs, cmd).with_bytes(runtime::run_command);
+                        *index_mut(&mut self.ints, dst) = index(&self.strs, cmd)
+                            .with_bytes(|bs| runtime::run_command(Cmd::Shell(bs)));
+                    }
+                    RunExec(dst, cmd) => {
+                        *index_mut(&mut self.ints, dst) = index(&self.strs, cmd)
+                            .with_bytes(|bs| runtime::run_command(Cmd::Argv(bs)));
                     }
                     Exit(code) => return Ok(*index(&self.ints, code) as i32),
                     Lookup {
@@ -1153,6 +1367,28 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     } => self.contains(*map_ty, *dst, *map, *key),
                     Delete { map_ty, map, key } => self.delete(*map_ty, *map, *key),
                     Clear { map_ty, map } => self.clear(*map_ty, *map),
+                    Asort {
+                        src_ty,
+                        src,
+                        dst_ty,
+                        dst,
+                        how,
+                        dst_len,
+                    } => {
+                        let n = self.asort(*src_ty, *src, *dst_ty, *dst, *how, false)?;
+                        *index_mut(&mut self.ints, dst_len) = n;
+                    }
+                    Asorti {
+                        src_ty,
+                        src,
+                        dst_ty,
+                        dst,
+                        how,
+                        dst_len,
+                    } => {
+                        let n = self.asort(*src_ty, *src, *dst_ty, *dst, *how, true)?;
+                        *index_mut(&mut self.ints, dst_len) = n;
+                    }
                     Len { map_ty, map, dst } => self.len(*map_ty, *map, *dst),
                     Store {
                         map_ty,
@@ -1182,6 +1418,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     StoreVarStr(var, src) => {
                         let src = *src;
                         let s = self.get(src).clone();
+                        self.read_files.update_splitting(*var, &s)?;
                         self.core.vars.store_str(*var, s)?;
                     }
                     LoadVarInt(dst, var) => {
@@ -1220,8 +1457,18 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let s = self.get(src).clone();
                         self.core.vars.store_strmap(*var, s)?;
                     }
+                    LoadVarStrStrMap(dst, var) => {
+                        let arr = self.core.vars.load_strstrmap(*var)?;
+                        let dst = *dst;
+                        *self.get_mut(dst) = arr;
+                    }
+                    StoreVarStrStrMap(var, src) => {
+                        let src = *src;
+                        let s = self.get(src).clone();
+                        self.core.vars.store_strstrmap(*var, s)?;
+                    }
 
-                    IterBegin { map_ty, map, dst } => self.iter_begin(*map_ty, *map, *dst),
+                    IterBegin { map_ty, map, dst } => self.iter_begin(*map_ty, *map, *dst)?,
                     IterHasNext { iter_ty, dst, iter } => self.iter_has_next(*iter_ty, *dst, *iter),
                     IterGetNext { iter_ty, dst, iter } => self.iter_get_next(*iter_ty, *dst, *iter),
 
@@ -1231,24 +1478,28 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     AllocMap(ty, reg) => self.alloc_map(*ty, *reg),
 
                     // TODO add error logging for these errors perhaps?
-                    ReadErr(dst, file, is_file) => {
+                    ReadErr(dst, file, spec) => {
                         let dst = *dst;
                         let file = index(&self.strs, file);
-                        let res = if *is_file {
-                            self.read_files.read_err(file)?
-                        } else {
-                            self.read_files.read_err_cmd(file)?
+                        let res = match spec {
+                            InputSpec::File => self.read_files.read_err(file)?,
+                            InputSpec::Cmd => self.read_files.read_err_cmd(file, false)?,
+                            InputSpec::Exec => self.read_files.read_err_cmd(file, true)?,
+                            InputSpec::Coproc => self
+                                .read_files
+                                .read_err_coproc(file, &mut self.core.write_files)?,
                         };
                         *self.get_mut(dst) = res;
                     }
-                    NextLine(dst, file, is_file) => {
+                    NextLine(dst, file, spec) => {
                         let dst = *dst;
                         let file = index(&self.strs, file);
                         match self.core.regexes.get_line(
                             file,
                             &self.core.vars.rs,
                             &mut self.read_files,
-                            *is_file,
+                            &mut self.core.write_files,
+                            *spec,
                         ) {
                             Ok(l) => *self.get_mut(dst) = l,
                             Err(_) => *self.get_mut(dst) = "".into(),
@@ -1268,6 +1519,8 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         if changed {
                             self.reset_file_vars();
                         }
+                        self.update_rt();
+                        self.update_fi();
                         *self.get_mut(dst) = res;
                     }
                     NextLineStdinFused() => {
@@ -1279,15 +1532,24 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         if changed {
                             self.reset_file_vars()
                         }
+                        self.update_rt();
+                        self.update_fi();
                     }
-                    NextFile() => {
-                        self.read_files.next_file()?;
-                        self.reset_file_vars();
-                    }
+                    NextFile() => self.read_files.next_file()?,
                     UpdateUsedFields() => {
                         let fi = &self.core.vars.fi;
                         self.read_files.update_named_columns(fi);
                     }
+                    SetJSONKey(col) => {
+                        let col = *index(&self.ints, col);
+                        let name = self.line.get_col(
+                            col,
+                            &self.core.vars.fs,
+                            &self.core.vars.ofs,
+                            &mut self.core.regexes,
+                        )?;
+                        self.core.json_keys.set_header_name(col, &name)?;
+                    }
                     SetFI(key, val) => {
                         let key = *index(&self.ints, key);
                         let val = *index(&self.ints, val);
@@ -1400,6 +1662,40 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         map_regs!(map_ty, map, self.get(map).clear());
     }
 
+    fn asort(
+        &mut self,
+        src_ty: Ty,
+        src: NumTy,
+        dst_ty: Ty,
+        dst: NumTy,
+        how: Reg<Str<'a>>,
+        indices: bool,
+    ) -> Result<Int> {
+        let how = self.get(how).clone();
+        let str_keys = dst_ty.key()? == Ty::Str;
+        map_regs!(src_ty, src, {
+            let src = self.get(src).clone();
+            match (indices, str_keys) {
+                (false, false) => {
+                    let dst: Reg<runtime::IntMap<_>> = dst.into();
+                    sort::asort(&src, self.get(dst), &how)
+                }
+                (false, true) => {
+                    let dst: Reg<runtime::StrMap<_>> = dst.into();
+                    sort::asort(&src, self.get(dst), &how)
+                }
+                (true, false) => {
+                    let dst: Reg<runtime::IntMap<_>> = dst.into();
+                    sort::asorti(&src, self.get(dst), &how)
+                }
+                (true, true) => {
+                    let dst: Reg<runtime::StrMap<_>> = dst.into();
+                    sort::asorti(&src, self.get(dst), &how)
+                }
+            }
+        })
+    }
+
     // Allowing this because it allows for easier use of the map_regs macro.
     #[allow(clippy::clone_on_copy)]
     fn store_map(&mut self, map_ty: Ty, map: NumTy, key: NumTy, val: NumTy) {
@@ -1431,13 +1727,15 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         let len = map_regs!(map_ty, map, self.get(map).len() as Int);
         *index_mut(&mut self.ints, &dst.into()) = len;
     }
-    fn iter_begin(&mut self, map_ty: Ty, map: NumTy, dst: NumTy) {
+    fn iter_begin(&mut self, map_ty: Ty, map: NumTy, dst: NumTy) -> Result<()> {
         let _k = 0u32;
         let _v = 0u32;
+        let order = SortOrder::sorted_in(&self.core.vars.procinfo)?;
         map_regs!(map_ty, map, _k, _v, dst, {
-            let iter = self.get(map).to_iter();
+            let iter = self.get(map).to_iter(order);
             *self.get_mut(dst) = iter;
-        })
+        });
+        Ok(())
     }
     fn iter_has_next(&mut self, iter_ty: Ty, dst: NumTy, iter: NumTy) {
         match iter_ty {
@@ -1613,7 +1911,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         self.stack = Default::default();
         self.core.vars = Default::default();
         self.line = Default::default();
-        self.core.regexes = Default::default();
+        self.core.regexes = runtime::RegexCache::new(self.core.regexes.posix());
         self.floats.reset();
         self.ints.reset();
         self.strs.reset();
@@ -23,6 +23,8 @@ pub enum Tok<'a> {
     Begin,
     Prepare,
     End,
+    BeginFile,
+    EndFile,
     Break,
     Continue,
     Next,
@@ -82,6 +84,7 @@ pub enum Tok<'a> {
     QUESTION,
     COLON,
     Pipe,
+    PipeAmp, // |&
 
     Append, // >>
 
@@ -142,6 +145,8 @@ keyword_map!(
     [b"PREPARE", Tok::Prepare],
     [b"BEGIN", Tok::Begin, WS_BRACE.clone()],
     [b"END", Tok::End, WS_BRACE.clone()],
+    [b"BEGINFILE", Tok::BeginFile, WS_BRACE.clone()],
+    [b"ENDFILE", Tok::EndFile, WS_BRACE.clone()],
     [b"break", Tok::Break, WS_SEMI.clone()],
     [b"continue", Tok::Continue, WS_SEMI.clone()],
     [b"next", Tok::Next],
@@ -165,6 +170,7 @@ keyword_map!(
     [b")", Tok::RParen],
     [b"getline", Tok::Getline, WS_SEMI_RPAREN.clone()],
     [b"|", Tok::Pipe],
+    [b"|&", Tok::PipeAmp],
     [b"=", Tok::Assign],
     [b"+", Tok::Add],
     [b"+=", Tok::AddAssign],
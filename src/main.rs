@@ -41,6 +41,8 @@ use common::{CancelSignal, ExecutionStrategy, Stage};
 use runtime::{
     splitter::{
         batch::{ByteReader, CSVReader, InputFormat},
+        compress::Decompress,
+        fixed::FieldWidths,
         regex::RegexSplitter,
     },
     ChainedReader, LineReader, CHUNK_SIZE,
@@ -65,7 +67,9 @@ macro_rules! fail {
 struct PreludeScalars {
     arbitrary_shell: bool,
     fold_regexes: bool,
+    posix_regex: bool,
     parse_header: bool,
+    jsonl_input: bool,
     escaper: Escaper,
     stage: Stage<()>,
 }
@@ -73,7 +77,10 @@ struct PreludeScalars {
 struct RawPrelude {
     argv: Vec<String>,
     var_decs: Vec<String>,
+    input_files: Vec<String>,
+    operand_assigns: Vec<(usize, String)>,
     field_sep: Option<String>,
+    field_widths: Option<String>,
     output_sep: Option<&'static str>,
     output_record_sep: Option<&'static str>,
     scalars: PreludeScalars,
@@ -81,7 +88,10 @@ struct RawPrelude {
 
 struct Prelude<'a> {
     var_decs: Vec<(&'a str, &'a ast::Expr<'a, 'a, &'a str>)>,
+    operand_assigns: Vec<(usize, &'a str, &'a ast::Expr<'a, 'a, &'a str>)>,
+    input_files: Vec<&'a str>,
     field_sep: Option<&'a [u8]>,
+    field_widths: Option<&'a [u8]>,
     output_sep: Option<&'a [u8]>,
     output_record_sep: Option<&'a [u8]>,
     argv: Vec<&'a str>,
@@ -115,7 +125,10 @@ fn open_file_read(f: &str) -> impl io::BufRead {
     }
 
     let filename = String::from(f);
-    BufReader::new(LazyReader::Uninit(move || File::open(filename.as_str())))
+    BufReader::new(LazyReader::Uninit(move || {
+        let file = File::open(filename.as_str())?;
+        Ok(Decompress::new(file, filename.as_str()))
+    }))
 }
 
 fn chained<LR: LineReader>(lr: LR) -> ChainedReader<LR> {
@@ -153,6 +166,21 @@ fn get_vars<'a, 'b>(
     res
 }
 
+// Operands of the form `var=value` are assignments, not input files. They take effect once all of
+// the input files before them have been read. Returns the input files, along with each
+// assignment and the number of files that precede it.
+fn split_operands(operands: Vec<String>) -> (Vec<String>, Vec<(usize, String)>) {
+    let mut files = Vec::new();
+    let mut assigns = Vec::new();
+    for op in operands {
+        match op.find('=') {
+            Some(ix) if ix > 0 && lexer::is_ident(&op[..ix]) => assigns.push((files.len(), op)),
+            _ => files.push(op),
+        }
+    }
+    (files, assigns)
+}
+
 fn get_prelude<'a>(a: &'a Arena, raw: &RawPrelude) -> Prelude<'a> {
     let mut buf = Vec::new();
     let output_sep = raw
@@ -167,7 +195,23 @@ fn get_prelude<'a>(a: &'a Arena, raw: &RawPrelude) -> Prelude<'a> {
         .map(|s| lexer::parse_string_literal(s.as_str(), a, &mut buf));
     Prelude {
         field_sep,
+        field_widths: raw
+            .field_widths
+            .as_ref()
+            .map(|s| a.alloc_bytes(s.as_bytes())),
         var_decs: get_vars(raw.var_decs.iter().map(|s| s.as_str()), a, &mut buf),
+        operand_assigns: raw
+            .operand_assigns
+            .iter()
+            .map(|(files_before, _)| *files_before)
+            .zip(get_vars(
+                raw.operand_assigns.iter().map(|(_, s)| s.as_str()),
+                a,
+                &mut buf,
+            ))
+            .map(|(files_before, (ident, exp))| (files_before, ident, exp))
+            .collect(),
+        input_files: raw.input_files.iter().map(|s| a.alloc_str(s)).collect(),
         scalars: raw.scalars.clone(),
         output_sep,
         output_record_sep,
@@ -186,13 +230,19 @@ fn get_context<'a>(
     let parser = parsing::syntax::ProgParser::new();
     let mut prog = ast::Prog::from_stage(a, prelude.scalars.stage.clone());
     prog.argv = mem::take(&mut prelude.argv);
+    prog.operand_assigns = mem::take(&mut prelude.operand_assigns);
+    prog.input_files = mem::take(&mut prelude.input_files);
     let stmt = match parser.parse(a, &mut buf, &mut prog, lexer) {
         Ok(()) => {
             prog.field_sep = prelude.field_sep;
+            prog.field_widths = prelude.field_widths;
             prog.prelude_vardecs = prelude.var_decs;
             prog.output_sep = prelude.output_sep;
             prog.output_record_sep = prelude.output_record_sep;
             prog.parse_header = prelude.scalars.parse_header;
+            prog.jsonl_input = prelude.scalars.jsonl_input;
+            prog.header_names =
+                prog.parse_header && matches!(prelude.scalars.escaper, Escaper::JSON);
             a.alloc(prog)
         }
         Err(e) => {
@@ -203,6 +253,7 @@ fn get_context<'a>(
         Ok(mut ctx) => {
             ctx.allow_arbitrary_commands = prelude.scalars.arbitrary_shell;
             ctx.fold_regex_constants = prelude.scalars.fold_regexes;
+            ctx.posix_regex = prelude.scalars.posix_regex;
             ctx
         }
         Err(e) => fail!("failed to create program context: {}", e),
@@ -345,10 +396,9 @@ fn main() {
         .arg(Arg::new("input-format")
              .long("input-format")
              .short('i')
-             .value_name("csv|tsv")
+             .value_name("csv|tsv|jsonl|fixed:WIDTHS")
              .conflicts_with("field-separator")
-             .help("Input is split according to the rules of (csv|tsv). $0 contains the unescaped line. Assigning to columns does nothing")
-             .possible_values(&["csv", "tsv"]))
+             .help("Input is split according to the rules of (csv|tsv|jsonl). $0 contains the unescaped line. Assigning to columns does nothing. csv and tsv take an optional list of overrides for their delimiter, quote, escape and comment characters, as in 'csv;delim=;,quote=\\'' or 'tsv;delim=|,escape=none,comment=#'. Both use \\\\ as the escape character unless given escape=none, and neither has a comment character by default; lines starting with the comment character are skipped. For jsonl, each line is a JSON value and each top-level key of an object is assigned a column in the order in which it first appears; with -H, `FI` maps keys to their columns, including keys first seen after the first record, and no input is skipped; without -H, FI may not be used. fixed:5,10,3 splits each line into fields of the given widths, as if FIELDWIDTHS were set to \"5 10 3\""))
         .arg(Arg::new("var")
              .short('v')
              .takes_value(true)
@@ -370,9 +420,9 @@ fn main() {
         .arg(Arg::new("output-format")
              .long("output-format")
              .short('o')
-             .value_name("csv|tsv")
-             .help("If set, records output via print are escaped according to the rules of the corresponding format")
-             .possible_values(&["csv", "tsv"]))
+             .value_name("csv|tsv|json|jsonl")
+             .help("If set, records output via print are escaped according to the rules of the corresponding format. json and jsonl are synonyms for JSON lines output: each print statement writes one JSON array per line. With -H, each line is an object keyed by the header's column names: $N is keyed by the name of column N, other values by their position in the print statement. A bare print writes all fields")
+             .possible_values(["csv", "tsv", "json", "jsonl"]))
         .arg(Arg::new("program")
              .index(1)
              .help("The frawk program to execute"))
@@ -384,6 +434,11 @@ fn main() {
              .short('p')
              .help("Attempt to execute the script in parallel. Strategy r[ecord] parallelizes within the current input file. Strategy f[ile] parallelizes between input files")
              .possible_values(&["r", "record", "f", "file"]))
+        .arg(Arg::new("ordered")
+             .long("ordered")
+             .requires("parallel-strategy")
+             .takes_value(false)
+             .help("With record-level parallelism, write output in the order of the input records, as serial execution would. Only standard output is ordered, so print and printf in the main loop may not redirect their output. Output is held in memory until all earlier records have been processed"))
         .arg(Arg::new("chunk-size")
              .long("chunk-size")
              .takes_value(true)
@@ -393,6 +448,10 @@ fn main() {
              .long("arbitrary-shell")
              .takes_value(false)
              .help("By default, strings that are passed to the shell via pipes or the 'system' function are restricted from potentially containing user input. This flag bypasses that check, for the cases where such a use is known to be safe"))
+        .arg(Arg::new("rust-regex")
+             .long("rust-regex")
+             .takes_value(false)
+             .help("Interpret regular expressions using the syntax of Rust's regex crate, rather than translating them from POSIX extended regular expressions"))
         .arg(Arg::new("jobs")
              .short('j')
              .requires("parallel-strategy")
@@ -407,14 +466,30 @@ fn main() {
         }
     }
     let matches = app.get_matches();
+    let mut field_widths = None;
     let ifmt = match matches.value_of("input-format") {
-        Some("csv") => Some(InputFormat::CSV),
-        Some("tsv") => Some(InputFormat::TSV),
-        Some(x) => fail!("invalid input format: {}", x),
+        Some(spec) if spec.starts_with("fixed:") => {
+            let widths = spec["fixed:".len()..].replace(',', " ");
+            if let Err(e) = FieldWidths::parse(widths.as_bytes()) {
+                fail!("{}", e);
+            }
+            field_widths = Some(widths);
+            None
+        }
+        Some(spec) => match InputFormat::parse(spec, matches.is_present("parse-header")) {
+            Ok(ifmt) => Some(ifmt),
+            Err(e) => fail!("{}", e),
+        },
         None => None,
     };
     let exec_strategy = match matches.value_of("parallel-strategy") {
+        Some("r") | Some("record") if matches.is_present("ordered") => {
+            ExecutionStrategy::ShardPerRecordOrdered
+        }
         Some("r") | Some("record") => ExecutionStrategy::ShardPerRecord,
+        Some("f") | Some("file") if matches.is_present("ordered") => {
+            fail!("--ordered is only supported with record-level parallelism (-pr)")
+        }
         Some("f") | Some("file") => ExecutionStrategy::ShardPerFile,
         None => ExecutionStrategy::Serial,
         Some(x) => fail!(
@@ -477,11 +552,22 @@ fn main() {
             fail!("must specify program at command line, or in a file via -f");
         }
     };
+    let (input_files, operand_assigns) = split_operands(input_files);
+    // Parallel workers do not read input files in order, so there is no point at which an
+    // assignment between two files could take effect.
+    if !matches!(exec_strategy, ExecutionStrategy::Serial)
+        && operand_assigns
+            .iter()
+            .any(|(files_before, _)| *files_before > 0)
+    {
+        fail!("assignment operands after the first input file are not supported with -pf or -pr");
+    }
     let (escaper, output_sep, output_record_sep) = match matches.value_of("output-format") {
         Some("csv") => (Escaper::CSV, Some(","), Some("\r\n")),
         Some("tsv") => (Escaper::TSV, Some("\t"), Some("\n")),
+        Some("json") | Some("jsonl") => (Escaper::JSON, None, Some("\n")),
         Some(s) => fail!(
-            "invalid output format {:?}; expected csv or tsv (or the empty string)",
+            "invalid output format {:?}; expected csv, tsv, json or jsonl (or the empty string)",
             s
         ),
         None => (Escaper::Identity, None, None),
@@ -500,6 +586,7 @@ fn main() {
     };
     let raw = RawPrelude {
         field_sep: matches.value_of("field-separator").map(String::from),
+        field_widths,
         var_decs: matches
             .values_of("var")
             .map(|x| x.map(String::from).collect())
@@ -509,11 +596,15 @@ fn main() {
             escaper,
             arbitrary_shell,
             fold_regexes: opt_level >= 3,
+            posix_regex: !matches.is_present("rust-regex"),
             stage: exec_strategy.stage(),
             parse_header,
+            jsonl_input: matches!(ifmt, Some(InputFormat::JSONL { .. })),
         },
         output_record_sep,
         argv,
+        input_files: input_files.clone(),
+        operand_assigns,
     };
     let opt_dump_bytecode = matches.is_present("dump-bytecode");
     let opt_dump_cfg = matches.is_present("dump-cfg");
@@ -554,6 +645,8 @@ fn main() {
     }
     let check_utf8 = matches.is_present("utf8");
     let signal = CancelSignal::default();
+    // Other field separators can only be split in parallel by splitting each record lazily.
+    let parallel = !matches!(exec_strategy, ExecutionStrategy::Serial);
 
     // This horrid macro is here because all of the different ways of reading input are different
     // types, making functions hard to write. Still, there must be something to be done to clean
@@ -579,11 +672,12 @@ fn main() {
                         cfg::SepAssign::Potential {
                             field_sep,
                             record_sep,
+                            field_pat,
                         },
                     ) => {
                         let field_sep = field_sep.unwrap_or(b" ");
                         let record_sep = record_sep.unwrap_or(b"\n");
-                        if field_sep.len() == 1 && record_sep.len() == 1 {
+                        if field_pat.is_none() && field_sep.len() == 1 && record_sep.len() == 1 {
                             if field_sep == b" " && record_sep == b"\n" {
                                 let $inp = ByteReader::new_whitespace(
                                     once((_reader, String::from("-"))),
@@ -605,6 +699,16 @@ fn main() {
                                 );
                                 $body
                             }
+                        } else if field_pat.is_none() && record_sep.len() == 1 && parallel {
+                            let $inp = ByteReader::new_lazy(
+                                once((_reader, String::from("-"))),
+                                record_sep[0],
+                                chunk_size,
+                                check_utf8,
+                                exec_strategy,
+                                signal.clone(),
+                            );
+                            $body
                         } else {
                             let $inp =
                                 chained(RegexSplitter::new(_reader, chunk_size, "-", check_utf8));
@@ -637,10 +741,11 @@ fn main() {
                     cfg::SepAssign::Potential {
                         field_sep,
                         record_sep,
+                        field_pat,
                     } => {
                         let field_sep = field_sep.unwrap_or(b" ");
                         let record_sep = record_sep.unwrap_or(b"\n");
-                        if field_sep.len() == 1 && record_sep.len() == 1 {
+                        if field_pat.is_none() && field_sep.len() == 1 && record_sep.len() == 1 {
                             let file_handles: Vec<_> = input_files
                                 .iter()
                                 .cloned()
@@ -667,6 +772,21 @@ fn main() {
                                 );
                                 $body
                             }
+                        } else if field_pat.is_none() && record_sep.len() == 1 && parallel {
+                            let file_handles: Vec<_> = input_files
+                                .iter()
+                                .cloned()
+                                .map(move |file| (open_file_read(file.as_str()), file))
+                                .collect();
+                            let $inp = ByteReader::new_lazy(
+                                file_handles.into_iter(),
+                                record_sep[0],
+                                chunk_size,
+                                check_utf8,
+                                exec_strategy,
+                                signal.clone(),
+                            );
+                            $body
                         } else {
                             let iter = input_files.iter().cloned().map(|file| {
                                 let reader: Box<dyn io::Read + Send> =
@@ -693,6 +813,17 @@ fn main() {
 
     let a = Arena::default();
     let ctx = get_context(program_string.as_str(), &a, get_prelude(&a, &raw));
+    if ctx.file_events
+        && matches!(
+            exec_strategy,
+            ExecutionStrategy::ShardPerRecord | ExecutionStrategy::ShardPerRecordOrdered
+        )
+    {
+        fail!("BEGINFILE and ENDFILE blocks are not supported with record-level parallelism (-pr)");
+    }
+    if exec_strategy.ordered() && ctx.main_loop_redirects_output() {
+        fail!("--ordered only orders standard output; print and printf in the main loop cannot redirect their output");
+    }
     let analysis_result = ctx.analyze_sep_assignments();
     let out_file = matches.value_of("out-file");
     macro_rules! with_io {
@@ -291,6 +291,10 @@ impl UsedFieldAnalysis {
                 self.dfa.add_query(col_reg);
                 self.dfa.add_src(dst, FieldSet::all());
             }
+            JoinJSON(dst, _) => {
+                self.dfa.add_query(dst);
+                self.dfa.add_src(dst, FieldSet::all());
+            }
             JoinCSV(dst, start, end)
             | JoinTSV(dst, start, end)
             | JoinColumns(dst, start, end, _) => {
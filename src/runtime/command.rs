@@ -1,9 +1,79 @@
 use std::io;
-use std::process::{ChildStdin, ChildStdout, Command, Stdio};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
 
 use crate::runtime::Int;
 
-fn prepare_command(bs: &[u8]) -> io::Result<Command> {
+/// The separator of arguments in commands built with `exec_cmd`: each argument is preceded by a
+/// NUL byte. This only names the command; it is only ever decoded as a `Cmd::Argv`.
+const ARGV_PREFIX: u8 = 0;
+
+/// A command to run, and how to run it.
+#[derive(Copy, Clone)]
+pub enum Cmd<'a> {
+    /// A command line passed to the shell.
+    Shell(&'a [u8]),
+    /// An argv array encoded by `argv_command`, run without a shell.
+    Argv(&'a [u8]),
+}
+
+impl<'a> Cmd<'a> {
+    pub fn bytes(&self) -> &'a [u8] {
+        match self {
+            Cmd::Shell(bs) | Cmd::Argv(bs) => bs,
+        }
+    }
+}
+
+/// Encode `argv` as a command that is run directly, rather than through a shell. An empty `argv`
+/// is encoded as an empty command, which fails to start like any other missing program.
+pub fn argv_command<'a>(argv: impl Iterator<Item = &'a [u8]>) -> io::Result<Vec<u8>> {
+    let mut res = Vec::new();
+    for arg in argv {
+        if arg.contains(&ARGV_PREFIX) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "command arguments cannot contain NUL bytes",
+            ));
+        }
+        res.push(ARGV_PREFIX);
+        res.extend_from_slice(arg);
+    }
+    Ok(res)
+}
+
+#[cfg(unix)]
+fn os_str(bs: &[u8]) -> io::Result<&std::ffi::OsStr> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(std::ffi::OsStr::from_bytes(bs))
+}
+
+#[cfg(not(unix))]
+fn os_str(bs: &[u8]) -> io::Result<&std::ffi::OsStr> {
+    match std::str::from_utf8(bs) {
+        Ok(s) => Ok(s.as_ref()),
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+    }
+}
+
+fn prepare_command(cmd: Cmd) -> io::Result<Command> {
+    let bs = match cmd {
+        Cmd::Argv(bs) => {
+            if bs.first() != Some(&ARGV_PREFIX) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "cannot run an empty command",
+                ));
+            }
+            let mut args = bs[1..].split(|b| *b == ARGV_PREFIX);
+            let mut cmd = Command::new(os_str(args.next().unwrap())?);
+            for arg in args {
+                cmd.arg(os_str(arg)?);
+            }
+            return Ok(cmd);
+        }
+        Cmd::Shell(bs) => bs,
+    };
     let prog = match std::str::from_utf8(bs) {
         Ok(s) => s,
         Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
@@ -19,28 +89,114 @@ fn prepare_command(bs: &[u8]) -> io::Result<Command> {
     }
 }
 
-pub fn run_command(bs: &[u8]) -> Int {
+pub fn run_command(cmd: Cmd) -> Int {
     fn wrap_err(e: Option<i32>) -> Int {
         e.map(Int::from).unwrap_or(1)
     }
-    fn run_command_inner(bs: &[u8]) -> io::Result<Int> {
-        let status = prepare_command(bs)?.status()?;
+    fn run_command_inner(cmd: Cmd) -> io::Result<Int> {
+        let status = prepare_command(cmd)?.status()?;
         Ok(wrap_err(status.code()))
     }
-    match run_command_inner(bs) {
+    match run_command_inner(cmd) {
         Ok(i) => i,
         Err(e) => wrap_err(e.raw_os_error()),
     }
 }
 
-pub fn command_for_write(bs: &[u8]) -> io::Result<ChildStdin> {
-    let mut cmd = prepare_command(bs)?;
+pub fn command_for_write(cmd: Cmd) -> io::Result<ChildStdin> {
+    let mut cmd = prepare_command(cmd)?;
     let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::inherit()).spawn()?;
     Ok(child.stdin.take().unwrap())
 }
 
-pub fn command_for_read(bs: &[u8]) -> io::Result<ChildStdout> {
-    let mut cmd = prepare_command(bs)?;
+pub fn command_for_read(cmd: Cmd) -> io::Result<ChildStdout> {
+    let mut cmd = prepare_command(cmd)?;
     let mut child = cmd.stdin(Stdio::inherit()).stdout(Stdio::piped()).spawn()?;
     Ok(child.stdout.take().unwrap())
 }
+
+/// A coprocess started with `|&`, which owns both of its pipes.
+///
+/// Clones share the process. Each thread keeps its coprocesses in its writers::Registry, and the
+/// two ends are claimed separately: the writers registry takes the input of a coprocess once it
+/// is written to, and the reader registry takes its output once it is read from.
+#[derive(Clone)]
+pub struct Coprocess {
+    cmd: Arc<[u8]>,
+    child: Arc<Mutex<Child>>,
+}
+
+impl Coprocess {
+    /// Start `cmd` with pipes for both its standard input and output.
+    pub fn start(cmd: &[u8]) -> io::Result<Coprocess> {
+        let child = prepare_command(Cmd::Shell(cmd))?
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        Ok(Coprocess {
+            cmd: cmd.into(),
+            child: Arc::new(Mutex::new(child)),
+        })
+    }
+
+    fn take<R>(&self, f: impl FnOnce(&mut Child) -> Option<R>) -> io::Result<R> {
+        f(&mut self.child.lock().unwrap()).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                format!(
+                    "coprocess {:?} has already been closed in this direction",
+                    String::from_utf8_lossy(&self.cmd)
+                ),
+            )
+        })
+    }
+
+    /// Claim the input of the coprocess.
+    pub fn take_stdin(&self) -> io::Result<ChildStdin> {
+        self.take(|child| child.stdin.take())
+    }
+
+    /// Claim the output of the coprocess.
+    pub fn take_stdout(&self) -> io::Result<ChildStdout> {
+        self.take(|child| child.stdout.take())
+    }
+
+    /// Close the input (or the output) of the coprocess if it has not been claimed yet; this is
+    /// how `close(cmd, "to")` sends end-of-file to a coprocess that was never written to.
+    pub fn close_half(&self, input: bool) {
+        let mut child = self.child.lock().unwrap();
+        if input {
+            child.stdin = None;
+        } else {
+            child.stdout = None;
+        }
+    }
+
+    /// Wait for the coprocess to exit, returning its exit status. Callers should close both ends
+    /// of the coprocess first.
+    pub fn wait(&self) -> Int {
+        let mut child = self.child.lock().unwrap();
+        child.stdin = None;
+        child.stdout = None;
+        match child.wait() {
+            Ok(status) => status.code().map(Int::from).unwrap_or(1),
+            Err(e) => e.raw_os_error().map(Int::from).unwrap_or(1),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn argv_commands_skip_the_shell() {
+        let argv = argv_command([&b"true"[..]].iter().cloned()).unwrap();
+        assert_eq!(run_command(Cmd::Argv(&argv[..])), 0);
+        // The same bytes passed to the shell are never decoded as an argv array.
+        assert_ne!(run_command(Cmd::Shell(&argv[..])), 0);
+        assert_ne!(run_command(Cmd::Argv(b"true")), 0);
+        let empty = argv_command(std::iter::empty()).unwrap();
+        assert_eq!(run_command(Cmd::Argv(&empty[..])), 1);
+    }
+}
@@ -0,0 +1,489 @@
+//! Translation of POSIX extended regular expressions (EREs) into the syntax of the `regex` crate.
+//!
+//! Awk patterns are EREs, which differ from Rust's regex syntax in a few places: bracket
+//! expressions can contain character classes like `[:alpha:]`, a `{` that does not begin a valid
+//! interval is a literal, `.` matches a newline, and escape sequences follow Awk's rules. We parse
+//! the ERE into a `regex_syntax` HIR and print it back out as a pattern `regex` can compile.
+//!
+//! Along with POSIX syntax, we support the escape sequences Awk permits in strings, as well as
+//! gawk's `\y`, `\B`, `\w`, `\W`, `\s`, `\S`, `` \` `` and `\'` operators. Anything else that
+//! cannot be translated faithfully (e.g. gawk's `\<` and `\>`) is an error.
+use regex_syntax::hir::{
+    Anchor, Class, ClassUnicode, ClassUnicodeRange, Group, GroupKind, Hir, HirKind, Literal,
+    Repetition, RepetitionKind, RepetitionRange,
+};
+
+use crate::common::Result;
+
+/// Translate the ERE `pat` into a pattern using the syntax of the `regex` crate.
+pub(crate) fn translate(pat: &str) -> Result<String> {
+    let mut parser = Parser {
+        pat,
+        chars: pat.chars().collect(),
+        pos: 0,
+        next_group: 1,
+    };
+    let hir = parser.alternation(0)?;
+    if parser.pos < parser.chars.len() {
+        // The only way to stop early at depth zero is an unmatched ')'.
+        return parser.error("unmatched ')'");
+    }
+    Ok(hir.to_string())
+}
+
+struct Parser<'a> {
+    pat: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+    next_group: u32,
+}
+
+// A member of a bracket expression.
+enum BracketItem {
+    Char(char),
+    Class(ClassUnicode),
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, msg: impl std::fmt::Display) -> Result<T> {
+        err!(
+            "invalid regular expression {:?}: {} (pass --rust-regex to use Rust regex syntax)",
+            self.pat,
+            msg
+        )
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn peek_at(&self, off: usize) -> Option<char> {
+        self.chars.get(self.pos + off).cloned()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let res = self.peek();
+        if res.is_some() {
+            self.pos += 1;
+        }
+        res
+    }
+
+    fn alternation(&mut self, depth: usize) -> Result<Hir> {
+        let mut branches = vec![self.concat(depth)?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.concat(depth)?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Hir::alternation(branches)
+        })
+    }
+
+    fn concat(&mut self, depth: usize) -> Result<Hir> {
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some('|') => break,
+                Some(')') if depth > 0 => break,
+                Some(')') => return self.error("unmatched ')'"),
+                Some(_) => {}
+            }
+            let mut atom = self.atom(depth)?;
+            while let Some(kind) = self.repetition()? {
+                atom = Hir::repetition(Repetition {
+                    kind,
+                    greedy: true,
+                    hir: Box::new(repeatable(atom)),
+                });
+            }
+            items.push(atom);
+        }
+        Ok(Hir::concat(items))
+    }
+
+    // Parse a repetition operator, if there is one at the current position.
+    fn repetition(&mut self) -> Result<Option<RepetitionKind>> {
+        let kind = match self.peek() {
+            Some('*') => RepetitionKind::ZeroOrMore,
+            Some('+') => RepetitionKind::OneOrMore,
+            Some('?') => RepetitionKind::ZeroOrOne,
+            Some('{') => match self.interval()? {
+                Some(kind) => return Ok(Some(kind)),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        Ok(Some(kind))
+    }
+
+    // Parse an interval expression like `{2}`, `{2,}` or `{2,3}`. A `{` that does not begin one of
+    // these is left alone, to be parsed as a literal.
+    fn interval(&mut self) -> Result<Option<RepetitionKind>> {
+        let start = self.pos;
+        self.pos += 1;
+        let min = self.number();
+        let max = if self.peek() == Some(',') {
+            self.pos += 1;
+            Some(self.number())
+        } else {
+            None
+        };
+        if min.is_none() || self.peek() != Some('}') {
+            self.pos = start;
+            return Ok(None);
+        }
+        self.pos += 1;
+        let min = min.unwrap();
+        let range = match max {
+            None => RepetitionRange::Exactly(min),
+            Some(None) => RepetitionRange::AtLeast(min),
+            Some(Some(max)) if max < min => {
+                return self.error(format!("invalid interval {{{},{}}}", min, max))
+            }
+            Some(Some(max)) => RepetitionRange::Bounded(min, max),
+        };
+        Ok(Some(RepetitionKind::Range(range)))
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let mut res: Option<u32> = None;
+        while let Some(d) = self.peek().and_then(|c| c.to_digit(10)) {
+            self.pos += 1;
+            res = Some(res.unwrap_or(0).saturating_mul(10).saturating_add(d));
+        }
+        res
+    }
+
+    fn atom(&mut self, depth: usize) -> Result<Hir> {
+        let c = self.bump().unwrap();
+        Ok(match c {
+            '(' => {
+                if self.peek() == Some('?') {
+                    return self.error("'(?' is not valid in an ERE");
+                }
+                let index = self.next_group;
+                self.next_group += 1;
+                let hir = self.alternation(depth + 1)?;
+                if self.bump() != Some(')') {
+                    return self.error("unmatched '('");
+                }
+                Hir::group(Group {
+                    kind: GroupKind::CaptureIndex(index),
+                    hir: Box::new(hir),
+                })
+            }
+            // Awk matches against strings rather than lines, so '.' matches a newline and anchors
+            // match at the start and end of the text.
+            '.' => Hir::any(false),
+            '^' => Hir::anchor(Anchor::StartText),
+            '$' => Hir::anchor(Anchor::EndText),
+            '[' => Hir::class(Class::Unicode(self.bracket()?)),
+            '\\' => self.escape()?,
+            // This includes repetition operators with nothing to repeat, as in gawk.
+            c => Hir::literal(Literal::Unicode(c)),
+        })
+    }
+
+    fn escape(&mut self) -> Result<Hir> {
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return self.error("trailing backslash"),
+        };
+        Ok(match c {
+            'y' => perl_class(r"\b"),
+            'B' => perl_class(r"\B"),
+            '`' => Hir::anchor(Anchor::StartText),
+            '\'' => Hir::anchor(Anchor::EndText),
+            'w' | 'W' | 's' | 'S' | 'd' | 'D' => perl_class(&format!("\\{}", c)),
+            '<' | '>' => return self.error(format!("'\\{}' is not supported; use '\\y'", c)),
+            _ => match self.escaped_char(c)? {
+                Ok(c) => Hir::literal(Literal::Unicode(c)),
+                Err(b) => Hir::literal(Literal::Byte(b)),
+            },
+        })
+    }
+
+    // Interpret the escape sequence beginning with `c`, returning a byte if the sequence denotes
+    // a non-ASCII byte.
+    fn escaped_char(&mut self, c: char) -> Result<std::result::Result<char, u8>> {
+        let byte = |b: u8| {
+            if b.is_ascii() {
+                Ok(b as char)
+            } else {
+                Err(b)
+            }
+        };
+        Ok(match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            'f' => Ok('\x0c'),
+            'v' => Ok('\x0b'),
+            'a' => Ok('\x07'),
+            'b' => Ok('\x08'),
+            '0'..='7' => {
+                let mut n = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match self.peek().and_then(|c| c.to_digit(8)) {
+                        Some(d) => {
+                            self.pos += 1;
+                            n = n * 8 + d;
+                        }
+                        None => break,
+                    }
+                }
+                if n > 0xff {
+                    return self.error(format!("octal escape \\{:o} is out of range", n));
+                }
+                byte(n as u8)
+            }
+            'x' => {
+                let mut n = match self.peek().and_then(|c| c.to_digit(16)) {
+                    Some(d) => d,
+                    None => return self.error("'\\x' must be followed by hexadecimal digits"),
+                };
+                self.pos += 1;
+                if let Some(d) = self.peek().and_then(|c| c.to_digit(16)) {
+                    self.pos += 1;
+                    n = n * 16 + d;
+                }
+                byte(n as u8)
+            }
+            c if c.is_alphanumeric() => {
+                return self.error(format!("unsupported escape sequence '\\{}'", c))
+            }
+            // Any other escaped character stands for itself.
+            c => Ok(c),
+        })
+    }
+
+    fn bracket(&mut self) -> Result<ClassUnicode> {
+        let negated = if self.peek() == Some('^') {
+            self.pos += 1;
+            true
+        } else {
+            false
+        };
+        let mut class = ClassUnicode::empty();
+        let mut first = true;
+        loop {
+            let item = match self.peek() {
+                None => return self.error("unterminated bracket expression"),
+                Some(']') if !first => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => self.bracket_item()?,
+            };
+            first = false;
+            let start = match item {
+                BracketItem::Class(c) => {
+                    class.union(&c);
+                    continue;
+                }
+                BracketItem::Char(c) => c,
+            };
+            // A '-' is a literal if it comes at the end of the expression.
+            if self.peek() == Some('-') && !matches!(self.peek_at(1), Some(']') | None) {
+                self.pos += 1;
+                let end = match self.bracket_item()? {
+                    BracketItem::Char(c) => c,
+                    BracketItem::Class(_) => {
+                        return self.error("a character class cannot end a range")
+                    }
+                };
+                if end < start {
+                    return self.error(format!("invalid range {:?}-{:?}", start, end));
+                }
+                class.push(ClassUnicodeRange::new(start, end));
+            } else {
+                class.push(ClassUnicodeRange::new(start, start));
+            }
+        }
+        if negated {
+            class.negate();
+        }
+        if class.ranges().is_empty() {
+            return self.error("bracket expression matches nothing");
+        }
+        Ok(class)
+    }
+
+    fn bracket_item(&mut self) -> Result<BracketItem> {
+        let c = self.bump().unwrap();
+        let delim = match (c, self.peek()) {
+            ('[', Some(d @ (':' | '=' | '.'))) => d,
+            ('\\', _) => {
+                let c = match self.bump() {
+                    Some(c) => c,
+                    None => return self.error("trailing backslash"),
+                };
+                if let 'w' | 'W' | 's' | 'S' | 'd' | 'D' = c {
+                    match perl_class(&format!("\\{}", c)).into_kind() {
+                        HirKind::Class(Class::Unicode(cls)) => return Ok(BracketItem::Class(cls)),
+                        _ => unreachable!(),
+                    }
+                }
+                return match self.escaped_char(c)? {
+                    Ok(c) => Ok(BracketItem::Char(c)),
+                    Err(b) => self.error(format!(
+                        "non-ASCII byte \\x{:x} cannot appear in a bracket expression",
+                        b
+                    )),
+                };
+            }
+            (c, _) => return Ok(BracketItem::Char(c)),
+        };
+        self.pos += 1;
+        let start = self.pos;
+        loop {
+            match self.bump() {
+                None => return self.error("unterminated bracket expression"),
+                Some(c) if c == delim && self.peek() == Some(']') => break,
+                Some(_) => {}
+            }
+        }
+        let name: String = self.chars[start..self.pos - 1].iter().collect();
+        self.pos += 1;
+        if delim == ':' {
+            return match posix_class(&name) {
+                Some(cls) => Ok(BracketItem::Class(cls)),
+                None => self.error(format!("unknown character class [:{}:]", name)),
+            };
+        }
+        // Equivalence classes and collating symbols are only supported for single characters, where
+        // they stand for the character itself.
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(BracketItem::Char(c)),
+            _ => self.error(format!(
+                "unsupported collating element [{}{}{}]",
+                delim, name, delim
+            )),
+        }
+    }
+}
+
+// Wrap `hir` in a non-capturing group if it would not parse as the operand of a repetition
+// operator when printed.
+fn repeatable(hir: Hir) -> Hir {
+    match hir.kind() {
+        HirKind::Literal(_) | HirKind::Class(_) | HirKind::Group(_) => hir,
+        _ => Hir::group(Group {
+            kind: GroupKind::NonCapturing,
+            hir: Box::new(hir),
+        }),
+    }
+}
+
+// Use the `regex_syntax` translation of a Perl-style escape like `\w`.
+fn perl_class(pat: &str) -> Hir {
+    regex_syntax::Parser::new().parse(pat).unwrap()
+}
+
+fn posix_class(name: &str) -> Option<ClassUnicode> {
+    let ranges: &[(char, char)] = match name {
+        "alnum" => &[('0', '9'), ('A', 'Z'), ('a', 'z')],
+        "alpha" => &[('A', 'Z'), ('a', 'z')],
+        "blank" => &[(' ', ' '), ('\t', '\t')],
+        "cntrl" => &[('\0', '\x1f'), ('\x7f', '\x7f')],
+        "digit" => &[('0', '9')],
+        "graph" => &[('!', '~')],
+        "lower" => &[('a', 'z')],
+        "print" => &[(' ', '~')],
+        "punct" => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+        "space" => &[(' ', ' '), ('\t', '\r')],
+        "upper" => &[('A', 'Z')],
+        "xdigit" => &[('0', '9'), ('A', 'F'), ('a', 'f')],
+        _ => return None,
+    };
+    Some(ClassUnicode::new(
+        ranges
+            .iter()
+            .map(|(lo, hi)| ClassUnicodeRange::new(*lo, *hi)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::bytes::Regex;
+
+    fn matches(pat: &str, s: &str) -> bool {
+        let translated = translate(pat).unwrap();
+        Regex::new(&translated)
+            .unwrap_or_else(|e| panic!("{:?} translated to {:?}: {}", pat, translated, e))
+            .is_match(s.as_bytes())
+    }
+
+    #[test]
+    fn bracket_expressions() {
+        assert!(matches("^[[:alpha:]]+$", "abcXYZ"));
+        assert!(!matches("^[[:alpha:]]+$", "abc1"));
+        assert!(matches("^[^[:digit:][:space:]]$", "x"));
+        assert!(!matches("^[^[:digit:][:space:]]$", " "));
+        assert!(matches("^[]a]+$", "]a]"));
+        assert!(matches("^[^]a]$", "b"));
+        assert!(matches("^[a-]+$", "-a-"));
+        assert!(matches("^[[.-.]x]+$", "x-"));
+        assert!(matches(r"^[\]\\]+$", r"\]"));
+        assert!(matches(r"^[[:punct:]]+$", "!{}~"));
+        assert!(matches("[^a]", "\n"));
+    }
+
+    #[test]
+    fn braces_and_repetition() {
+        assert!(matches("^a{2}$", "aa"));
+        assert!(matches("^a{2,}$", "aaaa"));
+        assert!(matches("^(ab){1,2}$", "abab"));
+        assert!(!matches("^(ab){1,2}$", "ababab"));
+        // Braces that do not form an interval are literals.
+        assert!(matches("^a{$", "a{"));
+        assert!(matches("^{x}$", "{x}"));
+        assert!(matches("^a{,2}$", "a{,2}"));
+        assert!(matches("^a{1,x}$", "a{1,x}"));
+        assert!(matches("^*a$", "*a"));
+        assert!(matches("^x(+)$", "x+"));
+        assert!(matches("^a+*$", "aaa"));
+    }
+
+    #[test]
+    fn escapes_and_anchors() {
+        assert!(matches(r"^a\.b$", "a.b"));
+        assert!(!matches(r"^a\.b$", "axb"));
+        assert!(matches("a.b", "a\nb"));
+        assert!(matches(r"\t\/\101", "\t/A"));
+        assert!(matches(r"\yfoo\y", "a foo b"));
+        assert!(!matches(r"\yfoo\y", "afoo"));
+        assert!(matches(r"^\w+\s\S$", "ab_1 !"));
+        assert!(!matches("x^", "x^"));
+        assert!(matches("(^a|b$)", "ab"));
+        assert!(matches("", "anything"));
+        assert!(matches("a|", "b"));
+    }
+
+    #[test]
+    fn untranslatable() {
+        for pat in &[
+            "(?i)a",
+            "a)",
+            "(a",
+            "[a",
+            "[[:alpha:]",
+            "[[:bogus:]]",
+            "[z-a]",
+            "[[.ch.]]",
+            r"\<word\>",
+            r"\q",
+            "a{3,2}",
+            "a\\",
+            r"[\xff]",
+        ] {
+            assert!(translate(pat).is_err(), "{:?} should not translate", pat);
+        }
+    }
+}
@@ -75,6 +75,28 @@ pub fn strtod(bs: &[u8]) -> f64 {
     }
 }
 
+/// Parse `bs` as a number if the whole string, up to leading and trailing blanks, looks like one.
+/// This is POSIX's test for whether input that may hold a number ("strnum") compares
+/// numerically.
+pub fn strnum(bs: &[u8]) -> Option<f64> {
+    let is_blank = |b: &u8| matches!(*b, b' ' | b'\t' | b'\n');
+    let start = bs.iter().position(|b| !is_blank(b))?;
+    let end = bs.iter().rposition(|b| !is_blank(b)).unwrap() + 1;
+    let bs = &bs[start..end];
+    // fast_float also accepts spellings like "inf" and "nan"; Awk does not.
+    let unsigned = match bs {
+        [b'+' | b'-', rest @ ..] => rest,
+        _ => bs,
+    };
+    if !matches!(unsigned.first(), Some(b'0'..=b'9' | b'.')) {
+        return None;
+    }
+    match fast_float::parse_partial::<f64, _>(bs) {
+        Ok((f, len)) if len == bs.len() => Some(f),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -94,4 +116,23 @@ mod tests {
         assert_eq!(strtod(imax.as_bytes()), i64::max_value() as f64);
         assert_eq!(strtod(imin.as_bytes()), i64::min_value() as f64);
     }
+
+    #[test]
+    fn strnums() {
+        assert_eq!(strnum(b"12"), Some(12.0));
+        assert_eq!(strnum(b" -1.5e3\t"), Some(-1500.0));
+        assert_eq!(strnum(b"+.5"), Some(0.5));
+        for bs in &[
+            &b""[..],
+            b"  ",
+            b"12abc",
+            b"0x1A",
+            b"inf",
+            b"-nan",
+            b".",
+            b"1 2",
+        ] {
+            assert_eq!(strnum(bs), None, "{:?}", String::from_utf8_lossy(bs));
+        }
+    }
 }
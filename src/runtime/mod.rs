@@ -1,4 +1,4 @@
-use crate::common::{FileSpec, Result};
+use crate::common::{FileSpec, InputSpec, Result};
 use hashbrown::HashMap;
 use regex::bytes::Regex;
 use std::cell::{Cell, RefCell};
@@ -10,41 +10,79 @@ use std::mem;
 use std::process::ChildStdout;
 use std::rc::Rc;
 use std::str;
+use std::sync::Arc;
 
 mod command;
+pub(crate) mod ere;
 pub mod float_parse;
 pub mod printf;
+pub(crate) mod sort;
 pub mod splitter;
 pub mod str_impl;
 pub mod string_search;
+pub mod time;
 pub mod utf8;
 pub mod writers;
 
+use crate::builtins::Variable;
 use crate::pushdown::FieldSet;
-use splitter::regex::RegexSplitter;
+use sort::{SortOrder, Sortable};
+use splitter::{compress::Decompress, regex::RegexSplitter, SplitMode};
 
 // TODO: remove the pub use for Variables here.
 pub(crate) use crate::builtins::Variables;
-pub use command::run_command;
-pub(crate) use float_parse::{hextoi, strtod, strtoi};
+pub use command::{run_command, Cmd};
+pub(crate) use float_parse::{hextoi, strnum, strtod, strtoi};
 pub(crate) use printf::FormatArg;
 pub use splitter::{
     batch::{escape_csv, escape_tsv},
+    json::{escape_json, float_to_json, join_json, Keys as JSONKeys},
     ChainedReader, Line, LineReader,
 };
 pub use str_impl::{Str, UniqueStr};
 
-#[derive(Default)]
-pub struct RegexCache(Registry<Regex>);
+/// Compile `pat`, interpreting it as a POSIX extended regular expression if `posix` is set and
+/// with the syntax of the `regex` crate otherwise.
+pub(crate) fn compile_regex(pat: &str, posix: bool) -> Result<Regex> {
+    let translated;
+    let pat = if posix {
+        translated = ere::translate(pat)?;
+        translated.as_str()
+    } else {
+        pat
+    };
+    match Regex::new(pat) {
+        Ok(r) => Ok(r),
+        Err(e) => err!("{}", e),
+    }
+}
+
+pub struct RegexCache {
+    regexes: Registry<Regex>,
+    posix: bool,
+}
+
+impl Default for RegexCache {
+    fn default() -> RegexCache {
+        RegexCache::new(/*posix=*/ true)
+    }
+}
 
 impl RegexCache {
+    pub(crate) fn new(posix: bool) -> RegexCache {
+        RegexCache {
+            regexes: Default::default(),
+            posix,
+        }
+    }
+    pub(crate) fn posix(&self) -> bool {
+        self.posix
+    }
     pub(crate) fn with_regex<T>(&mut self, pat: &Str, mut f: impl FnMut(&Regex) -> T) -> Result<T> {
-        self.0.get(
+        let posix = self.posix;
+        self.regexes.get(
             pat,
-            |s| match Regex::new(s) {
-                Ok(r) => Ok(r),
-                Err(e) => err!("{}", e),
-            },
+            |s| compile_regex(s, posix),
             // eta-expansion required to get this compiling..
             |x| f(x),
         )
@@ -54,12 +92,10 @@ impl RegexCache {
         pat: &Str,
         mut f: impl FnMut(&Regex) -> Result<T>,
     ) -> Result<T> {
-        self.0.get_fallible(
+        let posix = self.posix;
+        self.regexes.get_fallible(
             pat,
-            |s| match Regex::new(s) {
-                Ok(r) => Ok(r),
-                Err(e) => err!("{}", e),
-            },
+            |s| compile_regex(s, posix),
             // eta-expansion required to get this compiling..
             |x| f(x),
         )
@@ -70,16 +106,21 @@ impl RegexCache {
         file: &Str<'a>,
         pat: &Str<'a>,
         reg: &mut FileRead<LR>,
-        is_file: bool,
+        write_files: &mut FileWrite,
+        spec: InputSpec,
     ) -> Result<Str<'a>> {
-        Ok(if is_file {
-            reg.with_file(file, |reader| {
+        Ok(match spec {
+            InputSpec::File => reg.with_file(file, |reader| {
                 self.with_regex(pat, |re| reader.read_line_regex(re))
-            })?
-        } else {
-            reg.with_cmd(file, |reader| {
+            })?,
+            InputSpec::Cmd | InputSpec::Exec => {
+                reg.with_cmd(file, spec == InputSpec::Exec, |reader| {
+                    self.with_regex(pat, |re| reader.read_line_regex(re))
+                })?
+            }
+            InputSpec::Coproc => reg.with_coproc(file, write_files, |reader| {
                 self.with_regex(pat, |re| reader.read_line_regex(re))
-            })?
+            })?,
         }
         .clone()
         .upcast())
@@ -91,7 +132,13 @@ impl RegexCache {
         pat: &Str<'a>,
         reg: &mut FileRead<LR>,
     ) -> Result<(/* file changed */ bool, Str<'a>)> {
-        let (changed, mut line) = reg.stdin.read_line(pat, self)?;
+        let (mut changed, mut line) = reg.stdin.read_line(pat, self)?;
+        // getline reads the next record, skipping past the start and end of any input files.
+        while reg.stdin.read_state() > splitter::ReaderState::OK as Int {
+            let (next_changed, next) = reg.stdin.read_line(pat, self)?;
+            changed |= next_changed;
+            line = next;
+        }
         // NB both of these `pat`s are "wrong" but we are fine because they are only used
         // when the column is nonzero, or someone has overwritten a nonzero column.
         Ok((changed, line.get_col(0, pat, pat, self)?.clone().upcast()))
@@ -112,7 +159,7 @@ impl RegexCache {
         mut push: impl FnMut(Str<'a>),
     ) -> Result<()> {
         if pat == &Str::from(" ") {
-            self.with_regex(&Str::from(r#"[ \t]+"#), |re| {
+            self.with_regex(&Str::from(r#"[ \t\n]+"#), |re| {
                 s.split(
                     re,
                     |s, is_empty| {
@@ -149,6 +196,50 @@ impl RegexCache {
         self.split_internal(pat, s, used_fields, |s| v.push(s))
     }
 
+    // With FPAT set, the fields of a record are the successive non-overlapping matches of the
+    // pattern, rather than the text between matches.
+    pub(crate) fn split_regex_fpat<'a>(
+        &mut self,
+        pat: &Str,
+        s: &Str<'a>,
+        used_fields: &FieldSet,
+        v: &mut Vec<Str<'a>>,
+    ) -> Result<()> {
+        self.with_regex(pat, |re| {
+            s.with_bytes(|bs| {
+                for (i, m) in re.find_iter(bs).enumerate() {
+                    v.push(if used_fields.get(i + 1) {
+                        s.slice(m.start(), m.end())
+                    } else {
+                        Str::default()
+                    });
+                }
+            })
+        })
+    }
+
+    // In paragraph mode (RS = ""), newlines separate fields in addition to FS. The default FS
+    // already splits on newlines.
+    pub(crate) fn split_regex_paragraph<'a>(
+        &mut self,
+        pat: &Str,
+        s: &Str<'a>,
+        used_fields: &FieldSet,
+        v: &mut Vec<Str<'a>>,
+    ) -> Result<()> {
+        if pat == &Str::from(" ") {
+            return self.split_regex(pat, s, used_fields, v);
+        }
+        let pat = pat.with_bytes(|bs| {
+            let mut buf = Vec::with_capacity(bs.len() + 5);
+            buf.push(b'(');
+            buf.extend_from_slice(bs);
+            buf.extend_from_slice(b")|\n");
+            Str::from(&buf[..]).unmoor()
+        });
+        self.split_regex(&pat, s, used_fields, v)
+    }
+
     pub(crate) fn split_regex_intmap<'a>(
         &mut self,
         pat: &Str<'a>,
@@ -180,7 +271,6 @@ impl RegexCache {
     }
 
     pub(crate) fn regex_const_match_loc(vars: &mut Variables, re: &Regex, s: &Str) -> Result<Int> {
-        use crate::builtins::Variable;
         let (start, len) = s.with_bytes(|bs| match re.find(bs) {
             Some(m) => {
                 let start = m.start() as Int;
@@ -202,6 +292,79 @@ impl RegexCache {
         self.with_regex_fallible(pat, |re| Self::regex_const_match_loc(vars, re, s))
     }
 
+    pub(crate) fn regex_match_captures_intmap<'a>(
+        &mut self,
+        vars: &mut Variables,
+        pat: &Str,
+        s: &Str<'a>,
+        arr: &IntMap<Str<'a>>,
+    ) -> Result<Int> {
+        let mut arr_b = arr.0.borrow_mut();
+        arr_b.clear();
+        self.match_captures_internal(vars, pat, s, |i, group| {
+            arr_b.insert(i, group);
+        })
+    }
+
+    pub(crate) fn regex_match_captures_strmap<'a>(
+        &mut self,
+        vars: &mut Variables,
+        pat: &Str,
+        s: &Str<'a>,
+        arr: &StrMap<'a, Str<'a>>,
+    ) -> Result<Int> {
+        let mut arr_b = arr.0.borrow_mut();
+        arr_b.clear();
+        self.match_captures_internal(vars, pat, s, |i, group| {
+            arr_b.insert(convert::<i64, Str<'_>>(i), group);
+        })
+    }
+
+    // match(s, re, arr): like regex_match_loc, but arr[0] is also set to the matched text and
+    // arr[i] to the text of the ith capture group, for each group that took part in the match.
+    fn match_captures_internal<'a>(
+        &mut self,
+        vars: &mut Variables,
+        pat: &Str,
+        s: &Str<'a>,
+        mut insert: impl FnMut(Int, Str<'a>),
+    ) -> Result<Int> {
+        self.with_regex_fallible(pat, |re| {
+            let (start, len) = s.with_bytes(|bs| match re.captures(bs) {
+                Some(caps) => {
+                    for (i, group) in caps.iter().enumerate() {
+                        if let Some(group) = group {
+                            insert(i as Int, s.slice(group.start(), group.end()));
+                        }
+                    }
+                    let m = caps.get(0).unwrap();
+                    (m.start() as Int + 1, (m.end() - m.start()) as Int)
+                }
+                None => (0, -1),
+            });
+            vars.store_int(Variable::RSTART, start)?;
+            vars.store_int(Variable::RLENGTH, len)?;
+            Ok(start)
+        })
+    }
+
+    // gensub(re, repl, how, s): `how` is either a string starting with "g" or "G", in which case
+    // every match is replaced, or the number of the match to replace.
+    pub(crate) fn gensub<'a>(
+        &mut self,
+        pat: &Str,
+        repl: &Str<'a>,
+        how: &Str,
+        s: &Str<'a>,
+    ) -> Result<Str<'a>> {
+        let nth = if how.with_bytes(|bs| matches!(bs.first(), Some(b'g') | Some(b'G'))) {
+            None
+        } else {
+            Some(std::cmp::max(convert::<&Str, Int>(how), 1) as usize)
+        };
+        self.with_regex(pat, |re| s.subst_captures(re, repl, nth))
+    }
+
     pub(crate) fn regex_const_match(pat: &Regex, s: &Str) -> bool {
         s.with_bytes(|bs| pat.is_match(bs))
     }
@@ -227,6 +390,11 @@ impl FileWrite {
     pub(crate) fn close(&mut self, path: &Str) -> Result<()> {
         self.0.close(path)
     }
+    /// Flush `path`, or all output if `path` is empty. Returns 0 on success, or -1 if `path` is
+    /// not an open output file or command.
+    pub(crate) fn flush(&mut self, path: &Str) -> Result<Int> {
+        Ok(if self.0.flush(path)? { 0 } else { -1 })
+    }
     pub(crate) fn new(ff: impl writers::FileFactory) -> FileWrite {
         FileWrite(writers::Registry::from_factory(ff))
     }
@@ -240,6 +408,7 @@ impl FileWrite {
         path: Option<(&Str, FileSpec)>,
         spec: &Str,
         pa: &[printf::FormatArg],
+        convfmt: &Str,
     ) -> Result<()> {
         let (handle, fspec) = if let Some((out_file, fspec)) = path {
             (self.0.get_handle(Some(out_file), fspec)?, fspec)
@@ -250,7 +419,7 @@ impl FileWrite {
             )
         };
         let mut text = str_impl::DynamicBuf::default();
-        spec.with_bytes(|spec| printf::printf(&mut text, spec, pa))?;
+        spec.with_bytes(|spec| printf::printf(&mut text, spec, pa, convfmt))?;
         let s = unsafe { text.into_str() };
         handle.write(&s, fspec)
     }
@@ -269,12 +438,70 @@ impl FileWrite {
     }
 }
 
+/// Implements `close(file, how)`. An empty `how` closes everything open under the name `file`,
+/// waiting for it to exit if it is a coprocess; "to" and "from" close only the input or the
+/// output of the coprocess `file`.
+pub(crate) fn close_file<LR: LineReader>(
+    write_files: &mut FileWrite,
+    read_files: &mut FileRead<LR>,
+    file: &Str,
+    how: &Str,
+) -> Result<()> {
+    let (to, from) = how.with_bytes(|bs| match bs {
+        b"" => Ok((true, true)),
+        b"to" => Ok((true, false)),
+        b"from" => Ok((false, true)),
+        _ => err!(
+            "close: expected \"to\" or \"from\" as the second argument, got {:?}",
+            String::from_utf8_lossy(bs)
+        ),
+    })?;
+    match (to, from) {
+        (true, false) => write_files.0.close_coproc(file)?,
+        (false, true) => read_files.close_coproc(file, write_files),
+        _ => {
+            // NB this may create an unused entry in write_files. It would not be terribly
+            // difficult to optimize the close path to include an existence check first.
+            write_files.close(file)?;
+            read_files.close(file);
+            write_files.0.wait_coproc(file);
+        }
+    }
+    Ok(())
+}
+
+/// Implements `exec_cmd(argv)`: returns `argv[1]` through `argv[length(argv)]` as a command that
+/// is run directly, without a shell.
+pub(crate) fn exec_command<'a>(argv: &IntMap<Str<'a>>) -> Result<Str<'a>> {
+    use std::io::Write;
+    let len = argv.len() as Int;
+    let mut args = Vec::with_capacity(len as usize);
+    for i in 1..=len {
+        if !argv.contains(&i) {
+            return err!(
+                "exec: argv must hold elements 1 through {}, but element {} is missing",
+                len,
+                i
+            );
+        }
+        args.push(argv.get(&i).with_bytes(|bs| bs.to_vec()));
+    }
+    let cmd = match command::argv_command(args.iter().map(|arg| &arg[..])) {
+        Ok(cmd) => cmd,
+        Err(e) => return err!("exec: {}", e),
+    };
+    let mut buf = str_impl::DynamicBuf::new(cmd.len());
+    buf.write_all(&cmd[..]).unwrap();
+    Ok(unsafe { buf.into_str() })
+}
+
 pub const CHUNK_SIZE: usize = 8 << 10;
 
 #[derive(Default)]
 pub(crate) struct Inputs {
-    files: Registry<RegexSplitter<File>>,
+    files: Registry<RegexSplitter<Decompress<File>>>,
     commands: Registry<RegexSplitter<ChildStdout>>,
+    coprocs: Registry<RegexSplitter<ChildStdout>>,
 }
 
 pub(crate) struct FileRead<LR = RegexSplitter<Box<dyn io::Read + Send>>> {
@@ -312,13 +539,23 @@ impl<LR: LineReader> FileRead<LR> {
 
     pub(crate) fn close(&mut self, path: &Str) {
         self.inputs.files.remove(path);
+        self.inputs.coprocs.remove(path);
+    }
+
+    /// Close the output of the coprocess `cmd`, as in `close(cmd, "from")`.
+    pub(crate) fn close_coproc(&mut self, cmd: &Str, write_files: &mut FileWrite) {
+        self.inputs.coprocs.remove(cmd);
+        write_files.0.close_coproc_output(cmd);
     }
 
     pub(crate) fn new(
-        stdin: LR,
+        mut stdin: LR,
         used_fields: FieldSet,
         named_columns: Option<Vec<&[u8]>>,
     ) -> FileRead<LR> {
+        if let Some(cs) = &named_columns {
+            stdin.declare_columns(cs);
+        }
         let backup_used_fields = used_fields;
         let used_fields = if named_columns.is_some() {
             // In header-parsing mode we parse all columns until `update_named_columns` is called
@@ -372,10 +609,43 @@ impl<LR: LineReader> FileRead<LR> {
         self.stdin.set_used_fields(&self.used_fields)
     }
 
+    // Add any columns the main input has assigned to new names since the last read to FI.
+    pub(crate) fn update_fi<'a>(&mut self, fi: &StrMap<'a, Int>) {
+        for (name, col) in self.stdin.take_new_columns() {
+            fi.insert(name.upcast(), col);
+        }
+    }
+
     pub(crate) fn stdin_filename(&self) -> Str<'static> {
         self.stdin.filename()
     }
 
+    pub(crate) fn stdin_fnr_offset(&self) -> Int {
+        self.stdin.fnr_offset()
+    }
+
+    pub(crate) fn stdin_terminator(&self) -> Option<Str<'static>> {
+        self.stdin.last_terminator()
+    }
+
+    // Assigning FIELDWIDTHS or FPAT switches the main input to fixed-width or pattern-based
+    // splitting, and assigning FS switches it back.
+    pub(crate) fn update_splitting<'a>(&mut self, var: Variable, s: &Str<'a>) -> Result<()> {
+        match var {
+            Variable::FIELDWIDTHS => {
+                let widths = s.with_bytes(splitter::fixed::FieldWidths::parse)?;
+                self.stdin
+                    .set_split_mode(SplitMode::Fixed(Arc::new(widths)));
+            }
+            Variable::FPAT => self
+                .stdin
+                .set_split_mode(SplitMode::Pattern(s.clone().unmoor())),
+            Variable::FS => self.stdin.set_split_mode(SplitMode::Regex),
+            _ => {}
+        }
+        Ok(())
+    }
+
     pub(crate) fn read_err_stdin(&mut self) -> Int {
         self.stdin.read_state()
     }
@@ -383,8 +653,15 @@ impl<LR: LineReader> FileRead<LR> {
     pub(crate) fn read_err<'a>(&mut self, path: &Str<'a>) -> Result<Int> {
         self.with_file(path, |reader| Ok(reader.read_state()))
     }
-    pub(crate) fn read_err_cmd<'a>(&mut self, cmd: &Str<'a>) -> Result<Int> {
-        self.with_cmd(cmd, |reader| Ok(reader.read_state()))
+    pub(crate) fn read_err_cmd<'a>(&mut self, cmd: &Str<'a>, argv: bool) -> Result<Int> {
+        self.with_cmd(cmd, argv, |reader| Ok(reader.read_state()))
+    }
+    pub(crate) fn read_err_coproc<'a>(
+        &mut self,
+        cmd: &Str<'a>,
+        write_files: &mut FileWrite,
+    ) -> Result<Int> {
+        self.with_coproc(cmd, write_files, |reader| Ok(reader.read_state()))
     }
 
     pub(crate) fn next_file(&mut self) -> Result<()> {
@@ -392,15 +669,21 @@ impl<LR: LineReader> FileRead<LR> {
         Ok(())
     }
 
+    // Read from `cmd`, or from the command built by `exec_cmd` if `argv` is set.
     fn with_cmd<'a, R>(
         &mut self,
         cmd: &Str<'a>,
+        argv: bool,
         f: impl FnMut(&mut RegexSplitter<ChildStdout>) -> Result<R>,
     ) -> Result<R> {
         let check_utf8 = self.stdin.check_utf8();
         self.inputs.commands.get_fallible(
             cmd,
-            |s| match command::command_for_read(s.as_bytes()) {
+            |s| match command::command_for_read(if argv {
+                Cmd::Argv(s.as_bytes())
+            } else {
+                Cmd::Shell(s.as_bytes())
+            }) {
                 Ok(r) => Ok(RegexSplitter::new(
                     r,
                     CHUNK_SIZE,
@@ -413,17 +696,38 @@ impl<LR: LineReader> FileRead<LR> {
         )
     }
 
+    // Read from the coprocess `cmd`, which is started by (and kept in) `write_files`.
+    fn with_coproc<'a, R>(
+        &mut self,
+        cmd: &Str<'a>,
+        write_files: &mut FileWrite,
+        f: impl FnMut(&mut RegexSplitter<ChildStdout>) -> Result<R>,
+    ) -> Result<R> {
+        let check_utf8 = self.stdin.check_utf8();
+        self.inputs.coprocs.get_fallible(
+            cmd,
+            |_| match write_files.0.coprocess(cmd)?.take_stdout() {
+                Ok(r) => Ok(
+                    RegexSplitter::new(r, CHUNK_SIZE, cmd.clone().unmoor(), check_utf8)
+                        .interactive(),
+                ),
+                Err(e) => err!("failed to read from coprocess: {}", e),
+            },
+            f,
+        )
+    }
+
     fn with_file<'a, R>(
         &mut self,
         path: &Str<'a>,
-        f: impl FnMut(&mut RegexSplitter<File>) -> Result<R>,
+        f: impl FnMut(&mut RegexSplitter<Decompress<File>>) -> Result<R>,
     ) -> Result<R> {
         let check_utf8 = self.stdin.check_utf8();
         self.inputs.files.get_fallible(
             path,
             |s| match File::open(s) {
                 Ok(f) => Ok(RegexSplitter::new(
-                    f,
+                    Decompress::new(f, s),
                     CHUNK_SIZE,
                     path.clone().unmoor(),
                     check_utf8,
@@ -540,6 +844,44 @@ impl<'b, 'a> Convert<&'b Str<'a>, Int> for _Carrier {
     }
 }
 
+/// Convert `f` to a string using `fmt`, the current value of `CONVFMT` or `OFMT`.
+///
+/// As in other awks, integral values are printed as integers regardless of the format; those too
+/// large for an `Int` use the same (ryu-based) conversion as `Convert<Float, Str>`. Otherwise we
+/// fall back to `printf`, unless `fmt` is the default, in which case we use that conversion too.
+pub(crate) fn format_float<'a>(fmt: &Str, f: Float) -> Result<Str<'a>> {
+    if f.is_finite() && f.fract() == 0.0 {
+        // `Int::MAX as Float` rounds up to 2^63, which does not fit in an Int.
+        if f >= Int::MIN as Float && f < Int::MAX as Float {
+            return Ok(convert::<_, Str>(f as Int));
+        }
+        return Ok(convert::<_, Str>(f));
+    }
+    if fmt.with_bytes(|bs| bs == crate::builtins::DEFAULT_FLOAT_FMT.as_bytes()) {
+        return Ok(convert::<_, Str>(f));
+    }
+    // Should `fmt` print the float as a string, there is no other format to fall back on.
+    let default = Str::from(crate::builtins::DEFAULT_FLOAT_FMT);
+    let mut buf = Vec::new();
+    fmt.with_bytes(|spec| printf::printf(&mut buf, spec, &[FormatArg::F(f)], &default))?;
+    Ok(Str::from(&buf[..]).unmoor().upcast())
+}
+
+/// Compare a string that may hold a number with a number, as in [Str::strnum_cmp]: numerically
+/// if `s` has a numeric value, and otherwise as strings, converting `f` according to the format
+/// returned by `convfmt`.
+pub(crate) fn strnum_cmp_float<'a>(
+    s: &Str,
+    f: Float,
+    convfmt: impl FnOnce() -> Result<Str<'a>>,
+) -> Result<std::cmp::Ordering> {
+    if let Some(n) = s.strnum() {
+        return Ok(n.partial_cmp(&f).unwrap_or(std::cmp::Ordering::Equal));
+    }
+    let fs = format_float(&convfmt()?, f)?;
+    Ok(s.with_bytes(|bs1| fs.with_bytes(|bs2| bs1.cmp(bs2))))
+}
+
 pub(crate) trait Inc {
     fn inc_int(&mut self, by: Int);
     fn inc_float(&mut self, by: Float);
@@ -662,6 +1004,16 @@ impl<'a> From<Shuttle<HashMap<UniqueStr<'a>, Int>>> for StrMap<'a, Int> {
     }
 }
 
+impl<'a> From<Shuttle<HashMap<UniqueStr<'a>, UniqueStr<'a>>>> for StrMap<'a, Str<'a>> {
+    fn from(sh: Shuttle<HashMap<UniqueStr<'a>, UniqueStr<'a>>>) -> Self {
+        SharedMap(Rc::new(RefCell::new(
+            sh.0.into_iter()
+                .map(|(x, y)| (x.into_str(), y.into_str()))
+                .collect(),
+        )))
+    }
+}
+
 impl<K, V> SharedMap<K, V> {
     fn borrow_mut(&self) -> impl std::ops::DerefMut<Target = HashMap<K, V>> + '_ {
         // Unlike the full std::collections APIs, we are careful not to hand out any references
@@ -730,12 +1082,28 @@ impl<'a> StrMap<'a, Int> {
     }
 }
 
-impl<K: Hash + Eq + Clone, V> SharedMap<K, V> {
-    pub(crate) fn to_iter(&self) -> Iter<K> {
-        self.0.borrow().keys().cloned().collect()
+impl<'a> StrMap<'a, Str<'a>> {
+    pub(crate) fn shuttle(&self) -> Shuttle<HashMap<UniqueStr<'a>, UniqueStr<'a>>> {
+        Shuttle(
+            self.0
+                .borrow()
+                .iter()
+                .map(|(x, y)| (UniqueStr::from(x.clone()), UniqueStr::from(y.clone())))
+                .collect(),
+        )
+    }
+}
+
+impl<'a, K: Hash + Eq + Clone + Sortable<'a>, V: Clone + Sortable<'a>> SharedMap<K, V> {
+    pub(crate) fn to_iter(&self, order: SortOrder) -> Iter<K> {
+        self.to_vec(order).into_iter().collect()
     }
-    pub(crate) fn to_vec(&self) -> Vec<K> {
-        self.0.borrow().keys().cloned().collect()
+    pub(crate) fn to_vec(&self, order: SortOrder) -> Vec<K> {
+        if order.is_unsorted() {
+            return self.0.borrow().keys().cloned().collect();
+        }
+        let entries = sort::sorted_entries(self, order).into_iter();
+        entries.map(|(k, _)| k).collect()
     }
 }
 
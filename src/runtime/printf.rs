@@ -1,19 +1,16 @@
-//! This module implements much of printf in awk.
+//! This module implements printf in awk.
 //!
-//! We lean heavily on ryu and the std::fmt machinery; as such, most of the work is parsing
-//! awk-style format strings and translating them to individual calls to write!.
-//!
-//! TODO: Originally, frawk enforced that all Strs contained valid UTF-8. We have since allowed
-//! strings to contain arbitrary byte sequences, but this module will eagerly replace invalid UTF8
-//! byte sequences with REPLACEMENT CHARACTER using String's from_utf8_lossy function. This means
-//! that users hoping to output raw bytes using `printf` (as may be necessary, given that print
-//! appends a newline) may find some bytes replaced inadvertently. We could solve this by adding a
-//! new print function that does not append a newline.
+//! The goal is to match the libc `printf` family: we parse awk-style format strings (including
+//! `*` widths and precisions and `%n$` positional arguments) and then hand numeric conversions
+//! off to `snprintf` itself. The common cases of `%d` and `%g` without any sign or grouping
+//! flags are formatted natively, as is all padding for strings and characters, so that arbitrary
+//! bytes are written out unchanged and widths count characters for UTF-8 input, as they do in
+//! gawk.
 use crate::common::Result;
-use crate::runtime::{convert, strtoi, Float, Int, Str};
+use crate::runtime::{convert, format_float, Float, Int, Str};
 
-use std::convert::TryFrom;
-use std::fmt;
+use libc::c_char;
+use std::cmp;
 use std::io::Write;
 use std::str;
 
@@ -22,12 +19,6 @@ type SmallVec<T> = smallvec::SmallVec<[T; 32]>;
 #[derive(Default)]
 struct StackWriter(pub SmallVec<u8>);
 
-impl StackWriter {
-    pub fn len(&self) -> usize {
-        self.0.len()
-    }
-}
-
 impl Write for StackWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         self.0.extend_from_slice(buf);
@@ -38,13 +29,6 @@ impl Write for StackWriter {
     }
 }
 
-struct DisplayBytes<'a>(&'a [u8]);
-impl<'a> fmt::Display for DisplayBytes<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&*std::string::String::from_utf8_lossy(self.0), fmt)
-    }
-}
-
 #[derive(Clone, Debug)]
 pub(crate) enum FormatArg<'a> {
     S(Str<'a>),
@@ -99,302 +83,510 @@ impl<'a> FormatArg<'a> {
             I(i) => *i,
         }
     }
-    fn with_bytes<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+    // Floats are converted to strings according to `convfmt`, the current value of CONVFMT.
+    fn with_bytes<R>(&self, convfmt: &Str, f: impl FnOnce(&[u8]) -> R) -> Result<R> {
         use FormatArg::*;
         let s: Str<'a> = match self {
             S(s) => s.clone(),
-            F(f) => convert::<_, Str>(*f),
+            F(f) => format_float(convfmt, *f)?,
             I(i) => convert::<_, Str>(*i),
         };
-        s.with_bytes(f)
+        Ok(s.with_bytes(f))
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+// Widths and precisions are C ints, so we reject anything larger.
+const MAX_COUNT: usize = i32::MAX as usize;
+
+#[derive(Copy, Clone, Debug, Default)]
 struct FormatSpec {
-    // leading '-' ? -- left justification.
+    // '-': left justification.
     minus: bool,
-    // number to the left of '.', if any
-    leading_zeros: bool,
-    // padding
-    lnum: usize,
-    // maximum string width, or floating point precision.
-    rnum: usize,
+    // '+': always print a sign for signed conversions.
+    plus: bool,
+    // ' ': print a space in place of a '+' sign.
+    space: bool,
+    // '#': the "alternate form" for the conversion, e.g. a leading 0x for %x.
+    alt: bool,
+    // '0': pad with zeros rather than spaces.
+    zero: bool,
+    // '\'': group the integer part of a number into thousands.
+    group: bool,
+    // minimum field width.
+    width: usize,
+    // maximum string width, or the precision for numbers.
+    prec: Option<usize>,
     // format specifier: e.g. c, d, s, x.
     spec: u8,
 }
 
-impl Default for FormatSpec {
-    fn default() -> FormatSpec {
-        FormatSpec {
-            minus: false,
-            leading_zeros: false,
-            lnum: 0,
-            rnum: usize::max_value(),
-            spec: b'z', /* invalid */
-        }
-    }
+/// A width or precision given in a format specifier.
+#[derive(Copy, Clone, Debug)]
+enum Count {
+    Lit(usize),
+    // `*`: taken from the next argument.
+    Next,
+    // `*n$`: taken from the nth argument (counting from 0).
+    Pos(usize),
+}
+
+/// A parsed format specifier, before any arguments are consulted.
+#[derive(Copy, Clone, Debug)]
+struct Directive {
+    fs: FormatSpec,
+    width: Option<Count>,
+    prec: Option<Count>,
+    // `%n$`: the (0-indexed) argument to format, if it is not the next one.
+    arg: Option<usize>,
 }
 
 fn is_spec(c: u8) -> bool {
-    matches!(c, b'f' | b'c' | b'd' | b'e' | b'g' | b'o' | b's' | b'x')
+    matches!(
+        c,
+        b'd' | b'i'
+            | b'o'
+            | b'u'
+            | b'x'
+            | b'X'
+            | b'e'
+            | b'E'
+            | b'f'
+            | b'F'
+            | b'g'
+            | b'G'
+            | b'a'
+            | b'A'
+            | b'c'
+            | b's'
+            | b'%'
+    )
 }
 
-fn process_spec(mut w: impl Write, fspec: &mut FormatSpec, arg: &FormatArg) -> Result<()> {
-    macro_rules! match_for_spec {
-        ($s:expr, $arg:expr) => {
-            match (
-                fspec.minus,
-                fspec.leading_zeros,
-                fspec.lnum,
-                fspec.rnum == usize::max_value(),
-            ) {
-                (true, true, lnum, true) => write!(w, concat!("{:0<l$", $s, "}"), $arg, l = lnum),
-                (true, false, lnum, true) => write!(w, concat!("{:<l$", $s, "}"), $arg, l = lnum),
-                (true, true, lnum, false) => write!(
-                    w,
-                    concat!("{:0<l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-                (true, false, lnum, false) => write!(
-                    w,
-                    concat!("{:<l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-                (false, true, lnum, true) => write!(w, concat!("{:0>l$", $s, "}"), $arg, l = lnum),
-                (false, false, lnum, true) => write!(w, concat!("{:>l$", $s, "}"), $arg, l = lnum),
-                (false, true, lnum, false) => write!(
-                    w,
-                    concat!("{:0>l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-                (false, false, lnum, false) => write!(
-                    w,
-                    concat!("{:>l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-            }
-        };
-    }
-    let res = match fspec.spec {
-        b'f' => {
-            if !fspec.leading_zeros && fspec.lnum == 0 && fspec.rnum == usize::max_value() {
-                // Fast path: use Ryu, which today is more efficient than the standard library.
-                // NB Ryu prints some things a bit differently than most awk implementations.
-                // `write!(w, "{}", arg.to_float())` is a bit closer.
-                let mut buf = ryu::Buffer::new();
-                write!(w, "{}", buf.format(arg.to_float()))
-            } else {
-                match_for_spec!("", arg.to_float())
-            }
+/// Parse the format specifier starting just after the `%` at `spec[start-1]`. Returns the
+/// directive and the index just past it, or None if the specifier is malformed.
+fn parse_directive(spec: &[u8], start: usize) -> Option<(Directive, usize)> {
+    let mut ix = start;
+    let num = |ix: &mut usize| -> Option<usize> {
+        let begin = *ix;
+        let mut n = 0usize;
+        while let Some(d @ b'0'..=b'9') = spec.get(*ix) {
+            n = n.checked_mul(10)?.checked_add((d - b'0') as usize)?;
+            *ix += 1;
         }
-        b'e' => match_for_spec!("e", arg.to_float()),
-        b'g' => {
-            let mut buf = StackWriter::default();
-            // %g means "pick the shorter of standard and scientific notation". We do the obvious
-            // thing of computing both and writing out the smaller one.
-            fspec.spec = b'f';
-            process_spec(&mut buf, fspec, arg)?;
-            let l1 = buf.len();
-            fspec.spec = b'e';
-            process_spec(&mut buf, fspec, arg)?;
-            let l2 = buf.len() - l1;
-            let bytes = if l1 < l2 {
-                &buf.0[0..l1]
-            } else {
-                &buf.0[l1..(l1 + l2)]
-            };
-            return write_bytes(&mut w, bytes);
+        if *ix == begin || n > MAX_COUNT {
+            None
+        } else {
+            Some(n)
         }
-        b'd' => match_for_spec!("", arg.to_int()),
-        b'o' => match_for_spec!("o", arg.to_int()),
-        b'x' => match_for_spec!("x", arg.to_int()),
-        b'c' => {
-            // First, see if we have something ascii/UTF8 here
-            match char::try_from(arg.to_int() as u32) {
-                Ok(ch) => match_for_spec!("", ch),
-                // TODO: Unclear what we should do here, write out the raw bytes? write out the
-                // character code? Awk may just write the raw bytes out, but it's hard to say
-                // (different behavior across implementations)
-                _ => match_for_spec!("", "?"),
+    };
+    // Parses a `n$` argument position, leaving `ix` unchanged if there is not one.
+    let position = |ix: &mut usize| -> Option<usize> {
+        let mut cur = *ix;
+        match num(&mut cur) {
+            Some(n) if n > 0 && spec.get(cur) == Some(&b'$') => {
+                *ix = cur + 1;
+                Some(n - 1)
             }
+            _ => None,
+        }
+    };
+    let count = |ix: &mut usize| -> Option<Count> {
+        if spec.get(*ix) == Some(&b'*') {
+            *ix += 1;
+            Some(match position(ix) {
+                Some(n) => Count::Pos(n),
+                None => Count::Next,
+            })
+        } else {
+            num(ix).map(Count::Lit)
         }
-        b's' => arg.with_bytes(|bs| match_for_spec!("", DisplayBytes(bs))),
-        x => return err!("unsupported format specifier: {}", x),
     };
-    wrap_result(res)
+    let mut res = Directive {
+        fs: FormatSpec::default(),
+        width: None,
+        prec: None,
+        arg: position(&mut ix),
+    };
+    loop {
+        match spec.get(ix)? {
+            b'-' => res.fs.minus = true,
+            b'+' => res.fs.plus = true,
+            b' ' => res.fs.space = true,
+            b'#' => res.fs.alt = true,
+            b'0' => res.fs.zero = true,
+            b'\'' => res.fs.group = true,
+            _ => break,
+        }
+        ix += 1;
+    }
+    if matches!(spec.get(ix)?, b'*' | b'1'..=b'9') {
+        res.width = Some(count(&mut ix)?);
+    }
+    if spec.get(ix)? == &b'.' {
+        ix += 1;
+        // A lone '.' means a precision of zero.
+        res.prec = Some(count(&mut ix).unwrap_or(Count::Lit(0)));
+    }
+    // Length modifiers are meaningless in awk; accept and ignore them as gawk does.
+    while matches!(
+        spec.get(ix)?,
+        b'h' | b'l' | b'L' | b'q' | b'j' | b'z' | b't'
+    ) {
+        ix += 1;
+    }
+    let ch = *spec.get(ix)?;
+    if !is_spec(ch) {
+        return None;
+    }
+    res.fs.spec = ch;
+    Some((res, ix + 1))
 }
 
-fn wrap_result<T>(r: std::result::Result<T, impl fmt::Display>) -> Result<()> {
-    match r {
-        Ok(_) => Ok(()),
-        Err(e) => err!("formatter: {}", e),
+/// A value passed to snprintf.
+#[derive(Copy, Clone)]
+enum CArg {
+    I(i64),
+    U(u64),
+    F(f64),
+}
+
+/// Write `arg` to `w` using the C format string `cfmt`, which must be NUL-terminated and contain
+/// exactly one conversion matching `arg`.
+fn write_c(mut w: impl Write, cfmt: &[u8], arg: CArg) -> Result<()> {
+    debug_assert_eq!(cfmt.last(), Some(&0));
+    let snprintf = |buf: &mut [u8]| unsafe {
+        let (dst, len, fmt) = (
+            buf.as_mut_ptr() as *mut c_char,
+            buf.len(),
+            cfmt.as_ptr() as *const c_char,
+        );
+        match arg {
+            CArg::I(i) => libc::snprintf(dst, len, fmt, i as libc::c_longlong),
+            CArg::U(u) => libc::snprintf(dst, len, fmt, u as libc::c_ulonglong),
+            CArg::F(f) => libc::snprintf(dst, len, fmt, f as libc::c_double),
+        }
+    };
+    let mut buf = [0u8; 64];
+    let n = snprintf(&mut buf[..]);
+    if n < 0 {
+        return err!(
+            "snprintf failed for format {}",
+            String::from_utf8_lossy(&cfmt[..cfmt.len() - 1])
+        );
     }
+    let n = n as usize;
+    if n < buf.len() {
+        return write_bytes(w, &buf[..n]);
+    }
+    let mut buf = vec![0u8; n + 1];
+    snprintf(&mut buf[..]);
+    write_bytes(&mut w, &buf[..n])
 }
 
-fn write_bytes(mut w: impl Write, bs: &[u8]) -> Result<()> {
-    wrap_result(w.write(bs))
+/// Build a NUL-terminated C format string for `fs`, with the conversion `conv`.
+fn c_format(fs: &FormatSpec, width: bool, conv: &str) -> StackWriter {
+    let mut res = StackWriter::default();
+    res.0.push(b'%');
+    for (set, flag) in [
+        (fs.minus, b'-'),
+        (fs.plus, b'+'),
+        (fs.space, b' '),
+        (fs.alt, b'#'),
+        (fs.zero, b'0'),
+    ]
+    .iter()
+    {
+        if *set {
+            res.0.push(*flag);
+        }
+    }
+    if width && fs.width > 0 {
+        let _ = write!(&mut res, "{}", fs.width);
+    }
+    if let Some(prec) = fs.prec {
+        let _ = write!(&mut res, ".{}", prec);
+    }
+    res.0.extend_from_slice(conv.as_bytes());
+    res.0.push(0);
+    res
 }
 
-pub(crate) fn printf(mut w: impl Write, spec: &[u8], mut args: &[FormatArg]) -> Result<()> {
-    #[derive(Copy, Clone)]
-    enum State {
-        // Byte index of start of string
-        Raw(usize),
-        // Byte index of percent sign
-        Format(usize),
-    }
-
-    use State::*;
-    let mut iter = spec.iter().cloned().enumerate();
-    macro_rules! next_state {
-        ($e:expr) => {
-            match $e {
-                Some((_, b'%')) => Format(0),
-                Some(_) => Raw(0),
-                None => return Ok(()),
-            }
-        };
+/// Insert a ',' between each group of three digits in the integer part of the number in `bs`.
+fn group_thousands(bs: &[u8]) -> SmallVec<u8> {
+    let start = bs.iter().position(u8::is_ascii_digit).unwrap_or(bs.len());
+    let end = start
+        + bs[start..]
+            .iter()
+            .position(|b| !b.is_ascii_digit())
+            .unwrap_or(bs.len() - start);
+    let mut res = SmallVec::with_capacity(bs.len() + (end - start) / 3);
+    res.extend_from_slice(&bs[..start]);
+    for (i, d) in bs[start..end].iter().enumerate() {
+        if i > 0 && (end - start - i) % 3 == 0 {
+            res.push(b',');
+        }
+        res.push(*d);
     }
-    let mut state = next_state!(iter.next());
-    let default = FormatArg::S(Default::default());
-    let mut next_arg = || {
-        if args.is_empty() {
-            &default
+    res.extend_from_slice(&bs[end..]);
+    res
+}
+
+/// Write `bs`, which is `len` characters wide, padded out to `fs.width`. If `zeros` is set, zero
+/// padding is placed after the first `prefix` bytes of `bs` (e.g. a sign).
+fn write_padded(
+    mut w: impl Write,
+    fs: &FormatSpec,
+    bs: &[u8],
+    len: usize,
+    zeros: bool,
+    prefix: usize,
+) -> Result<()> {
+    if len >= fs.width {
+        return write_bytes(w, bs);
+    }
+    let padding = fs.width - len;
+    let pad = |w: &mut dyn Write, b: u8| -> Result<()> {
+        const CHUNK: [u8; 32] = [0; 32];
+        let mut chunk = CHUNK;
+        for x in chunk.iter_mut() {
+            *x = b;
+        }
+        let mut rem = padding;
+        while rem > 0 {
+            let n = cmp::min(rem, chunk.len());
+            write_bytes(&mut *w, &chunk[..n])?;
+            rem -= n;
+        }
+        Ok(())
+    };
+    if fs.minus {
+        write_bytes(&mut w, bs)?;
+        pad(&mut w, b' ')
+    } else if zeros {
+        write_bytes(&mut w, &bs[..prefix])?;
+        pad(&mut w, b'0')?;
+        write_bytes(&mut w, &bs[prefix..])
+    } else {
+        pad(&mut w, b' ')?;
+        write_bytes(&mut w, bs)
+    }
+}
+
+/// Format a number with snprintf, handling the `'` flag ourselves. libc only groups digits in
+/// locales that define a thousands separator, and we always run in the C locale.
+fn write_number(w: impl Write, fs: &FormatSpec, conv: &str, arg: CArg) -> Result<()> {
+    let grouped = fs.group && matches!(fs.spec, b'd' | b'i' | b'u' | b'f' | b'F' | b'g' | b'G');
+    if !grouped {
+        return write_c(w, &c_format(fs, true, conv).0[..], arg);
+    }
+    let mut buf = StackWriter::default();
+    write_c(&mut buf, &c_format(fs, false, conv).0[..], arg)?;
+    let bs = group_thousands(&buf.0[..]);
+    let prefix = if matches!(bs.first(), Some(b'-' | b'+' | b' ')) {
+        1
+    } else {
+        0
+    };
+    // As in C, the '0' flag is ignored for integers with a precision, and for inf and nan.
+    let zeros = fs.zero
+        && !(fs.prec.is_some() && matches!(fs.spec, b'd' | b'i' | b'u'))
+        && bs.get(prefix).is_some_and(u8::is_ascii_digit);
+    write_padded(w, fs, &bs[..], bs.len(), zeros, prefix)
+}
+
+/// Write `i` as `%d` would, for format specifiers without sign, grouping or precision.
+fn write_int(w: impl Write, fs: &FormatSpec, i: Int) -> Result<()> {
+    let mut buf = itoa::Buffer::new();
+    let bs = buf.format(i).as_bytes();
+    write_padded(w, fs, bs, bs.len(), fs.zero, (i < 0) as usize)
+}
+
+/// Write `f` as `%g` would, for finite `f` and format specifiers without flags other than `-`.
+fn write_general(w: impl Write, fs: &FormatSpec, f: Float) -> Result<()> {
+    // A precision of zero is taken as one significant digit.
+    let prec = cmp::max(fs.prec.unwrap_or(6), 1);
+    // Like C, we pick between fixed and scientific notation based on the exponent of `f` once it
+    // is rounded to `prec` significant digits.
+    let mut sci = StackWriter::default();
+    let _ = write!(&mut sci, "{:.*e}", prec - 1, f);
+    let e_ix = sci.0.iter().position(|b| *b == b'e').unwrap();
+    let exp: i64 = str::from_utf8(&sci.0[e_ix + 1..]).unwrap().parse().unwrap();
+    // Both notations drop trailing zeros after the decimal point.
+    let trim = |bs: &[u8]| -> usize {
+        if !bs.contains(&b'.') {
+            return bs.len();
+        }
+        let end = bs.iter().rposition(|b| *b != b'0').unwrap();
+        if bs[end] == b'.' {
+            end
         } else {
-            let res = &args[0];
-            args = &args[1..];
-            res
+            end + 1
         }
     };
-    let mut buf = SmallVec::new();
-    'outer: loop {
-        match state {
-            Raw(start) => {
-                for (ix, ch) in iter.by_ref() {
-                    if ch == b'%' {
-                        write_bytes(&mut w, &spec[start..ix])?;
-                        state = Format(ix);
-                        continue 'outer;
-                    }
-                }
-                write_bytes(&mut w, &spec[start..])?;
-                break 'outer;
+    let mut res = StackWriter::default();
+    if exp < -4 || exp >= prec as i64 {
+        let mantissa = &sci.0[..e_ix];
+        res.0.extend_from_slice(&mantissa[..trim(mantissa)]);
+        // The exponent has a sign and at least two digits.
+        let _ = write!(&mut res, "e{:+03}", exp);
+    } else {
+        let _ = write!(&mut res, "{:.*}", (prec as i64 - 1 - exp) as usize, f);
+        let len = trim(&res.0[..]);
+        res.0.truncate(len);
+    }
+    write_padded(w, fs, &res.0[..], res.0.len(), false, 0)
+}
+
+/// The number of characters in `bs`: UTF-8 input is measured in characters, anything else in bytes.
+fn char_len(bs: &[u8]) -> usize {
+    match str::from_utf8(bs) {
+        Ok(s) => s.chars().count(),
+        Err(_) => bs.len(),
+    }
+}
+
+/// The first `n` characters of `bs`, using the same notion of "character" as `char_len`.
+fn char_prefix(bs: &[u8], n: usize) -> &[u8] {
+    match str::from_utf8(bs) {
+        Ok(s) => match s.char_indices().nth(n) {
+            Some((ix, _)) => &bs[..ix],
+            None => bs,
+        },
+        Err(_) => &bs[..cmp::min(n, bs.len())],
+    }
+}
+
+fn process_spec(mut w: impl Write, fs: &FormatSpec, arg: &FormatArg, convfmt: &Str) -> Result<()> {
+    match fs.spec {
+        b'd' | b'i' => match arg {
+            // Floats that do not fit in an integer are printed without their fractional part.
+            FormatArg::F(f) if f.is_nan() || f.abs() >= Int::MAX as Float => {
+                let fs = FormatSpec {
+                    prec: Some(0),
+                    spec: b'f',
+                    ..*fs
+                };
+                write_number(w, &fs, "f", CArg::F(*f))
             }
-            Format(start) => {
-                let mut fs = FormatSpec::default();
-                #[derive(Copy, Clone)]
-                enum Stage {
-                    Begin,
-                    Lnum,
-                    Rnum,
-                }
-                use Stage::*;
-                let mut stage = Begin;
-                let mut next = iter.next();
-                // AWK is, as usual, rather permissive when it comes to invalid format specifiers:
-                // If something is formatted incorrectly, it is simply treated like a normal
-                // string. We implement by checking for error conditions and `break`ing out of the
-                // inner loop, which will change state to Raw(start).
-                while let Some((ix, ch)) = next {
-                    if !ch.is_ascii() {
-                        // We cast characters to bytes in what follows.
-                        break;
-                    }
-                    match (ch, stage) {
-                        (b'%', Begin) => {
-                            fs.spec = b'%';
-                            process_spec(&mut w, &mut fs, next_arg())?;
-                            state = Raw(ix + 1);
-                            continue 'outer;
-                        }
-                        (ch, _) if is_spec(ch) => {
-                            fs.spec = ch as u8;
-                            process_spec(&mut w, &mut fs, next_arg())?;
-                            state = Raw(ix + 1);
-                            continue 'outer;
-                        }
-                        (b'-', Begin) => {
-                            stage = Lnum;
-                            fs.minus = true;
-                        }
-                        (b'-', _) | (b'%', _) => break,
-                        (ch, Lnum) | (ch, Begin) => {
-                            if fs.lnum != 0 {
-                                break;
-                            }
-                            buf.clear();
-                            if ch == b'0' {
-                                fs.leading_zeros = true;
-                            } else if ch == b'.' {
-                                stage = Rnum;
-                                continue;
-                            } else {
-                                buf.push(ch);
-                            };
-                            next = None;
-                            while let Some((ix, ch)) = iter.next() {
-                                if !matches!(ch, b'0'..=b'9') {
-                                    next = Some((ix, ch));
-                                    break;
-                                }
-                                buf.push(ch);
-                            }
-                            let num = strtoi(&buf[..]);
-                            if num < 0 {
-                                break;
-                            }
-                            fs.lnum = num as usize;
-                            stage = Rnum;
-                            continue;
-                        }
-                        (ch, Rnum) => {
-                            if fs.rnum != usize::max_value() {
-                                break;
-                            }
-                            if ch != b'.' {
-                                break;
-                            }
-                            buf.clear();
-                            next = None;
-                            for (ix, ch) in iter.by_ref() {
-                                if !matches!(ch, b'0'..=b'9') {
-                                    next = Some((ix, ch));
-                                    break;
-                                }
-                                buf.push(ch);
-                            }
-                            let num = strtoi(&buf[..]);
-                            if num < 0 {
-                                break;
-                            }
-                            fs.rnum = num as usize;
-                            continue;
-                        }
-                    };
-                    next = iter.next();
-                }
-                // We do not have a complete format specifier, and we have exhausted the string.
-                // Just print it out.
-                state = Raw(start);
-                continue 'outer;
+            _ if !(fs.plus || fs.space || fs.group || fs.prec.is_some()) => {
+                write_int(w, fs, arg.to_int())
+            }
+            _ => write_number(w, fs, "lld", CArg::I(arg.to_int())),
+        },
+        b'o' | b'u' | b'x' | b'X' => {
+            let conv = match fs.spec {
+                b'o' => "llo",
+                b'u' => "llu",
+                b'x' => "llx",
+                _ => "llX",
+            };
+            write_number(w, fs, conv, CArg::U(arg.to_int() as u64))
+        }
+        b'g' if !(fs.plus || fs.space || fs.alt || fs.zero || fs.group) => match arg.to_float() {
+            f if f.is_finite() => write_general(w, fs, f),
+            f => write_number(w, fs, "g", CArg::F(f)),
+        },
+        b'e' | b'E' | b'f' | b'F' | b'g' | b'G' | b'a' | b'A' => {
+            let conv = [fs.spec];
+            let conv = str::from_utf8(&conv[..]).unwrap();
+            write_number(w, fs, conv, CArg::F(arg.to_float()))
+        }
+        // As in C, the '0' flag has no effect on %c and %s.
+        b'c' => {
+            let bs: SmallVec<u8> = match arg {
+                // Strings print their first character.
+                FormatArg::S(s) => s.with_bytes(|bs| char_prefix(bs, 1).into()),
+                // Numbers print the byte with that value, truncated to 8 bits as C converts the
+                // argument to an unsigned char.
+                _ => SmallVec::from_slice(&[arg.to_int() as u8]),
+            };
+            write_padded(w, fs, &bs[..], cmp::min(bs.len(), 1), false, 0)
+        }
+        b's' => arg.with_bytes(convfmt, |bs| {
+            let bs = match fs.prec {
+                Some(prec) => char_prefix(bs, prec),
+                None => bs,
+            };
+            write_padded(&mut w, fs, bs, char_len(bs), false, 0)
+        })?,
+        b'%' => write_bytes(w, b"%"),
+        x => err!("unsupported format specifier: {}", x as char),
+    }
+}
+
+fn write_bytes(mut w: impl Write, bs: &[u8]) -> Result<()> {
+    match w.write_all(bs) {
+        Ok(()) => Ok(()),
+        Err(e) => err!("formatter: {}", e),
+    }
+}
+
+/// Write `args` to `w` according to the format string `spec`. `convfmt` is the current value of
+/// CONVFMT, used to convert floats printed with `%s`.
+pub(crate) fn printf(
+    mut w: impl Write,
+    spec: &[u8],
+    args: &[FormatArg],
+    convfmt: &Str,
+) -> Result<()> {
+    // As in other awks, missing arguments are treated as empty strings.
+    let default = FormatArg::S(Default::default());
+    let mut next = 0;
+    let mut get_arg = |pos: Option<usize>| {
+        let ix = pos.unwrap_or_else(|| {
+            next += 1;
+            next - 1
+        });
+        args.get(ix).unwrap_or(&default)
+    };
+    let mut start = 0;
+    while let Some(off) = memchr::memchr(b'%', &spec[start..]) {
+        let pct = start + off;
+        write_bytes(&mut w, &spec[start..pct])?;
+        let (d, end) = match parse_directive(spec, pct + 1) {
+            Some(x) => x,
+            None => {
+                // AWK is, as usual, rather permissive when it comes to invalid format
+                // specifiers: they are simply printed as normal text.
+                write_bytes(&mut w, b"%")?;
+                start = pct + 1;
+                continue;
             }
+        };
+        start = end;
+        let mut fs = d.fs;
+        if fs.spec == b'%' {
+            process_spec(&mut w, &fs, &default, convfmt)?;
+            continue;
+        }
+        let mut count = |c: Count| match c {
+            Count::Lit(n) => n as Int,
+            Count::Next => get_arg(None).to_int(),
+            Count::Pos(n) => get_arg(Some(n)).to_int(),
+        };
+        if let Some(width) = d.width.map(&mut count) {
+            // A negative width is taken as a '-' flag followed by a positive width.
+            fs.minus |= width < 0;
+            fs.width = cmp::min(
+                width.checked_abs().unwrap_or(Int::MAX) as u64,
+                MAX_COUNT as u64,
+            ) as usize;
         }
+        // A negative precision is taken as if the precision were omitted.
+        fs.prec = d
+            .prec
+            .map(&mut count)
+            .filter(|p| *p >= 0)
+            .map(|p| cmp::min(p as u64, MAX_COUNT as u64) as usize);
+        process_spec(&mut w, &fs, get_arg(d.arg), convfmt)?;
     }
-    Ok(())
+    write_bytes(w, &spec[start..])
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::builtins::DEFAULT_FLOAT_FMT;
 
     use std::io::Cursor;
 
@@ -402,7 +594,8 @@ mod tests {
         ($fmt:expr $(, $e:expr)*) => {{
             let mut v = Vec::<u8>::new();
             let w = Cursor::new(&mut v);
-            printf(w, $fmt, &[$( $e.into() ),*]).expect("printf failure");
+            printf(w, $fmt, &[$( $e.into() ),*], &DEFAULT_FLOAT_FMT.into())
+                .expect("printf failure");
             String::from_utf8(v).expect("printf should produce valid utf8")
         }}
     }
@@ -417,16 +610,17 @@ mod tests {
             w,
             b"Hi %s, to my %d friends %f percent of the time: %g!",
             &[S("there".into()), F(2.5), I(1), F(1.25369E23)],
+            &DEFAULT_FLOAT_FMT.into(),
         )
         .expect("printf failed");
         let s = str::from_utf8(&v[..]).unwrap();
         assert_eq!(
             s,
-            "Hi there, to my 2 friends 1.0 percent of the time: 1.25369e23!"
+            "Hi there, to my 2 friends 1.000000 percent of the time: 1.25369e+23!"
         );
 
         let s2 = sprintf!(b"%e %d ~~ %s", 12535, 3, "hi");
-        assert_eq!(s2.as_str(), "1.2535e4 3 ~~ hi");
+        assert_eq!(s2.as_str(), "1.253500e+04 3 ~~ hi");
     }
 
     #[test]
@@ -444,4 +638,144 @@ mod tests {
         let s2 = sprintf!(b"%.2f", 2.375);
         assert_eq!(s2.as_str(), "2.38");
     }
+
+    #[test]
+    fn libc_conversions() {
+        assert_eq!(
+            sprintf!(b"%i %u %X %#x %+d % d", 42, 7, 255, 255, 5, 5),
+            "42 7 FF 0xff +5  5"
+        );
+        assert_eq!(
+            sprintf!(b"%E %G %g", 12535.0, 1e-10, 0.0001),
+            "1.253500E+04 1E-10 0.0001"
+        );
+        assert_eq!(sprintf!(b"%a %A", 1.0, 0.5), "0x1p+0 0X1P-1");
+        assert_eq!(
+            sprintf!(b"%x %d", -1, 1e30),
+            "ffffffffffffffff 1000000000000000019884624838656"
+        );
+        assert_eq!(
+            sprintf!(b"%5.1f|%-8.3e|%08.3f", 2.468, 2.5, -1.5),
+            "  2.5|2.500e+00|-001.500"
+        );
+        assert_eq!(sprintf!(b"%ld %lf %5%|", 3, 0.5), "3 0.500000 %|");
+    }
+
+    #[test]
+    fn star_and_positional() {
+        assert_eq!(
+            sprintf!(b"[%*d] [%-*s] [%.*f]", 5, 42, 4, "ab", 2, 1.236),
+            "[   42] [ab  ] [1.24]"
+        );
+        assert_eq!(sprintf!(b"[%*d] [%.*s]", -4, 1, -1, "abc"), "[1   ] [abc]");
+        assert_eq!(
+            sprintf!(b"%2$s %1$s %2$s", "world", "hello"),
+            "hello world hello"
+        );
+        assert_eq!(sprintf!(b"%1$*2$d|", 7, 3), "  7|");
+    }
+
+    #[test]
+    fn native_conversions() {
+        // %d and %g without sign or grouping flags do not go through snprintf; check that they
+        // agree with it.
+        let via_libc = |fmt: &[u8], arg: CArg| {
+            let mut cfmt = fmt.to_vec();
+            cfmt.push(0);
+            let mut buf = StackWriter::default();
+            write_c(&mut buf, &cfmt[..], arg).expect("snprintf failure");
+            String::from_utf8(buf.0.to_vec()).unwrap()
+        };
+        for i in &[0, 7, -7, 42, -12345, Int::MAX, Int::MIN] {
+            for fmt in &["%d", "%5d", "%-5d|", "%05d", "%-05d|"] {
+                let lfmt = fmt.replace('d', "lld");
+                assert_eq!(
+                    sprintf!(fmt.as_bytes(), *i),
+                    via_libc(lfmt.as_bytes(), CArg::I(*i)),
+                    "format={} value={}",
+                    fmt,
+                    i
+                );
+            }
+        }
+        let floats = [
+            0.0,
+            -0.0,
+            1.0,
+            -2.5,
+            0.5,
+            0.0001,
+            0.00001234,
+            1e-10,
+            123456.0,
+            1234567.0,
+            999999.5,
+            9999995.0,
+            0.1,
+            2.0 / 3.0,
+            1.25369e23,
+            -6.02e-23,
+            1e100,
+            5e-324,
+            Float::MAX,
+        ];
+        for f in floats.iter() {
+            for fmt in &[
+                "%g", "%.0g", "%.1g", "%.3g", "%.10g", "%.17g", "%12g", "%-12g|",
+            ] {
+                assert_eq!(
+                    sprintf!(fmt.as_bytes(), *f),
+                    via_libc(fmt.as_bytes(), CArg::F(*f)),
+                    "format={} value={:e}",
+                    fmt,
+                    f
+                );
+            }
+        }
+        assert_eq!(
+            sprintf!(
+                b"%g %g %5g",
+                Float::INFINITY,
+                Float::NAN,
+                Float::NEG_INFINITY
+            ),
+            "inf nan  -inf"
+        );
+    }
+
+    #[test]
+    fn thousands_grouping() {
+        assert_eq!(
+            sprintf!(b"%'d %'d %'.2f %'d", 1234567, -1000, 1234567.891, 999),
+            "1,234,567 -1,000 1,234,567.89 999"
+        );
+        assert_eq!(
+            sprintf!(b"[%'10d] [%-'10d] [%'010d]", 12345, 12345, -12345),
+            "[    12,345] [12,345    ] [-00012,345]"
+        );
+    }
+
+    #[test]
+    fn chars_and_bytes() {
+        let bytes = |fmt: &[u8], args: &[FormatArg]| {
+            let mut v = Vec::<u8>::new();
+            printf(Cursor::new(&mut v), fmt, args, &DEFAULT_FLOAT_FMT.into())
+                .expect("printf failure");
+            v
+        };
+        use FormatArg::*;
+        assert_eq!(sprintf!(b"%c%c", 65, "hello"), "Ah");
+        assert_eq!(bytes(b"%c%c", &[I(200), I(9731)]), vec![200u8, 3]);
+        assert_eq!(bytes(b"%c", &[F(321.5)]), b"A".to_vec());
+        assert_eq!(
+            bytes(b"%3c|%-3c|", &[I(255), S("é!".into())]),
+            b"  \xff|\xc3\xa9  |".to_vec()
+        );
+        assert_eq!(
+            bytes(b"%-4s|%.1s", &[S(b"\xff\xfe"[..].into()), S("éa".into())]),
+            b"\xff\xfe  |\xc3\xa9".to_vec()
+        );
+        assert_eq!(sprintf!(b"%5s|%05s", "日本", "ab"), "   日本|   ab");
+        assert_eq!(sprintf!(b"%03c|%-03s", "x", "y"), "  x|y  ");
+    }
 }
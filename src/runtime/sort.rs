@@ -0,0 +1,296 @@
+//! Sorted traversal of arrays, for `asort`, `asorti` and `PROCINFO["sorted_in"]`.
+//!
+//! Orders use gawk's names: `@ind_*` orders compare indices and `@val_*` orders compare values,
+//! either as strings (`str`), as numbers (`num`) or numbers before strings (`type`). Ties are
+//! broken by comparing indices as strings, so the order never depends on the hash table.
+use std::cmp::Ordering;
+use std::hash::Hash;
+
+use crate::common::Result;
+use crate::runtime::{convert, strnum, Float, Int, SharedMap, Str, StrMap};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum By {
+    Unsorted,
+    IndStr,
+    IndNum,
+    ValStr,
+    ValNum,
+    ValType,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SortOrder {
+    by: By,
+    desc: bool,
+}
+
+impl SortOrder {
+    pub(crate) const UNSORTED: SortOrder = SortOrder {
+        by: By::Unsorted,
+        desc: false,
+    };
+    /// The order `asort` uses when none is given.
+    pub(crate) const VALUES: SortOrder = SortOrder {
+        by: By::ValType,
+        desc: false,
+    };
+    /// The order `asorti` uses when none is given.
+    pub(crate) const INDICES: SortOrder = SortOrder {
+        by: By::IndStr,
+        desc: false,
+    };
+
+    /// Parse an order name such as `@ind_num_desc`. The empty string selects `default`.
+    pub(crate) fn parse(how: &Str, default: SortOrder) -> Result<SortOrder> {
+        use By::*;
+        how.with_bytes(|bs| {
+            let (by, desc) = match bs {
+                b"" => return Ok(default),
+                b"@unsorted" => (Unsorted, false),
+                b"@ind_str_asc" => (IndStr, false),
+                b"@ind_str_desc" => (IndStr, true),
+                b"@ind_num_asc" => (IndNum, false),
+                b"@ind_num_desc" => (IndNum, true),
+                b"@val_str_asc" => (ValStr, false),
+                b"@val_str_desc" => (ValStr, true),
+                b"@val_num_asc" => (ValNum, false),
+                b"@val_num_desc" => (ValNum, true),
+                b"@val_type_asc" => (ValType, false),
+                b"@val_type_desc" => (ValType, true),
+                _ => {
+                    return err!(
+                        "unknown sort order {:?}, expected a name like \"@ind_str_asc\"",
+                        String::from_utf8_lossy(bs)
+                    )
+                }
+            };
+            Ok(SortOrder { by, desc })
+        })
+    }
+
+    /// The order that `for (k in m)` loops follow, given by `PROCINFO["sorted_in"]`.
+    pub(crate) fn sorted_in(procinfo: &StrMap<Str>) -> Result<SortOrder> {
+        if procinfo.len() == 0 {
+            return Ok(SortOrder::UNSORTED);
+        }
+        match procinfo.0.borrow().get(&Str::from("sorted_in")) {
+            Some(how) => SortOrder::parse(how, SortOrder::UNSORTED),
+            None => Ok(SortOrder::UNSORTED),
+        }
+    }
+
+    pub(crate) fn is_unsorted(&self) -> bool {
+        self.by == By::Unsorted
+    }
+}
+
+/// A key or value, prepared for comparison.
+#[derive(Clone)]
+pub(crate) struct Item<'a> {
+    num: Float,
+    text: Str<'a>,
+    is_num: bool,
+}
+
+impl<'a> Item<'a> {
+    fn num_cmp(&self, other: &Item) -> Ordering {
+        self.num.total_cmp(&other.num)
+    }
+    fn str_cmp(&self, other: &Item) -> Ordering {
+        self.text
+            .with_bytes(|bs1| other.text.with_bytes(|bs2| bs1.cmp(bs2)))
+    }
+    fn type_cmp(&self, other: &Item) -> Ordering {
+        match (self.is_num, other.is_num) {
+            (true, true) => self.num_cmp(other),
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (false, false) => self.str_cmp(other),
+        }
+    }
+}
+
+pub(crate) trait Sortable<'a> {
+    fn item(&self) -> Item<'a>;
+}
+
+impl<'a> Sortable<'a> for Int {
+    fn item(&self) -> Item<'a> {
+        Item {
+            num: *self as Float,
+            text: convert::<Int, Str>(*self),
+            is_num: true,
+        }
+    }
+}
+
+impl<'a> Sortable<'a> for Float {
+    fn item(&self) -> Item<'a> {
+        Item {
+            num: *self,
+            text: convert::<Float, Str>(*self),
+            is_num: true,
+        }
+    }
+}
+
+impl<'a> Sortable<'a> for Str<'a> {
+    fn item(&self) -> Item<'a> {
+        Item {
+            num: convert::<&Str, Float>(self),
+            text: self.clone(),
+            // Strings that look like numbers (e.g. fields read from input) sort as numbers.
+            is_num: self.with_bytes(|bs| strnum(bs).is_some()),
+        }
+    }
+}
+
+/// Keys that `asort` and `asorti` can store their results under.
+pub(crate) trait FromIndex {
+    fn from_index(i: Int) -> Self;
+}
+
+impl FromIndex for Int {
+    fn from_index(i: Int) -> Int {
+        i
+    }
+}
+
+impl<'a> FromIndex for Str<'a> {
+    fn from_index(i: Int) -> Str<'a> {
+        convert::<Int, Str>(i)
+    }
+}
+
+/// The entries of `map`, in the given order.
+pub(crate) fn sorted_entries<'a, K, V>(map: &SharedMap<K, V>, order: SortOrder) -> Vec<(K, V)>
+where
+    K: Hash + Eq + Clone + Sortable<'a>,
+    V: Clone + Sortable<'a>,
+{
+    let entries: Vec<(K, V)> = map.iter(|it| it.map(|(k, v)| (k.clone(), v.clone())).collect());
+    if order.is_unsorted() {
+        return entries;
+    }
+    let by_val = matches!(order.by, By::ValStr | By::ValNum | By::ValType);
+    let mut keyed: Vec<(Item<'a>, Item<'a>, (K, V))> = entries
+        .into_iter()
+        .map(|(k, v)| {
+            let index = k.item();
+            let primary = if by_val { v.item() } else { index.clone() };
+            (primary, index, (k, v))
+        })
+        .collect();
+    keyed.sort_by(|(p1, i1, _), (p2, i2, _)| {
+        let ord = match order.by {
+            By::IndStr | By::ValStr => p1.str_cmp(p2),
+            By::IndNum | By::ValNum => p1.num_cmp(p2),
+            By::ValType => p1.type_cmp(p2),
+            By::Unsorted => Ordering::Equal,
+        }
+        .then_with(|| i1.str_cmp(i2));
+        if order.desc {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+    keyed.into_iter().map(|(_, _, entry)| entry).collect()
+}
+
+fn fill<D: Hash + Eq + FromIndex, T>(dst: &SharedMap<D, T>, items: Vec<T>) -> Int {
+    let n = items.len() as Int;
+    dst.clear();
+    for (i, item) in items.into_iter().enumerate() {
+        dst.insert(D::from_index(i as Int + 1), item);
+    }
+    n
+}
+
+/// Store the values of `src`, sorted by `how`, in `dst` at indices 1 through n, and return n.
+/// Any previous contents of `dst` are removed; `src` and `dst` may be the same array.
+pub(crate) fn asort<'a, K, V, D>(
+    src: &SharedMap<K, V>,
+    dst: &SharedMap<D, V>,
+    how: &Str,
+) -> Result<Int>
+where
+    K: Hash + Eq + Clone + Sortable<'a>,
+    V: Clone + Sortable<'a>,
+    D: Hash + Eq + FromIndex,
+{
+    let order = SortOrder::parse(how, SortOrder::VALUES)?;
+    let vals = sorted_entries(src, order).into_iter().map(|(_, v)| v);
+    Ok(fill(dst, vals.collect()))
+}
+
+/// Like [`asort`], but stores the indices of `src` rather than its values.
+pub(crate) fn asorti<'a, K, V, D>(
+    src: &SharedMap<K, V>,
+    dst: &SharedMap<D, K>,
+    how: &Str,
+) -> Result<Int>
+where
+    K: Hash + Eq + Clone + Sortable<'a>,
+    V: Clone + Sortable<'a>,
+    D: Hash + Eq + FromIndex,
+{
+    let order = SortOrder::parse(how, SortOrder::INDICES)?;
+    let keys = sorted_entries(src, order).into_iter().map(|(k, _)| k);
+    Ok(fill(dst, keys.collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::IntMap;
+
+    fn strs(m: &IntMap<Str>) -> Vec<String> {
+        (1..=m.len() as Int)
+            .map(|i| {
+                m.get(&i)
+                    .with_bytes(|bs| String::from_utf8(bs.to_vec()).unwrap())
+            })
+            .collect()
+    }
+
+    fn order(s: &str) -> SortOrder {
+        SortOrder::parse(&Str::from(s), SortOrder::UNSORTED).unwrap()
+    }
+
+    #[test]
+    fn parse_orders() {
+        assert_eq!(order(""), SortOrder::UNSORTED);
+        assert_eq!(order("@val_type_asc"), SortOrder::VALUES);
+        assert_eq!(order("@ind_str_asc"), SortOrder::INDICES);
+        assert!(SortOrder::parse(&Str::from("@ind_bogus"), SortOrder::UNSORTED).is_err());
+    }
+
+    #[test]
+    fn sort_values() {
+        let src: StrMap<Str> = vec![("a", "10"), ("b", "9"), ("c", "x"), ("d", "-1")]
+            .into_iter()
+            .map(|(k, v)| (Str::from(k), Str::from(v)))
+            .collect();
+        let dst: IntMap<Str> = Default::default();
+        assert_eq!(asort(&src, &dst, &Str::default()).unwrap(), 4);
+        assert_eq!(strs(&dst), vec!["-1", "9", "10", "x"]);
+        asort(&src, &dst, &Str::from("@val_str_asc")).unwrap();
+        assert_eq!(strs(&dst), vec!["-1", "10", "9", "x"]);
+        asorti(&src, &dst, &Str::from("@val_num_desc")).unwrap();
+        assert_eq!(strs(&dst), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn sort_in_place() {
+        let m: IntMap<Int> = vec![(1, 3), (2, 1), (3, 2)].into_iter().collect();
+        let keys: Vec<Int> = sorted_entries(&m, order("@val_num_desc"))
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(keys, vec![1, 3, 2]);
+        assert_eq!(asort(&m, &m, &Str::default()).unwrap(), 3);
+        assert_eq!((m.get(&1), m.get(&2), m.get(&3)), (1, 2, 3));
+    }
+}
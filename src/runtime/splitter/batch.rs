@@ -39,7 +39,7 @@ use super::{
         self, CancellableChunkProducer, Chunk, ChunkProducer, OffsetChunk, ParallelChunkProducer,
         ShardedChunkProducer,
     },
-    normalize_join_indexes, DefaultLine, LineReader, ReaderState,
+    json, normalize_join_indexes, DefaultLine, FileEvents, LineReader, ReaderState, SplitMode,
 };
 
 pub struct CSVReader<P> {
@@ -52,6 +52,14 @@ pub struct CSVReader<P> {
     // Used to trigger updating FILENAME on the first read.
     ifmt: InputFormat,
     field_set: FieldSet,
+    // Column assignments for JSON Lines input.
+    json_cols: json::Columns,
+    // Set if we have yet to return a synthetic header line for JSON Lines input.
+    json_header: bool,
+    // Keys that the program looks up through FI, which are assigned columns along with the keys
+    // in the header.
+    json_declared: Vec<Box<[u8]>>,
+    events: FileEvents,
 
     empty_buf: Buf,
     check_utf8: bool,
@@ -62,6 +70,9 @@ impl LineReader for CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
     fn filename(&self) -> Str<'static> {
         Str::from(self.cur_chunk.get_name()).unmoor()
     }
+    fn fnr_offset(&self) -> Int {
+        self.cur_chunk.get_fnr_offset() as Int
+    }
     fn wait(&self) -> bool {
         self.prod.wait()
     }
@@ -75,6 +86,8 @@ impl LineReader for CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
         for p_factory in producers.into_iter() {
             let field_set = self.field_set.clone();
             let check_utf8 = self.check_utf8;
+            let json_cols = self.json_cols.clone();
+            let file_events = self.events.enabled();
             res.push(Box::new(move || {
                 let empty_buf = UniqueBuf::new(0).into_buf();
                 let cur_buf = empty_buf.clone();
@@ -88,6 +101,11 @@ impl LineReader for CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
                     last_len: 0,
                     ifmt,
                     field_set,
+                    json_cols,
+                    // Only the main reader consumes the header.
+                    json_header: false,
+                    json_declared: Vec::new(),
+                    events: FileEvents::new(file_events),
                     check_utf8,
                 }
             }) as _)
@@ -105,14 +123,20 @@ impl LineReader for CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
         _rc: &mut RegexCache,
         old: &'a mut Line,
     ) -> Result<bool> {
+        if self.events.enabled() {
+            if let Some(changed) = self.file_event()? {
+                return Ok(changed);
+            }
+        }
         self.read_line_inner(old)
     }
     fn read_state(&self) -> i64 {
-        if self.cur_chunk.version != 0 && self.last_len == 0 {
-            ReaderState::EOF as i64
-        } else {
-            ReaderState::OK as i64
-        }
+        self.events
+            .read_state(if self.cur_chunk.version != 0 && self.last_len == 0 {
+                ReaderState::EOF as i64
+            } else {
+                ReaderState::OK as i64
+            })
     }
     fn next_file(&mut self) -> Result<bool> {
         self.cur_chunk.off.clear();
@@ -124,6 +148,24 @@ impl LineReader for CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
     fn set_used_fields(&mut self, field_set: &FieldSet) {
         self.field_set = field_set.clone();
     }
+    fn set_file_events(&mut self) {
+        self.events.enable();
+        self.prod.report_empty_files();
+    }
+    fn declare_columns(&mut self, names: &[&[u8]]) {
+        if self.json_header {
+            self.json_declared = names.iter().map(|name| Box::from(*name)).collect();
+        }
+    }
+    fn take_new_columns(&mut self) -> Vec<(Str<'static>, Int)> {
+        let new = self.json_cols.take_new();
+        if !matches!(self.ifmt, InputFormat::JSONL { header: true }) {
+            return Vec::new();
+        }
+        new.into_iter()
+            .map(|(name, col)| (Buf::read_from_bytes(&name).into_str(), col as Int))
+            .collect()
+    }
 }
 
 impl CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
@@ -143,7 +185,8 @@ impl CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
             ExecutionStrategy::Serial => Box::new(chunk::new_chained_offset_chunk_producer_csv(
                 rs, chunk_size, ifmt, check_utf8,
             )),
-            x @ ExecutionStrategy::ShardPerRecord => {
+            x @ ExecutionStrategy::ShardPerRecord
+            | x @ ExecutionStrategy::ShardPerRecordOrdered => {
                 Box::new(CancellableChunkProducer::new(
                     cancel_signal,
                     ParallelChunkProducer::new(
@@ -153,6 +196,7 @@ impl CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
                             )
                         },
                         /*channel_size*/ x.num_workers() * 2,
+                        /*ordered*/ x.ordered(),
                     ),
                 ))
             }
@@ -186,6 +230,10 @@ impl CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
             last_len: 0,
             field_set: FieldSet::all(),
             ifmt,
+            json_cols: Default::default(),
+            json_header: matches!(ifmt, InputFormat::JSONL { header: true }),
+            json_declared: Vec::new(),
+            events: Default::default(),
             empty_buf,
             check_utf8,
         }
@@ -211,7 +259,40 @@ impl<P: ChunkProducer<Chunk = OffsetChunk>> CSVReader<P> {
         self.cur_buf = self.cur_chunk.buf.take().unwrap().into_buf();
         self.buf_len = self.cur_chunk.len;
         self.prev_ix = 0;
-        Ok((false, prev_version != self.cur_chunk.version))
+        // A chunk that starts partway through a file (see ChunkProducer::track_fnr) also counts as
+        // a change, so that FNR picks up from the chunk's offset.
+        let changed = prev_version != self.cur_chunk.version || self.cur_chunk.fnr_offset != 0;
+        Ok((false, changed))
+    }
+
+    // With file events enabled, we fetch the next chunk ourselves before reading a record, so
+    // that we can report the end of a file before reading past it. Empty files still yield a
+    // chunk in this mode (see ChunkProducer::report_empty_files).
+    fn file_event(&mut self) -> Result<Option</*file changed*/ bool>> {
+        if let Some(changed) = self.events.pending() {
+            return Ok(Some(changed));
+        }
+        loop {
+            let exhausted = if let InputFormat::JSONL { .. } = self.ifmt {
+                self.prev_ix >= self.buf_len
+            } else {
+                self.cur_chunk.off.rel.start == self.cur_chunk.off.rel.fields.len()
+            };
+            if !exhausted {
+                if self.skip_comment() {
+                    continue;
+                }
+                return Ok(None);
+            }
+            let version = self.cur_chunk.version;
+            let (is_eof, _) = self.refresh_buf()?;
+            if is_eof {
+                return Ok(self.events.transition(/*more=*/ false));
+            }
+            if self.cur_chunk.version != version {
+                return Ok(self.events.transition(/*more=*/ true));
+            }
+        }
     }
 
     fn stepper<'a, 'b: 'a>(&'b mut self, st: State, line: &'a mut Line) -> Stepper<'a> {
@@ -231,17 +312,24 @@ impl<P: ChunkProducer<Chunk = OffsetChunk>> CSVReader<P> {
         line: &'a mut Line,
     ) -> Result</*file changed*/ bool> {
         line.clear();
+        if let InputFormat::JSONL { .. } = self.ifmt {
+            return self.read_line_jsonl(line);
+        }
         let mut changed = false;
-        if self.cur_chunk.off.rel.start == self.cur_chunk.off.rel.fields.len() {
-            // NB: see comment on corresponding condition in ByteReader.
-            let (is_eof, has_changed) = self.refresh_buf()?;
-            changed = has_changed;
-            // NB: >= because the `push_past` logic in stepper can result in prev_ix pointing two
-            // past the end of the buffer.
-            if is_eof && self.prev_ix >= self.buf_len {
-                self.last_len = 0;
-                debug_assert!(!changed);
-                return Ok(false);
+        loop {
+            if self.cur_chunk.off.rel.start == self.cur_chunk.off.rel.fields.len() {
+                // NB: see comment on corresponding condition in ByteReader.
+                let (is_eof, has_changed) = self.refresh_buf()?;
+                changed |= has_changed;
+                // NB: >= because the `push_past` logic in stepper can result in prev_ix pointing
+                // two past the end of the buffer.
+                if is_eof && self.prev_ix >= self.buf_len {
+                    self.last_len = 0;
+                    return Ok(false);
+                }
+            }
+            if !self.skip_comment() {
+                break;
             }
         }
 
@@ -257,6 +345,107 @@ impl<P: ChunkProducer<Chunk = OffsetChunk>> CSVReader<P> {
         }
         Ok(changed)
     }
+
+    // If the current line is a comment, move past it and return true. The index kernels only
+    // emit two offsets for a comment line: the comment character and the newline ending it.
+    fn skip_comment(&mut self) -> bool {
+        let comment = match self.ifmt {
+            InputFormat::Delimited(Dialect {
+                comment: Some(c), ..
+            }) => c,
+            _ => return false,
+        };
+        let offs = &mut self.cur_chunk.off.rel;
+        match offs.fields.get(offs.start) {
+            Some(&ix)
+                if ix as usize == self.prev_ix
+                    && self.cur_buf.as_bytes()[self.prev_ix] == comment => {}
+            _ => return false,
+        }
+        self.prev_ix = match offs.fields.get(offs.start + 1) {
+            Some(&nl) => {
+                offs.start += 2;
+                nl as usize + 1
+            }
+            // The last line of the input need not end in a newline.
+            None => {
+                offs.start += 1;
+                self.buf_len
+            }
+        };
+        true
+    }
+
+    // JSON Lines input only has newlines in its offsets; field splitting happens one line at a
+    // time in the json module.
+    fn read_line_jsonl(&mut self, line: &mut Line) -> Result</*file changed*/ bool> {
+        let mut changed = false;
+        // Unlike CSV, we check for leftover bytes in the buffer before refreshing: the last line
+        // of the input may not end in a newline.
+        while self.prev_ix >= self.buf_len {
+            let (is_eof, has_changed) = self.refresh_buf()?;
+            changed |= has_changed;
+            if is_eof {
+                self.last_len = 0;
+                return Ok(false);
+            }
+        }
+        let offs = &mut self.cur_chunk.off.rel;
+        let start = self.prev_ix;
+        let nl = offs.fields.get(offs.start).map(|nl| *nl as usize);
+        let (mut end, next_ix) = match nl {
+            Some(nl) => (nl, nl + 1),
+            None => (self.buf_len, self.buf_len),
+        };
+        if end > start && self.cur_buf.as_bytes()[end - 1] == b'\r' {
+            end -= 1;
+        }
+        if self.json_header {
+            // With -H, the first record is not consumed. Instead we return a line containing the
+            // record's keys, followed by any other keys the program looks up through FI, so that
+            // FI is populated with the column assigned to each key.
+            self.json_header = false;
+            json::split_record(
+                &self.cur_buf,
+                start,
+                end,
+                &mut self.json_cols,
+                &FieldSet::empty(),
+                &mut line.fields,
+            )?;
+            for name in mem::take(&mut self.json_declared) {
+                self.json_cols.get_or_insert(&name);
+            }
+            line.fields.clear();
+            line.fields.extend(
+                self.json_cols
+                    .names()
+                    .iter()
+                    .map(|name| Buf::read_from_bytes(name).into_str()),
+            );
+            line.len = end - start;
+            self.last_len = next_ix - start;
+            return Ok(changed);
+        }
+        if nl.is_some() {
+            offs.start += 1;
+        }
+        self.prev_ix = next_ix;
+        self.last_len = next_ix - start;
+        line.len = end - start;
+        if self.field_set.get(0) {
+            line.raw = self.cur_buf.slice_to_str(start, end);
+        }
+        json::split_record(
+            &self.cur_buf,
+            start,
+            end,
+            &mut self.json_cols,
+            &self.field_set,
+            &mut line.fields,
+        )?;
+        Ok(changed)
+    }
 }
 
 #[derive(Default, Debug)]
@@ -372,11 +561,15 @@ impl<'a> super::Line<'a> for Line {
 impl Line {
     pub fn promote(&mut self) {
         let partial = mem::replace(&mut self.partial, Str::default());
-        self.fields.push(partial);
+        self.fields.push(if partial.is_empty() {
+            Str::empty_value()
+        } else {
+            partial
+        });
     }
     pub fn promote_null(&mut self) {
         debug_assert_eq!(self.partial, Str::default());
-        self.fields.push(Str::default());
+        self.fields.push(Str::empty_value());
     }
     pub fn clear(&mut self) {
         self.fields.clear();
@@ -441,16 +634,21 @@ impl<'a> Stepper<'a> {
     }
 
     pub unsafe fn step(&mut self) -> usize {
-        let sep = self.ifmt.sep();
+        let dialect = match self.ifmt {
+            InputFormat::Delimited(d) => d,
+            InputFormat::JSONL { .. } => unreachable!("JSON Lines input is not split by a Stepper"),
+        };
+        let sep = dialect.delim;
         let line_start = self.prev_ix;
         let bs = &self.buf.as_bytes()[0..self.buf_len];
         let mut cur = self.off.rel.start;
-        let bs_transition = match self.ifmt {
+        let bs_transition = if dialect.quote.is_some() {
             // Escape sequences only occur within quotes for CSV-formatted data.
-            InputFormat::CSV => State::Quote,
+            State::Quote
+        } else {
             // There are no "quoted fields" in TSV, and escape sequences simply occur at any point
             // in a field.
-            InputFormat::TSV => State::Init,
+            State::Init
         };
         macro_rules! get_next {
             () => {
@@ -477,77 +675,67 @@ impl<'a> Stepper<'a> {
                             }
                             let ix = *self.off.rel.fields.get_unchecked(cur) as usize;
                             cur += 1;
-                            match *bs.get_unchecked(ix) {
-                                b'\r' | b'"' | b'\\' => {}
-                                b'\n' => {
-                                    self.prev_ix = ix + 1;
-                                    self.promote_null();
-                                    self.st = State::Done;
-                                    return self.get(line_start, ix, cur);
-                                }
-                                _x => {
-                                    debug_assert_eq!(_x, sep);
-                                    self.prev_ix = ix + 1;
-                                    self.promote_null();
-                                    continue 'init;
-                                }
+                            let c = *bs.get_unchecked(ix);
+                            if c == b'\n' {
+                                self.prev_ix = ix + 1;
+                                self.promote_null();
+                                self.st = State::Done;
+                                return self.get(line_start, ix, cur);
+                            } else if c == sep {
+                                self.prev_ix = ix + 1;
+                                self.promote_null();
+                                continue 'init;
                             }
+                            // Otherwise, c is a carriage return, quote or escape character.
                         }
                     }
                     // Common case: Loop through records until the end of the line.
                     let ix = get_next!();
-                    match *bs.get_unchecked(ix) {
-                        b'\r' => {
-                            self.push_past(ix);
-                            continue;
-                        }
-                        b'\n' => {
-                            self.push_past(ix);
-                            self.promote();
-                            self.st = State::Done;
-                            return self.get(line_start, ix, cur);
-                        }
-                        b'"' => {
-                            self.push_past(ix);
-                            self.st = State::Quote;
-                            continue 'outer;
-                        }
-                        // Only happens in TSV mode
-                        b'\\' => {
-                            self.push_past(ix);
-                            self.st = State::BS;
-                            continue 'outer;
-                        }
-                        _x => {
-                            debug_assert_eq!(_x, sep);
-                            self.push_past(ix);
-                            self.promote();
-                            continue;
-                        }
+                    let c = *bs.get_unchecked(ix);
+                    if c == b'\r' {
+                        self.push_past(ix);
+                        continue;
+                    } else if c == b'\n' {
+                        self.push_past(ix);
+                        self.promote();
+                        self.st = State::Done;
+                        return self.get(line_start, ix, cur);
+                    } else if Some(c) == dialect.quote {
+                        self.push_past(ix);
+                        self.st = State::Quote;
+                        continue 'outer;
+                    } else if Some(c) == dialect.escape {
+                        // Only happens for unquoted dialects, like TSV.
+                        self.push_past(ix);
+                        self.st = State::BS;
+                        continue 'outer;
+                    } else {
+                        debug_assert_eq!(c, sep);
+                        self.push_past(ix);
+                        self.promote();
+                        continue;
                     }
                 },
                 State::Quote => {
                     // Parse a quoted field; this will only happen in CSV mode.
                     let ix = get_next!();
-                    match *bs.get_unchecked(ix) {
-                        b'"' => {
-                            // We have found a quote, time to figure out if the next character is a
-                            // quote, or if it is the end of the quoted portion of the field.
-                            //
-                            // One interesting thing to note here is that this allows for a mixture
-                            // of quoted and unquoted portions of a single CSV field, which is
-                            // technically more than is supported by the standard IIUC.
-                            self.push_past(ix);
-                            self.st = State::QuoteInQuote;
-                            continue;
-                        }
-                        b'\\' => {
-                            // A similar lookahead case: handling escaped sequences.
-                            self.push_past(ix);
-                            self.st = State::BS;
-                            continue;
-                        }
-                        _ => unreachable!(),
+                    let c = *bs.get_unchecked(ix);
+                    if Some(c) == dialect.quote {
+                        // We have found a quote, time to figure out if the next character is a
+                        // quote, or if it is the end of the quoted portion of the field.
+                        //
+                        // One interesting thing to note here is that this allows for a mixture
+                        // of quoted and unquoted portions of a single CSV field, which is
+                        // technically more than is supported by the standard IIUC.
+                        self.push_past(ix);
+                        self.st = State::QuoteInQuote;
+                        continue;
+                    } else {
+                        // A similar lookahead case: handling escaped sequences.
+                        debug_assert_eq!(Some(c), dialect.escape);
+                        self.push_past(ix);
+                        self.st = State::BS;
+                        continue;
                     }
                 }
                 State::QuoteInQuote => {
@@ -559,8 +747,8 @@ impl<'a> Stepper<'a> {
                         debug_assert_eq!(self.off.rel.fields.len(), cur);
                         return self.get(line_start, bs.len(), cur);
                     }
-                    if *bs.get_unchecked(self.prev_ix) == b'"' {
-                        self.append("\"".into());
+                    if Some(*bs.get_unchecked(self.prev_ix)) == dialect.quote {
+                        self.append_slice(self.prev_ix, self.prev_ix + 1);
                         self.st = State::Quote;
                         // burn the next entry. It should be a quote. Using get_next here is a
                         // convenience: if we hit the branch that returns early within the macro,
@@ -568,7 +756,7 @@ impl<'a> Stepper<'a> {
                         // should appear in the offsets vector, and we know that there is more
                         // space in `bs`.
                         let _q = get_next!();
-                        debug_assert_eq!(bs[_q], b'"');
+                        debug_assert_eq!(Some(bs[_q]), dialect.quote);
                         self.prev_ix += 1;
                     } else {
                         self.st = State::Init;
@@ -582,15 +770,16 @@ impl<'a> Stepper<'a> {
                     match *bs.get_unchecked(self.prev_ix) {
                         b'n' => self.append("\n".into()),
                         b't' => self.append("\t".into()),
-                        b'\\' => self.append("\\".into()),
-                        x => {
-                            let buf = &[x];
-                            let s: Str<'static> = Str::concat(
-                                "\\".into(),
-                                Str::from(str::from_utf8_unchecked(buf)).unmoor(),
-                            );
-                            self.append(s);
+                        // Escaped control characters stand for themselves.
+                        x if x == b'\n'
+                            || x == sep
+                            || Some(x) == dialect.quote
+                            || Some(x) == dialect.escape =>
+                        {
+                            self.append_slice(self.prev_ix, self.prev_ix + 1)
                         }
+                        // Other escape sequences are left alone, escape character and all.
+                        _ => self.append_slice(self.prev_ix - 1, self.prev_ix + 1),
                     }
                     self.prev_ix += 1;
                     self.st = bs_transition;
@@ -601,19 +790,173 @@ impl<'a> Stepper<'a> {
     }
 }
 
-#[derive(Copy, Clone)]
+/// The bytes that control how delimited (CSV-like) input is split.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Dialect {
+    /// Separates fields. Must be a single byte, so that the vectorized kernels can find it.
+    pub delim: u8,
+    /// Surrounds fields that contain delimiters or newlines; a doubled quote inside a quoted
+    /// field stands for one quote.
+    pub quote: Option<u8>,
+    /// Escapes the byte following it. In quoted dialects, escapes are only recognized inside
+    /// quotes. Both CSV and TSV use a backslash unless told otherwise.
+    pub escape: Option<u8>,
+    /// Marks a line as a comment when it is the first byte of that line. Comment lines are
+    /// skipped entirely, and do not count towards NR or FNR.
+    pub comment: Option<u8>,
+}
+
+impl Dialect {
+    pub const CSV: Dialect = Dialect {
+        delim: b',',
+        quote: Some(b'"'),
+        escape: Some(b'\\'),
+        comment: None,
+    };
+    pub const TSV: Dialect = Dialect {
+        delim: b'\t',
+        quote: None,
+        escape: Some(b'\\'),
+        comment: None,
+    };
+
+    fn validate(&self) -> Result<()> {
+        let delim = Some(self.delim);
+        for (name, b) in [
+            ("delim", delim),
+            ("quote", self.quote),
+            ("escape", self.escape),
+            ("comment", self.comment),
+        ] {
+            if let Some(b @ (b'\n' | b'\r')) = b {
+                return err!("{} cannot be a line terminator (got {:?})", name, b as char);
+            }
+        }
+        if delim == self.quote || delim == self.escape {
+            return err!("delim must differ from quote and escape characters");
+        }
+        if self.quote.is_some() && self.quote == self.escape {
+            // We could support this (as Excel-style doubled quotes), but doubled quotes already
+            // work without an escape character.
+            return err!(
+                "quote and escape characters must differ; \
+                 doubled quotes are always accepted within quoted fields"
+            );
+        }
+        if self.comment.is_some()
+            && (self.comment == delim || self.comment == self.quote || self.comment == self.escape)
+        {
+            return err!("comment must differ from the delim, quote and escape characters");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum InputFormat {
-    CSV,
-    TSV,
+    /// CSV, TSV, and their variants.
+    Delimited(Dialect),
+    /// One JSON value per line. If `header` is set, the keys of the first record are returned as
+    /// an initial line, ahead of the record itself.
+    JSONL { header: bool },
 }
 
 impl InputFormat {
-    fn sep(self) -> u8 {
+    pub const CSV: InputFormat = InputFormat::Delimited(Dialect::CSV);
+    pub const TSV: InputFormat = InputFormat::Delimited(Dialect::TSV);
+
+    pub fn dialect(self) -> Dialect {
         match self {
-            InputFormat::CSV => b',',
-            InputFormat::TSV => b'\t',
+            InputFormat::Delimited(d) => d,
+            // JSON Lines input only cares about newlines.
+            InputFormat::JSONL { .. } => Dialect {
+                delim: b'\n',
+                quote: None,
+                escape: None,
+                comment: None,
+            },
+        }
+    }
+
+    /// Parse an input format given on the command line. Formats are "csv", "tsv" or "jsonl",
+    /// where the first two can be followed by a list of overrides for the dialect, as in
+    /// `csv;delim=;,quote=\'`. The options are `delim`, `quote`, `escape` and `comment`; each is
+    /// set to a single character, a backslash escape (e.g. `\t`, `\\` or `\,`), or `none` to
+    /// disable it.
+    pub fn parse(spec: &str, header: bool) -> Result<InputFormat> {
+        let (name, opts) = match spec.split_once(';') {
+            Some((name, opts)) => (name, Some(opts)),
+            None => (spec, None),
+        };
+        let mut dialect = match name {
+            "csv" => Dialect::CSV,
+            "tsv" => Dialect::TSV,
+            "jsonl" if opts.is_none() => return Ok(InputFormat::JSONL { header }),
+            "jsonl" => return err!("the jsonl input format does not take options"),
+            _ => return err!("invalid input format: {}", spec),
+        };
+        let mut rest = opts.unwrap_or("");
+        while !rest.is_empty() {
+            let (key, val) = match rest.split_once('=') {
+                Some(x) => x,
+                None => return err!("expected key=value in input format, got {:?}", rest),
+            };
+            let (val, next) = parse_dialect_char(val)?;
+            match key {
+                "delim" => match val {
+                    Some(c) => dialect.delim = c,
+                    None => return err!("delim cannot be none"),
+                },
+                "quote" => dialect.quote = val,
+                "escape" => dialect.escape = val,
+                "comment" => dialect.comment = val,
+                _ => {
+                    return err!(
+                        "unknown input format option {:?} \
+                         (expected delim, quote, escape or comment)",
+                        key
+                    )
+                }
+            }
+            rest = match next.strip_prefix(',') {
+                Some(r) if !r.is_empty() => r,
+                None if next.is_empty() => next,
+                _ => return err!("malformed input format options: {:?}", opts.unwrap()),
+            };
         }
+        dialect.validate()?;
+        Ok(InputFormat::Delimited(dialect))
+    }
+}
+
+// Read a single dialect character off the front of `s`, returning it along with the remainder.
+fn parse_dialect_char(s: &str) -> Result<(Option<u8>, &str)> {
+    if let Some(rest) = s.strip_prefix("none") {
+        if rest.is_empty() || rest.starts_with(',') {
+            return Ok((None, rest));
+        }
+    }
+    let mut chars = s.chars();
+    let c = match chars.next() {
+        Some('\\') => match chars.next() {
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some(c) => c,
+            None => return err!("dangling backslash in input format"),
+        },
+        Some(c) => c,
+        None => return err!("missing value in input format"),
+    };
+    if !c.is_ascii() {
+        // Non-ASCII characters take more than one byte in UTF-8.
+        return err!(
+            "input format options must each be a single byte, got {:?}; \
+             use -F for multi-byte separators",
+            c
+        );
     }
+    Ok((Some(c as u8), chars.as_str()))
 }
 
 // get_find_indexes{_bytes,_ascii_whitespace}, what's that all about?
@@ -628,45 +971,108 @@ impl InputFormat {
 unsafe fn find_indexes_csv_avx2(
     buf: &[u8],
     offsets: &mut Offsets,
+    dialect: &Dialect,
     prev_iter_inside_quote: u64,
     prev_iter_cr_end: u64,
 ) -> (u64, u64) {
-    generic::find_indexes_csv::<avx2::Impl>(buf, offsets, prev_iter_inside_quote, prev_iter_cr_end)
+    generic::find_indexes_csv::<avx2::Impl>(
+        buf,
+        offsets,
+        dialect,
+        prev_iter_inside_quote,
+        prev_iter_cr_end,
+    )
 }
 
 #[target_feature(enable = "avx2")]
 unsafe fn find_indexes_tsv_avx2(
     buf: &[u8],
     offsets: &mut Offsets,
+    dialect: &Dialect,
     prev_iter_inside_quote: u64,
     prev_iter_cr_end: u64,
 ) -> (u64, u64) {
-    generic::find_indexes_tsv::<avx2::Impl>(buf, offsets, prev_iter_inside_quote, prev_iter_cr_end)
+    generic::find_indexes_tsv::<avx2::Impl>(
+        buf,
+        offsets,
+        dialect,
+        prev_iter_inside_quote,
+        prev_iter_cr_end,
+    )
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn find_indexes_jsonl_avx2(
+    buf: &[u8],
+    offsets: &mut Offsets,
+    dialect: &Dialect,
+    prev_iter_inside_quote: u64,
+    prev_iter_cr_end: u64,
+) -> (u64, u64) {
+    generic::find_indexes_jsonl::<avx2::Impl>(
+        buf,
+        offsets,
+        dialect,
+        prev_iter_inside_quote,
+        prev_iter_cr_end,
+    )
 }
 
 #[target_feature(enable = "sse2")]
 unsafe fn find_indexes_csv_sse2(
     buf: &[u8],
     offsets: &mut Offsets,
+    dialect: &Dialect,
     prev_iter_inside_quote: u64,
     prev_iter_cr_end: u64,
 ) -> (u64, u64) {
-    generic::find_indexes_csv::<sse2::Impl>(buf, offsets, prev_iter_inside_quote, prev_iter_cr_end)
+    generic::find_indexes_csv::<sse2::Impl>(
+        buf,
+        offsets,
+        dialect,
+        prev_iter_inside_quote,
+        prev_iter_cr_end,
+    )
 }
 
 #[target_feature(enable = "avx2")]
 unsafe fn find_indexes_tsv_sse2(
     buf: &[u8],
     offsets: &mut Offsets,
+    dialect: &Dialect,
     prev_iter_inside_quote: u64,
     prev_iter_cr_end: u64,
 ) -> (u64, u64) {
-    generic::find_indexes_tsv::<sse2::Impl>(buf, offsets, prev_iter_inside_quote, prev_iter_cr_end)
+    generic::find_indexes_tsv::<sse2::Impl>(
+        buf,
+        offsets,
+        dialect,
+        prev_iter_inside_quote,
+        prev_iter_cr_end,
+    )
 }
 
-pub fn get_find_indexes(
-    ifmt: InputFormat,
-) -> unsafe fn(&[u8], &mut Offsets, u64, u64) -> (u64, u64) {
+#[target_feature(enable = "sse2")]
+unsafe fn find_indexes_jsonl_sse2(
+    buf: &[u8],
+    offsets: &mut Offsets,
+    dialect: &Dialect,
+    prev_iter_inside_quote: u64,
+    prev_iter_cr_end: u64,
+) -> (u64, u64) {
+    generic::find_indexes_jsonl::<sse2::Impl>(
+        buf,
+        offsets,
+        dialect,
+        prev_iter_inside_quote,
+        prev_iter_cr_end,
+    )
+}
+
+pub type DelimitedIndexKernel = unsafe fn(&[u8], &mut Offsets, &Dialect, u64, u64) -> (u64, u64);
+
+// Quoted dialects go through the CSV kernel, and unquoted ones through the TSV kernel.
+pub fn get_find_indexes(ifmt: InputFormat) -> DelimitedIndexKernel {
     #[cfg(feature = "allow_avx2")]
     const ALLOW_AVX2: bool = true;
     #[cfg(not(feature = "allow_avx2"))]
@@ -674,18 +1080,23 @@ pub fn get_find_indexes(
 
     if ALLOW_AVX2 && is_x86_feature_detected!("avx2") && is_x86_feature_detected!("pclmulqdq") {
         match ifmt {
-            InputFormat::CSV => find_indexes_csv_avx2,
-            InputFormat::TSV => find_indexes_tsv_avx2,
+            InputFormat::Delimited(Dialect { quote: Some(_), .. }) => find_indexes_csv_avx2,
+            InputFormat::Delimited(_) => find_indexes_tsv_avx2,
+            InputFormat::JSONL { .. } => find_indexes_jsonl_avx2,
         }
     } else if is_x86_feature_detected!("sse2") && is_x86_feature_detected!("pclmulqdq") {
         match ifmt {
-            InputFormat::CSV => find_indexes_csv_sse2,
-            InputFormat::TSV => find_indexes_tsv_sse2,
+            InputFormat::Delimited(Dialect { quote: Some(_), .. }) => find_indexes_csv_sse2,
+            InputFormat::Delimited(_) => find_indexes_tsv_sse2,
+            InputFormat::JSONL { .. } => find_indexes_jsonl_sse2,
         }
     } else {
         match ifmt {
-            InputFormat::CSV => generic::find_indexes_csv::<generic::Impl>,
-            InputFormat::TSV => generic::find_indexes_tsv::<generic::Impl>,
+            InputFormat::Delimited(Dialect { quote: Some(_), .. }) => {
+                generic::find_indexes_csv::<generic::Impl>
+            }
+            InputFormat::Delimited(_) => generic::find_indexes_tsv::<generic::Impl>,
+            InputFormat::JSONL { .. } => generic::find_indexes_jsonl::<generic::Impl>,
         }
     }
 }
@@ -831,7 +1242,8 @@ mod escape_tests {
 }
 
 mod generic {
-    use super::{Offsets, WhitespaceOffsets};
+    use super::{Dialect, Offsets, WhitespaceOffsets};
+    use std::mem;
     const MAX_INPUT_SIZE: usize = 64;
 
     pub trait Vector: Copy {
@@ -852,10 +1264,8 @@ mod generic {
             self.cmp_against_input(m).mask()
         }
 
-        unsafe fn find_quote_mask(
-            self,
-            prev_iter_inside_quote: &mut u64,
-        ) -> (/*inside quotes*/ u64, /*quote locations*/ u64);
+        // Compute a mask of the bytes inside quotes, given the locations of the quotes.
+        unsafe fn find_quote_mask(quote_bits: u64, prev_iter_inside_quote: &mut u64) -> u64;
 
         // SIMD splitting by whitespace.
         //
@@ -970,9 +1380,8 @@ mod generic {
             foreach_impl!(ix, if self.0[ix] == m { 1u8 } else { 0u8 })
         }
 
-        unsafe fn find_quote_mask(self, prev_iter_inside_quote: &mut u64) -> (u64, u64) {
+        unsafe fn find_quote_mask(quote_mask: u64, prev_iter_inside_quote: &mut u64) -> u64 {
             // NB: this implementation is pretty naive. We could definitely speed this up.
-            let quote_mask = self.cmp_against_input(b'"').mask();
             let mut running_xor = 0;
             let mut res = 0u64;
             for ix in 0..64 {
@@ -981,15 +1390,15 @@ mod generic {
             }
             let in_quotes_mask = res ^ *prev_iter_inside_quote;
             *prev_iter_inside_quote = (in_quotes_mask as i64).wrapping_shr(63) as u64;
-            (in_quotes_mask, quote_mask)
+            in_quotes_mask
         }
     }
 
     #[cfg(target_arch = "x86_64")]
-    pub unsafe fn default_x86_find_quote_mask<V: Vector>(
-        inp: V,
+    pub unsafe fn default_x86_find_quote_mask(
+        quote_bits: u64,
         prev_iter_inside_quote: &mut u64,
-    ) -> (/*inside quotes*/ u64, /*quote locations*/ u64) {
+    ) -> u64 {
         use std::arch::x86_64::*;
         // This is about finding a mask that has 1s for all characters inside a quoted pair, plus
         // the starting quote, but not the ending one. For example:
@@ -997,8 +1406,8 @@ mod generic {
         // has the mask
         // [000000000000001111111111110]
         // We will use this mask to avoid splitting on commas that are inside a quoted field. We
-        // start by generating a mask for all the quote characters appearing in the string.
-        let quote_bits = inp.cmp_mask_against_input(b'"');
+        // start with a mask for all the quote characters appearing in the string.
+        //
         // Then we pull this trick from the simdjson paper. Lets use the example from the comments
         // above:
         // [unquoted text "quoted text"]
@@ -1021,7 +1430,7 @@ mod generic {
         quote_mask ^= prev;
         // We want all 1s if we ended in a quote, all zeros if not
         *prev_iter_inside_quote = (quote_mask as i64).wrapping_shr(63) as u64;
-        (quote_mask, quote_bits)
+        quote_mask
     }
 
     #[inline(always)]
@@ -1166,46 +1575,233 @@ mod generic {
         state
     }
 
+    // Given the locations of escape characters, find the characters that they escape. In a run of
+    // escape characters, every other one escapes the next, starting with the first (unless the
+    // previous input ended with an escape). This is a variant of the find_odd_backslash_sequences
+    // routine from simdjson.
+    #[inline(always)]
+    fn find_escaped<V: Vector>(esc: u64, prev_iter_ends_escape: &mut u64) -> u64 {
+        const EVEN_BITS: u64 = 0x5555_5555_5555_5555;
+        const ODD_BITS: u64 = !EVEN_BITS;
+        let start_edges = esc & !(esc << 1);
+        // Flip the lowest bit if the first character is escaped.
+        let even_start_mask = EVEN_BITS ^ *prev_iter_ends_escape;
+        let even_starts = start_edges & even_start_mask;
+        let odd_starts = start_edges & !even_start_mask;
+        // Adding the start of a run to the mask clears the bits of that run.
+        let even_runs = esc & !esc.wrapping_add(even_starts);
+        let odd_runs = esc & !esc.wrapping_add(odd_starts);
+        let escaping = (even_runs & EVEN_BITS) | (odd_runs & ODD_BITS);
+        let escaped = escaping.wrapping_shl(1) | *prev_iter_ends_escape;
+        *prev_iter_ends_escape = escaping.wrapping_shr(V::INPUT_SIZE as u32 - 1) & 1;
+        escaped
+    }
+
+    // Find the escape characters in the input, returning them along with the characters that they
+    // escape.
+    #[inline(always)]
+    unsafe fn escape_masks<V: Vector>(
+        inp: V,
+        escape: Option<u8>,
+        prev_iter_ends_escape: &mut u64,
+    ) -> (
+        /* escape characters */ u64,
+        /* escaped characters */ u64,
+    ) {
+        match escape {
+            Some(e) => {
+                let esc = inp.cmp_mask_against_input(e);
+                (esc, find_escaped::<V>(esc, prev_iter_ends_escape))
+            }
+            None => (0, 0),
+        }
+    }
+
+    // State carried from one block of delimited input to the next.
+    #[derive(Copy, Clone)]
+    struct DelimitedState {
+        inside_quote: u64,
+        cr_end: u64,
+        ends_escape: u64,
+        // Whether the next block begins a new line, and whether it begins partway through a
+        // comment line. These are only tracked for dialects with a comment character.
+        line_start: bool,
+        in_comment: bool,
+    }
+
+    // Comment lines are rare, and whether a comment character starts a line depends on the
+    // quotes and escapes preceding it, so blocks that may contain one are split a byte at a time.
+    // The offsets emitted for a comment line are the comment character and the newline ending
+    // the line, which lets the reader skip it.
+    unsafe fn find_indexes_scalar<V: Vector>(
+        buf: *const u8,
+        dialect: &Dialect,
+        st: &mut DelimitedState,
+    ) -> u64 {
+        let mut mask = 0u64;
+        let mut in_quote = st.inside_quote != 0;
+        let mut escaped = st.ends_escape != 0;
+        for i in 0..V::INPUT_SIZE {
+            let b = *buf.add(i);
+            if st.in_comment {
+                if b == b'\n' {
+                    mask |= 1 << i;
+                    st.in_comment = false;
+                    st.line_start = true;
+                }
+                continue;
+            }
+            if mem::replace(&mut st.line_start, false) && Some(b) == dialect.comment {
+                mask |= 1 << i;
+                st.in_comment = true;
+                continue;
+            }
+            let is_escaped = escaped;
+            let escaping = !is_escaped && Some(b) == dialect.escape;
+            escaped = escaping;
+            let mark = match dialect.quote {
+                Some(q) if b == q && !is_escaped => {
+                    in_quote = !in_quote;
+                    true
+                }
+                Some(_) if in_quote => escaping,
+                Some(_) => b == dialect.delim || b == b'\r' || b == b'\n',
+                None => !is_escaped && (b == dialect.delim || b == b'\n' || escaping),
+            };
+            if mark {
+                mask |= 1 << i;
+            }
+            st.line_start = b == b'\n' && !in_quote && (dialect.quote.is_some() || !is_escaped);
+        }
+        st.inside_quote = if in_quote { !0 } else { 0 };
+        st.ends_escape = escaped as u64;
+        st.cr_end = (*buf.add(V::INPUT_SIZE - 1) == b'\r') as u64;
+        mask
+    }
+
+    // Check whether a block needs to be split by find_indexes_scalar, given the newlines in the
+    // block that end a line. Otherwise, update the line_start state for the next block.
+    #[inline(always)]
+    unsafe fn check_comments<V: Vector>(
+        inp: V,
+        comment: Option<u8>,
+        line_ends: u64,
+        prev: &DelimitedState,
+        next: &mut DelimitedState,
+    ) -> bool {
+        let comment = match comment {
+            Some(c) => c,
+            None => return false,
+        };
+        let line_starts = line_ends.wrapping_shl(1) | prev.line_start as u64;
+        if prev.in_comment || inp.cmp_mask_against_input(comment) & line_starts != 0 {
+            return true;
+        }
+        next.line_start = line_ends.wrapping_shr(V::INPUT_SIZE as u32 - 1) & 1 == 1;
+        false
+    }
+
+    fn initial_state(prev_iter_inside_quote: u64, prev_iter_cr_end: u64) -> DelimitedState {
+        DelimitedState {
+            inside_quote: prev_iter_inside_quote,
+            cr_end: prev_iter_cr_end,
+            ends_escape: 0,
+            // Offsets are always computed starting at the beginning of a line.
+            line_start: true,
+            in_comment: false,
+        }
+    }
+
+    // Split a quoted dialect, like CSV.
     pub unsafe fn find_indexes_csv<V: Vector>(
         buf: &[u8],
         offsets: &mut Offsets,
+        dialect: &Dialect,
         prev_iter_inside_quote: u64, /*start at 0*/
         prev_iter_cr_end: u64,       /*start at 0*/
     ) -> (u64, u64) {
-        let f = |(mut prev_iter_inside_quote, mut prev_iter_cr_end), buf| {
+        let Dialect {
+            delim,
+            quote,
+            escape,
+            comment,
+        } = *dialect;
+        let quote = quote.expect("find_indexes_csv requires a quote character");
+        let f = |prev: DelimitedState, buf| {
+            let mut next = prev;
             let inp = V::fill_input(buf);
-            let (quote_mask, quote_locs) = inp.find_quote_mask(&mut prev_iter_inside_quote);
-            let sep = inp.cmp_mask_against_input(b',');
-            let esc = inp.cmp_mask_against_input(b'\\');
+            let (esc, escaped) = escape_masks(inp, escape, &mut next.ends_escape);
+            // Escaped quotes do not begin or end a quoted field.
+            let quote_locs = inp.cmp_mask_against_input(quote) & !escaped;
+            let quote_mask = V::find_quote_mask(quote_locs, &mut next.inside_quote);
+            let sep = inp.cmp_mask_against_input(delim);
 
             let cr = inp.cmp_mask_against_input(0x0d);
-            let cr_adjusted = cr.wrapping_shl(1) | prev_iter_cr_end;
+            let cr_adjusted = cr.wrapping_shl(1) | prev.cr_end;
             let lf = inp.cmp_mask_against_input(0x0a);
             // Allow for either \r\n or \n.
             let end = (lf & cr_adjusted) | lf;
-            prev_iter_cr_end = cr.wrapping_shr(V::INPUT_SIZE as u32 - 1);
+            next.cr_end = cr.wrapping_shr(V::INPUT_SIZE as u32 - 1);
             // NB: for now, NL is going to be unused for csv
             // Don't use NL here for now
             // let nl = end & !quote_mask;
-            let mask = ((sep | cr | end) & !quote_mask) | (esc & quote_mask) | quote_locs;
-            ((prev_iter_inside_quote, prev_iter_cr_end), mask, 0)
+            if check_comments(inp, comment, end & !quote_mask, &prev, &mut next) {
+                let mut next = prev;
+                let mask = find_indexes_scalar::<V>(buf, dialect, &mut next);
+                return (next, mask, 0);
+            }
+            let mask =
+                ((sep | cr | end) & !quote_mask) | (esc & !escaped & quote_mask) | quote_locs;
+            (next, mask, 0)
         };
-        find_indexes::<V, _, _>(buf, offsets, (prev_iter_inside_quote, prev_iter_cr_end), f)
+        let st = find_indexes::<V, _, _>(
+            buf,
+            offsets,
+            initial_state(prev_iter_inside_quote, prev_iter_cr_end),
+            f,
+        );
+        (st.inside_quote, st.cr_end)
     }
 
+    // Split an unquoted dialect, like TSV.
     pub unsafe fn find_indexes_tsv<V: Vector>(
         buf: &[u8],
         offsets: &mut Offsets,
+        dialect: &Dialect,
         // These two are ignored for TSV
         _prev_iter_inside_quote: u64,
         _prev_iter_cr_end: u64,
+    ) -> (u64, u64) {
+        let (delim, escape, comment) = (dialect.delim, dialect.escape, dialect.comment);
+        let f = |prev: DelimitedState, ptr| {
+            let mut next = prev;
+            let inp = V::fill_input(ptr);
+            let (esc, escaped) = escape_masks(inp, escape, &mut next.ends_escape);
+            let sep = inp.cmp_mask_against_input(delim);
+            let lf = inp.cmp_mask_against_input(b'\n');
+            if check_comments(inp, comment, lf & !escaped, &prev, &mut next) {
+                let mut next = prev;
+                let mask = find_indexes_scalar::<V>(ptr, dialect, &mut next);
+                return (next, mask, 0);
+            }
+            (next, (sep | esc | lf) & !escaped, 0)
+        };
+        find_indexes::<V, _, _>(buf, offsets, initial_state(0, 0), f);
+        (0, 0)
+    }
+
+    // JSON strings cannot contain a literal newline, so all we need to find are newlines.
+    pub unsafe fn find_indexes_jsonl<V: Vector>(
+        buf: &[u8],
+        offsets: &mut Offsets,
+        // These three are ignored for JSON Lines
+        _dialect: &Dialect,
+        _prev_iter_inside_quote: u64,
+        _prev_iter_cr_end: u64,
     ) -> (u64, u64) {
         find_indexes_unquoted::<V, _>(buf, offsets, |ptr| {
             let inp = V::fill_input(ptr);
-            let sep = inp.cmp_against_input(b'\t');
-            let esc = inp.cmp_against_input(b'\\');
-            let lf = inp.cmp_against_input(b'\n');
-            (sep.or(esc).or(lf).mask(), 0)
+            (inp.cmp_mask_against_input(b'\n'), 0)
         });
         (0, 0)
     }
@@ -1307,11 +1903,8 @@ mod sse2 {
         }
 
         #[inline(always)]
-        unsafe fn find_quote_mask(
-            self,
-            prev_iter_inside_quote: &mut u64,
-        ) -> (/*inside quotes*/ u64, /*quote locations*/ u64) {
-            default_x86_find_quote_mask::<Self>(self, prev_iter_inside_quote)
+        unsafe fn find_quote_mask(quote_bits: u64, prev_iter_inside_quote: &mut u64) -> u64 {
+            default_x86_find_quote_mask(quote_bits, prev_iter_inside_quote)
         }
     }
 }
@@ -1369,11 +1962,8 @@ mod avx2 {
         }
 
         #[inline(always)]
-        unsafe fn find_quote_mask(
-            self,
-            prev_iter_inside_quote: &mut u64,
-        ) -> (/*inside quotes*/ u64, /*quote locations*/ u64) {
-            default_x86_find_quote_mask::<Self>(self, prev_iter_inside_quote)
+        unsafe fn find_quote_mask(quote_bits: u64, prev_iter_inside_quote: &mut u64) -> u64 {
+            default_x86_find_quote_mask(quote_bits, prev_iter_inside_quote)
         }
     }
 }
@@ -1387,9 +1977,14 @@ pub struct ByteReader<P: ChunkProducer> {
     // Progress in the current buffer.
     progress: usize,
     record_sep: u8,
+    // When set, the chunk producer only finds record boundaries and fields are split by FS when
+    // they are first used, as they are for RegexSplitter. This holds the fields that the program
+    // uses, while `used_fields` stays `all()` so that $0 is always materialized.
+    lazy_fields: Option<FieldSet>,
 
     last_len: usize,
     check_utf8: bool,
+    events: FileEvents,
 }
 
 impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
@@ -1418,6 +2013,37 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
         )
     }
 
+    /// A reader that splits records on a single byte, leaving fields to be split by an arbitrary
+    /// FS after the record is read. This allows for parallel execution when FS is a regular
+    /// expression or a multi-byte string.
+    pub fn new_lazy<I, S>(
+        rs: I,
+        record_sep: u8,
+        chunk_size: usize,
+        check_utf8: bool,
+        exec_strategy: ExecutionStrategy,
+        cancel_signal: CancelSignal,
+    ) -> Self
+    where
+        I: Iterator<Item = (S, String)> + 'static + Send,
+        S: Read + Send + 'static,
+    {
+        // Using the record separator as the field separator means that each record is a single
+        // field.
+        let mut res = Self::new_internal(
+            rs,
+            record_sep,
+            record_sep,
+            chunk_size,
+            check_utf8,
+            exec_strategy,
+            get_find_indexes_bytes(),
+            cancel_signal,
+        );
+        res.lazy_fields = Some(FieldSet::all());
+        res
+    }
+
     // Not great, but grouping into a separate type is a bit awkward given the
     // different permutations used between these modules.
     //
@@ -1441,7 +2067,8 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
             ExecutionStrategy::Serial => Box::new(chunk::new_chained_offset_chunk_producer_bytes(
                 rs, chunk_size, field_sep, record_sep, check_utf8, kernel,
             )),
-            x @ ExecutionStrategy::ShardPerRecord => {
+            x @ ExecutionStrategy::ShardPerRecord
+            | x @ ExecutionStrategy::ShardPerRecordOrdered => {
                 Box::new(CancellableChunkProducer::new(
                     cancel_signal,
                     ParallelChunkProducer::new(
@@ -1451,6 +2078,7 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
                             )
                         },
                         /*channel_size*/ x.num_workers() * 2,
+                        /*ordered*/ x.ordered(),
                     ),
                 ))
             }
@@ -1482,9 +2110,11 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
             buf_len: 0,
             progress: 0,
             record_sep,
+            lazy_fields: None,
             used_fields: FieldSet::all(),
             last_len: usize::max_value(),
             check_utf8,
+            events: Default::default(),
         }
     }
 }
@@ -1532,7 +2162,8 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
                         find_indexes,
                     ))
                 }
-                x @ ExecutionStrategy::ShardPerRecord => {
+                x @ ExecutionStrategy::ShardPerRecord
+                | x @ ExecutionStrategy::ShardPerRecordOrdered => {
                     Box::new(CancellableChunkProducer::new(
                         cancel_signal,
                         ParallelChunkProducer::new(
@@ -1545,6 +2176,7 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
                                 )
                             },
                             /*channel_size*/ x.num_workers() * 2,
+                            /*ordered*/ x.ordered(),
                         ),
                     ))
                 }
@@ -1574,9 +2206,11 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
             buf_len: 0,
             progress: 0,
             record_sep: 0, // unused
+            lazy_fields: None,
             used_fields: FieldSet::all(),
             last_len: usize::max_value(),
             check_utf8,
+            events: Default::default(),
         }
     }
 }
@@ -1589,6 +2223,9 @@ where
     fn filename(&self) -> Str<'static> {
         Str::from(self.cur_chunk.get_name()).unmoor()
     }
+    fn fnr_offset(&self) -> Int {
+        self.cur_chunk.get_fnr_offset() as Int
+    }
     fn check_utf8(&self) -> bool {
         self.check_utf8
     }
@@ -1600,8 +2237,10 @@ where
         let mut res = Vec::with_capacity(producers.len());
         for p_factory in producers.into_iter() {
             let used_fields = self.used_fields.clone();
+            let lazy_fields = self.lazy_fields.clone();
             let record_sep = self.record_sep;
             let check_utf8 = self.check_utf8;
+            let file_events = self.events.enabled();
             res.push(Box::new(move || ByteReader {
                 prod: p_factory(),
                 cur_chunk: Default::default(),
@@ -1609,9 +2248,11 @@ where
                 buf_len: 0,
                 progress: 0,
                 record_sep,
+                lazy_fields,
                 last_len: usize::max_value(),
                 used_fields,
                 check_utf8,
+                events: FileEvents::new(file_events),
             }) as _)
         }
         res
@@ -1627,24 +2268,36 @@ where
         _rc: &mut RegexCache,
         old: &'a mut DefaultLine,
     ) -> Result<bool> {
+        if self.events.enabled() {
+            if let Some(changed) = self.file_event()? {
+                return Ok(changed);
+            }
+        }
         let start = self.cur_chunk_version() == 0;
         old.diverged = false;
         // We use the same protocol as DefaultSplitter, RegexSplitter. See comments for more info.
+        let used_fields = self.lazy_fields.as_mut().unwrap_or(&mut self.used_fields);
         if start {
-            old.used_fields = self.used_fields.clone();
-        } else if old.used_fields != self.used_fields {
-            self.used_fields = old.used_fields.clone()
+            old.used_fields = used_fields.clone();
+        } else if &old.used_fields != used_fields {
+            *used_fields = old.used_fields.clone()
         }
         old.fields.clear();
         let changed = self.read_line_inner(&mut old.line, &mut old.fields)?;
+        if self.lazy_fields.is_some() {
+            // Leave the fields to DefaultLine::split_if_needed.
+            old.fields.clear();
+            old.mode = SplitMode::Regex;
+        }
         Ok(changed)
     }
     fn read_state(&self) -> i64 {
-        if self.cur_chunk_version() != 0 && self.last_len == 0 {
-            ReaderState::EOF as i64
-        } else {
-            ReaderState::OK as i64
-        }
+        self.events
+            .read_state(if self.cur_chunk_version() != 0 && self.last_len == 0 {
+                ReaderState::EOF as i64
+            } else {
+                ReaderState::OK as i64
+            })
     }
 
     fn next_file(&mut self) -> Result<bool> {
@@ -1656,7 +2309,41 @@ where
     }
 
     fn set_used_fields(&mut self, field_set: &FieldSet) {
-        self.used_fields = field_set.clone();
+        match &mut self.lazy_fields {
+            Some(fields) => *fields = field_set.clone(),
+            None => self.used_fields = field_set.clone(),
+        }
+    }
+    fn set_file_events(&mut self) {
+        self.events.enable();
+        self.prod.report_empty_files();
+    }
+}
+
+impl<P: ChunkProducer> ByteReader<P>
+where
+    Self: ByteReaderBase,
+{
+    // See CSVReader::file_event.
+    fn file_event(&mut self) -> Result<Option</*file changed*/ bool>> {
+        if let Some(changed) = self.events.pending() {
+            return Ok(Some(changed));
+        }
+        while self.maybe_done() {
+            let version = self.cur_chunk_version();
+            let (is_eof, _) = self.refresh_buf()?;
+            if is_eof {
+                if self.progress < self.buf_len {
+                    // The rest of the buffer holds the file's last record.
+                    break;
+                }
+                return Ok(self.events.transition(/*more=*/ false));
+            }
+            if self.cur_chunk_version() != version {
+                return Ok(self.events.transition(/*more=*/ true));
+            }
+        }
+        Ok(None)
     }
 }
 
@@ -1696,7 +2383,9 @@ where
     br.cur_buf = br.cur_chunk.buf.take().unwrap().into_buf();
     br.buf_len = br.cur_chunk.len;
     br.progress = 0;
-    Ok((false, prev_version != br.cur_chunk.version))
+    // See the comment in the equivalent line in CSVReader.
+    let changed = prev_version != br.cur_chunk.version || br.cur_chunk.fnr_offset != 0;
+    Ok((false, changed))
 }
 
 fn read_line_inner_impl<'a, 'b: 'a, T, P: ChunkProducer<Chunk = OffsetChunk<T>>>(
@@ -1957,7 +2646,7 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
         mem.reserve(32);
         let mut offsets: Offsets = Default::default();
         let (in_quote, in_cr) =
-            unsafe { generic::find_indexes_csv::<V>(&mem[..], &mut offsets, 0, 0) };
+            unsafe { generic::find_indexes_csv::<V>(&mem[..], &mut offsets, &Dialect::CSV, 0, 0) };
         assert_ne!(in_quote, 0);
         assert_eq!(in_cr, 0);
         assert_eq!(
@@ -1984,6 +2673,264 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
         smoke_test::<generic::Impl>();
     }
 
+    // A byte-at-a-time version of the delimited kernels.
+    fn reference_indexes(bs: &[u8], d: &Dialect) -> Vec<u64> {
+        let mut res = Vec::new();
+        let (mut in_quote, mut escaped) = (false, false);
+        let (mut line_start, mut in_comment) = (true, false);
+        for (i, &b) in bs.iter().enumerate() {
+            if in_comment {
+                if b == b'\n' {
+                    res.push(i as u64);
+                    in_comment = false;
+                    line_start = true;
+                }
+                continue;
+            }
+            if line_start && Some(b) == d.comment {
+                res.push(i as u64);
+                in_comment = true;
+                continue;
+            }
+            let is_escaped = escaped;
+            let escaping = !is_escaped && Some(b) == d.escape;
+            escaped = escaping;
+            let mark = match d.quote {
+                Some(q) if b == q && !is_escaped => {
+                    in_quote = !in_quote;
+                    true
+                }
+                Some(_) if in_quote => escaping,
+                Some(_) => b == d.delim || b == b'\r' || b == b'\n',
+                None => !is_escaped && (b == d.delim || b == b'\n' || escaping),
+            };
+            if mark {
+                res.push(i as u64);
+            }
+            line_start = b == b'\n' && !in_quote && (d.quote.is_some() || !is_escaped);
+        }
+        res
+    }
+
+    fn dialect_indexes<V: generic::Vector>() {
+        let dialects = [
+            Dialect::CSV,
+            Dialect::TSV,
+            InputFormat::parse("csv;delim=;,quote=',escape=^", false)
+                .unwrap()
+                .dialect(),
+            InputFormat::parse("csv;delim=|,escape=none", false)
+                .unwrap()
+                .dialect(),
+            InputFormat::parse("tsv;delim=,", false).unwrap().dialect(),
+            InputFormat::parse(r#"csv;escape=\\"#, false)
+                .unwrap()
+                .dialect(),
+            InputFormat::parse(r#"tsv;escape=\\"#, false)
+                .unwrap()
+                .dialect(),
+            InputFormat::parse("csv;comment=#", false)
+                .unwrap()
+                .dialect(),
+            InputFormat::parse(r#"csv;escape=\\,comment=#"#, false)
+                .unwrap()
+                .dialect(),
+            InputFormat::parse(r#"tsv;escape=\\,comment=#"#, false)
+                .unwrap()
+                .dialect(),
+        ];
+        // A small LCG is enough to cover runs of escapes that span vector boundaries.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for d in dialects.iter() {
+            let alphabet = [
+                b'a',
+                b'\n',
+                b'\r',
+                d.delim,
+                d.quote.unwrap_or(b'"'),
+                d.escape.unwrap_or(b'\\'),
+                d.escape.unwrap_or(b'\\'),
+                d.comment.unwrap_or(b'#'),
+            ];
+            for len in 0..300 {
+                let mut mem: Vec<u8> = (0..len)
+                    .map(|_| {
+                        state = state
+                            .wrapping_mul(6364136223846793005)
+                            .wrapping_add(1442695040888963407);
+                        alphabet[(state >> 33) as usize % alphabet.len()]
+                    })
+                    .collect();
+                let expected = reference_indexes(&mem[..], d);
+                mem.reserve(64);
+                let mut offsets: Offsets = Default::default();
+                // flatten_bits may write past the last offset.
+                offsets.rel.fields.reserve(len + 64);
+                offsets.nl.fields.reserve(len + 64);
+                unsafe {
+                    if d.quote.is_some() {
+                        generic::find_indexes_csv::<V>(&mem[..], &mut offsets, d, 0, 0);
+                    } else {
+                        generic::find_indexes_tsv::<V>(&mem[..], &mut offsets, d, 0, 0);
+                    }
+                }
+                assert_eq!(
+                    &offsets.rel.fields[..],
+                    &expected[..],
+                    "dialect={:?} input={:?}",
+                    d,
+                    String::from_utf8_lossy(&mem[..])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dialect_kernels() {
+        if is_x86_feature_detected!("avx2") {
+            dialect_indexes::<avx2::Impl>();
+        }
+        if is_x86_feature_detected!("sse2") {
+            dialect_indexes::<sse2::Impl>();
+        }
+        dialect_indexes::<generic::Impl>();
+    }
+
+    #[test]
+    fn parse_dialects() {
+        let parse = |s| InputFormat::parse(s, false);
+        assert_eq!(parse("csv").unwrap(), InputFormat::CSV);
+        assert_eq!(parse("tsv").unwrap(), InputFormat::TSV);
+        assert_eq!(
+            parse("jsonl").unwrap(),
+            InputFormat::JSONL { header: false }
+        );
+        assert_eq!(
+            parse(r#"csv;delim=;,quote=\'"#).unwrap(),
+            InputFormat::Delimited(Dialect {
+                delim: b';',
+                quote: Some(b'\''),
+                escape: Some(b'\\'),
+                comment: None,
+            })
+        );
+        assert_eq!(
+            parse(r#"tsv;delim=|,quote=",escape=none"#).unwrap(),
+            InputFormat::Delimited(Dialect {
+                delim: b'|',
+                quote: Some(b'"'),
+                escape: None,
+                comment: None,
+            })
+        );
+        // Commas and backslashes can be used as values.
+        assert_eq!(
+            parse(r#"tsv;delim=,,escape=\\"#).unwrap(),
+            InputFormat::Delimited(Dialect {
+                delim: b',',
+                quote: None,
+                escape: Some(b'\\'),
+                comment: None,
+            })
+        );
+        assert_eq!(
+            parse(r#"csv;comment=#"#).unwrap(),
+            InputFormat::Delimited(Dialect {
+                delim: b',',
+                quote: Some(b'"'),
+                escape: Some(b'\\'),
+                comment: Some(b'#'),
+            })
+        );
+        assert_eq!(parse(r#"csv;delim=\t"#).unwrap().dialect().delim, b'\t');
+        for bad in &[
+            "xsv",
+            "jsonl;delim=,",
+            "csv;delim",
+            "csv;delim=",
+            "csv;delim=none",
+            "csv;delim=ab",
+            "csv;delim=\u{2016}",
+            "csv;delim=;,",
+            "csv;sep=;",
+            "csv;delim=\"",
+            "csv;escape=\"",
+            "csv;comment=,",
+            r#"csv;comment=\n"#,
+            r#"csv;escape=\\,comment=\\"#,
+            r#"csv;delim=\n"#,
+        ] {
+            assert!(parse(bad).is_err(), "{:?} should not parse", bad);
+        }
+    }
+
+    fn split_dialect(ifmt: &str, corpus: &'static str) -> Vec<Vec<String>> {
+        let mut _cache = RegexCache::default();
+        let _pat = Str::default();
+        let mut reader = CSVReader::new(
+            iter::once((io::Cursor::new(corpus), String::from("fake-stdin"))),
+            InputFormat::parse(ifmt, false).unwrap(),
+            /*chunk_size=*/ 512,
+            /*check_utf8=*/ true,
+            ExecutionStrategy::Serial,
+            Default::default(),
+        );
+        let mut got = Vec::new();
+        loop {
+            let (_, line) = reader
+                .read_line(&_pat, &mut _cache)
+                .expect("failed to read line");
+            if reader.read_state() != 1 {
+                break;
+            }
+            got.push(line.fields.iter().map(|s| format!("{}", s)).collect());
+        }
+        got
+    }
+
+    #[test]
+    fn dialect_split() {
+        assert_eq!(
+            split_dialect("csv;delim=;", "a;\"b;c\";d\n1;\"2\"\"\";3\n"),
+            vec![vec!["a", "b;c", "d"], vec!["1", "2\"", "3"]]
+        );
+        assert_eq!(
+            split_dialect("csv;delim=|,quote='", "a|'b|c'|'it''s'\n"),
+            vec![vec!["a", "b|c", "it's"]]
+        );
+        // MySQL-style exports escape delimiters, newlines and the escape character itself.
+        assert_eq!(
+            split_dialect("tsv", "O\\'Brien\ta\\\tb\tx\\\ny\n\\\\\t\\N\t\\tz\n"),
+            vec![vec!["O\\'Brien", "a\tb", "x\ny"], vec!["\\", "\\N", "\tz"]]
+        );
+        assert_eq!(
+            split_dialect("csv", "\"a\\\"b\",\"c\\\\\",d\n"),
+            vec![vec!["a\"b", "c\\", "d"]]
+        );
+        // Backslashes are read literally once escapes are turned off.
+        assert_eq!(
+            split_dialect("csv;escape=none", "\"C:\\dir\\\",y\n"),
+            vec![vec!["C:\\dir\\", "y"]]
+        );
+        assert_eq!(
+            split_dialect("tsv;escape=none", "a\\tb\tc\n"),
+            vec![vec!["a\\tb", "c"]]
+        );
+        // Comment lines are skipped, wherever they fall, but only if they begin a line outside
+        // of a quoted field.
+        assert_eq!(
+            split_dialect(
+                "csv;comment=#",
+                "# a,\"b\n#c\n1,\"2\n# 3\"\n\n# 4\n #5,6\n#"
+            ),
+            vec![vec!["1", "2\n# 3"], vec![""], vec![" #5", "6"]]
+        );
+        assert_eq!(
+            split_dialect(r#"tsv;escape=\\,comment=#"#, "a\\\n#b\tc\n#d\n"),
+            vec![vec!["a\n#b", "c"]]
+        );
+    }
+
     fn disp_vec(v: &[Str]) -> String {
         format!(
             "{:?}",
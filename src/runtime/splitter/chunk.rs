@@ -1,11 +1,11 @@
 use std::borrow::Borrow;
 use std::io::Read;
 use std::mem;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
 
-use crate::common::{CancelSignal, Result};
+use crate::common::{CancelSignal, CompileError, Result};
 use crate::runtime::{
     splitter::{
         batch::{
@@ -15,6 +15,7 @@ use crate::runtime::{
         Reader,
     },
     str_impl::UniqueBuf,
+    writers::{self, Sequencer},
 };
 
 /// A vector of dynamically typed factory functions producing a chunk producer
@@ -44,10 +45,21 @@ pub trait ChunkProducer {
     }
     fn get_chunk(&mut self, chunk: &mut Self::Chunk) -> Result<bool /*done*/>;
     fn next_file(&mut self) -> Result<bool /*new file available*/>;
+    // Count the records in each chunk, so that chunks can report their FNR offset. This is only
+    // needed when chunks from the same file may be read by different threads.
+    fn track_fnr(&mut self) {}
+    // Yield an empty chunk for each input file with no data, so that readers reporting file
+    // events see every file (see LineReader::set_file_events).
+    fn report_empty_files(&mut self) {}
 }
 
 pub trait Chunk: Send + Default {
     fn get_name(&self) -> &str;
+    // Changes whenever a producer moves on to a new file.
+    fn get_version(&self) -> u32;
+    fn get_fnr_offset(&self) -> u64 {
+        0
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -63,7 +75,15 @@ pub struct OffsetChunkProducer<R, F> {
     name: Arc<str>,
     find_indexes: F,
     record_sep: u8,
+    // Lines starting with this byte are comments, which are skipped rather than counted as
+    // records.
+    comment: Option<u8>,
     state: ChunkState,
+    // The number of records yielded so far from this file, if we are counting them.
+    records: Option<u64>,
+    // Set if we should yield an empty chunk should this file turn out to have no data (see
+    // ChunkProducer::report_empty_files). Cleared once we yield a chunk.
+    report_empty: bool,
 }
 
 pub fn new_offset_chunk_producer_csv<R: Read>(
@@ -75,15 +95,19 @@ pub fn new_offset_chunk_producer_csv<R: Read>(
     check_utf8: bool,
 ) -> OffsetChunkProducer<R, impl FnMut(&[u8], &mut Offsets)> {
     let find_indexes = get_find_indexes(ifmt);
+    let dialect = ifmt.dialect();
     OffsetChunkProducer {
         name: name.into(),
         inner: Reader::new(r, chunk_size, /*padding=*/ 128, check_utf8),
         find_indexes: move |bs: &[u8], offs: &mut Offsets| {
-            unsafe { find_indexes(bs, offs, 0, 0) };
+            unsafe { find_indexes(bs, offs, &dialect, 0, 0) };
         },
         record_sep: b'\n',
+        comment: dialect.comment,
         cur_file_version: start_version,
         state: ChunkState::Init,
+        records: None,
+        report_empty: false,
     }
 }
 
@@ -105,7 +129,10 @@ pub fn new_offset_chunk_producer_bytes<R: Read>(
         },
         cur_file_version: start_version,
         record_sep,
+        comment: None,
         state: ChunkState::Init,
+        records: None,
+        report_empty: false,
     }
 }
 
@@ -126,7 +153,10 @@ pub fn new_offset_chunk_producer_ascii_whitespace<R: Read>(
             },
             cur_file_version: start_version,
             record_sep: 0u8, // unused
+            comment: None,
             state: ChunkState::Init,
+            records: None,
+            report_empty: false,
         },
         1,
     )
@@ -230,11 +260,20 @@ impl<C: Chunk> ChunkProducer for Box<dyn ChunkProducer<Chunk = C>> {
     fn get_chunk(&mut self, chunk: &mut C) -> Result<bool> {
         (&mut **self).get_chunk(chunk)
     }
+    fn track_fnr(&mut self) {
+        (**self).track_fnr()
+    }
+    fn report_empty_files(&mut self) {
+        (**self).report_empty_files()
+    }
 }
 
 pub struct OffsetChunk<Off = Offsets> {
     pub version: u32,
     pub name: Arc<str>,
+    // The number of records in the file before this chunk. This is always zero unless the
+    // producer is tracking FNR (see ChunkProducer::track_fnr).
+    pub fnr_offset: u64,
     pub buf: Option<UniqueBuf>,
     pub len: usize,
     pub off: Off,
@@ -245,6 +284,7 @@ impl<Off: Default> Default for OffsetChunk<Off> {
         OffsetChunk {
             version: 0,
             name: "".into(),
+            fnr_offset: 0,
             buf: None,
             len: 0,
             off: Default::default(),
@@ -256,6 +296,30 @@ impl<Off: Default + Send> Chunk for OffsetChunk<Off> {
     fn get_name(&self) -> &str {
         &*self.name
     }
+    fn get_version(&self) -> u32 {
+        self.version
+    }
+    fn get_fnr_offset(&self) -> u64 {
+        self.fnr_offset
+    }
+}
+
+impl<R, F> OffsetChunkProducer<R, F> {
+    // Called once we run out of data in the file. If the file had no data at all and we are
+    // reporting empty files, fill in `chunk` as an empty chunk for it and return true.
+    fn finish<Off: Default>(&mut self, chunk: &mut OffsetChunk<Off>) -> bool {
+        self.state = ChunkState::Done;
+        if !mem::take(&mut self.report_empty) {
+            return false;
+        }
+        chunk.version = self.cur_file_version;
+        chunk.name = self.name.clone();
+        chunk.fnr_offset = 0;
+        chunk.buf = Some(UniqueBuf::new(0));
+        chunk.len = 0;
+        chunk.off = Off::default();
+        true
+    }
 }
 
 impl<R: Read, F: FnMut(&[u8], &mut Offsets)> ChunkProducer for OffsetChunkProducer<R, F> {
@@ -265,19 +329,26 @@ impl<R: Read, F: FnMut(&[u8], &mut Offsets)> ChunkProducer for OffsetChunkProduc
         self.inner.force_eof();
         Ok(false)
     }
+    fn track_fnr(&mut self) {
+        self.records = Some(0);
+    }
+    fn report_empty_files(&mut self) {
+        self.report_empty = true;
+    }
     fn get_chunk(&mut self, chunk: &mut OffsetChunk) -> Result<bool> {
         loop {
             match self.state {
                 ChunkState::Init => {
-                    self.state = if self.inner.reset()? {
-                        ChunkState::Done
-                    } else {
-                        ChunkState::Main
-                    };
+                    if !self.inner.reset()? {
+                        self.state = ChunkState::Main;
+                    } else if self.finish(chunk) {
+                        return Ok(false);
+                    }
                 }
                 ChunkState::Main => {
                     chunk.version = self.cur_file_version;
                     chunk.name = self.name.clone();
+                    chunk.fnr_offset = self.records.unwrap_or(0);
                     let buf = self.inner.buf.clone();
                     let bs = buf.as_bytes();
                     (self.find_indexes)(bs, &mut chunk.off);
@@ -326,13 +397,39 @@ impl<R: Read, F: FnMut(&[u8], &mut Offsets)> ChunkProducer for OffsetChunkProduc
                     return match (is_partial, is_eof) {
                         (false, false) => {
                             // Yield buffer, stay in main.
-                            chunk.buf = Some(buf.try_unique().unwrap());
+                            self.report_empty = false;
                             chunk.off.rel.fields.truncate(new_len);
+                            if let Some(records) = &mut self.records {
+                                let (record_sep, comment) = (self.record_sep, self.comment);
+                                let fields = &chunk.off.rel.fields;
+                                let count = |b| {
+                                    fields.iter().filter(|off| bs[**off as usize] == b).count()
+                                        as u64
+                                };
+                                *records += count(record_sep);
+                                // Each comment line contributes its comment character and its
+                                // newline to the offsets, and the reader skips both.
+                                if let Some(c) = comment {
+                                    *records -= count(c);
+                                }
+                            }
+                            chunk.buf = Some(buf.try_unique().unwrap());
                             chunk.len = target.unwrap();
                             Ok(false)
                         }
+                        (true, true) if chunk.len == 0 => {
+                            // There is no data left in this file (e.g. because it was empty).
+                            // Readers treat an empty chunk as the end of all input, so we move
+                            // on to the next file without yielding one.
+                            self.inner.clear_buf();
+                            if self.finish(chunk) {
+                                return Ok(false);
+                            }
+                            continue;
+                        }
                         (false, true) | (true, true) => {
                             // Yield the entire buffer, this was the last piece of data.
+                            self.report_empty = false;
                             self.inner.clear_buf();
                             chunk.buf = Some(buf.try_unique().unwrap());
                             chunk.off.rel.fields.truncate(always_truncate);
@@ -362,19 +459,26 @@ impl<R: Read, F: FnMut(&[u8], &mut WhitespaceOffsets, u64) -> u64> ChunkProducer
         self.0.inner.force_eof();
         Ok(false)
     }
+    fn track_fnr(&mut self) {
+        self.0.records = Some(0);
+    }
+    fn report_empty_files(&mut self) {
+        self.0.report_empty = true;
+    }
     fn get_chunk(&mut self, chunk: &mut Self::Chunk) -> Result<bool> {
         loop {
             match self.0.state {
                 ChunkState::Init => {
-                    self.0.state = if self.0.inner.reset()? {
-                        ChunkState::Done
-                    } else {
-                        ChunkState::Main
-                    };
+                    if !self.0.inner.reset()? {
+                        self.0.state = ChunkState::Main;
+                    } else if self.0.finish(chunk) {
+                        return Ok(false);
+                    }
                 }
                 ChunkState::Main => {
                     chunk.version = self.0.cur_file_version;
                     chunk.name = self.0.name.clone();
+                    chunk.fnr_offset = self.0.records.unwrap_or(0);
                     let buf = self.0.inner.buf.clone();
                     let bs = buf.as_bytes();
                     self.1 = (self.0.find_indexes)(bs, &mut chunk.off, self.1);
@@ -405,13 +509,27 @@ impl<R: Read, F: FnMut(&[u8], &mut WhitespaceOffsets, u64) -> u64> ChunkProducer
                     return match (is_partial, is_eof) {
                         (false, false) => {
                             // Yield buffer, stay in main.
+                            self.0.report_empty = false;
+                            if let Some(records) = &mut self.0.records {
+                                // Records are separated by newlines in this format.
+                                *records += chunk.off.0.nl.fields.len() as u64;
+                            }
                             chunk.buf = Some(buf.try_unique().unwrap());
                             chunk.off.0.rel.fields.truncate(truncate_to);
                             chunk.len = len_if_not_last;
                             Ok(false)
                         }
+                        (true, true) if chunk.len == 0 => {
+                            // See the equivalent case for OffsetChunkProducer<R, F>
+                            self.0.inner.clear_buf();
+                            if self.0.finish(chunk) {
+                                return Ok(false);
+                            }
+                            continue;
+                        }
                         (false, true) | (true, true) => {
                             // Yield the entire buffer, this was the last piece of data.
+                            self.0.report_empty = false;
                             self.0.inner.clear_buf();
                             chunk.buf = Some(buf.try_unique().unwrap());
                             self.0.state = ChunkState::Done;
@@ -462,6 +580,18 @@ impl<P: ChunkProducer> ChunkProducer for ChainedChunkProducer<P> {
         }
     }
 
+    fn track_fnr(&mut self) {
+        for p in self.0.iter_mut() {
+            p.track_fnr();
+        }
+    }
+
+    fn report_empty_files(&mut self) {
+        for p in self.0.iter_mut() {
+            p.report_empty_files();
+        }
+    }
+
     fn get_chunk(&mut self, chunk: &mut P::Chunk) -> Result<bool> {
         while let Some(cur) = self.0.last_mut() {
             if !cur.get_chunk(chunk)? {
@@ -476,10 +606,24 @@ impl<P: ChunkProducer> ChunkProducer for ChainedChunkProducer<P> {
 
 /// ParallelChunkProducer allows for consumption of individual chunks from a ChunkProducer in
 /// parallel.
+///
+/// Chunks are numbered in the order they are read. If the producer is ordered, each consumer
+/// passes these numbers to a Sequencer so that standard output is written in input order.
+///
+/// Each chunk also carries a CancelSignal shared by all chunks from the same file. A consumer
+/// calling `next_file` cancels it, after which consumers skip the file's remaining chunks and
+/// the producing thread stops reading the file.
+///
+/// If reading fails, the producing thread stops and the error is returned to every consumer once
+/// the chunks read before it have been handed out.
 pub struct ParallelChunkProducer<P: ChunkProducer> {
     start: Receiver<()>,
-    incoming: Receiver<P::Chunk>,
+    incoming: Receiver<(u64, CancelSignal, P::Chunk)>,
     spent: Sender<P::Chunk>,
+    error: Arc<Mutex<Option<CompileError>>>,
+    sequencer: Option<Arc<Sequencer>>,
+    // The signal for the file of the chunk most recently returned by get_chunk.
+    cur_file: CancelSignal,
 }
 
 impl<P: ChunkProducer> Clone for ParallelChunkProducer<P> {
@@ -488,6 +632,9 @@ impl<P: ChunkProducer> Clone for ParallelChunkProducer<P> {
             start: self.start.clone(),
             incoming: self.incoming.clone(),
             spent: self.spent.clone(),
+            error: self.error.clone(),
+            sequencer: self.sequencer.clone(),
+            cur_file: Default::default(),
         }
     }
 }
@@ -496,29 +643,50 @@ impl<P: ChunkProducer + 'static> ParallelChunkProducer<P> {
     pub fn new(
         p_factory: impl FnOnce() -> P + Send + 'static,
         chan_size: usize,
+        ordered: bool,
     ) -> ParallelChunkProducer<P> {
         let (start_sender, start_receiver) = bounded(chan_size);
         let (in_sender, in_receiver) = bounded(chan_size);
         let (spent_sender, spent_receiver) = bounded(chan_size);
+        let error = Arc::new(Mutex::new(None));
+        let producer_error = error.clone();
         std::thread::spawn(move || {
             let mut n_workers = 0;
             let mut p = p_factory();
+            // Workers read chunks out of order, so they need each chunk's position in its file.
+            p.track_fnr();
             let mut n_failures = 0;
+            let mut seq = 0u64;
+            let mut file = CancelSignal::default();
+            let mut version = None;
             loop {
                 let mut chunk = spent_receiver
                     .try_recv()
                     .ok()
                     .unwrap_or_else(P::Chunk::default);
-                let chunk_res = p.get_chunk(&mut chunk);
-                if chunk_res.is_err() || matches!(chunk_res, Ok(true)) {
+                if file.cancelled() && p.next_file().is_err() {
                     return;
                 }
-                match in_sender.try_send(chunk) {
+                match p.get_chunk(&mut chunk) {
+                    Ok(false) => {}
+                    Ok(true) => return,
+                    Err(e) => {
+                        *producer_error.lock().unwrap() = Some(e);
+                        return;
+                    }
+                }
+                if version != Some(chunk.get_version()) {
+                    version = Some(chunk.get_version());
+                    file = CancelSignal::default();
+                }
+                let chunk_seq = seq;
+                seq += 1;
+                match in_sender.try_send((chunk_seq, file.clone(), chunk)) {
                     Ok(()) => {
                         n_failures = 0;
                         continue;
                     }
-                    Err(TrySendError::Full(c)) => {
+                    Err(TrySendError::Full((_, _, c))) => {
                         n_failures += 1;
                         chunk = c;
                     }
@@ -549,7 +717,7 @@ impl<P: ChunkProducer + 'static> ParallelChunkProducer<P> {
                     }
                     n_failures = 0;
                 }
-                if in_sender.send(chunk).is_err() {
+                if in_sender.send((chunk_seq, file.clone(), chunk)).is_err() {
                     return;
                 }
             }
@@ -558,6 +726,13 @@ impl<P: ChunkProducer + 'static> ParallelChunkProducer<P> {
             start: start_receiver,
             incoming: in_receiver,
             spent: spent_sender,
+            error,
+            sequencer: if ordered {
+                Some(Default::default())
+            } else {
+                None
+            },
+            cur_file: Default::default(),
         }
     }
 }
@@ -573,7 +748,11 @@ impl<P: ChunkProducer + 'static> ChunkProducer for ParallelChunkProducer<P> {
         res
     }
     fn next_file(&mut self) -> Result<bool> {
-        err!("nextfile is not supported in record-oriented parallel mode")
+        // Other workers may still be processing earlier chunks of this file, but no new ones
+        // will be handed out. We cannot tell if there are more files without blocking, so
+        // report that there may be.
+        self.cur_file.cancel(0);
+        Ok(true)
     }
     fn wait(&self) -> bool {
         self.start
@@ -581,12 +760,32 @@ impl<P: ChunkProducer + 'static> ChunkProducer for ParallelChunkProducer<P> {
             .is_ok()
     }
     fn get_chunk(&mut self, chunk: &mut P::Chunk) -> Result<bool> {
-        if let Ok(mut new_chunk) = self.incoming.recv() {
+        loop {
+            if self.sequencer.is_some() {
+                // Finish the last chunk before waiting on the next one, so that other workers can
+                // write their output in the meantime.
+                writers::finish_chunk()?;
+            }
+            let (seq, file, mut new_chunk) = match self.incoming.recv() {
+                Ok(x) => x,
+                Err(_) => match &*self.error.lock().unwrap() {
+                    Some(e) => return Err(e.clone()),
+                    None => return Ok(true),
+                },
+            };
+            if let Some(sequencer) = &self.sequencer {
+                // Skipped chunks are still handed to the sequencer (with no output), so that
+                // later chunks are not left waiting on them.
+                writers::start_chunk(sequencer, seq)?;
+            }
+            if file.cancelled() {
+                let _ = self.spent.try_send(new_chunk);
+                continue;
+            }
             mem::swap(chunk, &mut new_chunk);
             let _ = self.spent.try_send(new_chunk);
-            Ok(false)
-        } else {
-            Ok(true)
+            self.cur_file = file;
+            return Ok(false);
         }
     }
 }
@@ -601,6 +800,8 @@ enum ProducerState<T> {
 pub struct ShardedChunkProducer<P> {
     incoming: Receiver<Box<dyn FnOnce() -> P + Send>>,
     state: ProducerState<P>,
+    // Passed along to each producer; see ChunkProducer::report_empty_files.
+    report_empty: bool,
 }
 
 impl<P: ChunkProducer + 'static> ShardedChunkProducer<P> {
@@ -623,6 +824,7 @@ impl<P: ChunkProducer + 'static> ShardedChunkProducer<P> {
         ShardedChunkProducer {
             incoming: receiver,
             state: ProducerState::Init,
+            report_empty: false,
         }
     }
 
@@ -633,7 +835,11 @@ impl<P: ChunkProducer + 'static> ShardedChunkProducer<P> {
             self.state = ProducerState::Done;
             return false;
         };
-        self.state = ProducerState::Main(next());
+        let mut p = next();
+        if self.report_empty {
+            p.report_empty_files();
+        }
+        self.state = ProducerState::Main(p);
         true
     }
 }
@@ -644,10 +850,12 @@ impl<P: ChunkProducer + 'static> ChunkProducer for ShardedChunkProducer<P> {
         let mut res = Vec::with_capacity(requested_size);
         for _ in 0..requested_size {
             let incoming = self.incoming.clone();
+            let report_empty = self.report_empty;
             res.push(Box::new(move || {
                 Box::new(ShardedChunkProducer {
                     incoming,
                     state: ProducerState::Init,
+                    report_empty,
                 }) as Box<dyn ChunkProducer<Chunk = P::Chunk>>
             }) as _)
         }
@@ -660,6 +868,12 @@ impl<P: ChunkProducer + 'static> ChunkProducer for ShardedChunkProducer<P> {
             ProducerState::Main(p) => Ok(p.next_file()? || self.refresh_producer()),
         }
     }
+    fn report_empty_files(&mut self) {
+        self.report_empty = true;
+        if let ProducerState::Main(p) = &mut self.state {
+            p.report_empty_files();
+        }
+    }
     fn get_chunk(&mut self, chunk: &mut Self::Chunk) -> Result<bool> {
         loop {
             match &mut self.state {
@@ -718,6 +932,9 @@ impl<P: ChunkProducer + 'static> ChunkProducer for CancellableChunkProducer<P> {
         }
         self.prod.next_file()
     }
+    fn report_empty_files(&mut self) {
+        self.prod.report_empty_files()
+    }
     fn get_chunk(&mut self, chunk: &mut P::Chunk) -> Result<bool> {
         if self.signal.cancelled() {
             return Ok(true);
@@ -742,17 +959,21 @@ mod tests {
         move || IterChunkProducer {
             iter: (low..high),
             name,
+            // Tests use disjoint ranges for different files.
+            version: low as u32,
         }
     }
 
     struct IterChunkProducer<I> {
         iter: I,
         name: Arc<str>,
+        version: u32,
     }
 
     struct ItemChunk<T> {
         item: T,
         name: Arc<str>,
+        version: u32,
     }
 
     impl<T: Default> Default for ItemChunk<T> {
@@ -760,6 +981,7 @@ mod tests {
             ItemChunk {
                 item: Default::default(),
                 name: "".into(),
+                version: 0,
             }
         }
     }
@@ -768,6 +990,9 @@ mod tests {
         fn get_name(&self) -> &str {
             &*self.name
         }
+        fn get_version(&self) -> u32 {
+            self.version
+        }
     }
 
     impl<I: Iterator> ChunkProducer for IterChunkProducer<I>
@@ -784,6 +1009,7 @@ mod tests {
             if let Some(item) = self.iter.next() {
                 chunk.item = item;
                 chunk.name = self.name.clone();
+                chunk.version = self.version;
                 Ok(false)
             } else {
                 Ok(true)
@@ -879,8 +1105,11 @@ mod tests {
     #[test]
     fn parallel_all_elements() {
         use std::{sync::Mutex, thread};
-        let parallel_producer =
-            ParallelChunkProducer::new(new_iter(0, 100, "file1"), /*chan_size=*/ 10);
+        let parallel_producer = ParallelChunkProducer::new(
+            new_iter(0, 100, "file1"),
+            /*chan_size=*/ 10,
+            /*ordered=*/ false,
+        );
         let got = Arc::new(Mutex::new(Vec::new()));
         let threads = {
             let _guard = got.lock().unwrap();
@@ -911,6 +1140,36 @@ mod tests {
         assert_eq!(*g, (0..100).collect::<Vec<_>>());
     }
 
+    #[test]
+    fn parallel_next_file() {
+        let mut parallel_producer = ParallelChunkProducer::new(
+            || {
+                ChainedChunkProducer(vec![
+                    new_iter(2000, 3000, "file3")(),
+                    new_iter(1000, 2000, "file2")(),
+                    new_iter(0, 1000, "file1")(),
+                ])
+            },
+            /*chan_size=*/ 10,
+            /*ordered=*/ false,
+        );
+        let mut got = Vec::new();
+        let mut chunk = ItemChunk::default();
+        while !parallel_producer
+            .get_chunk(&mut chunk)
+            .expect("get_chunk should succeed")
+        {
+            got.push(chunk.item);
+            if chunk.item == 1010 {
+                parallel_producer
+                    .next_file()
+                    .expect("next_file should succeed");
+            }
+        }
+        let expected: Vec<_> = (0..1011).chain(2000..3000).collect();
+        assert_eq!(got, expected);
+    }
+
     #[test]
     fn sharded_all_elements() {
         use std::{sync::Mutex, thread};
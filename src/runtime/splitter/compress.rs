@@ -0,0 +1,175 @@
+//! Transparent decompression of input files.
+//!
+//! Inputs compressed with gzip, zstd or bzip2 are detected by their magic bytes, falling back to
+//! the file extension if the input is too short to tell. Detection happens on the first read,
+//! so readers that are opened lazily (as they are for `-pf`) decompress in the worker thread that
+//! consumes them.
+use std::io::{self, BufReader, Chain, Cursor, Read};
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+
+// Long enough to cover the bzip2 header and the magic number of its first block.
+const SNIFF_LEN: usize = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    fn from_magic(bs: &[u8]) -> Option<Compression> {
+        match bs {
+            // ID1, ID2, and CM=deflate
+            [0x1f, 0x8b, 0x08, ..] => Some(Compression::Gzip),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Compression::Zstd),
+            // "BZh", the block size, and the block header magic (the first 6 digits of pi).
+            [b'B', b'Z', b'h', b'1'..=b'9', 0x31, 0x41, 0x59, 0x26, 0x53, 0x59] => {
+                Some(Compression::Bzip2)
+            }
+            // "BZh", the block size, and the end-of-stream magic (an empty file).
+            [b'B', b'Z', b'h', b'1'..=b'9', 0x17, 0x72, 0x45, 0x38, 0x50, 0x90] => {
+                Some(Compression::Bzip2)
+            }
+            _ => None,
+        }
+    }
+
+    fn from_extension(name: &str) -> Option<Compression> {
+        let ext = name.rsplit_once('.')?.1;
+        match ext {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "bz2" => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+}
+
+type Prefixed<R> = Chain<Cursor<Vec<u8>>, R>;
+
+enum State<R: Read> {
+    Sniff(R, Option<Compression>),
+    Plain(Prefixed<R>),
+    Gzip(MultiGzDecoder<Prefixed<R>>),
+    Zstd(zstd::Decoder<'static, BufReader<Prefixed<R>>>),
+    Bzip2(MultiBzDecoder<Prefixed<R>>),
+    // Only observable if initializing the decoder failed.
+    Invalid,
+}
+
+/// A reader that decompresses its input if it appears to be compressed, and passes it through
+/// unchanged otherwise.
+pub struct Decompress<R: Read>(State<R>);
+
+impl<R: Read> Decompress<R> {
+    /// Wrap `r`, which was opened from the path `name`.
+    pub fn new(r: R, name: &str) -> Decompress<R> {
+        Decompress(State::Sniff(r, Compression::from_extension(name)))
+    }
+
+    fn init(&mut self) -> io::Result<()> {
+        let (mut r, by_ext) = match std::mem::replace(&mut self.0, State::Invalid) {
+            State::Sniff(r, by_ext) => (r, by_ext),
+            other => {
+                self.0 = other;
+                return Ok(());
+            }
+        };
+        let mut prefix = vec![0u8; SNIFF_LEN];
+        let mut filled = 0;
+        while filled < SNIFF_LEN {
+            match r.read(&mut prefix[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        prefix.truncate(filled);
+        let compression = if filled == SNIFF_LEN {
+            Compression::from_magic(&prefix[..])
+        } else {
+            // Too short to be a valid compressed stream, unless the extension says otherwise.
+            by_ext.filter(|_| filled > 0)
+        };
+        let inner = Cursor::new(prefix).chain(r);
+        self.0 = match compression {
+            None => State::Plain(inner),
+            Some(Compression::Gzip) => State::Gzip(MultiGzDecoder::new(inner)),
+            Some(Compression::Zstd) => State::Zstd(zstd::Decoder::new(inner)?),
+            Some(Compression::Bzip2) => State::Bzip2(MultiBzDecoder::new(inner)),
+        };
+        Ok(())
+    }
+}
+
+// The decoders report a stream that ends early as UnexpectedEof, which readers further up treat
+// as the end of the input. Report it as corrupt data instead, so that it is not silently ignored.
+fn truncated(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("truncated compressed input: {}", e),
+        )
+    } else {
+        e
+    }
+}
+
+impl<R: Read> Read for Decompress<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            State::Sniff(..) => {
+                self.init()?;
+                self.read(buf)
+            }
+            State::Plain(r) => r.read(buf),
+            State::Gzip(r) => r.read(buf).map_err(truncated),
+            State::Zstd(r) => r.read(buf).map_err(truncated),
+            State::Bzip2(r) => r.read(buf).map_err(truncated),
+            State::Invalid => Err(io::Error::other("failed to initialize decompression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const TEXT: &[u8] = b"hello\nthere\nthis is some text to compress\n";
+
+    fn read_all(bs: &[u8], name: &str) -> Vec<u8> {
+        let mut res = Vec::new();
+        Decompress::new(bs, name).read_to_end(&mut res).unwrap();
+        res
+    }
+
+    #[test]
+    fn detect_and_decompress() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(TEXT).unwrap();
+        let gz = gz.finish().unwrap();
+        let zst = zstd::encode_all(TEXT, 0).unwrap();
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz.write_all(TEXT).unwrap();
+        let bz = bz.finish().unwrap();
+
+        // Magic bytes take precedence over the file name.
+        for (compressed, name) in [(&gz, "x"), (&zst, "x.gz"), (&bz, "x.txt")] {
+            assert_eq!(read_all(&compressed[..], name), TEXT);
+        }
+        assert_eq!(read_all(TEXT, "x"), TEXT);
+        assert_eq!(read_all(TEXT, "x.gz"), TEXT);
+        assert_eq!(read_all(b"short", "x"), b"short");
+        assert_eq!(read_all(b"", "x.gz"), b"");
+        // Short inputs with a compressed extension are passed to the decoder.
+        let mut res = Vec::new();
+        assert!(Decompress::new(&b"short"[..], "x.gz")
+            .read_to_end(&mut res)
+            .is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Fixed-width field splitting, used when FIELDWIDTHS is set.
+//!
+//! Like gawk, FIELDWIDTHS is a whitespace-separated list of field widths. Each width may be
+//! preceded by a number of characters to skip before the field starts (as in `2:5`), and the last
+//! entry may be `*` to assign the rest of the record to a final field. Widths count characters
+//! for UTF-8 input and bytes otherwise.
+use crate::common::Result;
+use crate::pushdown::FieldSet;
+use crate::runtime::Str;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldWidths {
+    // (characters to skip, width) for each field.
+    fields: Vec<(usize, usize)>,
+    // Whether a final field holds the remainder of the record.
+    rest: bool,
+}
+
+impl FieldWidths {
+    pub fn parse(s: &[u8]) -> Result<FieldWidths> {
+        let parse_num = |bs: &[u8]| match std::str::from_utf8(bs).ok().map(str::parse::<usize>) {
+            Some(Ok(n)) => Ok(n),
+            _ => err!(
+                "invalid FIELDWIDTHS entry {:?}, expected a non-negative integer",
+                String::from_utf8_lossy(bs)
+            ),
+        };
+        let mut fields = Vec::new();
+        let mut rest = false;
+        for entry in s
+            .split(|b| b.is_ascii_whitespace())
+            .filter(|e| !e.is_empty())
+        {
+            if rest {
+                return err!("`*` must be the last entry in FIELDWIDTHS");
+            }
+            if entry == b"*" {
+                rest = true;
+                continue;
+            }
+            let (skip, width) = match entry.iter().position(|b| *b == b':') {
+                Some(ix) => (parse_num(&entry[..ix])?, parse_num(&entry[ix + 1..])?),
+                None => (0, parse_num(entry)?),
+            };
+            fields.push((skip, width));
+        }
+        if fields.is_empty() && !rest {
+            return err!("FIELDWIDTHS must contain at least one field width");
+        }
+        Ok(FieldWidths { fields, rest })
+    }
+
+    /// Split `line` into fields, passing each of them to `push`. Fields that are not in
+    /// `used_fields` are passed as empty strings. As in gawk, fields that start past the end of
+    /// the line are omitted, so NF counts only the fields that are at least partly filled.
+    pub fn split<'a>(&self, line: &Str<'a>, used_fields: &FieldSet, mut push: impl FnMut(Str<'a>)) {
+        line.with_bytes(|bs| {
+            let utf8 = !bs.is_ascii() && std::str::from_utf8(bs).is_ok();
+            let advance = |mut pos: usize, n: usize| {
+                if !utf8 {
+                    return std::cmp::min(pos + n, bs.len());
+                }
+                for _ in 0..n {
+                    if pos == bs.len() {
+                        break;
+                    }
+                    pos += 1;
+                    while pos < bs.len() && bs[pos] & 0xC0 == 0x80 {
+                        pos += 1;
+                    }
+                }
+                pos
+            };
+            let mut field = |ix: usize, start: usize, end: usize| {
+                push(if used_fields.get(ix) {
+                    line.slice(start, end)
+                } else {
+                    Str::default()
+                })
+            };
+            let mut pos = 0;
+            for (i, (skip, width)) in self.fields.iter().enumerate() {
+                pos = advance(pos, *skip);
+                if pos == bs.len() {
+                    return;
+                }
+                let end = advance(pos, *width);
+                field(i + 1, pos, end);
+                pos = end;
+            }
+            if self.rest && pos < bs.len() {
+                field(self.fields.len() + 1, pos, bs.len());
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split_all(widths: &str, line: &'static str) -> Vec<Str<'static>> {
+        let widths = FieldWidths::parse(widths.as_bytes()).unwrap();
+        let mut res = Vec::new();
+        widths.split(&Str::from(line), &FieldSet::all(), |s| res.push(s));
+        res
+    }
+
+    #[test]
+    fn parse_widths() {
+        assert_eq!(
+            FieldWidths::parse(b" 5 2:10\t3 *").unwrap(),
+            FieldWidths {
+                fields: vec![(0, 5), (2, 10), (0, 3)],
+                rest: true,
+            }
+        );
+        assert!(FieldWidths::parse(b"").is_err());
+        assert!(FieldWidths::parse(b"5 x").is_err());
+        assert!(FieldWidths::parse(b"-1").is_err());
+        assert!(FieldWidths::parse(b"* 5").is_err());
+    }
+
+    #[test]
+    fn split_fields() {
+        let strs = |v: &[&'static str]| v.iter().map(|s| Str::from(*s)).collect::<Vec<_>>();
+        assert_eq!(
+            split_all("3 2 4", "abcdefghijkl"),
+            strs(&["abc", "de", "fghi"])
+        );
+        assert_eq!(split_all("3 2 4", "abcd"), strs(&["abc", "d"]));
+        assert_eq!(split_all("3 2 4", ""), strs(&[]));
+        assert_eq!(
+            split_all("1:2 1:2 *", "_ab_cd_ef"),
+            strs(&["ab", "cd", "_ef"])
+        );
+        assert_eq!(split_all("2 3", "häß€xyz"), strs(&["hä", "ß€x"]));
+    }
+
+    #[test]
+    fn split_projected() {
+        let widths = FieldWidths::parse(b"2 2 2").unwrap();
+        let mut used = FieldSet::singleton(2);
+        used.set(3);
+        let mut res = Vec::new();
+        widths.split(&Str::from("aabbcc"), &used, |s| res.push(s));
+        assert_eq!(res, vec![Str::default(), Str::from("bb"), Str::from("cc")]);
+    }
+}
@@ -0,0 +1,672 @@
+//! Field splitting for JSON Lines input.
+//!
+//! Each line of input is parsed as a single JSON value. Top-level objects have their keys mapped
+//! onto column indexes: the first time a key is seen it is assigned the next free column, so a
+//! file of homogeneous records behaves much like a CSV file with a header. Top-level arrays are
+//! split positionally, and any other value becomes a single-column record.
+//!
+//! Values are converted to strings as follows:
+//! * Strings are unescaped.
+//! * Numbers are passed through as they appear in the input.
+//! * `true` and `false` become "1" and "0", so that they behave as expected in a boolean context.
+//! * `null` becomes the empty string.
+//! * Nested objects and arrays are passed through as (unparsed) JSON text.
+//!
+//! Values for columns that the program never references are skipped without being decoded.
+//!
+//! This module also contains the escaping routines and the object keys used for JSON output.
+use hashbrown::{HashMap, HashSet};
+
+use std::sync::{Arc, Mutex};
+
+use super::Line;
+use crate::common::Result;
+use crate::pushdown::FieldSet;
+use crate::runtime::str_impl::{Buf, Str};
+use crate::runtime::{Float, Int};
+
+/// The mapping from top-level keys to column indexes.
+///
+/// Column assignments depend on the order in which keys are encountered. Clones share the same
+/// assignments, so that in record-parallel mode a key maps to the same column in every worker.
+#[derive(Clone, Default)]
+pub struct Columns {
+    shared: Arc<Mutex<Names>>,
+    // A cache of the assignments in `shared`, so that we only take the lock for new keys.
+    local: HashMap<Box<[u8]>, usize>,
+    // Keys added to `local` since the last call to `take_new`.
+    new: Vec<(Box<[u8]>, usize)>,
+}
+
+#[derive(Default)]
+struct Names {
+    indexes: HashMap<Box<[u8]>, usize>,
+    names: Vec<Box<[u8]>>,
+}
+
+impl Columns {
+    /// The column assigned to `key`, assigning it the next free column if it has not been seen.
+    pub fn get_or_insert(&mut self, key: &[u8]) -> usize {
+        if let Some(ix) = self.local.get(key) {
+            return *ix;
+        }
+        let ix = {
+            let mut shared = self.shared.lock().unwrap();
+            match shared.indexes.get(key) {
+                Some(ix) => *ix,
+                None => {
+                    shared.names.push(key.into());
+                    let ix = shared.names.len();
+                    shared.indexes.insert(key.into(), ix);
+                    ix
+                }
+            }
+        };
+        self.local.insert(key.into(), ix);
+        self.new.push((key.into(), ix));
+        ix
+    }
+
+    /// The keys (and their columns) that this handle has seen for the first time since the last
+    /// call to `take_new`.
+    pub fn take_new(&mut self) -> Vec<(Box<[u8]>, usize)> {
+        std::mem::take(&mut self.new)
+    }
+
+    /// The names of all columns seen so far, in column order.
+    pub fn names(&self) -> Vec<Box<[u8]>> {
+        self.shared.lock().unwrap().names.clone()
+    }
+}
+
+/// Split the JSON value in `buf[start..end]` into `fields`, learning any new keys in `cols`.
+pub fn split_record(
+    buf: &Buf,
+    start: usize,
+    end: usize,
+    cols: &mut Columns,
+    used: &FieldSet,
+    fields: &mut Vec<Str<'static>>,
+) -> Result<()> {
+    let mut p = Parser {
+        buf,
+        bs: &buf.as_bytes()[..end],
+        start,
+        pos: start,
+        scratch: Vec::new(),
+    };
+    p.skip_ws();
+    match p.peek() {
+        None => return Ok(()),
+        Some(b'{') => {
+            p.pos += 1;
+            p.skip_ws();
+            if p.peek() == Some(b'}') {
+                p.pos += 1;
+            } else {
+                loop {
+                    p.skip_ws();
+                    let col = p.key(cols)?;
+                    p.skip_ws();
+                    p.expect(b':')?;
+                    p.skip_ws();
+                    p.field(col, used, fields)?;
+                    p.skip_ws();
+                    match p.next_byte() {
+                        Some(b',') => continue,
+                        Some(b'}') => break,
+                        _ => return p.error("expected ',' or '}' in object"),
+                    }
+                }
+            }
+        }
+        Some(b'[') => {
+            p.pos += 1;
+            p.skip_ws();
+            if p.peek() == Some(b']') {
+                p.pos += 1;
+            } else {
+                let mut col = 1;
+                loop {
+                    p.skip_ws();
+                    p.field(col, used, fields)?;
+                    col += 1;
+                    p.skip_ws();
+                    match p.next_byte() {
+                        Some(b',') => continue,
+                        Some(b']') => break,
+                        _ => return p.error("expected ',' or ']' in array"),
+                    }
+                }
+            }
+        }
+        Some(_) => p.field(1, used, fields)?,
+    }
+    p.skip_ws();
+    if p.pos != p.bs.len() {
+        return p.error("trailing characters after JSON value");
+    }
+    Ok(())
+}
+
+struct Parser<'a> {
+    buf: &'a Buf,
+    bs: &'a [u8],
+    start: usize,
+    pos: usize,
+    scratch: Vec<u8>,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, msg: &str) -> Result<T> {
+        err!(
+            "invalid JSON input at byte {} of record: {}",
+            self.pos - self.start,
+            msg
+        )
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bs.get(self.pos).cloned()
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let res = self.peek();
+        self.pos += 1;
+        res
+    }
+
+    fn expect(&mut self, b: u8) -> Result<()> {
+        if self.next_byte() == Some(b) {
+            Ok(())
+        } else {
+            self.error(&format!("expected {:?}", b as char))
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(b' ' | b'\t' | b'\r' | b'\n') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    // Find the end of a string starting at `self.pos` (which points just past the opening quote).
+    // Returns the index of the closing quote and whether the string contains any escapes.
+    fn string_end(&self) -> Result<(usize, bool)> {
+        let mut has_escape = false;
+        let mut i = self.pos;
+        while i < self.bs.len() {
+            match self.bs[i] {
+                b'"' => return Ok((i, has_escape)),
+                b'\\' => {
+                    has_escape = true;
+                    i += 2;
+                }
+                _ => i += 1,
+            }
+        }
+        self.error("unterminated string")
+    }
+
+    fn key(&mut self, cols: &mut Columns) -> Result<usize> {
+        self.expect(b'"')?;
+        let (end, has_escape) = self.string_end()?;
+        let col = if has_escape {
+            let mut scratch = std::mem::take(&mut self.scratch);
+            scratch.clear();
+            self.unescape(self.pos, end, &mut scratch)?;
+            let col = cols.get_or_insert(&scratch[..]);
+            self.scratch = scratch;
+            col
+        } else {
+            cols.get_or_insert(&self.bs[self.pos..end])
+        };
+        self.pos = end + 1;
+        Ok(col)
+    }
+
+    fn field(&mut self, col: usize, used: &FieldSet, fields: &mut Vec<Str<'static>>) -> Result<()> {
+        if fields.len() < col {
+            fields.resize_with(col, Str::default);
+        }
+        if used.get(col) {
+            fields[col - 1] = self.value()?;
+        } else {
+            self.skip_value()?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Str<'static>> {
+        let start = self.pos;
+        match self.peek() {
+            Some(b'"') => {
+                self.pos += 1;
+                let (end, has_escape) = self.string_end()?;
+                let res = if has_escape {
+                    let mut scratch = Vec::with_capacity(end - self.pos);
+                    self.unescape(self.pos, end, &mut scratch)?;
+                    Buf::read_from_bytes(&scratch[..]).into_str()
+                } else {
+                    self.buf.slice_to_str(self.pos, end)
+                };
+                self.pos = end + 1;
+                Ok(res)
+            }
+            Some(b't') => self.literal("true").map(|_| Str::from("1")),
+            Some(b'f') => self.literal("false").map(|_| Str::from("0")),
+            Some(b'n') => self.literal("null").map(|_| Str::default()),
+            _ => {
+                self.skip_value()?;
+                Ok(self.buf.slice_to_str(start, self.pos))
+            }
+        }
+    }
+
+    fn literal(&mut self, lit: &str) -> Result<()> {
+        if self.bs[self.pos..].starts_with(lit.as_bytes()) {
+            self.pos += lit.len();
+            Ok(())
+        } else {
+            self.error("invalid literal")
+        }
+    }
+
+    fn skip_value(&mut self) -> Result<()> {
+        match self.peek() {
+            Some(b'"') => {
+                self.pos += 1;
+                let (end, _) = self.string_end()?;
+                self.pos = end + 1;
+            }
+            Some(b'{') | Some(b'[') => {
+                // We do not validate nested values, only that their brackets are balanced.
+                let mut depth = 0usize;
+                loop {
+                    match self.next_byte() {
+                        Some(b'{') | Some(b'[') => depth += 1,
+                        Some(b'}') | Some(b']') => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        Some(b'"') => {
+                            let (end, _) = self.string_end()?;
+                            self.pos = end + 1;
+                        }
+                        Some(_) => {}
+                        None => return self.error("unterminated object or array"),
+                    }
+                }
+            }
+            Some(b't') => self.literal("true")?,
+            Some(b'f') => self.literal("false")?,
+            Some(b'n') => self.literal("null")?,
+            Some(b'-') | Some(b'0'..=b'9') => {
+                while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+                    self.pos += 1;
+                }
+            }
+            _ => return self.error("expected a value"),
+        }
+        Ok(())
+    }
+
+    fn hex4(&self, i: usize) -> Result<u32> {
+        let digits = match self.bs.get(i..i + 4) {
+            Some(d) => d,
+            None => return self.error("truncated unicode escape"),
+        };
+        let mut res = 0;
+        for d in digits {
+            let v = match (*d as char).to_digit(16) {
+                Some(v) => v,
+                None => return self.error("invalid unicode escape"),
+            };
+            res = res * 16 + v;
+        }
+        Ok(res)
+    }
+
+    // Decode the string contents in `bs[start..end]` into `out`. Unpaired surrogates are replaced
+    // with U+FFFD.
+    fn unescape(&self, start: usize, end: usize, out: &mut Vec<u8>) -> Result<()> {
+        let mut i = start;
+        while i < end {
+            let b = self.bs[i];
+            if b != b'\\' {
+                out.push(b);
+                i += 1;
+                continue;
+            }
+            let esc = self.bs[i + 1];
+            i += 2;
+            let c = match esc {
+                b'"' => '"',
+                b'\\' => '\\',
+                b'/' => '/',
+                b'b' => '\u{8}',
+                b'f' => '\u{c}',
+                b'n' => '\n',
+                b'r' => '\r',
+                b't' => '\t',
+                b'u' => {
+                    let hi = self.hex4(i)?;
+                    i += 4;
+                    let code = if (0xD800..0xDC00).contains(&hi)
+                        && self.bs.get(i..i + 2) == Some(b"\\u")
+                    {
+                        let lo = self.hex4(i + 2)?;
+                        if (0xDC00..0xE000).contains(&lo) {
+                            i += 6;
+                            0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00)
+                        } else {
+                            hi
+                        }
+                    } else {
+                        hi
+                    };
+                    std::char::from_u32(code).unwrap_or('\u{FFFD}')
+                }
+                _ => return self.error("invalid escape sequence"),
+            };
+            let mut enc = [0u8; 4];
+            out.extend_from_slice(c.encode_utf8(&mut enc).as_bytes());
+        }
+        Ok(())
+    }
+}
+
+/// Escape `s` as a JSON string literal, including the surrounding quotes.
+///
+/// JSON strings must be valid Unicode, so each invalid UTF-8 sequence in `s` is replaced with
+/// U+FFFD, in the same manner as `String::from_utf8_lossy`.
+pub fn escape_json<'a>(s: &Str<'a>) -> Str<'a> {
+    let bs = unsafe { &*s.get_bytes() };
+    let needs_escape = |b: u8| b < 0x20 || b == b'"' || b == b'\\';
+    if std::str::from_utf8(bs).is_ok() && !bs.iter().cloned().any(needs_escape) {
+        let quote = Str::from("\"");
+        return Str::concat(Str::concat(quote.clone(), s.clone()), quote);
+    }
+    let mut out = Vec::with_capacity(bs.len() + 8);
+    out.push(b'"');
+    let mut rest = bs;
+    while !rest.is_empty() {
+        let (valid, skip) = match std::str::from_utf8(rest) {
+            Ok(_) => (rest.len(), 0),
+            Err(e) => (
+                e.valid_up_to(),
+                e.error_len().unwrap_or(rest.len() - e.valid_up_to()),
+            ),
+        };
+        for &b in &rest[..valid] {
+            match b {
+                b'"' => out.extend_from_slice(b"\\\""),
+                b'\\' => out.extend_from_slice(b"\\\\"),
+                b'\n' => out.extend_from_slice(b"\\n"),
+                b'\r' => out.extend_from_slice(b"\\r"),
+                b'\t' => out.extend_from_slice(b"\\t"),
+                0x08 => out.extend_from_slice(b"\\b"),
+                0x0c => out.extend_from_slice(b"\\f"),
+                b if b < 0x20 => {
+                    const HEX: &[u8] = b"0123456789abcdef";
+                    out.extend_from_slice(b"\\u00");
+                    out.push(HEX[(b >> 4) as usize]);
+                    out.push(HEX[(b & 0xf) as usize]);
+                }
+                b => out.push(b),
+            }
+        }
+        if skip > 0 {
+            out.extend_from_slice("\u{FFFD}".as_bytes());
+        }
+        rest = &rest[valid + skip..];
+    }
+    out.push(b'"');
+    Buf::read_from_bytes(&out[..]).into_str()
+}
+
+/// Render `f` as a JSON number.
+///
+/// JSON has no representation for infinities or NaN, so these are written as `null`.
+pub fn float_to_json<'a>(f: Float) -> Str<'a> {
+    if f.is_finite() {
+        f.into()
+    } else {
+        Str::from("null")
+    }
+}
+
+/// The keys of the objects written by JSON output when parsing a header (-H).
+///
+/// A value printed from column n is keyed by the name of column n in the header, or by n itself
+/// past the end of the header; any other value is keyed by its position in the print statement.
+/// Repeated header names are reported once, while the header is parsed. Even then, a key can
+/// repeat another key in the same object (e.g. a column named "2" printed as the first of two
+/// values), so we also keep track of the keys in the object currently being written.
+#[derive(Clone, Default)]
+pub struct Keys {
+    // The names of the header's columns, escaped as JSON strings. Clones share the names, so that
+    // parallel workers use the header read before they start.
+    names: Arc<Vec<Box<[u8]>>>,
+    // The (escaped) keys in the current object, or in the header while it is being parsed.
+    seen: HashSet<Box<[u8]>>,
+}
+
+impl Keys {
+    /// Set the name of column `col` of the header, starting a new header when `col` is 1.
+    /// Columns are set in order.
+    pub fn set_header_name(&mut self, col: Int, name: &Str) -> Result<()> {
+        if col == 1 {
+            self.names = Default::default();
+            self.seen.clear();
+        }
+        let key: Box<[u8]> = escape_json(name).with_bytes(|bs| bs.into());
+        if !self.seen.insert(key.clone()) {
+            return err!(
+                "the header names more than one column {}, which would repeat a JSON key",
+                name
+            );
+        }
+        Arc::make_mut(&mut self.names).push(key);
+        Ok(())
+    }
+
+    /// The JSON member `key:value` for the `pos`th value of an object (starting a new object when
+    /// `pos` is 1). `value` has already been escaped. The key is for column `col`, or for `pos`
+    /// if `col` is negative.
+    pub fn member<'a>(&mut self, col: Int, pos: Int, value: &Str<'a>) -> Result<Str<'a>> {
+        if pos == 1 {
+            self.seen.clear();
+        }
+        let name = if col > 0 {
+            self.names.get(col as usize - 1)
+        } else {
+            None
+        };
+        let key: Box<[u8]> = match name {
+            Some(name) => name.clone(),
+            None => format!("\"{}\"", if col < 0 { pos } else { col })
+                .into_bytes()
+                .into(),
+        };
+        if self.seen.contains(&key) {
+            return err!(
+                "print would repeat the JSON key {}",
+                String::from_utf8_lossy(&key)
+            );
+        }
+        let mut out = Vec::with_capacity(key.len() + 1 + value.len());
+        out.extend_from_slice(&key);
+        out.push(b':');
+        value.with_bytes(|bs| out.extend_from_slice(bs));
+        self.seen.insert(key);
+        Ok(Buf::read_from_bytes(&out[..]).into_str())
+    }
+}
+
+/// Render the fields of `line` as a JSON array or, given the header's keys, as a JSON object.
+pub fn join_json<'a>(
+    line: &mut impl Line<'a>,
+    nf: usize,
+    keys: Option<&mut Keys>,
+) -> Result<Str<'a>> {
+    let sep = Str::from(",");
+    let (open, fields, close) = match keys {
+        None => (
+            "[",
+            line.join_cols(1, Int::MAX, &sep, nf, |s| escape_json(&s))?,
+            "]",
+        ),
+        Some(keys) => {
+            let mut col = 0;
+            let mut res = Ok(());
+            let fields = line.join_cols(1, Int::MAX, &sep, nf, |s| {
+                col += 1;
+                keys.member(col, col, &escape_json(&s)).unwrap_or_else(|e| {
+                    res = Err(e);
+                    Str::default()
+                })
+            })?;
+            res?;
+            ("{", fields, "}")
+        }
+    };
+    Ok(Str::concat(
+        Str::concat(Str::from(open), fields),
+        Str::from(close),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(cols: &mut Columns, used: &FieldSet, s: &str) -> Result<Vec<String>> {
+        let buf = Buf::read_from_bytes(s.as_bytes());
+        let mut fields = Vec::new();
+        split_record(&buf, 0, s.len(), cols, used, &mut fields)?;
+        Ok(fields.into_iter().map(|s| format!("{}", s)).collect())
+    }
+
+    #[test]
+    fn objects() {
+        let mut cols = Columns::default();
+        let all = FieldSet::all();
+        assert_eq!(
+            split(
+                &mut cols,
+                &all,
+                r#"{"a": 1.5, "b": "x\tyé😀", "c": [1, {"d": "]"}], "e": true}"#
+            )
+            .unwrap(),
+            vec!["1.5", "x\tyé😀", r#"[1, {"d": "]"}]"#, "1"]
+        );
+        // Keys keep their columns across records, even if they appear in a different order.
+        assert_eq!(
+            split(&mut cols, &all, r#"{"e": null, "b": "z", "f": false}"#).unwrap(),
+            vec!["", "z", "", "", "0"]
+        );
+        assert_eq!(
+            cols.names(),
+            vec![&b"a"[..], b"b", b"c", b"e", b"f"]
+                .into_iter()
+                .map(Box::from)
+                .collect::<Vec<_>>()
+        );
+        // Clones share column assignments, wherever a key is first seen.
+        let mut other = cols.clone();
+        assert_eq!(
+            split(&mut other, &all, r#"{"g": 1, "a": 2}"#).unwrap(),
+            vec!["2", "", "", "", "", "1"]
+        );
+        assert_eq!(
+            split(&mut cols, &all, r#"{"g": 3}"#).unwrap(),
+            vec!["", "", "", "", "", "3"]
+        );
+        assert_eq!(split(&mut cols, &all, "  ").unwrap(), Vec::<String>::new());
+        assert!(split(&mut cols, &all, r#"{"a": 1"#).is_err());
+        assert!(split(&mut cols, &all, r#"{"a": 1} x"#).is_err());
+    }
+
+    #[test]
+    fn new_keys() {
+        let mut cols = Columns::default();
+        let all = FieldSet::all();
+        split(&mut cols, &all, r#"{"z": 1, "a": 2}"#).unwrap();
+        assert_eq!(
+            cols.take_new(),
+            vec![(Box::from(&b"z"[..]), 1), (Box::from(&b"a"[..]), 2)]
+        );
+        split(&mut cols, &all, r#"{"a": 3, "q": 4}"#).unwrap();
+        assert_eq!(cols.take_new(), vec![(Box::from(&b"q"[..]), 3)]);
+        split(&mut cols, &all, r#"{"q": 5}"#).unwrap();
+        assert!(cols.take_new().is_empty());
+    }
+
+    #[test]
+    fn arrays_and_scalars() {
+        let mut cols = Columns::default();
+        let all = FieldSet::all();
+        assert_eq!(
+            split(&mut cols, &all, r#"[1, "two", {"three": 3}]"#).unwrap(),
+            vec!["1", "two", r#"{"three": 3}"#]
+        );
+        assert_eq!(split(&mut cols, &all, r#""hi""#).unwrap(), vec!["hi"]);
+        assert!(cols.names().is_empty());
+    }
+
+    #[test]
+    fn skip_unused() {
+        let mut cols = Columns::default();
+        let mut used = FieldSet::singleton(0);
+        used.set(2);
+        assert_eq!(
+            split(&mut cols, &used, r#"{"a": "A", "b": "B", "c": {"x": 1}}"#).unwrap(),
+            vec!["", "B", ""]
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        let esc = |bs: &[u8]| {
+            let s: Str = Buf::read_from_bytes(bs).into_str();
+            format!("{}", escape_json(&s))
+        };
+        assert_eq!(esc("plain é".as_bytes()), r#""plain é""#);
+        assert_eq!(esc(b"a\"b\\c\nd\x01"), r#""a\"b\\c\nd\u0001""#);
+        assert_eq!(esc(b"bad\xffbyte\xe2\x82"), "\"bad\u{FFFD}byte\u{FFFD}\"");
+        assert_eq!(format!("{}", float_to_json(1.5)), "1.5");
+        assert_eq!(format!("{}", float_to_json(f64::NAN)), "null");
+    }
+
+    #[test]
+    fn object_keys() {
+        let member = |keys: &mut Keys, col, pos, v: &str| {
+            keys.member(col, pos, &escape_json(&Str::from(v)))
+                .map(|s| format!("{}", s))
+        };
+        let mut keys = Keys::default();
+        for (col, name) in ["a", "2", "c\"d"].iter().enumerate() {
+            keys.set_header_name(col as Int + 1, &Str::from(*name))
+                .unwrap();
+        }
+        assert_eq!(member(&mut keys, 3, 1, "x").unwrap(), r#""c\"d":"x""#);
+        assert_eq!(member(&mut keys, -1, 2, "y").unwrap(), r#""2":"y""#);
+        assert_eq!(member(&mut keys, 4, 3, "z").unwrap(), r#""4":"z""#);
+        // The second column is named "2", like the second value.
+        assert_eq!(member(&mut keys, 2, 1, "x").unwrap(), r#""2":"x""#);
+        assert!(member(&mut keys, -1, 2, "y").is_err());
+        // Each object starts with no keys.
+        assert!(member(&mut keys, -1, 1, "y").is_ok());
+
+        // Clones share the header; a new header replaces it.
+        let mut other = keys.clone();
+        assert_eq!(member(&mut other, 1, 1, "x").unwrap(), r#""a":"x""#);
+        keys.set_header_name(1, &Str::from("b")).unwrap();
+        assert_eq!(member(&mut keys, 1, 1, "x").unwrap(), r#""b":"x""#);
+        assert_eq!(member(&mut keys, 2, 2, "x").unwrap(), r#""2":"x""#);
+        assert!(keys.set_header_name(2, &Str::from("b")).is_err());
+    }
+}
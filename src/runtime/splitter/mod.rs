@@ -7,6 +7,9 @@
 // TODO: add padding to the linereader trait
 pub mod batch;
 pub mod chunk;
+pub mod compress;
+pub mod fixed;
+pub mod json;
 pub mod regex;
 
 use super::str_impl::{Buf, Str, UniqueBuf};
@@ -14,8 +17,11 @@ use super::utf8::{is_utf8, validate_utf8_clipped};
 use super::{Int, RegexCache};
 use crate::common::Result;
 use crate::pushdown::FieldSet;
+use fixed::FieldWidths;
 
 use std::io::{ErrorKind, Read};
+use std::mem;
+use std::sync::Arc;
 
 // We have several implementations of "read and split a line"; they are governed by the LineReader
 // and Line traits.
@@ -62,8 +68,38 @@ pub trait LineReader: Sized {
         Ok(changed)
     }
     fn read_state(&self) -> i64;
+    // The text that matched the record separator at the end of the last record, used to set RT.
+    // Readers that do not track this return None; programs that reference RT are run with a
+    // reader that does (see analyze_sep_assignments).
+    fn last_terminator(&self) -> Option<Str<'static>> {
+        None
+    }
+    // The number of records in the current file that come before the current chunk of input. This
+    // is only nonzero in record-parallel mode, where a worker may start reading partway through a
+    // file; FNR is reset to it whenever a read reports that the file changed.
+    fn fnr_offset(&self) -> Int {
+        0
+    }
     fn next_file(&mut self) -> Result<bool>;
     fn set_used_fields(&mut self, used_fields: &FieldSet);
+    // Split subsequent records according to `mode`. Readers that do not support this ignore it;
+    // programs that assign FIELDWIDTHS or FPAT are run with a reader that does (see
+    // analyze_sep_assignments).
+    fn set_split_mode(&mut self, _mode: SplitMode) {}
+    // Report the start and end of each input file through reads of their own, which leave the
+    // current line untouched and set read_state to FILE_START or FILE_END. Programs with
+    // BEGINFILE or ENDFILE blocks are run with this enabled (see ProgramContext::file_events);
+    // readers that are never used for the main input ignore it.
+    fn set_file_events(&mut self) {}
+    // Columns that the program looks up by name through FI. Readers that assign columns to names
+    // as they go (JSON Lines input) give each of these a column once the header is read, so that
+    // they are split even in records that come before the name first appears in the input.
+    fn declare_columns(&mut self, _names: &[&[u8]]) {}
+    // The names and indexes of the columns assigned since the last call, for readers that assign
+    // columns to names as they go. With -H, these are added to FI after each read.
+    fn take_new_columns(&mut self) -> Vec<(Str<'static>, Int)> {
+        Vec::new()
+    }
     // Whether or not this LineReader is configured to check for valid UTF-8. This is used to
     // propagate consistent options across multiple LineReader instances.
     fn check_utf8(&self) -> bool;
@@ -98,6 +134,20 @@ pub struct DefaultLine {
     // After that first line, we set diverged to true, so we know to regenerate $0 when $0 is asked
     // for. This speeds up cases where multiple fields are assigned in a row.
     diverged: bool,
+    mode: SplitMode,
+}
+
+// How a DefaultLine is split into fields, if it was not split when it was read.
+#[derive(Clone)]
+pub enum SplitMode {
+    // Split by FS.
+    Regex,
+    // The line was read in paragraph mode (RS = ""), so newlines separate fields in addition to FS.
+    Paragraph,
+    // Split according to FIELDWIDTHS.
+    Fixed(Arc<FieldWidths>),
+    // Fields are the matches of FPAT.
+    Pattern(Str<'static>),
 }
 
 impl Default for DefaultLine {
@@ -107,6 +157,7 @@ impl Default for DefaultLine {
             used_fields: FieldSet::all(),
             fields: Vec::new(),
             diverged: false,
+            mode: SplitMode::Regex,
         }
     }
 }
@@ -114,12 +165,38 @@ impl Default for DefaultLine {
 impl DefaultLine {
     fn split_if_needed(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<()> {
         if self.fields.is_empty() {
-            rc.split_regex(pat, &self.line, &self.used_fields, &mut self.fields)?;
+            split_line(
+                &self.mode,
+                pat,
+                &self.line,
+                &self.used_fields,
+                &mut self.fields,
+                rc,
+            )?;
         }
         Ok(())
     }
 }
 
+fn split_line(
+    mode: &SplitMode,
+    pat: &Str,
+    line: &Str<'static>,
+    used_fields: &FieldSet,
+    fields: &mut Vec<Str<'static>>,
+    rc: &mut RegexCache,
+) -> Result<()> {
+    match mode {
+        SplitMode::Regex => rc.split_regex(pat, line, used_fields, fields),
+        SplitMode::Paragraph => rc.split_regex_paragraph(pat, line, used_fields, fields),
+        SplitMode::Fixed(widths) => {
+            widths.split(line, used_fields, |s| fields.push(s));
+            Ok(())
+        }
+        SplitMode::Pattern(fpat) => rc.split_regex_fpat(fpat, line, used_fields, fields),
+    }
+}
+
 impl<'a> Line<'a> for DefaultLine {
     fn join_cols<F>(
         &mut self,
@@ -163,7 +240,14 @@ impl<'a> Line<'a> for DefaultLine {
                 // this doesn't happen again for a while.
                 let old_set = std::mem::replace(&mut self.used_fields, FieldSet::all());
                 let mut new_vec = Vec::with_capacity(self.fields.len());
-                rc.split_regex(pat, &self.line, &self.used_fields, &mut new_vec)?;
+                split_line(
+                    &self.mode,
+                    pat,
+                    &self.line,
+                    &self.used_fields,
+                    &mut new_vec,
+                    rc,
+                )?;
 
                 for (i, field) in self.fields.iter().enumerate().rev() {
                     if i >= new_vec.len() {
@@ -208,18 +292,54 @@ impl<'a> Line<'a> for DefaultLine {
     }
 }
 
-pub struct ChainedReader<R>(Vec<R>, /*check_utf8=*/ bool);
+pub struct ChainedReader<R> {
+    readers: Vec<R>,
+    check_utf8: bool,
+    events: FileEvents,
+}
 
 impl<R: LineReader> ChainedReader<R> {
     pub fn new(rs: impl Iterator<Item = R>) -> ChainedReader<R> {
-        let mut v: Vec<_> = rs.collect();
-        v.reverse();
-        let check_utf8 = if let Some(r) = v.last() {
+        let mut readers: Vec<_> = rs.collect();
+        readers.reverse();
+        let check_utf8 = if let Some(r) = readers.last() {
             r.check_utf8()
         } else {
             false
         };
-        ChainedReader(v, check_utf8)
+        ChainedReader {
+            readers,
+            check_utf8,
+            events: Default::default(),
+        }
+    }
+
+    // Each reader covers a single file, so we report the start of a file before reading from it,
+    // and its end once it runs out of records.
+    fn read_line_events(
+        &mut self,
+        pat: &Str,
+        rc: &mut RegexCache,
+        old: &mut R::Line,
+    ) -> Result<bool> {
+        if let Some(changed) = self.events.pending() {
+            return Ok(changed);
+        }
+        let cur = match self.readers.last_mut() {
+            Some(cur) => cur,
+            None => return Ok(self.events.transition(/*more=*/ false).unwrap_or(false)),
+        };
+        if !self.events.open {
+            return Ok(self.events.transition(/*more=*/ true).unwrap_or(false));
+        }
+        cur.read_line_reuse(pat, rc, old)?;
+        if cur.read_state() == ReaderState::EOF as i64 {
+            self.readers.pop();
+            let more = !self.readers.is_empty();
+            return Ok(self.events.transition(more).unwrap_or(false));
+        }
+        // We already reported the file change along with the start of the file.
+        Ok(false)
     }
 }
 
@@ -229,10 +349,10 @@ where
 {
     type Line = R::Line;
     fn check_utf8(&self) -> bool {
-        self.1
+        self.check_utf8
     }
     fn filename(&self) -> Str<'static> {
-        self.0
+        self.readers
             .last()
             .map(LineReader::filename)
             .unwrap_or_else(Str::default)
@@ -248,7 +368,10 @@ where
         rc: &mut RegexCache,
         old: &'a mut Self::Line,
     ) -> Result<bool> {
-        let cur = match self.0.last_mut() {
+        if self.events.enabled() {
+            return self.read_line_events(pat, rc, old);
+        }
+        let cur = match self.readers.last_mut() {
             Some(cur) => cur,
             None => {
                 *old = Default::default();
@@ -264,16 +387,24 @@ where
         }
     }
     fn read_state(&self) -> i64 {
-        match self.0.last() {
+        self.events.read_state(match self.readers.last() {
             Some(cur) => cur.read_state(),
             None => 0, /* EOF */
-        }
+        })
+    }
+    fn last_terminator(&self) -> Option<Str<'static>> {
+        self.readers.last().and_then(LineReader::last_terminator)
+    }
+    fn fnr_offset(&self) -> Int {
+        self.readers.last().map(LineReader::fnr_offset).unwrap_or(0)
     }
     fn next_file(&mut self) -> Result<bool> {
-        Ok(match self.0.last_mut() {
+        Ok(match self.readers.last_mut() {
             Some(e) => {
-                if !e.next_file()? {
-                    self.0.pop();
+                // With file events enabled, we leave the reader in place so that the next read
+                // reports the end of its file.
+                if !e.next_file()? && !self.events.enabled() {
+                    self.readers.pop();
                 }
                 true
             }
@@ -281,20 +412,113 @@ where
         })
     }
     fn set_used_fields(&mut self, used_fields: &FieldSet) {
-        for i in self.0.iter_mut() {
+        for i in self.readers.iter_mut() {
             i.set_used_fields(used_fields);
         }
     }
+    fn set_split_mode(&mut self, mode: SplitMode) {
+        for i in self.readers.iter_mut() {
+            i.set_split_mode(mode.clone());
+        }
+    }
+    fn set_file_events(&mut self) {
+        self.events.enable();
+        for i in self.readers.iter_mut() {
+            i.set_file_events();
+        }
+    }
+    fn declare_columns(&mut self, names: &[&[u8]]) {
+        for i in self.readers.iter_mut() {
+            i.declare_columns(names);
+        }
+    }
+    fn take_new_columns(&mut self) -> Vec<(Str<'static>, Int)> {
+        self.readers
+            .last_mut()
+            .map(LineReader::take_new_columns)
+            .unwrap_or_default()
+    }
+}
+
+/// The bookkeeping for readers that report file events (see LineReader::set_file_events).
+#[derive(Default)]
+pub(crate) struct FileEvents {
+    enabled: bool,
+    // Set when we have reported the start of a file, but not its end.
+    open: bool,
+    // Set when we have reported the end of a file and the reader has already moved on to the
+    // next one.
+    start_next: bool,
+    // The event reported by the last read, if any.
+    last: Option<ReaderState>,
+}
+
+impl FileEvents {
+    pub(crate) fn new(enabled: bool) -> FileEvents {
+        FileEvents {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Called at the start of every read: reports the start of the next file if we have already
+    // reported the end of the previous one.
+    pub(crate) fn pending(&mut self) -> Option</*file changed*/ bool> {
+        self.last = None;
+        if mem::take(&mut self.start_next) {
+            Some(self.start())
+        } else {
+            None
+        }
+    }
+
+    fn start(&mut self) -> bool {
+        self.open = true;
+        self.last = Some(ReaderState::FILE_START);
+        true
+    }
+
+    // Called when the reader moves on to a new file, or runs out of input if `more` is false.
+    // Returns the "file changed" flag for the event that the read should report, if any.
+    pub(crate) fn transition(&mut self, more: bool) -> Option</*file changed*/ bool> {
+        if self.open {
+            self.open = false;
+            self.start_next = more;
+            self.last = Some(ReaderState::FILE_END);
+            Some(false)
+        } else if more {
+            Some(self.start())
+        } else {
+            None
+        }
+    }
+
+    // The read state to report, given that of the underlying input.
+    pub(crate) fn read_state(&self, state: i64) -> i64 {
+        self.last.map_or(state, |ev| ev as i64)
+    }
 }
 
 // Buffer management and io
 
 #[repr(i64)]
 #[derive(PartialEq, Eq, Copy, Clone)]
+#[allow(non_camel_case_types)]
 pub(crate) enum ReaderState {
     ERROR = -1,
     EOF = 0,
     OK = 1,
+    // Reported by readers with file events enabled (see LineReader::set_file_events).
+    FILE_START = 2,
+    FILE_END = 3,
 }
 
 /// frawk inputs read chunks of data into large contiguous buffers, and then advance progress
@@ -323,6 +547,8 @@ struct Reader<R> {
 
     // Validate input as UTF-8
     check_utf8: bool,
+    // Return from a read as soon as some data is available, rather than waiting to fill a chunk.
+    partial_reads: bool,
 }
 
 fn read_to_slice(r: &mut impl Read, mut buf: &mut [u8]) -> Result<usize> {
@@ -348,6 +574,20 @@ fn read_to_slice(r: &mut impl Read, mut buf: &mut [u8]) -> Result<usize> {
     Ok(read)
 }
 
+// Read whatever data is available from `r`, blocking only if there is none.
+fn read_some(r: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    loop {
+        match r.read(buf) {
+            Ok(n) => return Ok(n),
+            Err(e) => match e.kind() {
+                ErrorKind::Interrupted => continue,
+                ErrorKind::UnexpectedEof => return Ok(0),
+                _ => return err!("read error {}", e),
+            },
+        }
+    }
+}
+
 impl<R: Read> Reader<R> {
     pub(crate) fn new(r: R, chunk_size: usize, padding: usize, check_utf8: bool) -> Self {
         Reader {
@@ -361,6 +601,7 @@ impl<R: Read> Reader<R> {
             state: ReaderState::OK,
             last_len: 0,
             check_utf8,
+            partial_reads: false,
         }
     }
 
@@ -379,7 +620,6 @@ impl<R: Read> Reader<R> {
 
     fn read_state(&self) -> i64 {
         match self.state {
-            ReaderState::OK => self.state as i64,
             ReaderState::ERROR | ReaderState::EOF => {
                 // NB: last_len should really be "bytes consumed"; i.e. it should be the length
                 // of the line including any trimmed characters, and the record separator. I.e.
@@ -391,6 +631,7 @@ impl<R: Read> Reader<R> {
                     ReaderState::OK as i64
                 }
             }
+            _ => self.state as i64,
         }
     }
 
@@ -436,9 +677,15 @@ impl<R: Read> Reader<R> {
             );
         }
         let mut bytes = &mut data.as_mut_bytes()[..self.chunk_size];
-        let bytes_read = plen + read_to_slice(&mut self.inner, &mut bytes[plen..])?;
+        let new_bytes = if self.partial_reads {
+            read_some(&mut self.inner, &mut bytes[plen..])?
+        } else {
+            read_to_slice(&mut self.inner, &mut bytes[plen..])?
+        };
+        let bytes_read = plen + new_bytes;
         if bytes_read != self.chunk_size {
-            done = true;
+            // A short read normally means that we have reached the end of the input.
+            done = !self.partial_reads || new_bytes == 0;
             bytes = &mut bytes[..bytes_read];
         }
         let mut ulen = bytes.len();
@@ -4,9 +4,10 @@ use std::io::Read;
 use crate::common::Result;
 use crate::pushdown::FieldSet;
 use crate::runtime::Str;
+use lazy_static::lazy_static;
 use regex::bytes::Regex;
 
-use super::{DefaultLine, LineReader, Reader, ReaderState};
+use super::{DefaultLine, LineReader, Reader, ReaderState, SplitMode};
 
 // TODO: this can probably just be "Splitter"
 pub struct RegexSplitter<R> {
@@ -15,6 +16,13 @@ pub struct RegexSplitter<R> {
     used_fields: FieldSet,
     // Used to trigger updating FILENAME on the first read.
     start: bool,
+    // The text that matched the record separator at the end of the last record.
+    terminator: Str<'static>,
+    // How records are split when FIELDWIDTHS or FPAT is in effect; Regex otherwise.
+    mode: SplitMode,
+    // Leave the last record in place when we reach the end of the input, so that ChainedReader
+    // can report the end of the file with it (see LineReader::set_file_events).
+    keep_last: bool,
 }
 
 impl<R: Read> LineReader for RegexSplitter<R> {
@@ -35,6 +43,10 @@ impl<R: Read> LineReader for RegexSplitter<R> {
         rc: &mut super::RegexCache,
         old: &'a mut Self::Line,
     ) -> Result<bool> {
+        let line = self.read_line_pat(pat, rc)?;
+        if self.keep_last && self.read_state() == ReaderState::EOF as i64 {
+            return Ok(false);
+        }
         let start = self.start;
         if start {
             old.used_fields = self.used_fields.clone();
@@ -42,26 +54,29 @@ impl<R: Read> LineReader for RegexSplitter<R> {
         self.start = false;
         old.diverged = false;
         old.fields.clear();
-        rc.with_regex(pat, |re| {
-            old.line = self.read_line_regex(re);
-        })?;
+        old.mode = self.split_mode(pat);
+        old.line = line;
         Ok(/* file changed */ start)
     }
 
     fn read_line(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<(bool, Self::Line)> {
         let start = self.start;
         self.start = false;
-        let line = rc.with_regex(pat, |re| DefaultLine {
-            line: self.read_line_regex(re),
+        let line = DefaultLine {
+            line: self.read_line_pat(pat, rc)?,
             fields: Default::default(),
             used_fields: self.used_fields.clone(),
             diverged: false,
-        })?;
+            mode: self.split_mode(pat),
+        };
         Ok((/* file changed */ start, line))
     }
     fn read_state(&self) -> i64 {
         self.reader.read_state()
     }
+    fn last_terminator(&self) -> Option<Str<'static>> {
+        Some(self.terminator.clone())
+    }
     fn next_file(&mut self) -> Result<bool> {
         // There is just one file. Set EOF.
         self.reader.force_eof();
@@ -70,6 +85,12 @@ impl<R: Read> LineReader for RegexSplitter<R> {
     fn set_used_fields(&mut self, used_fields: &FieldSet) {
         self.used_fields = used_fields.clone();
     }
+    fn set_split_mode(&mut self, mode: SplitMode) {
+        self.mode = mode;
+    }
+    fn set_file_events(&mut self) {
+        self.keep_last = true;
+    }
 }
 
 impl<R: Read> RegexSplitter<R> {
@@ -79,9 +100,67 @@ impl<R: Read> RegexSplitter<R> {
             name: name.into(),
             used_fields: FieldSet::all(),
             start: true,
+            terminator: Str::default(),
+            mode: SplitMode::Regex,
+            keep_last: false,
+        }
+    }
+
+    /// Return records as soon as they are available, rather than waiting for a full chunk of
+    /// input. This is used for coprocesses, which may not produce more output until they are
+    /// sent more input.
+    pub fn interactive(mut self) -> Self {
+        self.reader.partial_reads = true;
+        self
+    }
+
+    fn split_mode(&self, pat: &Str) -> SplitMode {
+        match &self.mode {
+            SplitMode::Regex if pat.is_empty() => SplitMode::Paragraph,
+            mode => mode.clone(),
+        }
+    }
+
+    fn read_line_pat(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<Str<'static>> {
+        if pat.is_empty() {
+            Ok(self.read_paragraph())
+        } else {
+            rc.with_regex(pat, |re| self.read_line_regex(re))
         }
     }
 
+    // An empty RS puts us in "paragraph mode": records are separated by one or more blank lines,
+    // and newlines at the start and end of the input are ignored.
+    fn read_paragraph(&mut self) -> Str<'static> {
+        lazy_static! {
+            static ref BLANK_LINES: Regex = Regex::new("\n\n+").unwrap();
+        }
+        // Skip leading newlines, which may span several chunks.
+        loop {
+            let s = &self.reader.buf.as_bytes()[self.reader.start..self.reader.end];
+            self.reader.start += s.iter().take_while(|b| **b == b'\n').count();
+            if self.reader.start < self.reader.end {
+                break;
+            }
+            match self.reader.reset() {
+                Ok(false) => continue,
+                Ok(true) => {}
+                Err(_) => self.reader.state = ReaderState::ERROR,
+            }
+            self.reader.last_len = 0;
+            self.terminator = Str::default();
+            return Str::default();
+        }
+        let line = self.read_line_regex(&BLANK_LINES);
+        // The last record may still end in a single newline.
+        let len = line.len();
+        if self.terminator.is_empty() && line.with_bytes(|bs| bs.last() == Some(&b'\n')) {
+            self.terminator = Str::from("\n");
+            return line.slice(0, len - 1);
+        }
+        line
+    }
+
     pub fn read_line_regex(&mut self, pat: &Regex) -> Str<'static> {
         // We keep this as a separate method because it helps in writing tests.
         let (res, consumed) = self.read_line_inner(pat);
@@ -91,6 +170,7 @@ impl<R: Read> RegexSplitter<R> {
 
     fn read_line_inner(&mut self, pat: &Regex) -> (Str<'static>, usize) {
         if self.reader.is_eof() {
+            self.terminator = Str::default();
             return (Str::default(), 0);
         }
         loop {
@@ -99,13 +179,20 @@ impl<R: Read> RegexSplitter<R> {
             // makes it harder for us to call mutable methods like advance in the body, so just get
             // the start and end pointers.
             match pat.find(s).map(|m| (m.start(), m.end())) {
-                // We need this check in case the regex matches across a chunk boundary.
-                Some((start, end)) if end + self.reader.start < self.reader.end => {
+                // We need this check in case the regex matches across a chunk boundary. Interactive
+                // readers cannot wait for the next chunk, so they take the match as it stands.
+                Some((start, end))
+                    if end + self.reader.start < self.reader.end || self.reader.partial_reads =>
+                {
                     // Valid offsets guaranteed by correctness of regex `find`.
                     let res = self
                         .reader
                         .buf
                         .slice_to_str(self.reader.start, self.reader.start + start);
+                    self.terminator = self
+                        .reader
+                        .buf
+                        .slice_to_str(self.reader.start + start, self.reader.start + end);
                     self.reader.start += end;
                     return (res, end);
                 }
@@ -119,6 +206,7 @@ impl<R: Read> RegexSplitter<R> {
                                 .buf
                                 .slice_to_str(self.reader.start, self.reader.end);
                             self.reader.start = self.reader.end;
+                            self.terminator = Str::default();
                             (line, consumed)
                         }
                         Ok(false) => {
@@ -143,6 +231,7 @@ impl<R: Read> RegexSplitter<R> {
                         }
                         Err(_) => {
                             self.reader.state = ReaderState::ERROR;
+                            self.terminator = Str::default();
                             (Str::default(), 0)
                         }
                     };
@@ -155,6 +244,10 @@ impl<R: Read> RegexSplitter<R> {
                                 .reader
                                 .buf
                                 .slice_to_str(self.reader.start, self.reader.start + start);
+                            self.terminator = self
+                                .reader
+                                .buf
+                                .slice_to_str(self.reader.start + start, self.reader.start + end);
                             self.reader.start += end;
                             (res, end)
                         }
@@ -164,6 +257,7 @@ impl<R: Read> RegexSplitter<R> {
                         }
                         Err(_) => {
                             self.reader.state = ReaderState::ERROR;
+                            self.terminator = Str::default();
                             (Str::default(), 0)
                         }
                     };
@@ -251,6 +345,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_paragraph_split() {
+        let mut bs = String::from("\n\n");
+        let mut expected = Vec::new();
+        for i in 0..100 {
+            let para = format!("paragraph {}\nline two", i);
+            bs.push_str(para.as_str());
+            bs.push_str(if i % 2 == 0 { "\n\n" } else { "\n\n\n\n" });
+            expected.push((para, if i % 2 == 0 { "\n\n" } else { "\n\n\n\n" }));
+        }
+        let c = Cursor::new(bs);
+        let mut rdr =
+            RegexSplitter::new(c, /*chunk_size=*/ 64, "", /*check_utf8=*/ false);
+        for (para, sep) in expected.iter() {
+            let line: Str = rdr.read_paragraph().upcast();
+            assert_eq!(rdr.read_state(), 1);
+            assert_eq!(line, Str::from(para.as_str()));
+            let terminator: Str = rdr.terminator.clone().upcast();
+            assert_eq!(terminator, Str::from(*sep));
+        }
+        assert_eq!(rdr.read_paragraph(), Str::default());
+        assert_eq!(rdr.read_state(), 0);
+    }
+
     #[test]
     fn test_clipped_chunk_split_pp() {
         // _random is more thorough, but this works as a sort of smoke test.
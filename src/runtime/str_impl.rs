@@ -9,7 +9,7 @@
 use crate::pushdown::FieldSet;
 use crate::runtime::{Float, Int};
 
-use regex::bytes::Regex;
+use regex::bytes::{Captures, Regex};
 use smallvec::SmallVec;
 
 use std::alloc::{alloc_zeroed, dealloc, realloc, Layout};
@@ -66,6 +66,11 @@ impl Default for Inline {
 }
 
 impl Inline {
+    // An empty string that holds a value, such as an empty field. It only differs from
+    // `Inline::default()`, which is also the representation of an uninitialized `Str`, in the
+    // unused bytes after the length. See `Str::strnum`.
+    const EMPTY_VALUE: Inline = Inline(StrTag::Inline as u128 | 1 << 8);
+
     unsafe fn from_raw(ptr: *const u8, len: usize) -> Inline {
         debug_assert!(len <= MAX_INLINE_SIZE);
         if len > MAX_INLINE_SIZE {
@@ -386,7 +391,14 @@ pub struct Str<'a>(UnsafeCell<StrRep<'a>>);
 
 impl<'a> Str<'a> {
     pub fn is_empty(&self) -> bool {
-        unsafe { mem::transmute::<&Str, &Inline>(self) == &Inline::default() }
+        // Empty strings are Inline strings of length 0; see `Inline::EMPTY_VALUE`.
+        unsafe { mem::transmute::<&Str, &Inline>(self).0 as u8 == 0 }
+    }
+
+    /// An empty string that holds a value, such as an empty field. Unlike the default `Str`, which
+    /// stands for an uninitialized value, it does not have a numeric value in [Str::strnum].
+    pub fn empty_value() -> Str<'a> {
+        Str::from_rep(Inline::EMPTY_VALUE.into())
     }
     unsafe fn rep(&self) -> &StrRep<'a> {
         &*self.0.get()
@@ -564,6 +576,37 @@ impl<'a> Str<'a> {
         })
     }
 
+    /// Substitute the `nth` match of `pat` (1-indexed), or every match if `nth` is None, as in
+    /// gawk's gensub. Unlike sub and gsub, `subst` can refer to capture groups.
+    pub fn subst_captures(&self, pat: &Regex, subst: &Str<'a>, nth: Option<usize>) -> Str<'a> {
+        self.with_bytes(|s| {
+            subst.with_bytes(|subst| {
+                let mut buf = DynamicBuf::new(0);
+                let mut prev = 0;
+                let mut changed = false;
+                for (i, caps) in pat.captures_iter(s).enumerate() {
+                    if matches!(nth, Some(n) if i + 1 < n) {
+                        continue;
+                    }
+                    let m = caps.get(0).unwrap();
+                    buf.write_all(&s[prev..m.start()]).unwrap();
+                    process_captures(&caps, subst, &mut buf).unwrap();
+                    prev = m.end();
+                    changed = true;
+                    if nth.is_some() {
+                        break;
+                    }
+                }
+                if !changed {
+                    self.clone()
+                } else {
+                    buf.write_all(&s[prev..s.len()]).unwrap();
+                    unsafe { buf.into_str() }
+                }
+            })
+        })
+    }
+
     pub fn len(&self) -> usize {
         unsafe { self.rep_mut() }.len()
     }
@@ -646,7 +689,7 @@ impl<'a> Str<'a> {
     unsafe fn slice_internal(&self, from: usize, to: usize) -> Str<'a> {
         assert!(from <= to);
         if from == to {
-            return Default::default();
+            return Str::empty_value();
         }
         let len = self.len();
         assert!(
@@ -822,6 +865,25 @@ impl<'a> Str<'a> {
         unsafe { f(&*raw) }
     }
 
+    /// The numeric value of a string that may hold a number (a "strnum"), if it has one.
+    /// Uninitialized values are 0, and other strings have a value if they look like numbers.
+    pub fn strnum(&self) -> Option<Float> {
+        if unsafe { self.rep() } == &StrRep::default() {
+            return Some(0.0);
+        }
+        self.with_bytes(crate::runtime::strnum)
+    }
+
+    /// Compare two strnums, such as input fields or values that are numbers along some paths and
+    /// uninitialized along others. If both strings have numeric values (see [Str::strnum]), they
+    /// are compared numerically; otherwise they are compared as strings.
+    pub fn strnum_cmp(&self, other: &Str) -> std::cmp::Ordering {
+        match (self.strnum(), other.strnum()) {
+            (Some(f1), Some(f2)) => f1.partial_cmp(&f2).unwrap_or(std::cmp::Ordering::Equal),
+            _ => self.with_bytes(|bs1| other.with_bytes(|bs2| bs1.cmp(bs2))),
+        }
+    }
+
     pub fn unmoor(self) -> Str<'static> {
         let rep = unsafe { self.rep_mut() };
         let tag = rep.get_tag();
@@ -1231,7 +1293,7 @@ impl Buf {
         debug_assert!(from <= to, "invalid slice [{}, {})", from, to);
         let len = to.saturating_sub(from);
         if len == 0 {
-            Str::default()
+            Str::empty_value()
         } else
         /* NB: we could also have the following.
          * This creates a tradeoff: in scripts where we split several fields, performing this copy
@@ -1319,6 +1381,38 @@ fn process_match(matched: &[u8], subst: &[u8], w: &mut impl Write) -> io::Result
     Ok(())
 }
 
+/// Helper function for `subst_captures`: handles '&' and '\0' (the whole match) as well as '\1'
+/// through '\9' (capture groups). '\&' and '\\' produce a literal '&' and '\'.
+fn process_captures(caps: &Captures, subst: &[u8], w: &mut impl Write) -> io::Result<()> {
+    let mut start = 0;
+    let mut i = 0;
+    while i < subst.len() {
+        let group = match (subst[i], subst.get(i + 1)) {
+            (b'&', _) => Some(0),
+            (b'\\', Some(d)) if d.is_ascii_digit() => Some((d - b'0') as usize),
+            (b'\\', Some(b'&')) | (b'\\', Some(b'\\')) => {
+                w.write_all(&subst[start..i])?;
+                start = i + 1;
+                i += 2;
+                continue;
+            }
+            _ => None,
+        };
+        if let Some(group) = group {
+            w.write_all(&subst[start..i])?;
+            if let Some(m) = caps.get(group) {
+                w.write_all(m.as_bytes())?;
+            }
+            i += if subst[i] == b'&' { 1 } else { 2 };
+            start = i;
+        } else {
+            i += 1;
+        }
+    }
+    w.write_all(&subst[start..])?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1414,6 +1508,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn strnum_compare() {
+        use std::cmp::Ordering::*;
+        let cmp = |s1: &str, s2: &str| Str::from(s1).strnum_cmp(&Str::from(s2));
+        assert_eq!(cmp("10", "9"), Greater);
+        assert_eq!(cmp(" 1e1", "10.0"), Equal);
+        assert_eq!(cmp("", "0"), Equal);
+        assert_eq!(cmp("-1", ""), Less);
+        let empty = Str::empty_value();
+        assert!(empty.is_empty());
+        assert_eq!(empty, Str::default());
+        assert_eq!(empty.strnum_cmp(&Str::from("0")), Less);
+        assert_eq!(empty.strnum_cmp(&Str::default()), Equal);
+        assert_eq!(cmp("10", "9a"), Less);
+        assert_eq!(cmp("abc", "abd"), Less);
+    }
+
     #[test]
     fn dynamic_string() {
         let mut d = DynamicBuf::new(0);
@@ -1474,6 +1585,21 @@ And this is the second part"#
         s6.with_bytes(|bs| assert_eq!(bs, b"hz&hbhc"));
         assert!(subbed);
     }
+
+    #[test]
+    fn subst_captures() {
+        let s1: Str = "a=1, b=2, c=3".into();
+        let re = Regex::new("([a-z])=([0-9])").unwrap();
+        let s2: Str = r"\2:\1".into();
+        s1.subst_captures(&re, &s2, None)
+            .with_bytes(|bs| assert_eq!(bs, b"1:a, 2:b, 3:c"));
+        s1.subst_captures(&re, &s2, Some(2))
+            .with_bytes(|bs| assert_eq!(bs, b"a=1, 2:b, c=3"));
+        assert_eq!(s1.subst_captures(&re, &s2, Some(4)), s1);
+        let s3: Str = r"[&|\0|\&|\\|\3]".into();
+        s1.subst_captures(&re, &s3, Some(1))
+            .with_bytes(|bs| assert_eq!(bs, br"[a=1|a=1|&|\|], b=2, c=3"));
+    }
 }
 
 #[cfg(all(feature = "unstable", test))]
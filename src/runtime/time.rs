@@ -0,0 +1,197 @@
+//! Date and time builtins: systime, strftime, mktime and strptime.
+//!
+//! These are thin wrappers around the C library, so that local times follow the `TZ` environment
+//! variable and formatting matches `date` and gawk. They rely on POSIX extensions (`strptime`,
+//! `timegm`, `tm_gmtoff`), so on other platforms everything but systime is a runtime error.
+#[cfg(unix)]
+use std::ffi::CString;
+#[cfg(unix)]
+use std::mem;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(unix)]
+use libc::{c_char, c_int, c_long, size_t, time_t, tm};
+
+use crate::common::Result;
+use crate::runtime::{Int, Str};
+
+// Not all of these are exposed by the libc crate.
+#[cfg(unix)]
+extern "C" {
+    fn tzset();
+    fn strftime(s: *mut c_char, max: size_t, format: *const c_char, tm: *const tm) -> size_t;
+    fn strptime(s: *const c_char, format: *const c_char, tm: *mut tm) -> *mut c_char;
+}
+
+/// The format strftime uses when called with no arguments, as in gawk.
+pub const DEFAULT_STRFTIME_FORMAT: &str = "%a %b %e %H:%M:%S %Z %Y";
+
+// strftime refuses to produce more output than this.
+const MAX_STRFTIME_LEN: usize = 1 << 20;
+
+#[cfg(unix)]
+fn to_cstring(s: &Str) -> Option<CString> {
+    s.with_bytes(|bs| CString::new(bs).ok())
+}
+
+/// The current time, in seconds since the epoch.
+pub fn systime() -> Int {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as Int,
+        Err(e) => -(e.duration().as_secs() as Int),
+    }
+}
+
+/// Format `ts` (seconds since the epoch) according to `fmt`, in local time or in UTC. Returns
+/// the empty string if `ts` cannot be represented.
+#[cfg(unix)]
+pub fn strftime_impl(fmt: &Str, ts: Int, utc: bool) -> Result<Str<'static>> {
+    let fmt = match to_cstring(fmt) {
+        Some(fmt) if !fmt.as_bytes().is_empty() => fmt,
+        _ => return Ok(Str::default()),
+    };
+    let t = ts as time_t;
+    let mut tm: tm = unsafe { mem::zeroed() };
+    let converted = unsafe {
+        if utc {
+            libc::gmtime_r(&t, &mut tm)
+        } else {
+            tzset();
+            libc::localtime_r(&t, &mut tm)
+        }
+    };
+    if converted.is_null() {
+        return Ok(Str::default());
+    }
+    // strftime returns 0 both when the buffer is too small and when the output is empty, so we
+    // retry with larger buffers up to a limit.
+    let mut buf = Vec::new();
+    let mut cap = 64 + 4 * fmt.as_bytes().len();
+    while cap <= MAX_STRFTIME_LEN {
+        buf.resize(cap, 0u8);
+        let len = unsafe { strftime(buf.as_mut_ptr() as *mut c_char, cap, fmt.as_ptr(), &tm) };
+        if len > 0 {
+            return Ok(Str::from(&buf[..len]).unmoor());
+        }
+        cap *= 4;
+    }
+    Ok(Str::default())
+}
+
+/// Convert a local time given as "YYYY MM DD HH MM SS [DST]" to seconds since the epoch, or -1 if
+/// `spec` is malformed. As with the C function, out-of-range values are normalized, and a
+/// negative DST flag (the default) asks for it to be determined automatically.
+#[cfg(unix)]
+pub fn mktime_impl(spec: &Str) -> Result<Int> {
+    let fields = spec.with_bytes(|bs| {
+        bs.split(|b| b.is_ascii_whitespace())
+            .filter(|f| !f.is_empty())
+            .map(|f| std::str::from_utf8(f).ok()?.parse::<c_int>().ok())
+            .collect::<Option<Vec<_>>>()
+    });
+    let fields = match fields {
+        Some(fields) if fields.len() == 6 || fields.len() == 7 => fields,
+        _ => return Ok(-1),
+    };
+    let mut tm: tm = unsafe { mem::zeroed() };
+    tm.tm_year = fields[0].saturating_sub(1900);
+    tm.tm_mon = fields[1].saturating_sub(1);
+    tm.tm_mday = fields[2];
+    tm.tm_hour = fields[3];
+    tm.tm_min = fields[4];
+    tm.tm_sec = fields[5];
+    tm.tm_isdst = fields.get(6).cloned().unwrap_or(-1);
+    unsafe {
+        tzset();
+        Ok(libc::mktime(&mut tm) as Int)
+    }
+}
+
+/// Parse `s` according to the strptime(3) format `fmt`, returning seconds since the epoch or -1
+/// if `s` does not match. Text after the parsed prefix of `s` is ignored. Times are local unless
+/// `fmt` contains a `%z` offset.
+#[cfg(unix)]
+pub fn strptime_impl(s: &Str, fmt: &Str) -> Result<Int> {
+    // strptime only sets tm_gmtoff when it parses %z.
+    const NO_OFFSET: c_long = c_long::MIN;
+    let (s, fmt) = match (to_cstring(s), to_cstring(fmt)) {
+        (Some(s), Some(fmt)) => (s, fmt),
+        _ => return Ok(-1),
+    };
+    let mut tm: tm = unsafe { mem::zeroed() };
+    tm.tm_mday = 1;
+    tm.tm_gmtoff = NO_OFFSET;
+    unsafe {
+        if strptime(s.as_ptr(), fmt.as_ptr(), &mut tm).is_null() {
+            return Ok(-1);
+        }
+        let offset = tm.tm_gmtoff;
+        Ok(if offset == NO_OFFSET {
+            tm.tm_isdst = -1;
+            tzset();
+            libc::mktime(&mut tm) as Int
+        } else {
+            libc::timegm(&mut tm) as Int - offset as Int
+        })
+    }
+}
+
+#[cfg(not(unix))]
+fn unsupported<T>(name: &str) -> Result<T> {
+    err!("{} is not supported on this platform", name)
+}
+
+#[cfg(not(unix))]
+pub fn strftime_impl(_fmt: &Str, _ts: Int, _utc: bool) -> Result<Str<'static>> {
+    unsupported("strftime")
+}
+
+#[cfg(not(unix))]
+pub fn mktime_impl(_spec: &Str) -> Result<Int> {
+    unsupported("mktime")
+}
+
+#[cfg(not(unix))]
+pub fn strptime_impl(_s: &Str, _fmt: &Str) -> Result<Int> {
+    unsupported("strptime")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_utc() {
+        let fmt = Str::from("%Y-%m-%d %H:%M:%S");
+        assert_eq!(
+            strftime_impl(&fmt, 1_000_000_000, true).unwrap(),
+            Str::from("2001-09-09 01:46:40")
+        );
+        assert_eq!(
+            strftime_impl(&Str::from(""), 0, true).unwrap(),
+            Str::default()
+        );
+    }
+
+    #[test]
+    fn parse_with_offset() {
+        let fmt = Str::from("%Y-%m-%dT%H:%M:%S%z");
+        assert_eq!(
+            strptime_impl(&Str::from("2001-09-09T03:46:40+0200 GET /"), &fmt).unwrap(),
+            1_000_000_000
+        );
+        assert_eq!(strptime_impl(&Str::from("yesterday"), &fmt).unwrap(), -1);
+    }
+
+    #[test]
+    fn make_time_round_trip() {
+        let ts = mktime_impl(&Str::from("2001 09 09 01 46 40")).unwrap();
+        let fmt = Str::from("%Y %m %d %H %M %S");
+        assert_eq!(
+            strftime_impl(&fmt, ts, false).unwrap(),
+            Str::from("2001 09 09 01 46 40")
+        );
+        assert_eq!(mktime_impl(&Str::from("2001 09 09")).unwrap(), -1);
+        assert_eq!(mktime_impl(&Str::from("2001 09 09 01 46 4x")).unwrap(), -1);
+    }
+}
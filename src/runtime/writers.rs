@@ -34,13 +34,23 @@
 //! To facilitate easier testing, the functionality of the file system that we use is abstracted in
 //! the `FileFactory` trait. The `testing` module contains an implementation of this trait that
 //! writes all data in memory.
+//!
+//! As in gawk, the file names "/dev/stdout", "/dev/stderr" and "/dev/fd/N" are not opened as
+//! paths: they refer to the process's existing streams and descriptors. Writes to "/dev/stdout"
+//! go through the same handle as `print` with no redirection, and writes to "/dev/stderr" are
+//! unbuffered, so that diagnostics stay in order with the rest of a program's output.
+//!
+//! In ordered record-parallel mode (`-pr --ordered`), writes to standard output made while a
+//! worker processes an input chunk are held in a per-chunk buffer instead. A `Sequencer` shared by
+//! the workers writes these buffers out in the order the chunks were read.
 
-use std::collections::VecDeque;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
 use std::io::{self, Write};
 use std::process::ChildStdin;
 use std::sync::{
     atomic::{AtomicBool, AtomicUsize, Ordering},
-    Arc, Mutex,
+    Arc, Condvar, Mutex,
 };
 
 #[cfg(not(feature = "unstable"))]
@@ -57,7 +67,10 @@ use crossbeam_channel::{bounded, Receiver, Sender};
 use hashbrown::HashMap;
 
 use crate::common::{CompileError, FileSpec, Notification, Result};
-use crate::runtime::{command::command_for_write, Str};
+use crate::runtime::{
+    command::{command_for_write, Cmd, Coprocess},
+    Str,
+};
 
 /// The maximum number of pending requests in the per-file channels.
 const IO_CHAN_SIZE: usize = 8;
@@ -65,6 +78,15 @@ const IO_CHAN_SIZE: usize = 8;
 /// The size of client-side batches.
 const BUFFER_SIZE: usize = 64 << 10;
 
+/// The amount of output from finished chunks that a Sequencer holds while it waits for an earlier
+/// chunk to finish. Workers that finish a chunk beyond this limit block until the earlier chunk
+/// is written.
+const MAX_PENDING_BYTES: usize = 32 << 20;
+
+/// The amount of output a worker buffers for the chunk it is working on. Beyond this, the worker
+/// waits for every earlier chunk to be written and then writes the output directly.
+const MAX_CHUNK_BYTES: usize = 4 << 20;
+
 /// FileFactory abstracts over the portions of the file system used for the output of a frawk
 /// program. It includes "file objects" as well as "stdout", which both implement the io::Write
 /// trait.
@@ -75,12 +97,20 @@ pub trait FileFactory: Clone + 'static + Send + Sync {
     type Output: io::Write;
     type Stdout: io::Write;
     // TODO: make ChildStdin an associated type, to permit better testing
-    fn cmd(&self, cmd: &[u8]) -> io::Result<ChildStdin> {
+    fn cmd(&self, cmd: Cmd) -> io::Result<ChildStdin> {
         command_for_write(cmd)
     }
     fn build(&self, path: &str, spec: FileSpec) -> io::Result<Self::Output>;
     // TODO maybe we shold support this returning an error.
     fn stdout(&self) -> Self::Stdout;
+    /// The destination for writes to "/dev/stderr".
+    fn stderr(&self) -> Box<dyn io::Write> {
+        Box::new(io::stderr())
+    }
+    /// The destination for writes to "/dev/fd/N", for an `fd` other than stdout and stderr.
+    fn fd(&self, fd: i32) -> io::Result<Box<dyn io::Write>> {
+        open_fd(fd)
+    }
 }
 
 impl<W: io::Write, T: Fn(&str, FileSpec) -> io::Result<W> + Clone + 'static + Send + Sync>
@@ -107,6 +137,66 @@ fn open_file(path: &str, spec: FileSpec) -> io::Result<FileWriter> {
     Ok(file)
 }
 
+#[cfg(unix)]
+fn open_fd(fd: i32) -> io::Result<Box<dyn io::Write>> {
+    use std::os::unix::io::FromRawFd;
+    // Duplicate the descriptor so that closing the file from awk does not close `fd` itself.
+    let dup = unsafe { libc::dup(fd) };
+    if dup < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Box::new(unsafe { std::fs::File::from_raw_fd(dup) }))
+}
+
+#[cfg(not(unix))]
+fn open_fd(fd: i32) -> io::Result<Box<dyn io::Write>> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        format!(
+            "writing to /dev/fd/{} is not supported on this platform",
+            fd
+        ),
+    ))
+}
+
+/// Output files that refer to the process's existing streams rather than to paths.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum SpecialFile {
+    Stdout,
+    Stderr,
+    Fd(i32),
+}
+
+impl SpecialFile {
+    fn from_name(name: &[u8]) -> Option<SpecialFile> {
+        match name {
+            b"/dev/stdout" | b"/dev/fd/1" => Some(SpecialFile::Stdout),
+            b"/dev/stderr" | b"/dev/fd/2" => Some(SpecialFile::Stderr),
+            _ => {
+                let fd = name.strip_prefix(b"/dev/fd/")?;
+                if fd.is_empty() || !fd.iter().all(u8::is_ascii_digit) {
+                    return None;
+                }
+                match std::str::from_utf8(fd).ok()?.parse::<i32>().ok()? {
+                    1 => Some(SpecialFile::Stdout),
+                    2 => Some(SpecialFile::Stderr),
+                    fd => Some(SpecialFile::Fd(fd)),
+                }
+            }
+        }
+    }
+
+    /// The name under which handles for this file are stored, so that aliases like "/dev/fd/2"
+    /// and "/dev/stderr" share a handle.
+    fn name(&self) -> String {
+        match self {
+            SpecialFile::Stdout => "/dev/stdout".into(),
+            SpecialFile::Stderr => "/dev/stderr".into(),
+            SpecialFile::Fd(fd) => format!("/dev/fd/{}", fd),
+        }
+    }
+}
+
 pub fn default_factory() -> impl FileFactory {
     open_file
 }
@@ -130,9 +220,29 @@ pub fn factory_from_file(fname: &str) -> io::Result<impl FileFactory> {
     Ok(FileStdout(fname.into()))
 }
 
+/// How a FileHandle batches writes before sending them to the writer thread.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Buffering {
+    Full,
+    // Send each complete line as soon as it is written; used for terminals.
+    Line,
+    // Wait for every write to complete before returning, like stderr in C.
+    Unbuffered,
+}
+
+impl Buffering {
+    fn stdout() -> Buffering {
+        if grep_cli::is_tty_stdout() {
+            Buffering::Line
+        } else {
+            Buffering::Full
+        }
+    }
+}
+
 fn build_handle<W: io::Write, F: Fn(FileSpec) -> io::Result<W> + Send + 'static>(
     f: F,
-    is_stdout: bool,
+    buffering: Buffering,
 ) -> RawHandle {
     let (sender, receiver) = bounded(IO_CHAN_SIZE);
     let error = Arc::new(Mutex::new(None));
@@ -141,7 +251,8 @@ fn build_handle<W: io::Write, F: Fn(FileSpec) -> io::Result<W> + Send + 'static>
     RawHandle {
         error,
         sender,
-        line_buffer: is_stdout && grep_cli::is_tty_stdout(),
+        buffering,
+        stdout: false,
     }
 }
 
@@ -153,6 +264,9 @@ pub struct Registry {
     global: Arc<dyn Root>,
     files: HashMap<Str<'static>, FileHandle>,
     cmds: HashMap<Str<'static>, FileHandle>,
+    // The coprocesses started on this thread, and the handles for those written to.
+    coprocesses: HashMap<Str<'static>, Coprocess>,
+    coprocs: HashMap<Str<'static>, FileHandle>,
     stdout: FileHandle,
 }
 
@@ -164,6 +278,8 @@ impl Registry {
             global: Arc::new(root_impl),
             files: Default::default(),
             cmds: Default::default(),
+            coprocesses: Default::default(),
+            coprocs: Default::default(),
             stdout,
         }
     }
@@ -179,12 +295,24 @@ impl Registry {
             return self.get_file(None);
         };
         match fspec {
-            FileSpec::Cmd => self.get_cmd(name),
+            FileSpec::Cmd => self.get_cmd(name, /*argv=*/ false),
+            FileSpec::Exec => self.get_cmd(name, /*argv=*/ true),
+            FileSpec::Coproc => self.get_coproc(name),
             FileSpec::Trunc | FileSpec::Append => self.get_file(Some(name)),
         }
     }
 
     pub fn close<'a>(&mut self, path_or_cmd: &Str<'a>) -> Result<()> {
+        if let Some(special) = path_or_cmd.with_bytes(SpecialFile::from_name) {
+            return match special {
+                // Closing stdout would only reopen it on the next write, so we just flush it.
+                SpecialFile::Stdout => self.stdout.flush(),
+                _ => match self.files.get_mut(&Str::from(special.name())) {
+                    Some(fh) => fh.close(),
+                    None => self.global.close(special.name().as_bytes()),
+                },
+            };
+        }
         // TODO: implement a newtype for heterogeneous lookup. We shouldn't have to do the clone or
         // the unmoor here, but we need to because we cannot implement Borrow<Str<'a>> for
         // Borrow<Str<'static>> (conflicts with the blanket impl for Borrow).
@@ -196,18 +324,112 @@ impl Registry {
             ch.close()?;
             return Ok(());
         }
+        if self.coprocs.contains_key(&path_or_cmd.clone().unmoor()) {
+            return self.close_coproc(path_or_cmd);
+        }
         path_or_cmd.with_bytes(|bs| self.global.close(bs))
     }
 
-    pub fn get_cmd<'a>(&mut self, cmd: &Str<'a>) -> Result<&mut FileHandle> {
+    /// Close the input of the coprocess `cmd`, waiting until the coprocess has been sent
+    /// end-of-file so that its output can then be read to completion.
+    pub fn close_coproc<'a>(&mut self, cmd: &Str<'a>) -> Result<()> {
+        let key = cmd.clone().unmoor();
+        if let Some(ch) = self.coprocs.get_mut(&key) {
+            ch.close()?;
+            ch.flush()?;
+        }
+        if let Some(coproc) = self.coprocesses.get(&key) {
+            coproc.close_half(/*input=*/ true);
+        }
+        Ok(())
+    }
+
+    /// Close the output of the coprocess `cmd` if it has not been read from.
+    pub fn close_coproc_output<'a>(&mut self, cmd: &Str<'a>) {
+        if let Some(coproc) = self.coprocesses.get(&cmd.clone().unmoor()) {
+            coproc.close_half(/*input=*/ false);
+        }
+    }
+
+    /// Wait for the coprocess `cmd` to exit, once both of its ends are closed. The next use of
+    /// `cmd` starts it again.
+    pub fn wait_coproc<'a>(&mut self, cmd: &Str<'a>) {
+        let key = cmd.clone().unmoor();
+        self.coprocs.remove(&key);
+        if let Some(coproc) = self.coprocesses.remove(&key) {
+            coproc.wait();
+        }
+    }
+
+    /// The coprocess `cmd`, starting it if it is not already running.
+    pub fn coprocess<'a>(&mut self, cmd: &Str<'a>) -> Result<Coprocess> {
+        use hashbrown::hash_map::Entry;
+        match self.coprocesses.entry(cmd.clone().unmoor()) {
+            Entry::Occupied(o) => Ok(o.get().clone()),
+            Entry::Vacant(v) => match cmd.with_bytes(Coprocess::start) {
+                Ok(coproc) => Ok(v.insert(coproc).clone()),
+                Err(e) => err!("failed to start coprocess: {}", e),
+            },
+        }
+    }
+
+    /// The handle for `print | cmd`, or for `print | exec_cmd(argv)` if `argv` is set. Commands
+    /// built by exec_cmd start with a NUL byte, which a shell command cannot contain.
+    pub fn get_cmd<'a>(&mut self, cmd: &Str<'a>, argv: bool) -> Result<&mut FileHandle> {
         use hashbrown::hash_map::Entry;
         // borrowed by with_bytes closure.
         let global = &self.global;
         match self.cmds.entry(cmd.clone().unmoor()) {
             Entry::Occupied(o) => Ok(o.into_mut()),
-            Entry::Vacant(v) => {
-                Ok(v.insert(cmd.with_bytes(|bs| global.get_command(bs)).into_handle()))
+            Entry::Vacant(v) => Ok(v.insert(
+                cmd.with_bytes(|bs| {
+                    global.get_command(if argv { Cmd::Argv(bs) } else { Cmd::Shell(bs) })
+                })
+                .into_handle(),
+            )),
+        }
+    }
+
+    pub fn get_coproc<'a>(&mut self, cmd: &Str<'a>) -> Result<&mut FileHandle> {
+        let key = cmd.clone().unmoor();
+        if !self.coprocs.contains_key(&key) {
+            let coproc = self.coprocess(cmd)?;
+            // Send lines to a coprocess as they are written: programs usually write a request and
+            // then read the response.
+            let handle = build_handle(move |_| coproc.take_stdin(), Buffering::Line);
+            self.coprocs.insert(key.clone(), handle.into_handle());
+        }
+        Ok(self.coprocs.get_mut(&key).unwrap())
+    }
+
+    /// Flush `path_or_cmd`, or all output files and commands if it is empty. Returns false if
+    /// `path_or_cmd` is not an open output file or command.
+    pub fn flush<'a>(&mut self, path_or_cmd: &Str<'a>) -> Result<bool> {
+        if path_or_cmd.with_bytes(|bs| bs.is_empty()) {
+            self.stdout.flush()?;
+            for fh in self
+                .files
+                .values_mut()
+                .chain(self.cmds.values_mut())
+                .chain(self.coprocs.values_mut())
+            {
+                fh.flush()?;
             }
+            return Ok(true);
+        }
+        if path_or_cmd.with_bytes(SpecialFile::from_name).is_some() {
+            self.get_file(Some(path_or_cmd))?.flush()?;
+            return Ok(true);
+        }
+        let key = path_or_cmd.clone().unmoor();
+        let (files, cmds, coprocs) = (&mut self.files, &mut self.cmds, &mut self.coprocs);
+        match files
+            .get_mut(&key)
+            .or_else(|| cmds.get_mut(&key))
+            .or_else(|| coprocs.get_mut(&key))
+        {
+            Some(fh) => fh.flush().map(|()| true),
+            None => Ok(false),
         }
     }
 
@@ -215,6 +437,23 @@ impl Registry {
         match name {
             Some(path) => {
                 use hashbrown::hash_map::Entry;
+                if let Some(special) = path.with_bytes(SpecialFile::from_name) {
+                    if special == SpecialFile::Stdout {
+                        return Ok(&mut self.stdout);
+                    }
+                    if special == SpecialFile::Stderr
+                        && self.stdout.raw.buffering == Buffering::Line
+                    {
+                        // stderr is unbuffered, so we only need to write out any pending lines on
+                        // a terminal to keep output in order.
+                        self.stdout.flush()?;
+                    }
+                    let global = &self.global;
+                    return Ok(self
+                        .files
+                        .entry(Str::from(special.name()))
+                        .or_insert_with(|| global.get_special(special).into_handle()));
+                }
                 // borrowed by with_bytes closure.
                 let global = &self.global;
                 match self.files.entry(path.clone().unmoor()) {
@@ -234,7 +473,12 @@ impl Registry {
 
     pub fn destroy_and_flush_all_files(&mut self) -> Result<()> {
         let mut last_error = Ok(());
-        for (_, mut fh) in self.files.drain().chain(self.cmds.drain()) {
+        for (_, mut fh) in self
+            .files
+            .drain()
+            .chain(self.cmds.drain())
+            .chain(self.coprocs.drain())
+        {
             let res = fh.flush();
             if res.is_err() {
                 last_error = res;
@@ -250,6 +494,8 @@ impl Clone for Registry {
             global: self.global.clone(),
             files: Default::default(),
             cmds: Default::default(),
+            coprocesses: Default::default(),
+            coprocs: Default::default(),
             stdout: self.stdout.raw().into_handle(),
         }
     }
@@ -258,8 +504,9 @@ impl Clone for Registry {
 // We place Root behind a trait so that we can maintain static dispatch at the level of the
 // receiver threads, while still avoiding an extra type parameter all the way up the stack.
 trait Root: 'static + Send + Sync {
-    fn get_command(&self, cmd: &[u8]) -> RawHandle;
+    fn get_command(&self, cmd: Cmd) -> RawHandle;
     fn get_handle(&self, fname: &str) -> RawHandle;
+    fn get_special(&self, file: SpecialFile) -> RawHandle;
     fn get_stdout(&self) -> RawHandle;
     // closes a file or command with name `fname`.
     fn close(&self, fname: &[u8]) -> Result<()>;
@@ -275,10 +522,11 @@ struct RootImpl<F> {
 impl<F: FileFactory> RootImpl<F> {
     fn from_factory(file_factory: F) -> RootImpl<F> {
         let local_factory = file_factory.clone();
-        let stdout_raw = build_handle(
+        let mut stdout_raw = build_handle(
             move |_append| Ok(local_factory.stdout()),
-            /*is_stdout*/ true,
+            Buffering::stdout(),
         );
+        stdout_raw.stdout = true;
         RootImpl {
             handles: Default::default(),
             commands: Default::default(),
@@ -321,17 +569,25 @@ impl<F: FileFactory> Root for RootImpl<F> {
         }
         Ok(())
     }
-    fn get_command(&self, cmd: &[u8]) -> RawHandle {
+    fn get_command(&self, cmd: Cmd) -> RawHandle {
         let mut cmds = self.commands.lock().unwrap();
-        if let Some(h) = cmds.get(cmd) {
+        if let Some(h) = cmds.get(cmd.bytes()) {
             return h.clone();
         }
         let local_factory = self.file_factory.clone();
+        let argv = matches!(cmd, Cmd::Argv(_));
+        let cmd = cmd.bytes();
         let local_name = Box::<[u8]>::from(cmd);
         let global_name = local_name.clone();
         let handle = build_handle(
-            move |_| local_factory.cmd(&*local_name),
-            /*is_stdout=*/ true,
+            move |_| {
+                local_factory.cmd(if argv {
+                    Cmd::Argv(&local_name)
+                } else {
+                    Cmd::Shell(&local_name)
+                })
+            },
+            Buffering::stdout(),
         );
         let _old = cmds.insert(global_name, handle.clone());
         debug_assert!(
@@ -351,11 +607,28 @@ impl<F: FileFactory> Root for RootImpl<F> {
         let global_name = local_name.clone();
         let handle = build_handle(
             move |append| local_factory.build(local_name.as_str(), append),
-            /*is_stdout=*/ false,
+            Buffering::Full,
         );
         handles.insert(global_name, handle.clone());
         handle
     }
+    fn get_special(&self, file: SpecialFile) -> RawHandle {
+        if file == SpecialFile::Stdout {
+            return self.get_stdout();
+        }
+        let mut handles = self.handles.lock().unwrap();
+        let name = file.name();
+        if let Some(h) = handles.get(&name) {
+            return h.clone();
+        }
+        let local_factory = self.file_factory.clone();
+        let handle = match file {
+            SpecialFile::Fd(fd) => build_handle(move |_| local_factory.fd(fd), Buffering::Full),
+            _ => build_handle(move |_| Ok(local_factory.stderr()), Buffering::Unbuffered),
+        };
+        handles.insert(name, handle.clone());
+        handle
+    }
     fn get_stdout(&self) -> RawHandle {
         self.stdout_raw.clone()
     }
@@ -445,13 +718,19 @@ impl FileHandle {
     }
 
     pub fn write_all<'a>(&mut self, ss: &[&Str<'a>], spec: FileSpec) -> Result<()> {
+        if self.raw.stdout {
+            if let Some(res) = capture_chunk_output(&self.raw, ss) {
+                return res;
+            }
+        }
         let cur_len = self.cur_batch.data.len();
         let mut added_bytes = 0;
         let mut last_line = None;
+        let line_buffer = self.raw.buffering == Buffering::Line;
         for s in ss.iter() {
             let bs = unsafe { &*s.get_bytes() };
             self.cur_batch.extend(&*bs, spec);
-            if self.raw.line_buffer {
+            if line_buffer {
                 if let Some(ix) = memchr::memchr(b'\n', bs) {
                     // +1 to include the newline
                     last_line = Some(cur_len + added_bytes + ix + 1);
@@ -459,7 +738,10 @@ impl FileHandle {
             }
             added_bytes += bs.len();
         }
-        if (self.raw.line_buffer && last_line.is_some()) || (added_bytes + cur_len > BUFFER_SIZE) {
+        if self.raw.buffering == Buffering::Unbuffered {
+            return self.flush();
+        }
+        if (line_buffer && last_line.is_some()) || (added_bytes + cur_len > BUFFER_SIZE) {
             self.clear_batch(last_line)?;
         }
         Ok(())
@@ -468,6 +750,12 @@ impl FileHandle {
         self.write_all(&[s], spec)
     }
 
+    // Send `bs` to the writer thread right away, bypassing any chunk buffer.
+    fn write_bytes(&mut self, bs: &[u8]) -> Result<()> {
+        self.cur_batch.extend(bs, FileSpec::default());
+        self.clear_batch(None)
+    }
+
     pub fn flush(&mut self) -> Result<()> {
         self.clear_batch(None)?;
         let (n, req) = Request::flush();
@@ -648,7 +936,10 @@ impl Drop for WriteGuard {
 struct RawHandle {
     error: Arc<Mutex<Option<CompileError>>>,
     sender: Sender<Request>,
-    line_buffer: bool,
+    buffering: Buffering,
+    // Set for the process's standard output, the only file whose output is reordered by a
+    // Sequencer.
+    stdout: bool,
 }
 
 impl RawHandle {
@@ -662,6 +953,146 @@ impl RawHandle {
     }
 }
 
+/// Sequencer writes the standard output of each chunk of input in the order the chunks were read,
+/// regardless of the order in which worker threads finish them.
+///
+/// Workers call `start_chunk` when they begin a chunk and `finish_chunk` once they are done with
+/// it. In between, writes to standard output from that thread go to a buffer for the chunk. The
+/// output of chunks that finish early is held until every earlier chunk has been written; once
+/// that output reaches MAX_PENDING_BYTES, workers block rather than buffer more. A chunk whose
+/// own output reaches MAX_CHUNK_BYTES is written as it goes once every earlier chunk has been
+/// written. The worker holding the earliest unfinished chunk never blocks, so the workers always
+/// make progress.
+#[derive(Default)]
+pub struct Sequencer {
+    state: Mutex<SequencerState>,
+    written: Condvar,
+}
+
+#[derive(Default)]
+struct SequencerState {
+    // The sequence number of the next chunk to write.
+    next: u64,
+    pending: BTreeMap<u64, Vec<u8>>,
+    pending_bytes: usize,
+    // A handle on standard output, set by the first chunk that writes anything.
+    out: Option<FileHandle>,
+}
+
+impl SequencerState {
+    fn emit(&mut self, data: &[u8]) -> Result<()> {
+        self.next += 1;
+        match &mut self.out {
+            Some(out) if !data.is_empty() => out.write_bytes(data),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Sequencer {
+    fn submit(&self, seq: u64, data: Vec<u8>, stdout: Option<RawHandle>) -> Result<()> {
+        let mut guard = self.state.lock().unwrap();
+        while seq != guard.next && guard.pending_bytes >= MAX_PENDING_BYTES {
+            guard = self.written.wait(guard).unwrap();
+        }
+        let state = &mut *guard;
+        if state.out.is_none() {
+            state.out = stdout.map(RawHandle::into_handle);
+        }
+        if seq != state.next {
+            state.pending_bytes += data.len();
+            state.pending.insert(seq, data);
+            return Ok(());
+        }
+        // Keep going after an error so that other workers are not left waiting on this chunk.
+        let mut res = state.emit(&data[..]);
+        while let Some(data) = state.pending.remove(&state.next) {
+            state.pending_bytes -= data.len();
+            let emitted = state.emit(&data[..]);
+            res = res.and(emitted);
+        }
+        self.written.notify_all();
+        res
+    }
+
+    // Write part of the output for chunk `seq`, waiting until it is the next chunk to write.
+    fn write_partial(&self, seq: u64, data: &[u8], stdout: Option<RawHandle>) -> Result<()> {
+        let mut guard = self.state.lock().unwrap();
+        while seq != guard.next {
+            guard = self.written.wait(guard).unwrap();
+        }
+        let state = &mut *guard;
+        if state.out.is_none() {
+            state.out = stdout.map(RawHandle::into_handle);
+        }
+        match &mut state.out {
+            Some(out) => out.write_bytes(data),
+            None => Ok(()),
+        }
+    }
+}
+
+struct ChunkOutput {
+    seq: u64,
+    data: Vec<u8>,
+    stdout: Option<RawHandle>,
+    sequencer: Arc<Sequencer>,
+}
+
+thread_local! {
+    // The chunk this thread is working on, if it is a worker in ordered record-parallel mode.
+    static CHUNK_OUTPUT: RefCell<Option<ChunkOutput>> = const { RefCell::new(None) };
+}
+
+/// Begin buffering this thread's standard output for chunk `seq`, finishing the previous chunk
+/// if there is one.
+pub(crate) fn start_chunk(sequencer: &Arc<Sequencer>, seq: u64) -> Result<()> {
+    let res = finish_chunk();
+    CHUNK_OUTPUT.with(|cur| {
+        *cur.borrow_mut() = Some(ChunkOutput {
+            seq,
+            data: Vec::new(),
+            stdout: None,
+            sequencer: sequencer.clone(),
+        })
+    });
+    res
+}
+
+/// Hand the output of this thread's current chunk, if any, to its Sequencer.
+///
+/// Workers must call this when they stop processing input, even on an error, so that threads
+/// writing later chunks are not left waiting.
+pub(crate) fn finish_chunk() -> Result<()> {
+    match CHUNK_OUTPUT.with(|cur| cur.borrow_mut().take()) {
+        Some(out) => out.sequencer.submit(out.seq, out.data, out.stdout),
+        None => Ok(()),
+    }
+}
+
+// Append `ss` to the current chunk's buffer, returning None if this thread has no chunk.
+fn capture_chunk_output(stdout: &RawHandle, ss: &[&Str]) -> Option<Result<()>> {
+    CHUNK_OUTPUT.with(|cur| match &mut *cur.borrow_mut() {
+        Some(out) => {
+            if out.stdout.is_none() {
+                out.stdout = Some(stdout.clone());
+            }
+            for s in ss.iter() {
+                out.data.extend_from_slice(unsafe { &*s.get_bytes() });
+            }
+            if out.data.len() < MAX_CHUNK_BYTES {
+                return Some(Ok(()));
+            }
+            let res = out
+                .sequencer
+                .write_partial(out.seq, &out.data[..], out.stdout.clone());
+            out.data.clear();
+            Some(res)
+        }
+        None => None,
+    })
+}
+
 // Implementation of the "server" thread issuing the writes.
 
 #[derive(Default)]
@@ -830,6 +1261,7 @@ pub mod testing {
     #[derive(Clone, Default)]
     pub struct FakeFs {
         pub stdout: FakeFile,
+        pub stderr: FakeFile,
         named: Arc<Mutex<HashMap<String, FakeFile>>>,
     }
 
@@ -855,6 +1287,14 @@ pub mod testing {
         fn stdout(&self) -> Self::Stdout {
             self.stdout.clone()
         }
+        fn stderr(&self) -> Box<dyn io::Write> {
+            Box::new(self.stderr.clone())
+        }
+        fn fd(&self, fd: i32) -> io::Result<Box<dyn io::Write>> {
+            Ok(Box::new(
+                self.build(&format!("/dev/fd/{}", fd), FileSpec::Append)?,
+            ))
+        }
     }
 
     #[derive(Default)]
@@ -1031,4 +1471,128 @@ mod tests {
         assert_eq!(fs.get_handle("/fake/A").unwrap().read_data(), expected_a);
         assert_eq!(fs.get_handle("/fake/B").unwrap().read_data(), expected_b);
     }
+
+    #[test]
+    fn ordered_chunks() {
+        const N_THREADS: usize = 4;
+        const N_CHUNKS: u64 = 200;
+        let fs = FakeFs::default();
+        let mut threads = Vec::with_capacity(N_THREADS);
+        {
+            let reg = Registry::from_factory(fs.clone());
+            let sequencer = Arc::new(Sequencer::default());
+            let next_chunk = Arc::new(AtomicUsize::new(0));
+            for _ in 0..N_THREADS {
+                let mut treg = reg.clone();
+                let sequencer = sequencer.clone();
+                let next_chunk = next_chunk.clone();
+                threads.push(std::thread::spawn(move || loop {
+                    let seq = next_chunk.fetch_add(1, Ordering::SeqCst) as u64;
+                    if seq >= N_CHUNKS {
+                        finish_chunk().unwrap();
+                        break;
+                    }
+                    start_chunk(&sequencer, seq).unwrap();
+                    // Finish chunks out of order, and leave some of them empty.
+                    std::thread::sleep(std::time::Duration::from_micros((seq % 7) * 100));
+                    if !seq.is_multiple_of(3) {
+                        let handle = treg.get_handle(None, FileSpec::default()).unwrap();
+                        handle
+                            .write(&Str::from(format!("{}\n", seq)), FileSpec::default())
+                            .unwrap();
+                    }
+                }));
+            }
+        }
+        for t in threads.into_iter() {
+            t.join().unwrap();
+        }
+        let expected: String = (0..N_CHUNKS)
+            .filter(|seq| seq % 3 != 0)
+            .map(|seq| format!("{}\n", seq))
+            .collect();
+        assert_eq!(fs.stdout.read_data(), expected.as_bytes());
+    }
+
+    #[test]
+    fn ordered_chunks_large_output() {
+        const LINE: &str = "a line of output from the second chunk\n";
+        let n_lines = 2 * MAX_CHUNK_BYTES / LINE.len();
+        let fs = FakeFs::default();
+        let mut threads = Vec::with_capacity(2);
+        {
+            let reg = Registry::from_factory(fs.clone());
+            let sequencer = Arc::new(Sequencer::default());
+            for seq in 0..2u64 {
+                let mut treg = reg.clone();
+                let sequencer = sequencer.clone();
+                threads.push(std::thread::spawn(move || {
+                    start_chunk(&sequencer, seq).unwrap();
+                    let handle = treg.get_handle(None, FileSpec::default()).unwrap();
+                    if seq == 0 {
+                        // Give the second chunk time to fill its buffer.
+                        std::thread::sleep(std::time::Duration::from_millis(50));
+                        handle
+                            .write(&Str::from("first\n"), FileSpec::default())
+                            .unwrap();
+                    } else {
+                        let line = Str::from(LINE);
+                        for _ in 0..n_lines {
+                            handle.write(&line, FileSpec::default()).unwrap();
+                        }
+                    }
+                    finish_chunk().unwrap();
+                }));
+            }
+        }
+        for t in threads.into_iter() {
+            t.join().unwrap();
+        }
+        let mut expected = String::from("first\n");
+        expected.push_str(&LINE.repeat(n_lines));
+        assert!(fs.stdout.read_data() == expected.as_bytes());
+    }
+
+    #[test]
+    fn special_files() {
+        let out = Str::from("out ");
+        let err = Str::from("err\n");
+        let fd = Str::from("fd\n");
+        let fs = FakeFs::default();
+        let mut reg = Registry::from_factory(fs.clone());
+        for name in &["/dev/stdout", "/dev/fd/1"] {
+            reg.get_handle(None, FileSpec::default())
+                .unwrap()
+                .write(&out, FileSpec::Append)
+                .unwrap();
+            reg.get_handle(Some(&Str::from(*name)), FileSpec::Trunc)
+                .unwrap()
+                .write(&Str::from(*name), FileSpec::Trunc)
+                .unwrap();
+        }
+        for name in &["/dev/stderr", "/dev/fd/2"] {
+            // stderr is unbuffered, so there is no need to flush.
+            reg.get_handle(Some(&Str::from(*name)), FileSpec::Append)
+                .unwrap()
+                .write(&err, FileSpec::Append)
+                .unwrap();
+            assert_eq!(
+                fs.stderr.read_data(),
+                b"err\n".repeat(1 + (*name == "/dev/fd/2") as usize)
+            );
+        }
+        reg.get_handle(Some(&Str::from("/dev/fd/5")), FileSpec::Trunc)
+            .unwrap()
+            .write(&fd, FileSpec::Trunc)
+            .unwrap();
+        assert!(reg.flush(&Str::from("")).unwrap());
+        assert!(!reg.flush(&Str::from("/not/open")).unwrap());
+        assert_eq!(
+            &fs.stdout.read_data()[..],
+            "out /dev/stdoutout /dev/fd/1".as_bytes()
+        );
+        assert_eq!(fs.get_handle("/dev/fd/5").unwrap().read_data(), b"fd\n");
+        assert!(fs.get_handle("/dev/stdout").is_none());
+        assert!(fs.get_handle("/dev/stderr").is_none());
+    }
 }
@@ -198,6 +198,32 @@
 //! function and build a subgraph corresponding to it. This is a bit wasteful, as it leads to
 //! duplicate functions for a single callsite; we can probably improve something on that front.
 //!
+//! ## Strings That May Be Numbers
+//!
+//! AWK values read from input, like fields, are "strnums": they compare numerically when they look
+//! like numbers and as strings otherwise. Uninitialized variables behave the same way: they print
+//! as the empty string, but compare equal to 0. The `StrNum` type covers both cases. It is stored
+//! as a string, but comparisons between two strnums use [crate::runtime::Str::strnum_cmp].
+//!
+//! For uninitialized values, we only use `StrNum` where static types cannot tell a null value
+//! apart from a number. For a local variable that is assigned on only some paths to a join point,
+//! as in
+//!
+//! ```text
+//! if (0) { x = 6 }; print x
+//! ```
+//!
+//! the Phi node for `x` joins its inputs with a `NullJoin` rule, which turns `Int` or `Float` into
+//! `StrNum`, so `x` prints an empty line. Variables that are always initialized, and loop-carried
+//! arithmetic like `sum += $1`, keep their numeric types. So do globals shared between functions,
+//! which are not in SSA form: we cannot tell whether they are assigned before they are read, so
+//! they print 0 where Awk prints an empty line.
+//!
+//! Whether a strnum looks like a number is decided at runtime: comparisons involving strnums are
+//! numeric if the strnum has a numeric value and string comparisons otherwise (see
+//! [crate::runtime::Str::strnum]). An uninitialized value is numeric (0), while an empty field is
+//! not.
+//!
 //! # Solving Constraints
 //!
 //! Once we have this graph, can push values of type [`State`] around according to the constraints
@@ -291,6 +317,8 @@ pub(crate) enum BaseTy {
     Int,
     Float,
     Str,
+    // A string that may hold a number, see "Strings That May Be Numbers" above.
+    StrNum,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
@@ -381,6 +409,11 @@ impl Constraint<State> {
                 key: None,
                 val: None,
             })),
+            // Keys read from input are stored as plain strings.
+            Constraint::KeyIn(Some(TVar::Scalar(Some(BaseTy::StrNum)))) => Ok(Some(TVar::Map {
+                key: Some(BaseTy::Str),
+                val: None,
+            })),
             Constraint::KeyIn(Some(TVar::Scalar(k))) => Ok(Some(TVar::Map {
                 key: k.clone(),
                 val: None,
@@ -446,6 +479,9 @@ struct Edge {
 #[derive(Copy, Clone)]
 enum Rule {
     Var,
+    // Like Var, but for Phi nodes that may observe an uninitialized value (see `null_joins`): a
+    // number joined with an uninitialized value is a strnum.
+    NullJoin,
     Const(State),
     AlwaysNotify,
 }
@@ -467,6 +503,7 @@ impl Rule {
             match (b1, b2) {
                 (Null, x) | (x, Null) => x,
                 (Str, _) | (_, Str) => Str,
+                (StrNum, _) | (_, StrNum) => StrNum,
                 (Float, _) | (_, Float) => Float,
                 (Int, Int) => Int,
             }
@@ -501,6 +538,8 @@ impl Rule {
                                 | (_, Float)
                                 | (Str, _)
                                 | (_, Str)
+                                | (StrNum, _)
+                                | (_, StrNum)
                                 | (Null, _)
                                 | (_, Null) => Str,
                                 (Int, _) => Int,
@@ -526,6 +565,11 @@ impl Rule {
                 },
             };
         }
+        if let Rule::NullJoin = self {
+            if let Some(TVar::Scalar(Some(BaseTy::Int | BaseTy::Float))) = cur {
+                cur = Some(TVar::Scalar(Some(BaseTy::StrNum)));
+            }
+        }
         Ok((prev != &cur, cur))
     }
 }
@@ -547,14 +591,14 @@ fn flatten(tv: TVar<BaseTy>) -> Result<compile::Ty> {
         match b {
             Int => Ty::Int,
             Float => Ty::Float,
-            Str => Ty::Str,
+            Str | StrNum => Ty::Str,
             Null => Ty::Null,
         }
     }
     match tv {
         Scalar(b) => Ok(flatten_base(b)),
         Iter(Int) => Ok(Ty::IterInt),
-        Iter(Null) | Iter(Str) => Ok(Ty::IterStr),
+        Iter(Null) | Iter(Str) | Iter(StrNum) => Ok(Ty::IterStr),
         Iter(x) => err!("Iterator over an unsupported type: {:?}", x),
         Map { key, val } => {
             let f = |ty| {
@@ -661,6 +705,8 @@ struct View<'a, 'b, 'c> {
     tc: &'a mut TypeContext<'b, 'c>,
     frame_id: NumTy, // which function are we in?
     frame_args: SmallVec<State>,
+    // Phi nodes that may observe an uninitialized value; see `null_joins`.
+    null_joins: HashSet<Ident>,
 }
 
 impl<'a, 'b, 'c> Deref for View<'a, 'b, 'c> {
@@ -692,6 +738,8 @@ pub(crate) struct TypeInfo {
     pub var_tys: HashMap<(Ident, NumTy, SmallVec<compile::Ty>), compile::Ty>,
     // Map a particular function invocation to a return type.
     pub func_tys: HashMap<(NumTy, SmallVec<compile::Ty>), compile::Ty>,
+    // The subset of the keys in `var_tys` that hold strnums. These all have type Str.
+    pub strnums: HashSet<(Ident, NumTy, SmallVec<compile::Ty>)>,
 }
 
 impl<'b, 'c> TypeContext<'b, 'c> {
@@ -724,6 +772,7 @@ impl<'b, 'c> TypeContext<'b, 'c> {
         tc.solve()?;
         let mut var_tys = HashMap::new();
         let mut func_tys = HashMap::new();
+        let mut strnums = HashMap::new();
         for (Args { id, args, .. }, ix) in tc.funcs.iter() {
             let mut flat_args = SmallVec::new();
             for a in args.iter().cloned() {
@@ -738,11 +787,15 @@ impl<'b, 'c> TypeContext<'b, 'c> {
             for a in args.iter().cloned() {
                 flat_args.push(flatten(concrete(a))?);
             }
-            let v = flatten(concrete(*tc.nw.read(*ix)))?;
+            let conc = concrete(*tc.nw.read(*ix));
+            let v = flatten(conc)?;
 
             // We won't use the function id if id.global, so setting it to 0 should be fine.
             // TODO clean up some of this to make it less misleading
-            match var_tys.entry((*id, func_id.unwrap_or(0), flat_args)) {
+            let key = (*id, func_id.unwrap_or(0), flat_args);
+            // Only treat a variable as a strnum if every copy of it is one.
+            *strnums.entry(key.clone()).or_insert(true) &= conc == TVar::Scalar(BaseTy::StrNum);
+            match var_tys.entry(key) {
                 Entry::Vacant(vac) => {
                     vac.insert(v);
                 }
@@ -781,7 +834,15 @@ impl<'b, 'c> TypeContext<'b, 'c> {
                 }
             }
         }
-        Ok(TypeInfo { var_tys, func_tys })
+        let strnums = strnums
+            .into_iter()
+            .filter_map(|(k, is_strnum)| if is_strnum { Some(k) } else { None })
+            .collect();
+        Ok(TypeInfo {
+            var_tys,
+            func_tys,
+            strnums,
+        })
     }
     fn solve(&mut self) -> Result<()> {
         let mut dep_indices: SmallVec<NodeIx> = Default::default();
@@ -856,6 +917,10 @@ impl<'b, 'c> TypeContext<'b, 'c> {
             }
         }
     }
+    /// A fresh node with no constraints, for builtins that relate their arguments indirectly.
+    pub(crate) fn var(&mut self) -> NodeIx {
+        self.nw.add_rule(Rule::Var)
+    }
     pub(crate) fn constrain_as_map(&mut self, ix: NodeIx) {
         // To be completely explicit, this function assigns a unique `Flows` constaint into a map
         // from the constant node that "just specifies the node is a Map".
@@ -917,10 +982,12 @@ impl<'b, 'c> TypeContext<'b, 'c> {
         let res = self.nw.add_rule(Rule::Var);
         self.nw.add_dep(res, base_node, Constraint::Flows(()));
         self.funcs.insert(key.clone(), res);
+        let null_joins = null_joins(cfg, args, self.local_globals);
         let mut view = View {
             tc: self,
             frame_id: *ident,
             frame_args: key.args.clone(),
+            null_joins,
         };
 
         // Apply the arguments appropriately:
@@ -938,6 +1005,90 @@ impl<'b, 'c> TypeContext<'b, 'c> {
     }
 }
 
+/// Find the Phi nodes in a function that can observe an uninitialized local variable.
+///
+/// These are the joins where at least one predecessor is a local that is never assigned along
+/// that path (subscript 0, and not a function argument). We skip Phis whose values are never read
+/// and Phis that sit on a cycle of arithmetic updates like `n++`: treating the latter as strnums
+/// would put a string conversion on every loop iteration.
+fn null_joins<'a>(
+    cfg: &cfg::Cfg<'a>,
+    args: &[cfg::Arg<&'a str>],
+    local_globals: &HashSet<NumTy>,
+) -> HashSet<Ident> {
+    let mut defs = HashMap::new();
+    let mut reads = Vec::new();
+    for bb in cfg.raw_nodes() {
+        for stmt in bb.weight.q.iter() {
+            if let cfg::PrimStmt::AsgnVar(id, e) = stmt {
+                if !id.is_global(local_globals) {
+                    defs.insert(*id, e);
+                }
+            }
+            stmt.uses(|id| reads.push(*id));
+        }
+    }
+    for e in cfg.raw_edges() {
+        if let Some(v) = &e.weight.0 {
+            v.uses(|id| reads.push(*id));
+        }
+    }
+    let phi_preds = |id: &Ident| match defs.get(id) {
+        Some(cfg::PrimExpr::Phi(preds)) => Some(preds),
+        _ => None,
+    };
+
+    // A Phi is observable if it is read directly, or if it flows into an observable Phi.
+    let mut observable = HashSet::new();
+    while let Some(id) = reads.pop() {
+        if let Some(preds) = phi_preds(&id) {
+            if observable.insert(id) {
+                reads.extend(preds.iter().map(|(_, p)| *p));
+            }
+        }
+    }
+
+    let is_uninit = |id: &Ident| {
+        id.sub == 0
+            && !cfg::is_unused(*id)
+            && !id.is_global(local_globals)
+            && !args.iter().any(|a| a.id.low == id.low)
+    };
+    // Does `phi` depend on itself through a non-Phi assignment?
+    let arith_cycle = |phi: &Ident| {
+        let mut visited = HashSet::new();
+        let mut stack: Vec<(Ident, bool)> = match phi_preds(phi) {
+            Some(preds) => preds.iter().map(|(_, p)| (*p, false)).collect(),
+            None => return false,
+        };
+        while let Some((id, through_arith)) = stack.pop() {
+            if id == *phi {
+                if through_arith {
+                    return true;
+                }
+                continue;
+            }
+            if !visited.insert((id, through_arith)) {
+                continue;
+            }
+            match defs.get(&id) {
+                Some(cfg::PrimExpr::Phi(preds)) => {
+                    stack.extend(preds.iter().map(|(_, p)| (*p, through_arith)))
+                }
+                Some(e) => e.uses(|u| stack.push((*u, true))),
+                None => {}
+            }
+        }
+        false
+    };
+    observable
+        .iter()
+        .filter(|id| phi_preds(id).is_some_and(|ps| ps.iter().any(|(_, p)| is_uninit(p))))
+        .filter(|id| !arith_cycle(id))
+        .cloned()
+        .collect()
+}
+
 impl<'b, 'c, 'd> View<'b, 'c, 'd> {
     fn add_builtin_call(&mut self, f: builtins::Function, args: SmallVec<NodeIx>, to: NodeIx) {
         // We want to give feedback the opportunity to influence function arguments based on the
@@ -989,7 +1140,13 @@ impl<'b, 'c, 'd> View<'b, 'c, 'd> {
             }
             AsgnVar(v, e) => {
                 let v_ix = self.ident_node(v);
-                self.constrain_expr(e, v_ix);
+                if self.null_joins.contains(v) {
+                    let join = self.nw.add_rule(Rule::NullJoin);
+                    self.constrain_expr(e, join);
+                    self.nw.add_dep(join, v_ix, Constraint::Flows(()));
+                } else {
+                    self.constrain_expr(e, v_ix);
+                }
             }
             Return(v) => {
                 let v_ix = self.val_node(v);
@@ -72,6 +72,39 @@ BEGIN {
     }
 }
 
+#[test]
+fn posix_regex_syntax() {
+    let prog: String = r#"BEGIN { re = "^a{2}$"; }
+$0 ~ /^[[:alpha:]]+$/ { print "alpha", $0; }
+$0 ~ re { print "dynamic", $0; }
+/{x}/ { print "brace", $0; }"#
+        .into();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(prog.clone())
+            .write_stdin("abc\na1\naa\n{x}\n")
+            .assert()
+            .stdout(String::from("alpha abc\nalpha aa\ndynamic aa\nbrace {x}\n"));
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("--rust-regex"))
+            .arg(String::from(r#"/^\pL+$/"#))
+            .write_stdin("abc\na1\n")
+            .assert()
+            .stdout(String::from("abc\n"));
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(r#"/\<a\>/"#))
+            .write_stdin("a\n")
+            .assert()
+            .failure();
+    }
+}
+
 #[test]
 fn simple_fi() {
     let input = r#"Item,Count
@@ -100,6 +133,312 @@ fn simple_fi() {
     }
 }
 
+#[test]
+fn input_format_dialects() {
+    let cases: &[(&str, &str, &str)] = &[
+        ("csv;delim=;", "a;\"b;c\";d\n", "3 b;c\n"),
+        ("csv;delim=|,quote=\\'", "x|'it''s'\n", "2 it's\n"),
+        ("csv", "c,\"a\\\"b\"\n", "2 a\"b\n"),
+        ("tsv", "c\ta\\tb\n", "2 a\tb\n"),
+        // Backslashes are read literally once escapes are turned off.
+        ("csv;escape=none", "y,\"C:\\dir\\\"\n", "2 C:\\dir\\\n"),
+        ("tsv;comment=#", "#x\ty\nb\ta\n# z\n#\nc\n", "2 a\n1 \n"),
+    ];
+    let prog: String = r#"{ print NF, $2; }"#.into();
+    for backend_arg in BACKEND_ARGS {
+        for (ifmt, input, expected) in cases {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(format!("-i{}", ifmt))
+                .arg(prog.clone())
+                .write_stdin(*input)
+                .assert()
+                .stdout(*expected);
+        }
+        // Comment lines do not count as records.
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-icsv;comment=#"))
+            .arg(String::from("END { print NR; }"))
+            .write_stdin("#a\nb\n#c\nd\n")
+            .assert()
+            .stdout("2\n");
+        for bad in &["xsv", "csv;delim=ab", "csv;sep=;", "csv;comment=,"] {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(format!("-i{}", bad))
+                .arg(prog.clone())
+                .write_stdin("a\n")
+                .assert()
+                .failure();
+        }
+    }
+}
+
+#[test]
+fn simple_fi_jsonl() {
+    let input = r#"{"Item": "carrots", "Count": 2}
+{"Count": 3, "Item": "potato chips"}
+{"Item": "custard", "Count": 1}"#;
+    let expected = "6 3\n";
+
+    let tmpdir = tempdir().unwrap();
+    let data_fname = tmpdir.path().join("numbers");
+    {
+        let mut file = File::create(data_fname.clone()).unwrap();
+        file.write_all(input.as_bytes()).unwrap();
+    }
+    let prog: String = r#"{n+=$FI["Count"]} END { print n, NR; }"#.into();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-ijsonl"))
+            .arg(String::from("-H"))
+            .arg(prog.clone())
+            .arg(fname_to_string(&data_fname))
+            .assert()
+            .stdout(expected);
+    }
+}
+
+#[test]
+fn fi_jsonl_late_keys() {
+    // "q" first appears in the second record, and "r" in the third; FI covers both, whether the
+    // key is known statically or only at runtime.
+    let input = "{\"z\":1,\"a\":2}\n{\"a\":3,\"q\":4}\n{\"r\":5,\"q\":6}\n";
+    for (prog, expected) in [
+        (r#"NR>=2{print $FI["q"]}"#, "4\n6\n"),
+        (r#"BEGIN{k="r"} NR==3{print $FI[k], $FI["a"]}"#, "5 \n"),
+    ] {
+        for backend_arg in BACKEND_ARGS {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(String::from("-ijsonl"))
+                .arg(String::from("-H"))
+                .arg(String::from(prog))
+                .write_stdin(input)
+                .assert()
+                .stdout(String::from(expected));
+        }
+    }
+    // Without -H, FI is never populated.
+    Command::cargo_bin("frawk")
+        .unwrap()
+        .arg(String::from("-ijsonl"))
+        .arg(String::from(r#"{print $FI["q"]}"#))
+        .write_stdin(input)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn json_output_with_header() {
+    let input = r#"Item,Count
+carrots,2
+"potato ""chips""",3"#;
+    let expected = r#"{"Item":"carrots","2":4,"3":"x"}
+{"Item":"potato \"chips\"","2":6,"3":"x"}
+"#;
+
+    let tmpdir = tempdir().unwrap();
+    let data_fname = tmpdir.path().join("numbers");
+    {
+        let mut file = File::create(data_fname.clone()).unwrap();
+        file.write_all(input.as_bytes()).unwrap();
+    }
+    let prog: String = r#"{ print $1, $2*2, "x"; }"#.into();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-icsv"))
+            .arg(String::from("-ojson"))
+            .arg(String::from("-H"))
+            .arg(prog.clone())
+            .arg(fname_to_string(&data_fname))
+            .assert()
+            .stdout(expected);
+    }
+}
+
+#[test]
+fn json_output_column_keys() {
+    let input = "Item,Count\ncarrots,2\nbeets,3,extra\n";
+    let tmpdir = tempdir().unwrap();
+    let data_fname = tmpdir.path().join("numbers");
+    {
+        let mut file = File::create(data_fname.clone()).unwrap();
+        file.write_all(input.as_bytes()).unwrap();
+    }
+    for (prog, expected) in &[
+        (
+            r#"{ i = 1; print $2, $i; }"#,
+            "{\"Count\":\"2\",\"Item\":\"carrots\"}\n{\"Count\":\"3\",\"Item\":\"beets\"}\n",
+        ),
+        (
+            r#"{ print "x", $1; }"#,
+            "{\"1\":\"x\",\"Item\":\"carrots\"}\n{\"1\":\"x\",\"Item\":\"beets\"}\n",
+        ),
+        (
+            r#"{ print; }"#,
+            "{\"Item\":\"carrots\",\"Count\":\"2\"}\n{\"Item\":\"beets\",\"Count\":\"3\",\"3\":\"extra\"}\n",
+        ),
+    ] {
+        for backend_arg in BACKEND_ARGS {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(String::from("-icsv"))
+                .arg(String::from("-ojson"))
+                .arg(String::from("-H"))
+                .arg(String::from(*prog))
+                .arg(fname_to_string(&data_fname))
+                .assert()
+                .stdout(String::from(*expected));
+        }
+    }
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-icsv"))
+            .arg(String::from("-ojson"))
+            .arg(String::from("-H"))
+            .arg(String::from("{ print $1, $1; }"))
+            .arg(fname_to_string(&data_fname))
+            .assert()
+            .failure();
+    }
+}
+
+#[test]
+fn json_output_repeated_keys() {
+    // Keys that only repeat once the header is known: $3 is past the end of the header and keyed
+    // "3", like the third value; and a header that names two columns "h1", which is reported once,
+    // when the header is read, whatever the program prints.
+    for (input, prog, msg) in &[
+        (
+            "h1,h2\n1,2,3\n",
+            r#"{ print $3, "x", "y"; }"#,
+            r#"print would repeat the JSON key "3""#,
+        ),
+        (
+            "h1,2\n1,2\n",
+            r#"{ print $2, "x"; }"#,
+            r#"print would repeat the JSON key "2""#,
+        ),
+        (
+            "h1,h1,h2\n1,2,3\n4,5,6\n",
+            r#"{ print; }"#,
+            "the header names more than one column h1",
+        ),
+        (
+            "h1,h1,h2\n1,2,3\n4,5,6\n",
+            r#"{ print $3; }"#,
+            "the header names more than one column h1",
+        ),
+    ] {
+        for backend_arg in BACKEND_ARGS {
+            let output = Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(String::from("-icsv"))
+                .arg(String::from("-ojson"))
+                .arg(String::from("-H"))
+                .arg(String::from(*prog))
+                .write_stdin(*input)
+                .assert()
+                .failure()
+                .stdout("")
+                .get_output()
+                .clone();
+            let stderr = String::from_utf8(output.stderr).unwrap();
+            assert_eq!(stderr.matches(msg).count(), 1, "stderr: {}", stderr);
+        }
+    }
+}
+
+#[test]
+fn compressed_inputs() {
+    let tmpdir = tempdir().unwrap();
+    let gz_fname = tmpdir.path().join("numbers.gz");
+    let zst_fname = tmpdir.path().join("more_numbers");
+    {
+        let file = File::create(gz_fname.clone()).unwrap();
+        let mut enc = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        enc.write_all(b"1\n2\n3\n").unwrap();
+        enc.finish().unwrap();
+        let file = File::create(zst_fname.clone()).unwrap();
+        zstd::stream::copy_encode(&b"4\n5\n"[..], file, 0).unwrap();
+    }
+    let prog: String = r#"{ n[FILENAME] += $1 } END { for (f in n) print f, n[f]; }"#.into();
+    let expected = format!(
+        "{} 6\n{} 9\n",
+        fname_to_string(&gz_fname),
+        fname_to_string(&zst_fname)
+    );
+    for backend_arg in BACKEND_ARGS {
+        for strategy in &["-pf", "-pr"] {
+            let output = Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(String::from(*strategy))
+                .arg(prog.clone())
+                .arg(fname_to_string(&gz_fname))
+                .arg(fname_to_string(&zst_fname))
+                .output()
+                .unwrap()
+                .stdout;
+            unordered_output_equals(&output[..], expected.as_bytes());
+        }
+    }
+}
+
+#[test]
+fn truncated_and_corrupt_compressed_inputs() {
+    let text: String = (1..=20000).map(|i| format!("{}\n", i)).collect();
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(text.as_bytes()).unwrap();
+    let gz = gz.finish().unwrap();
+    let zst = zstd::encode_all(text.as_bytes(), 0).unwrap();
+    let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+    bz.write_all(text.as_bytes()).unwrap();
+    let bz = bz.finish().unwrap();
+
+    let tmpdir = tempdir().unwrap();
+    let inputs: Vec<(&str, &[u8])> = vec![
+        ("truncated.gz", &gz[..gz.len() / 2]),
+        ("truncated.zst", &zst[..zst.len() / 2]),
+        ("truncated.bz2", &bz[..bz.len() / 2]),
+        ("corrupt.gz", b"\x1f\x8bjunk"),
+    ];
+    for (name, contents) in inputs {
+        let fname = tmpdir.path().join(name);
+        File::create(fname.clone())
+            .unwrap()
+            .write_all(contents)
+            .unwrap();
+        for backend_arg in BACKEND_ARGS {
+            for strategy in &[None, Some("-pf"), Some("-pr")] {
+                let mut cmd = Command::cargo_bin("frawk").unwrap();
+                cmd.arg(String::from(*backend_arg));
+                if let Some(strategy) = strategy {
+                    cmd.arg(String::from(*strategy));
+                }
+                cmd.arg(String::from("{ n++ } END { print n; }"))
+                    .arg(fname_to_string(&fname))
+                    .assert()
+                    .failure();
+            }
+        }
+    }
+}
+
 #[test]
 fn file_and_data_arg() {
     let input = r#"Hi"#;
@@ -171,6 +510,35 @@ file 2 3
     }
 }
 
+#[test]
+fn empty_input_files() {
+    let expected = "1 a\n2 b\n1 c\n";
+
+    let tmpdir = tempdir().unwrap();
+    let first = tmpdir.path().join("first");
+    let empty = tmpdir.path().join("empty");
+    let second = tmpdir.path().join("second");
+    for (fname, data) in &[(&first, "a\nb\n"), (&empty, ""), (&second, "c\n")] {
+        let mut file = File::create(fname).unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+    for backend_arg in BACKEND_ARGS {
+        for input_format in &["", "-icsv", "-itsv"] {
+            let mut cmd = Command::cargo_bin("frawk").unwrap();
+            cmd.arg(String::from(*backend_arg));
+            if !input_format.is_empty() {
+                cmd.arg(String::from(*input_format));
+            }
+            cmd.arg(String::from("{ print FNR, $0; }"))
+                .arg(fname_to_string(&first))
+                .arg(fname_to_string(&empty))
+                .arg(fname_to_string(&second))
+                .assert()
+                .stdout(expected);
+        }
+    }
+}
+
 mod v_args {
     //! Tests for v args.
     use super::*;
@@ -222,6 +590,323 @@ mod v_args {
     }
 }
 
+#[test]
+fn assignment_operands() {
+    let expected = "a 1 x\nb 1 x\nc 3 y\n: 3\n";
+
+    let tmpdir = tempdir().unwrap();
+    let first = tmpdir.path().join("first");
+    let empty = tmpdir.path().join("empty");
+    let second = tmpdir.path().join("second");
+    for (fname, data) in &[(&first, "a\nb\n"), (&empty, ""), (&second, "c:y\n")] {
+        let mut file = File::create(fname).unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+    let prog: String = r#"{ print $1, x, ($2 == "" ? "x" : $2); } END { print FS, x; }"#.into();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(prog.clone())
+            .arg(String::from("x=1"))
+            .arg(fname_to_string(&first))
+            .arg(String::from("x=2"))
+            .arg(fname_to_string(&empty))
+            .arg(String::from("x=3"))
+            .arg(String::from("FS=:"))
+            .arg(fname_to_string(&second))
+            .assert()
+            .stdout(expected);
+
+        // Each assignment takes effect as its file starts, even if the file is empty.
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("BEGINFILE { print x; }"))
+            .arg(String::from("x=1"))
+            .arg(fname_to_string(&first))
+            .arg(String::from("x=2"))
+            .arg(fname_to_string(&empty))
+            .arg(String::from("x=3"))
+            .arg(fname_to_string(&second))
+            .assert()
+            .stdout("1\n2\n3\n");
+
+        // Workers read files in no particular order with -pf, so there is no telling which
+        // assignment a record should see.
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-pf"))
+            .arg(prog.clone())
+            .arg(fname_to_string(&first))
+            .arg(String::from("x=2"))
+            .arg(fname_to_string(&second))
+            .assert()
+            .failure();
+    }
+}
+
+#[test]
+fn fixed_width_input() {
+    let tmpdir = tempdir().unwrap();
+    let data = tmpdir.path().join("data");
+    let mut file = File::create(&data).unwrap();
+    file.write_all(b"2024-01-15 alice   42\n2024-02-03 bob     7  \n")
+        .unwrap();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-ifixed:10,1:8,3"))
+            .arg(String::from(
+                r#"{ sum += $3; print $2 "|" $1; } END { print sum; }"#,
+            ))
+            .arg(fname_to_string(&data))
+            .assert()
+            .stdout("alice   |2024-01-15\nbob     |2024-02-03\n49\n");
+    }
+    Command::cargo_bin("frawk")
+        .unwrap()
+        .arg(String::from("-ifixed:3,x"))
+        .arg(String::from("{ print; }"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn beginfile_endfile() {
+    let tmpdir = tempdir().unwrap();
+    let first = tmpdir.path().join("first");
+    let second = tmpdir.path().join("second");
+    for (fname, data) in &[(&first, "1\n2\n"), (&second, "3\n4\n5\n")] {
+        let mut file = File::create(fname).unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+    let (first, second) = (fname_to_string(&first), fname_to_string(&second));
+    let prog: String =
+        r#"BEGINFILE { sum = 0; } { sum += $1; } ENDFILE { print FILENAME, FNR, sum; }"#.into();
+    let expected = format!("{} 2 3\n{} 3 12\n", first, second);
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(prog.clone())
+            .arg(first.clone())
+            .arg(second.clone())
+            .assert()
+            .stdout(expected.clone());
+
+        // Each worker reads whole files with -pf, so the blocks still run once per file, though
+        // the files may finish in any order.
+        let output = Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-pf"))
+            .arg(String::from("-j2"))
+            .arg(prog.clone())
+            .arg(first.clone())
+            .arg(second.clone())
+            .output()
+            .unwrap();
+        let mut lines: Vec<_> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![format!("{} 2 3", first), format!("{} 3 12", second)]
+        );
+    }
+}
+
+#[test]
+fn beginfile_endfile_empty_files() {
+    let tmpdir = tempdir().unwrap();
+    let first = tmpdir.path().join("first");
+    let empty = tmpdir.path().join("empty");
+    let second = tmpdir.path().join("second");
+    for (fname, data) in &[(&first, "a 1\nb 2\n"), (&empty, ""), (&second, "c 3\n")] {
+        let mut file = File::create(fname).unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+    let (first, empty, second) = (
+        fname_to_string(&first),
+        fname_to_string(&empty),
+        fname_to_string(&second),
+    );
+    // ENDFILE sees the file that just ended, along with its last record. Each FS picks a
+    // different reader.
+    let expected = format!(
+        "begin {0}\nend {0} 2 b 2\nbegin {1}\nend {1} 0 b 2\nbegin {2}\nend {2} 1 c 3\n",
+        first, empty, second
+    );
+    for fs in &[" ", ",", "[ ]+"] {
+        let prog = format!(
+            r#"BEGIN {{ FS = "{}" }} BEGINFILE {{ print "begin", FILENAME }} ENDFILE {{ print "end", FILENAME, FNR, $0 }}"#,
+            fs
+        );
+        for backend_arg in BACKEND_ARGS {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(prog.clone())
+                .arg(first.clone())
+                .arg(empty.clone())
+                .arg(second.clone())
+                .assert()
+                .stdout(expected.clone());
+        }
+    }
+}
+
+#[test]
+fn beginfile_endfile_getline() {
+    let tmpdir = tempdir().unwrap();
+    let first = tmpdir.path().join("first");
+    let second = tmpdir.path().join("second");
+    for (fname, data) in &[(&first, "1\n2\n"), (&second, "3\n")] {
+        let mut file = File::create(fname).unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+    let (first, second) = (fname_to_string(&first), fname_to_string(&second));
+    // getline reads past the end of both files, so only the first BEGINFILE block runs.
+    let prog: String = r#"BEGINFILE { print "begin", FILENAME; }
+        $0 == 1 { while ((getline) > 0) print "got", $0; }
+        ENDFILE { print "end", FILENAME; }"#
+        .into();
+    let expected = format!("begin {}\ngot 2\ngot 3\n", first);
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(prog.clone())
+            .arg(first.clone())
+            .arg(second.clone())
+            .assert()
+            .stdout(expected.clone());
+    }
+}
+
+#[test]
+fn stderr_special_file() {
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(
+                r#"{ print "out " $1; print "err " $1 > "/dev/stderr"; printf "%s\n", $1 > "/dev/fd/2"; }"#,
+            ))
+            .write_stdin("x\ny\n")
+            .assert()
+            .stdout("out x\nout y\n")
+            .stderr("err x\nx\nerr y\ny\n");
+    }
+}
+
+#[test]
+fn default_strftime_format() {
+    // -H turns on the string constant analysis, which reads the default format as a literal.
+    let prog = r#"BEGIN { n = split(strftime(), parts, " "); print (n >= 6) }"#;
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-icsv"))
+            .arg(String::from("-H"))
+            .arg(prog)
+            .write_stdin("a,b\n")
+            .assert()
+            .stdout("1\n");
+    }
+}
+
+#[test]
+fn coprocesses() {
+    let prog: String = r#"{ print $0 |& "sort"; }
+    END {
+        close("sort", "to");
+        while (("sort" |& getline line) > 0) print "sorted", line;
+        close("sort");
+        print "hello" |& "cat"; "cat" |& getline x; print x;
+        print "again" |& "cat"; "cat" |& getline x; print x;
+        close("cat");
+    }"#
+    .into();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(prog.clone())
+            .write_stdin("b\nc\na\n")
+            .assert()
+            .stdout("sorted a\nsorted b\nsorted c\nhello\nagain\n");
+    }
+}
+
+#[test]
+fn exec_without_shell() {
+    let prog: String = r#"{
+        argv[1] = "echo";
+        argv[2] = $1;
+        argv[3] = $2;
+        status = exec(argv);
+        print "status", status;
+        exec_cmd(argv) | getline line;
+        print "read", line;
+    }"#
+    .into();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-F,")
+            .arg(prog.clone())
+            .write_stdin("a b,$HOME;echo c\n")
+            .assert()
+            .stdout("a b $HOME;echo c\nstatus 0\nread a b $HOME;echo c\n");
+    }
+}
+
+#[test]
+fn exec_empty_argv() {
+    let prog: String = r#"BEGIN {
+        split("", argv);
+        print "status", exec(argv);
+        argv[1] = "true";
+        print "status", exec(argv);
+    }"#
+    .into();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(prog.clone())
+            .assert()
+            .success()
+            .stdout("status 1\nstatus 0\n");
+    }
+}
+
+#[test]
+fn environ() {
+    let expected = "from the environment 0\n";
+    let prog: String =
+        r#"BEGIN { print ENVIRON["FRAWK_TEST_VAR"], length(ENVIRON["FRAWK_UNSET_VAR"]); }"#.into();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .env("FRAWK_TEST_VAR", "from the environment")
+            .env_remove("FRAWK_UNSET_VAR")
+            .arg(String::from(*backend_arg))
+            .arg(prog.clone())
+            .assert()
+            .stdout(expected);
+    }
+}
+
 #[test]
 fn mixed_map() {
     let expected = "hi 0 5\n1 1 3\n";
@@ -320,6 +1005,188 @@ fn trivial_parallel_rc() {
     }
 }
 
+#[test]
+fn ordered_parallel_output() {
+    let mut text = String::default();
+    for i in 0..20_000 {
+        text.push_str(&format!("{} {}\n", i, i % 7));
+    }
+    let (_dir, data) = file_from_string("inputs", &text);
+    // Do enough work per record that several workers start and finish chunks out of order.
+    let prog = r#"BEGIN { print "begin" } $2 != 3 { x = 0; for (i = 0; i < 50; i++) x += $2; print $1, x } END { print "end" }"#;
+    let mut expected = String::from("begin\n");
+    for i in 0..20_000 {
+        if i % 7 != 3 {
+            expected.push_str(&format!("{} {}\n", i, (i % 7) * 50));
+        }
+    }
+    expected.push_str("end\n");
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-pr")
+            .arg("--ordered")
+            .arg("-j4")
+            .arg("--chunk-size=1024")
+            .arg(prog)
+            .arg(fname_to_string(&data))
+            .assert()
+            .stdout(expected.clone());
+    }
+}
+
+#[test]
+fn ordered_parallel_begin_end() {
+    let mut text = String::default();
+    for i in 0..5_000 {
+        text.push_str(&format!("{}\n", i));
+    }
+    let expected = format!("begin\n{}end\n", text);
+    let prog = r#"BEGIN { print "begin" } { print $1 } END { print "end" }"#;
+    for backend_arg in BACKEND_ARGS {
+        // With one worker, records are read on the thread that runs BEGIN and END.
+        for workers in &["-j1", "-j3"] {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg("-pr")
+                .arg("--ordered")
+                .arg(workers)
+                .arg("--chunk-size=1024")
+                .arg(prog)
+                .write_stdin(text.clone())
+                .assert()
+                .stdout(expected.clone());
+        }
+    }
+}
+
+#[test]
+fn ordered_parallel_redirects() {
+    let text: String = (0..100).map(|i| format!("{}\n", i)).collect();
+    // Only standard output is ordered, so the main loop may not redirect output elsewhere.
+    for prog in &[
+        r#"{ print > "out.txt" }"#,
+        r#"{ printf "%s\n", $1 > "/dev/stderr" }"#,
+        r#"function f(x) { print x | "cat" } { f($1) }"#,
+    ] {
+        for backend_arg in BACKEND_ARGS {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg("-pr")
+                .arg("--ordered")
+                .arg(*prog)
+                .write_stdin(text.clone())
+                .assert()
+                .failure();
+        }
+    }
+    let prog = r#"{ print > "/dev/stdout"; n++ } END { print n > "/dev/stderr" }"#;
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-pr")
+            .arg("--ordered")
+            .arg("-j4")
+            .arg("--chunk-size=64")
+            .arg(prog)
+            .write_stdin(text.clone())
+            .assert()
+            .stdout(text.clone())
+            .stderr("100\n");
+    }
+}
+
+#[test]
+fn record_parallel_multiple_files() {
+    // Files of very different sizes, so that workers read chunks from several of them at once.
+    let sizes = [5_000, 3, 0, 12_000];
+    let files: Vec<_> = sizes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| {
+            let text: String = (1..=*n).map(|j| format!("{} {}\n", i, j)).collect();
+            file_from_string(format!("input{}", i), text)
+        })
+        .collect();
+    let prog = r#"{ x = 0; for (i = 0; i < 20; i++) x += i; if (FNR != $2 || FILENAME !~ ("input" $1 "$")) print "bad", FILENAME, FNR, $0; n++ } END { print n }"#;
+    let expected = format!("{}\n", sizes.iter().sum::<usize>());
+    for backend_arg in BACKEND_ARGS {
+        let mut cmd = Command::cargo_bin("frawk").unwrap();
+        cmd.arg(String::from(*backend_arg))
+            .arg("-pr")
+            .arg("-j4")
+            .arg("--chunk-size=1024")
+            .arg(prog);
+        for (_, path) in files.iter() {
+            cmd.arg(fname_to_string(path));
+        }
+        cmd.assert().stdout(expected.clone());
+    }
+}
+
+#[test]
+fn record_parallel_nextfile() {
+    let files: Vec<_> = (0..4)
+        .map(|i| {
+            let text: String = (1..=20_000).map(|j| format!("{} {}\n", i, j)).collect();
+            file_from_string(format!("input{}", i), text)
+        })
+        .collect();
+    let prog = r#"FNR <= 2 { print (FILENAME ~ ("input" $1 "$")), FNR, $0; next } { nextfile }"#;
+    let expected: String = (0..4)
+        .flat_map(|i| (1..=2).map(move |j| format!("1 {} {} {}\n", j, i, j)))
+        .collect();
+    for backend_arg in BACKEND_ARGS {
+        let mut cmd = Command::cargo_bin("frawk").unwrap();
+        cmd.arg(String::from(*backend_arg))
+            .arg("-pr")
+            .arg("--ordered")
+            .arg("-j4")
+            .arg("--chunk-size=1024")
+            .arg(prog);
+        for (_, path) in files.iter() {
+            cmd.arg(fname_to_string(path));
+        }
+        cmd.assert().stdout(expected.clone());
+    }
+}
+
+#[test]
+fn record_parallel_regex_fs() {
+    let text: String = (0..20_000)
+        .map(|i| format!("{},  {}::x,{}\n", i, i % 7, i % 3))
+        .collect();
+    let (_dir, data) = file_from_string("inputs", text);
+    let expected: String = (0..20_000)
+        .map(|i| format!("4 {} {}\n", i % 7, i % 3))
+        .collect();
+    for (fs, prog) in [
+        ("::|, *", r#"{ print NF, $2, $NF }"#),
+        ("[:,] *", r#"{ print NF - 1, $2, $NF }"#),
+    ]
+    .iter()
+    {
+        for backend_arg in BACKEND_ARGS {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg("-pr")
+                .arg("--ordered")
+                .arg("-j4")
+                .arg("--chunk-size=1024")
+                .arg(format!("-F{}", fs))
+                .arg(prog)
+                .arg(fname_to_string(&data))
+                .assert()
+                .stdout(expected.clone());
+        }
+    }
+}
+
 #[test]
 fn multi_rc() {
     let mut text = String::default();
@@ -368,6 +1235,28 @@ fn nested_loops() {
     }
 }
 
+#[test]
+fn next_outside_main_loop() {
+    for backend_arg in BACKEND_ARGS {
+        for prog in &[
+            r#"function f() { next; } BEGIN { print "a"; f(); print "b"; }"#,
+            r#"function f() { nextfile; } END { print "a"; f(); print "b"; }"#,
+        ] {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(String::from(*prog))
+                .write_stdin("x\n")
+                .assert()
+                .code(2)
+                .stdout("a\n")
+                .stderr(
+                    "frawk: `next` and `nextfile` cannot be called outside of the main input loop\n",
+                );
+        }
+    }
+}
+
 #[test]
 fn dont_reorder_files_with_f() {
     let expected = "1 1\n2 2\n3 3\n";